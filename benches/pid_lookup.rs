@@ -0,0 +1,50 @@
+// Compares the poll loop's new-process lookup against the HashSet difference it
+// replaces, for container sizes around simd::MAX_PIDS. The blocks are built once, as the
+// poll loop keeps them. Run on the target host with `cargo bench --features simd`.
+use std::collections::HashSet;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use container_new_process_detector::simd::{self, PidBlocks};
+
+const ITERATIONS: u32 = 20_000;
+
+fn hashed(known: &HashSet<(i32, u64)>, current: &HashSet<(i32, u64)>) -> Vec<(i32, u64)> {
+    let mut new: Vec<(i32, u64)> = current
+        .difference(known)
+        .filter(|(pid, start)| *start != 0 || !known.iter().any(|(known_pid, _)| known_pid == pid))
+        .copied()
+        .collect();
+    new.sort_unstable();
+    new
+}
+
+fn time<F: Fn() -> Vec<(i32, u64)>>(lookup: F) -> Duration {
+    let started = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(lookup());
+    }
+    started.elapsed() / ITERATIONS
+}
+
+fn main() {
+    println!("{:>6} {:>12} {:>12} {:>8}", "pids", "hashset", "simd", "speedup");
+    for size in [8, 16, 24, 32, 48, 64, 96, 128, 256usize] {
+        let known: HashSet<(i32, u64)> = (0..size as i32).map(|i| (1000 + i * 7, 5000 + i as u64)).collect();
+        // A poll that finds one new process next to all the known ones.
+        let mut current = known.clone();
+        current.insert((99_999, 123_456));
+        let blocks = PidBlocks::new(&known);
+        assert_eq!(hashed(&known, &current), simd::new_processes(&blocks, &current));
+
+        let baseline = time(|| hashed(black_box(&known), black_box(&current)));
+        let vectorized = time(|| simd::new_processes(black_box(&blocks), black_box(&current)));
+        println!(
+            "{:>6} {:>10.0}ns {:>10.0}ns {:>7.2}x",
+            size,
+            baseline.as_nanos() as f64,
+            vectorized.as_nanos() as f64,
+            baseline.as_secs_f64() / vectorized.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,71 @@
+// Compares reading /proc/<pid>/stat one file at a time with tokio::fs, as the poll loop
+// does without the io-uring feature, against one batched AsyncProcReader call. Reads
+// the host's own processes, so run as root on the target host with
+// `cargo bench --features io-uring`.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use container_new_process_detector::uring::AsyncProcReader;
+
+const ITERATIONS: u32 = 200;
+
+fn host_pids() -> Vec<i32> {
+    let mut pids: Vec<i32> = std::fs::read_dir("/proc")
+        .unwrap()
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    pids.sort_unstable();
+    pids
+}
+
+fn stat_paths(pids: &[i32]) -> Vec<String> {
+    pids.iter().map(|pid| format!("/proc/{}/stat", pid)).collect()
+}
+
+async fn sequential(paths: &[String]) -> usize {
+    let mut read = 0;
+    for path in paths {
+        if let Ok(stat) = tokio::fs::read_to_string(path).await {
+            read += black_box(stat).len();
+        }
+    }
+    read
+}
+
+async fn batched(reader: &AsyncProcReader, paths: &[String]) -> usize {
+    let contents = reader.read_files(paths.to_vec()).await.unwrap();
+    contents.into_iter().flatten().map(|stat| black_box(stat).len()).sum()
+}
+
+#[tokio::main]
+async fn main() {
+    let reader = AsyncProcReader::new().expect("io_uring is not available on this host");
+    let pids = host_pids();
+    println!("{} processes on this host", pids.len());
+    println!("{:>6} {:>12} {:>12} {:>8}", "pids", "tokio::fs", "io_uring", "speedup");
+    let mut sizes: Vec<usize> = [16, 64, 256, 1024].into_iter().filter(|size| *size < pids.len()).collect();
+    sizes.push(pids.len());
+    for size in sizes {
+        let paths = stat_paths(&pids[..size]);
+
+        let started = Instant::now();
+        for _ in 0..ITERATIONS {
+            sequential(&paths).await;
+        }
+        let baseline: Duration = started.elapsed() / ITERATIONS;
+
+        let started = Instant::now();
+        for _ in 0..ITERATIONS {
+            batched(&reader, &paths).await;
+        }
+        let uring: Duration = started.elapsed() / ITERATIONS;
+
+        println!(
+            "{:>6} {:>10.1}us {:>10.1}us {:>7.2}x",
+            size,
+            baseline.as_secs_f64() * 1e6,
+            uring.as_secs_f64() * 1e6,
+            baseline.as_secs_f64() / uring.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::sync::Mutex;
+use tokio::fs;
+
+use crate::json::{self, Value};
+
+// Measurements taken before a baseline is trusted to flag anything.
+pub const MIN_SAMPLES: usize = 10;
+// Containers that always run the same number of processes have no spread at all, so
+// the spread used is at least this, or a single extra process would be an anomaly.
+const MIN_STDDEV: f64 = 1.0;
+
+// The last `window` process counts of one container, oldest first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessCountBaseline {
+    window: usize,
+    samples: VecDeque<u32>,
+}
+
+impl ProcessCountBaseline {
+    pub fn new(window: usize) -> ProcessCountBaseline {
+        ProcessCountBaseline {
+            window,
+            samples: VecDeque::with_capacity(window),
+        }
+    }
+
+    pub fn record(&mut self, count: u32) {
+        while self.samples.len() >= self.window.max(1) {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(count);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (!self.samples.is_empty()).then(|| self.samples.iter().map(|c| *c as f64).sum::<f64>() / self.samples.len() as f64)
+    }
+
+    // Population standard deviation of the window.
+    pub fn stddev(&self) -> Option<f64> {
+        let mean = self.mean()?;
+        let variance = self.samples.iter().map(|c| (*c as f64 - mean).powi(2)).sum::<f64>() / self.samples.len() as f64;
+        Some(variance.sqrt())
+    }
+
+    // mean + stddevs * stddev, above which a count is anomalous; None until
+    // MIN_SAMPLES have been recorded.
+    pub fn threshold(&self, stddevs: f64) -> Option<f64> {
+        if self.samples.len() < MIN_SAMPLES {
+            return None;
+        }
+        Some(self.mean()? + stddevs * self.stddev()?.max(MIN_STDDEV))
+    }
+}
+
+// How far a measurement was from its container's baseline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProcessCountAnomaly {
+    pub count: u32,
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+// The baselines of every container, kept in --process-count-baseline-file between
+// runs as `{"containers": {"<name or ID>": [counts...]}}`.
+pub struct ProcessCountBaselines {
+    path: String,
+    window: usize,
+    stddevs: f64,
+    baselines: Mutex<HashMap<String, ProcessCountBaseline>>,
+}
+
+impl ProcessCountBaselines {
+    // A missing file starts every baseline empty.
+    pub async fn load(path: &str, window: usize, stddevs: f64) -> Result<ProcessCountBaselines, Box<dyn Error>> {
+        let mut baselines = HashMap::new();
+        match fs::read_to_string(path).await {
+            Ok(content) => {
+                let doc = json::parse(&content).map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+                if let Some(Value::Object(containers)) = doc.get("containers") {
+                    for (container, counts) in containers {
+                        let mut baseline = ProcessCountBaseline::new(window);
+                        for count in counts.as_array().into_iter().flatten().filter_map(Value::as_i64) {
+                            baseline.record(count as u32);
+                        }
+                        baselines.insert(container.clone(), baseline);
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Failed to read {}: {}", path, e).into()),
+        }
+        Ok(ProcessCountBaselines {
+            path: path.to_string(),
+            window,
+            stddevs,
+            baselines: Mutex::new(baselines),
+        })
+    }
+
+    // Compares the count with the container's baseline before adding it.
+    pub fn record(&self, container: &str, count: u32) -> Option<ProcessCountAnomaly> {
+        let mut baselines = self.baselines.lock().unwrap();
+        let baseline = baselines
+            .entry(container.to_string())
+            .or_insert_with(|| ProcessCountBaseline::new(self.window));
+        let anomaly = baseline
+            .threshold(self.stddevs)
+            .filter(|threshold| count as f64 > *threshold)
+            .map(|_| ProcessCountAnomaly {
+                count,
+                mean: baseline.mean().unwrap_or_default(),
+                stddev: baseline.stddev().unwrap_or_default(),
+            });
+        baseline.record(count);
+        anomaly
+    }
+
+    pub async fn save(&self) -> std::io::Result<()> {
+        let containers = {
+            let baselines = self.baselines.lock().unwrap();
+            let mut containers: Vec<(String, Value)> = baselines
+                .iter()
+                .map(|(container, baseline)| (container.clone(), baseline.samples.iter().copied().collect::<Vec<u32>>().into()))
+                .collect();
+            containers.sort_by(|a, b| a.0.cmp(&b.0));
+            containers
+        };
+        let doc = Value::Object(vec![("containers".to_string(), Value::Object(containers))]);
+        let tmp = format!("{}.tmp", self.path);
+        fs::write(&tmp, format!("{}\n", doc)).await?;
+        fs::rename(&tmp, &self.path).await
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_counts_far_above_the_window() {
+        let mut baseline = ProcessCountBaseline::new(12);
+        for count in [10, 12, 11, 9, 10, 11, 10, 12, 9, 10] {
+            assert_eq!(baseline.threshold(3.0), None);
+            baseline.record(count);
+        }
+        assert_eq!(baseline.mean(), Some(10.4));
+        let threshold = baseline.threshold(3.0).unwrap();
+        assert!(threshold > 13.0 && threshold < 14.0, "{}", threshold);
+
+        // Old samples fall out of the window.
+        for _ in 0..12 {
+            baseline.record(50);
+        }
+        assert_eq!(baseline.len(), 12);
+        // No spread, so a single extra process is still within MIN_STDDEV.
+        assert_eq!(baseline.threshold(3.0), Some(53.0));
+    }
+}
@@ -0,0 +1,73 @@
+use std::error::Error;
+use std::path::Path;
+use tokio::fs;
+
+use crate::cgroup::{self, ContainerCgroup};
+use crate::procfs;
+use crate::state::{Baseline, BaselineProcess};
+
+// A copy of a stopped container's cgroup directory, for --forensics-mode. It holds the
+// container's last cgroup.procs and, under proc/, the /proc/<pid> entries of those
+// processes as copied with `cp -a` (exe stays a symlink, cmdline a NUL-separated file):
+//
+//     docker-<id>.scope/
+//         cgroup.procs
+//         proc/<pid>/exe
+//         proc/<pid>/cmdline
+pub struct ContainerArchive {
+    pub dir: String,
+    pub container_id: String,
+    pub processes: Vec<BaselineProcess>,
+}
+
+impl ContainerArchive {
+    pub async fn load(dir: &str) -> Result<ContainerArchive, Box<dyn Error>> {
+        let path = Path::new(dir.trim_end_matches('/'));
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        if !cgroup::is_container_scope(&name) {
+            return Err(format!("{} is not named after a container cgroup, such as docker-<id>.scope", dir).into());
+        }
+        let container_id = ContainerCgroup {
+            root: String::new(),
+            name,
+            memory_limit: None,
+        }
+        .container_id();
+
+        let procs_path = path.join("cgroup.procs");
+        let mut pids: Vec<i32> = cgroup::read_procs(&procs_path.to_string_lossy()).await?.into_iter().collect();
+        pids.sort_unstable();
+        let mut processes = Vec::new();
+        for pid in pids {
+            let proc_dir = path.join("proc").join(pid.to_string());
+            processes.push(BaselineProcess {
+                pid,
+                exe: fs::read_link(proc_dir.join("exe"))
+                    .await
+                    .ok()
+                    .map(|exe| exe.to_string_lossy().into_owned()),
+                cmdline: fs::read(proc_dir.join("cmdline")).await.ok().map(|raw| procfs::parse_cmdline(&raw)),
+            });
+        }
+        Ok(ContainerArchive {
+            dir: dir.to_string(),
+            container_id,
+            processes,
+        })
+    }
+
+    // Archived processes the baseline does not account for: PIDs it does not list, and
+    // PIDs it lists with another executable, which were reused by a new process. When
+    // either side has no exe, the PID alone has to match.
+    pub fn unexpected<'a>(&'a self, baseline: &Baseline) -> Vec<&'a BaselineProcess> {
+        self.processes
+            .iter()
+            .filter(|p| {
+                !baseline
+                    .processes
+                    .iter()
+                    .any(|b| b.pid == p.pid && (b.exe.is_none() || p.exe.is_none() || b.exe == p.exe))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+use tokio::sync::RwLock;
+
+// A HashSet shared between tasks. The async methods wait for the lock without holding
+// up the thread; the try_ ones never wait, for callers like the poll loop that would
+// rather skip a step than queue behind a reader.
+#[derive(Debug, Default)]
+pub struct AsyncHashSet<T> {
+    set: RwLock<HashSet<T>>,
+}
+
+impl<T: Hash + Eq + Clone> AsyncHashSet<T> {
+    pub fn new() -> AsyncHashSet<T> {
+        AsyncHashSet { set: RwLock::new(HashSet::new()) }
+    }
+
+    // Returns whether the value was not in the set yet.
+    pub async fn insert(&self, value: T) -> bool {
+        self.set.write().await.insert(value)
+    }
+
+    pub async fn contains(&self, value: &T) -> bool {
+        self.set.read().await.contains(value)
+    }
+
+    // Returns whether the value was in the set.
+    pub async fn remove(&self, value: &T) -> bool {
+        self.set.write().await.remove(value)
+    }
+
+    pub async fn snapshot(&self) -> HashSet<T> {
+        self.set.read().await.clone()
+    }
+
+    // None while a writer holds the set.
+    pub fn try_snapshot(&self) -> Option<HashSet<T>> {
+        self.set.try_read().ok().map(|set| set.clone())
+    }
+
+    // Replaces the contents with `values` unless the set is locked, in which case it is
+    // left as it was and false is returned.
+    pub fn try_replace(&self, values: &HashSet<T>) -> bool {
+        match self.set.try_write() {
+            Ok(mut set) => {
+                set.clone_from(values);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn shares_a_set_without_waiting_in_try() {
+        let set = AsyncHashSet::new();
+        assert!(set.insert(1).await);
+        assert!(!set.insert(1).await);
+        assert!(set.insert(2).await);
+        assert!(set.contains(&2).await);
+        assert!(set.remove(&2).await);
+        assert!(!set.contains(&2).await);
+        assert_eq!(set.snapshot().await, HashSet::from([1]));
+
+        let reader = set.set.read().await;
+        assert!(!set.try_replace(&HashSet::from([3])));
+        assert_eq!(set.try_snapshot(), Some(HashSet::from([1])));
+        drop(reader);
+        assert!(set.try_replace(&HashSet::from([3])));
+
+        let writer = set.set.write().await;
+        assert_eq!(set.try_snapshot(), None);
+        drop(writer);
+        assert_eq!(set.try_snapshot(), Some(HashSet::from([3])));
+    }
+}
@@ -0,0 +1,544 @@
+// Receives execve() records from the kernel audit subsystem over netlink. The kernel
+// reports each exec with its PID, UID, working directory and full argv as it happens,
+// so the argv is known even when the process exits or rewrites /proc/<pid>/cmdline
+// before a poll reads it.
+//
+// Records are read from the AUDIT_NLGRP_READLOG multicast group, which leaves auditd
+// (if any) in charge of the audit daemon socket. That needs CAP_AUDIT_READ, and
+// installing the execve rule needs CAP_AUDIT_CONTROL. The same group carries the
+// AUDIT_SECCOMP records of --watch-seccomp-violations, which need no rule.
+
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+use tokio::sync::mpsc;
+
+use crate::cgroup;
+use crate::debug;
+use crate::procfs;
+use crate::sandbox::AUDIT_ARCH;
+
+const AUDIT_NLGRP_READLOG: u32 = 1;
+const AUDIT_GET: u16 = 1000;
+const AUDIT_ADD_RULE: u16 = 1011;
+const AUDIT_DEL_RULE: u16 = 1012;
+const AUDIT_SYSCALL: u16 = 1300;
+const AUDIT_CWD: u16 = 1307;
+const AUDIT_EXECVE: u16 = 1309;
+const AUDIT_EOE: u16 = 1320;
+const AUDIT_SECCOMP: u16 = 1326;
+
+const AUDIT_FILTER_EXIT: u32 = 0x04;
+const AUDIT_ALWAYS: u32 = 2;
+const AUDIT_ARCH_FIELD: u32 = 11;
+const AUDIT_FILTERKEY: u32 = 210;
+const AUDIT_EQUAL: u32 = 0x4000_0000;
+const AUDIT_BITMASK_SIZE: usize = 64;
+const AUDIT_MAX_FIELDS: usize = 64;
+
+// Key of the rule we install, so it can be told apart from the administrator's rules.
+const RULE_KEY: &str = "cnpd";
+
+const NLMSG_HEADER: usize = 16;
+const NLMSG_ERROR: u16 = 2;
+
+// Exec records are kept this long, which covers the time from exec to detection.
+const EXEC_RETENTION: Duration = Duration::from_secs(60);
+const MAX_EXECS: usize = 10_000;
+// Events whose end-of-event record never arrived are dropped past this many.
+const MAX_PENDING: usize = 1024;
+
+// One execve() as reported by audit.
+#[derive(Debug, Clone)]
+pub struct ExecRecord {
+    pub pid: i32,
+    pub ppid: i32,
+    pub uid: Option<u32>,
+    pub exe: Option<String>,
+    pub cwd: Option<String>,
+    pub argv: Vec<String>,
+    pub seen_at: Instant,
+}
+
+// Execs of container processes from the last EXEC_RETENTION, filled by listen().
+#[derive(Default)]
+pub struct ExecLog {
+    records: Mutex<VecDeque<ExecRecord>>,
+}
+
+impl ExecLog {
+    pub fn record(&self, record: ExecRecord) {
+        let mut records = self.records.lock().unwrap();
+        while records
+            .front()
+            .is_some_and(|r| r.seen_at.elapsed() > EXEC_RETENTION || records.len() >= MAX_EXECS)
+        {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    // The most recent exec of `pid`, which is the image it is running now.
+    pub fn latest_for_pid(&self, pid: i32) -> Option<ExecRecord> {
+        let records = self.records.lock().unwrap();
+        records.iter().rev().find(|r| r.pid == pid && r.seen_at.elapsed() <= EXEC_RETENTION).cloned()
+    }
+}
+
+// The netlink socket subscribed to audit records. Dropping it removes the execve rule,
+// if it installed one.
+pub struct AuditListener {
+    fd: AsyncFd<OwnedFd>,
+    execve_rule: bool,
+}
+
+impl AuditListener {
+    pub fn new() -> io::Result<AuditListener> {
+        let mut listener = AuditListener::subscribe()?;
+        // Subscribed first, so no exec between adding the rule and listening is missed.
+        match audit_request(AUDIT_ADD_RULE, &execve_rule()) {
+            Err(e) if e.raw_os_error() != Some(libc::EEXIST) => return Err(e),
+            _ => {}
+        }
+        listener.execve_rule = true;
+        Ok(listener)
+    }
+
+    // Only subscribes to the records the kernel logs anyway, which needs CAP_AUDIT_READ
+    // but not CAP_AUDIT_CONTROL.
+    pub fn subscribe() -> io::Result<AuditListener> {
+        let fd = netlink_socket(libc::SOCK_NONBLOCK)?;
+        let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as u16;
+        addr.nl_groups = 1 << (AUDIT_NLGRP_READLOG - 1);
+        let result = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as u32,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if !audit_enabled()? {
+            return Err(io::Error::other("auditing is disabled in the kernel, enable it with auditctl -e 1"));
+        }
+        Ok(AuditListener {
+            fd: AsyncFd::with_interest(fd, Interest::READABLE)?,
+            execve_rule: false,
+        })
+    }
+
+    // Waits until at least one record is available and returns everything read, as
+    // (record type, text) pairs.
+    pub async fn read_records(&self) -> io::Result<Vec<(u16, String)>> {
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let mut guard = self.fd.readable().await?;
+            let n = unsafe { libc::recv(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+            if n < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::WouldBlock {
+                    guard.clear_ready();
+                    continue;
+                }
+                return Err(err);
+            }
+            return Ok(parse_messages(&buf[..n as usize])
+                .into_iter()
+                .map(|(kind, payload)| (kind, String::from_utf8_lossy(payload).trim_end_matches('\0').to_string()))
+                .collect());
+        }
+    }
+}
+
+impl Drop for AuditListener {
+    fn drop(&mut self) {
+        if !self.execve_rule {
+            return;
+        }
+        if let Err(e) = audit_request(AUDIT_DEL_RULE, &execve_rule()) {
+            eprintln!("Failed to remove the audit execve rule: {}", e);
+        }
+    }
+}
+
+fn netlink_socket(flags: libc::c_int) -> io::Result<OwnedFd> {
+    let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC | flags, libc::NETLINK_AUDIT) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+// struct audit_rule_data for an exit filter on execve and execveat of the native
+// architecture, tagged with RULE_KEY.
+fn execve_rule() -> Vec<u8> {
+    let mut mask = [0u32; AUDIT_BITMASK_SIZE];
+    for syscall in [libc::SYS_execve, libc::SYS_execveat] {
+        mask[syscall as usize / 32] |= 1 << (syscall % 32);
+    }
+    let mut fields = [0u32; AUDIT_MAX_FIELDS];
+    let mut values = [0u32; AUDIT_MAX_FIELDS];
+    let mut fieldflags = [0u32; AUDIT_MAX_FIELDS];
+    (fields[0], values[0], fieldflags[0]) = (AUDIT_ARCH_FIELD, AUDIT_ARCH, AUDIT_EQUAL);
+    (fields[1], values[1], fieldflags[1]) = (AUDIT_FILTERKEY, RULE_KEY.len() as u32, AUDIT_EQUAL);
+
+    let words = [AUDIT_FILTER_EXIT, AUDIT_ALWAYS, 2]
+        .into_iter()
+        .chain(mask)
+        .chain(fields)
+        .chain(values)
+        .chain(fieldflags)
+        .chain([RULE_KEY.len() as u32]);
+    let mut rule: Vec<u8> = words.flat_map(u32::to_ne_bytes).collect();
+    rule.extend_from_slice(RULE_KEY.as_bytes());
+    rule
+}
+
+// Sends one request to the kernel on a socket of its own and waits for the ack, so
+// records arriving on the listening socket cannot get mixed up with it.
+fn audit_request(kind: u16, payload: &[u8]) -> io::Result<()> {
+    let fd = netlink_socket(0)?;
+    let message = netlink_message(kind, (libc::NLM_F_REQUEST | libc::NLM_F_ACK) as u16, payload);
+    if unsafe { libc::send(fd.as_raw_fd(), message.as_ptr().cast(), message.len(), 0) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    wait_for_ack(fd.as_raw_fd())
+}
+
+// The `enabled` word of the kernel's struct audit_status.
+fn audit_enabled() -> io::Result<bool> {
+    let fd = netlink_socket(0)?;
+    let message = netlink_message(AUDIT_GET, libc::NLM_F_REQUEST as u16, &[]);
+    if unsafe { libc::send(fd.as_raw_fd(), message.as_ptr().cast(), message.len(), 0) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut buf = [0u8; 8192];
+    let n = unsafe { libc::recv(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    match parse_messages(&buf[..n as usize]).first() {
+        Some((AUDIT_GET, status)) if status.len() >= 8 => Ok(u32::from_ne_bytes([status[4], status[5], status[6], status[7]]) != 0),
+        _ => Err(io::Error::other("unexpected reply to AUDIT_GET")),
+    }
+}
+
+fn wait_for_ack(fd: RawFd) -> io::Result<()> {
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = unsafe { libc::recv(fd, buf.as_mut_ptr().cast(), buf.len(), 0) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        for (kind, payload) in parse_messages(&buf[..n as usize]) {
+            if kind == NLMSG_ERROR && payload.len() >= 4 {
+                let code = i32::from_ne_bytes([payload[0], payload[1], payload[2], payload[3]]);
+                return if code == 0 { Ok(()) } else { Err(io::Error::from_raw_os_error(-code)) };
+            }
+        }
+    }
+}
+
+fn netlink_message(kind: u16, flags: u16, payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(NLMSG_HEADER + payload.len());
+    message.extend_from_slice(&((NLMSG_HEADER + payload.len()) as u32).to_ne_bytes());
+    message.extend_from_slice(&kind.to_ne_bytes());
+    message.extend_from_slice(&flags.to_ne_bytes());
+    message.extend_from_slice(&1u32.to_ne_bytes());
+    message.extend_from_slice(&0u32.to_ne_bytes());
+    message.extend_from_slice(payload);
+    message
+}
+
+// Splits a datagram into (message type, payload) pairs. Audit records do not always
+// set nlmsg_len correctly, so a length past the end is cut to the datagram.
+fn parse_messages(mut buf: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut messages = Vec::new();
+    while buf.len() >= NLMSG_HEADER {
+        let len = u32::from_ne_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+        let kind = u16::from_ne_bytes([buf[4], buf[5]]);
+        let end = len.clamp(NLMSG_HEADER, buf.len());
+        messages.push((kind, &buf[NLMSG_HEADER..end]));
+        // Messages are padded to four bytes.
+        buf = &buf[((end + 3) & !3).min(buf.len())..];
+    }
+    messages
+}
+
+// The event serial from the "audit(<time>:<serial>): " prefix every record starts with.
+fn serial(text: &str) -> Option<u64> {
+    let prefix = text.strip_prefix("audit(")?;
+    let (_, serial) = prefix[..prefix.find(')')?].split_once(':')?;
+    serial.parse().ok()
+}
+
+// The raw value of `name=` in a record: a quoted string, hex-encoded bytes or a word.
+fn field<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let start = text.match_indices(name).find_map(|(i, _)| {
+        let before = text[..i].chars().next_back();
+        let after = &text[i + name.len()..];
+        (before.is_none_or(|c| c == ' ') && after.starts_with('=')).then_some(i + name.len() + 1)
+    })?;
+    let rest = &text[start..];
+    if let Some(quoted) = rest.strip_prefix('"') {
+        return Some(&rest[..quoted.find('"')? + 2]);
+    }
+    Some(rest.split(' ').next().unwrap_or(rest))
+}
+
+// Audit quotes plain values and hex-encodes ones with spaces or control characters.
+fn decode(raw: &str) -> String {
+    if let Some(quoted) = raw.strip_prefix('"').and_then(|r| r.strip_suffix('"')) {
+        return quoted.to_string();
+    }
+    let bytes: Option<Vec<u8>> = (raw.len().is_multiple_of(2) && !raw.is_empty())
+        .then(|| (0..raw.len()).step_by(2).map(|i| u8::from_str_radix(&raw[i..i + 2], 16).ok()).collect())
+        .flatten();
+    match bytes {
+        Some(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        None => raw.to_string(),
+    }
+}
+
+// argv from the EXECVE records of one event. Long arguments are split into
+// a<n>[0], a<n>[1], ... parts.
+fn parse_argv(execve: &str) -> Vec<String> {
+    let argc: usize = field(execve, "argc").and_then(|a| a.parse().ok()).unwrap_or(0);
+    (0..argc)
+        .map(|i| match field(execve, &format!("a{}", i)) {
+            Some(arg) => decode(arg),
+            None => (0..)
+                .map_while(|part| field(execve, &format!("a{}[{}]", i, part)))
+                .map(decode)
+                .collect(),
+        })
+        .collect()
+}
+
+// The records of one audit event, collected until its AUDIT_EOE arrives.
+#[derive(Default)]
+struct PendingExec {
+    syscall: Option<String>,
+    execve: String,
+    cwd: Option<String>,
+}
+
+impl PendingExec {
+    fn finish(self) -> Option<ExecRecord> {
+        let syscall = self.syscall?;
+        if field(&syscall, "success") == Some("no") || self.execve.is_empty() {
+            return None;
+        }
+        let number = |name: &str| field(&syscall, name).and_then(|v| v.parse::<i64>().ok());
+        Some(ExecRecord {
+            pid: number("pid")? as i32,
+            ppid: number("ppid").unwrap_or(0) as i32,
+            uid: number("uid").map(|uid| uid as u32),
+            exe: field(&syscall, "exe").map(decode),
+            cwd: self.cwd.as_deref().and_then(|cwd| field(cwd, "cwd")).map(decode),
+            argv: parse_argv(&self.execve),
+            seen_at: Instant::now(),
+        })
+    }
+}
+
+// Keeps only execs inside a container. The process usually still runs when its
+// record arrives; if it is already gone, its parent's cgroup decides.
+async fn in_container(record: &ExecRecord) -> bool {
+    for pid in [record.pid, record.ppid] {
+        if let Some(cgroup) = procfs::read_proc_file(pid, "cgroup").await {
+            return String::from_utf8_lossy(&cgroup)
+                .split('/')
+                .any(|part| cgroup::is_container_scope(part.trim_end()));
+        }
+    }
+    false
+}
+
+// Installs the execve rule and records every container exec in `log` from a
+// background task.
+pub fn listen(log: Arc<ExecLog>) -> Result<(), Box<dyn Error>> {
+    let listener = AuditListener::new().map_err(|e| format!("Failed to set up the audit listener: {}", e))?;
+
+    tokio::spawn(async move {
+        let mut pending: HashMap<u64, PendingExec> = HashMap::new();
+        loop {
+            let records = match listener.read_records().await {
+                Ok(records) => records,
+                // The socket buffer overflowed; the dropped records are lost, the socket is fine.
+                Err(e) if e.raw_os_error() == Some(libc::ENOBUFS) => {
+                    eprintln!("Warning: audit records were dropped, the receive buffer overflowed");
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("Audit listener stopped: {}", e);
+                    return;
+                }
+            };
+            for (kind, text) in records {
+                let Some(serial) = serial(&text) else {
+                    continue;
+                };
+                match kind {
+                    AUDIT_SYSCALL if field(&text, "key").map(decode).as_deref() == Some(RULE_KEY) => {
+                        if pending.len() >= MAX_PENDING {
+                            pending.clear();
+                        }
+                        pending.entry(serial).or_default().syscall = Some(text);
+                    }
+                    AUDIT_EXECVE | AUDIT_CWD => {
+                        if let Some(exec) = pending.get_mut(&serial) {
+                            if kind == AUDIT_CWD {
+                                exec.cwd = Some(text);
+                            } else {
+                                exec.execve.push(' ');
+                                exec.execve.push_str(&text);
+                            }
+                        }
+                    }
+                    AUDIT_EOE => {
+                        let Some(record) = pending.remove(&serial).and_then(PendingExec::finish) else {
+                            continue;
+                        };
+                        if in_container(&record).await {
+                            debug!("Audit: PID {} executed {:?}", record.pid, record.argv);
+                            log.record(record);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+// One AUDIT_SECCOMP record: a syscall a seccomp filter did not allow. The kernel logs
+// kills on its own, other actions only for filters installed with SECCOMP_FILTER_FLAG_LOG.
+#[derive(Debug, Clone)]
+pub struct SeccompRecord {
+    pub pid: i32,
+    pub uid: Option<u32>,
+    pub exe: Option<String>,
+    pub arch: Option<u32>,
+    pub syscall: i64,
+    // The filter's SECCOMP_RET_* value.
+    pub code: u32,
+}
+
+impl SeccompRecord {
+    pub fn parse(text: &str) -> Option<SeccompRecord> {
+        let number = |name: &str| field(text, name).and_then(|v| v.parse::<i64>().ok());
+        let hex = |name: &str| {
+            field(text, name).and_then(|v| u32::from_str_radix(v.trim_start_matches("0x"), 16).ok())
+        };
+        Some(SeccompRecord {
+            pid: number("pid")? as i32,
+            uid: number("uid").map(|uid| uid as u32),
+            exe: field(text, "exe").map(decode),
+            arch: hex("arch"),
+            syscall: number("syscall")?,
+            code: hex("code").unwrap_or(0),
+        })
+    }
+
+    // Whether the syscall was made in the native ABI, whose numbers syscall_names uses.
+    pub fn native(&self) -> bool {
+        self.arch.is_none_or(|arch| arch == AUDIT_ARCH)
+    }
+
+    // The filter action, from the SECCOMP_RET_ACTION_FULL bits of `code`.
+    pub fn action(&self) -> &'static str {
+        match self.code & 0xffff_0000 {
+            0x8000_0000 => "kill-process",
+            0x0000_0000 => "kill-thread",
+            0x0003_0000 => "trap",
+            0x0005_0000 => "errno",
+            0x7fc0_0000 => "user-notif",
+            0x7ff0_0000 => "trace",
+            0x7ffc_0000 => "log",
+            _ => "unknown",
+        }
+    }
+}
+
+// Sends every AUDIT_SECCOMP record of a container process to `tx` from a background
+// task, which ends once the receiver is dropped.
+pub fn listen_seccomp(tx: mpsc::UnboundedSender<SeccompRecord>) -> Result<(), Box<dyn Error>> {
+    let listener = AuditListener::subscribe().map_err(|e| format!("Failed to subscribe to audit records: {}", e))?;
+
+    tokio::spawn(async move {
+        loop {
+            let records = match listener.read_records().await {
+                Ok(records) => records,
+                Err(e) if e.raw_os_error() == Some(libc::ENOBUFS) => {
+                    eprintln!("Warning: audit records were dropped, the receive buffer overflowed");
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("Seccomp violation listener stopped: {}", e);
+                    return;
+                }
+            };
+            for (kind, text) in records {
+                let Some(record) = (kind == AUDIT_SECCOMP).then(|| SeccompRecord::parse(&text)).flatten() else {
+                    continue;
+                };
+                if tx.send(record).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYSCALL: &str = "audit(1700000000.123:4242): arch=c000003e syscall=59 success=yes exit=0 a0=55d0 a1=55d1 a2=55d2 a3=0 items=2 ppid=100 pid=123 auid=4294967295 uid=0 gid=0 euid=0 suid=0 fsuid=0 egid=0 sgid=0 fsgid=0 tty=(none) ses=4294967295 comm=\"sh\" exe=\"/bin/busybox\" key=\"cnpd\"";
+    const EXECVE: &str = "audit(1700000000.123:4242): argc=3 a0=\"sh\" a1=\"-c\" a2=6563686F2068656C6C6F";
+    const CWD: &str = "audit(1700000000.123:4242): cwd=\"/app\"";
+
+    #[test]
+    fn assembles_exec_records() {
+        assert_eq!(serial(SYSCALL), Some(4242));
+        let pending = PendingExec {
+            syscall: Some(SYSCALL.to_string()),
+            execve: format!(" {}", EXECVE),
+            cwd: Some(CWD.to_string()),
+        };
+        let record = pending.finish().unwrap();
+        assert_eq!(record.pid, 123);
+        assert_eq!(record.ppid, 100);
+        assert_eq!(record.uid, Some(0));
+        assert_eq!(record.exe.as_deref(), Some("/bin/busybox"));
+        assert_eq!(record.cwd.as_deref(), Some("/app"));
+        assert_eq!(record.argv, vec!["sh", "-c", "echo hello"]);
+    }
+
+    #[test]
+    fn parses_seccomp_records() {
+        let text = "audit(1700000000.456:4243): auid=4294967295 uid=0 gid=0 ses=4294967295 subj=unconfined pid=321 comm=\"sh\" exe=\"/bin/busybox\" sig=31 arch=c000003e syscall=165 compat=0 ip=0x7f3a code=0x80000000";
+        let record = SeccompRecord::parse(text).unwrap();
+        assert_eq!((record.pid, record.uid, record.syscall), (321, Some(0), 165));
+        assert_eq!(record.exe.as_deref(), Some("/bin/busybox"));
+        assert_eq!(record.arch, Some(0xc000_003e));
+        assert_eq!(record.action(), "kill-process");
+    }
+
+    #[test]
+    fn joins_split_arguments() {
+        let execve = "audit(1.0:1): argc=2 a0=\"cat\" a1_len=10 a1[0]=\"/etc/\" a1[1]=\"hosts\"";
+        assert_eq!(parse_argv(execve), vec!["cat", "/etc/hosts"]);
+    }
+}
@@ -0,0 +1,94 @@
+use std::fmt::Write;
+use tokio::fs;
+
+use crate::procfs;
+use crate::sha256;
+
+// A whitelisted process described by what it runs instead of by PID, so that a
+// learned baseline can be carried over to another host.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PortableProcess {
+    pub exe: String,
+    pub exe_sha256: String,
+    // A glob matched against the space-joined cmdline; exported as the literal cmdline.
+    pub cmdline_pattern: String,
+    pub uid: u32,
+}
+
+impl PortableProcess {
+    // None when the process has exited in the meantime.
+    pub async fn resolve(pid: i32) -> Option<PortableProcess> {
+        let exe = procfs::read_exe(pid).await?;
+        // Hash through /proc so the binary that is actually running is fingerprinted,
+        // even if the file on disk was replaced or deleted.
+        let binary = fs::read(procfs::pid_path(pid, "exe")).await.ok()?;
+        Some(PortableProcess {
+            exe,
+            exe_sha256: sha256::hex_digest(&binary),
+            cmdline_pattern: procfs::read_cmdline(pid).await.unwrap_or_default(),
+            uid: procfs::read_uid(pid).await?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PortableBaseline {
+    pub container_id: String,
+    pub processes: Vec<PortableProcess>,
+}
+
+impl PortableBaseline {
+    // Resolves the container's known PIDs, skipping the ones that no longer exist.
+    // Processes that only differ by PID (worker pools and the like) are listed once.
+    pub async fn resolve(container_id: &str, pids: &[i32]) -> PortableBaseline {
+        let mut processes = Vec::new();
+        for pid in pids {
+            if let Some(process) = PortableProcess::resolve(*pid).await {
+                processes.push(process);
+            }
+        }
+        processes.sort();
+        processes.dedup();
+        PortableBaseline {
+            container_id: container_id.to_string(),
+            processes,
+        }
+    }
+}
+
+pub fn to_toml(baselines: &[PortableBaseline]) -> String {
+    let mut out = String::from("# Baseline exported by cnpd-ctl export-baseline\n");
+    for baseline in baselines {
+        let _ = write!(out, "\n[[containers]]\ncontainer_id = {}\n", toml_string(&baseline.container_id));
+        for process in &baseline.processes {
+            let _ = write!(
+                out,
+                "\n[[containers.processes]]\nexe = {}\nexe_sha256 = {}\ncmdline_pattern = {}\nuid = {}\n",
+                toml_string(&process.exe),
+                toml_string(&process.exe_sha256),
+                toml_string(&process.cmdline_pattern),
+                process.uid
+            );
+        }
+    }
+    out
+}
+
+fn toml_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04X}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,129 @@
+use chrono::{DateTime, Local};
+use std::error::Error;
+use std::os::unix::fs::MetadataExt;
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::cgroup::ContainerCgroup;
+use crate::event::DetectionEvent;
+use crate::json::{self, Value};
+use crate::log;
+
+// The interpreter BCC's Python bindings are installed for.
+const PYTHON: &str = "python3";
+
+// The cgroup v2 ID of the container, the inode number of its cgroup directory, which is
+// what bpf_get_current_cgroup_id() returns in the script's probes.
+pub fn cgroup_id(container: &ContainerCgroup) -> std::io::Result<u64> {
+    Ok(std::fs::metadata(container.path())?.ino())
+}
+
+// One line of the script's stdout, a DetectionEvent in the JSON of the NDJSON format.
+// The script only knows the cgroup ID, so the container is always the one it was
+// started for, and the fields it has no use for may be left out: the action is decided
+// by the policy afterwards.
+pub fn parse_event(line: &str, container_id: &str, received_at: DateTime<Local>) -> Result<DetectionEvent, String> {
+    let Value::Object(mut fields) = json::parse(line).map_err(|e| e.to_string())? else {
+        return Err("not a JSON object".to_string());
+    };
+    fields.retain(|(key, _)| key != "container_id");
+    fields.push(("container_id".to_string(), container_id.into()));
+    if !fields.iter().any(|(key, _)| key == "action") {
+        fields.push(("action".to_string(), "log-only".into()));
+    }
+    let mut event = DetectionEvent::from_json(&Value::Object(fields))?;
+    let defaults = DetectionEvent::new(container_id, event.pid, received_at);
+    if event.id.is_empty() {
+        event.id = defaults.id;
+    }
+    if event.detected_at.is_empty() {
+        event.detected_at = defaults.detected_at;
+    }
+    Ok(event)
+}
+
+// A --use-bcc script running for one container, killed once this is dropped.
+pub struct BccProgram {
+    events: mpsc::UnboundedReceiver<DetectionEvent>,
+    _stop: oneshot::Sender<()>,
+}
+
+impl BccProgram {
+    // Runs `python3 <script> <cgroup ID>` and parses its events from a background task.
+    // Lines that are not events are logged and skipped.
+    pub fn start(script: &str, container: &ContainerCgroup) -> Result<BccProgram, Box<dyn Error>> {
+        let id = cgroup_id(container).map_err(|e| format!("Failed to read the cgroup ID of {}: {}", container.path(), e))?;
+        let mut child = Command::new(PYTHON)
+            .arg(script)
+            .arg(id.to_string())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to run {} {}: {}", PYTHON, script, e))?;
+        let stdout = child.stdout.take().ok_or("The BCC script has no stdout")?;
+
+        let (tx, events) = mpsc::unbounded_channel();
+        let (stop, mut stopped) = oneshot::channel();
+        let (script, container_id) = (script.to_string(), container.container_id());
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            loop {
+                let line = tokio::select! {
+                    line = lines.next_line() => line,
+                    _ = &mut stopped => return,
+                };
+                let Ok(Some(line)) = line else {
+                    break;
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match parse_event(&line, &container_id, Local::now()) {
+                    Ok(event) => {
+                        if tx.send(event).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => eprintln!("Warning: {} wrote an invalid event for {}: {}", script, log::id(&container_id), e),
+                }
+            }
+            let status = child.wait().await.map(|s| s.to_string()).unwrap_or_else(|e| e.to_string());
+            eprintln!("BCC detection stopped for {}: {} exited ({})", log::id(&container_id), script, status);
+        });
+        Ok(BccProgram { events, _stop: stop })
+    }
+
+    // The next event reported since the last call, without waiting.
+    pub fn try_next(&mut self) -> Option<DetectionEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventKind;
+    use crate::policy::Action;
+
+    #[test]
+    fn fills_in_what_the_script_leaves_out() {
+        let container_id = "0123456789abcdef0123456789abcdef";
+        let received_at = Local::now();
+        let event = parse_event(
+            r#"{"kind": "new-process", "pid": 4242, "exe": "/usr/bin/nc", "container_id": "other"}"#,
+            container_id,
+            received_at,
+        )
+        .unwrap();
+        assert_eq!(event.kind, EventKind::NewProcess);
+        assert_eq!(event.container_id, container_id);
+        assert_eq!(event.exe.as_deref(), Some("/usr/bin/nc"));
+        assert_eq!(event.action, Action::LogOnly);
+        assert_eq!(event.id, DetectionEvent::new(container_id, 4242, received_at).id);
+
+        assert!(parse_event(r#"{"exe": "/bin/sh"}"#, container_id, received_at).is_err());
+        assert!(parse_event("[1, 2]", container_id, received_at).is_err());
+    }
+}
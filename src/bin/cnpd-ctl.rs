@@ -0,0 +1,500 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use chrono::Local;
+use tokio::fs;
+use container_new_process_detector::baseline::{self, PortableBaseline};
+use container_new_process_detector::cgroup;
+use container_new_process_detector::config;
+use container_new_process_detector::control::{self, DEFAULT_CONTROL_SOCKET};
+use container_new_process_detector::docker;
+use container_new_process_detector::event::{self, DetectionEvent};
+use container_new_process_detector::inventory::ContainerStatus;
+use container_new_process_detector::json::Value;
+use container_new_process_detector::policy::{Policy, PolicyEngine, PolicySimulator};
+use container_new_process_detector::report::{self, Artifact, ReportFormat};
+use container_new_process_detector::sqlite::EventQuery;
+use container_new_process_detector::state::{self, BaselineProcess};
+use container_new_process_detector::webhook;
+
+const USAGE: &str = "Usage: cnpd-ctl <command> [options]
+
+Commands:
+  simulate-policy --events <file> --policy <file>
+      Replay a saved JSONL event log against a proposed policy
+  report --event <event-id> --events <file> [--format markdown|html]
+      Generate an incident report for a recorded detection event
+  diff <container-id> --state-file <file>
+      Compare a container's current processes against its saved baseline
+      (exit code 1 when they differ)
+  containers [--socket <path>] [--watch] [--json]
+      List the containers known to the running daemon and their monitoring status
+  top [--socket <path>]
+      Show the CPU usage and poll rate of each monitoring task, refreshed every second
+  export-baseline <file> [--socket <path>]
+      Write the running daemon's whitelisted processes as a portable TOML policy
+  whitelist add <container-id> <pid>... [--socket <path>]
+      Whitelist running processes in a container the daemon monitors
+  suppressed-events [--socket <path>] [--json]
+      Show the detections the running daemon's suppression rules silenced
+  query <database> [--container <id>] [--since <time>] [--action <action>] [--json]
+      List the events stored by --write-events-to-sqlite; --since takes a time such
+      as \"2024-05-01 12:00:00\" or a duration such as 1h
+  test-webhook <url>
+      POST a synthetic detection event to a webhook and print the response
+      (exit code 1 unless it answers with a 2xx status)";
+
+// test-webhook prints at most this much of the response body.
+const MAX_BODY_PRINTED: usize = 1024;
+
+async fn simulate_policy(args: &[String]) -> Result<ExitCode, Box<dyn Error>> {
+    let events_file = flag_value(args, "--events").ok_or("Missing --events <file>")?;
+    let policy_file = flag_value(args, "--policy").ok_or("Missing --policy <file>")?;
+
+    let policy = Policy::load(policy_file).await?;
+    let events = event::load_events(events_file).await?;
+
+    let summary = PolicySimulator::new(PolicyEngine::new(policy)).run(&events);
+    println!("{}", summary);
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn report(args: &[String]) -> Result<ExitCode, Box<dyn Error>> {
+    let event_id = flag_value(args, "--event").ok_or("Missing --event <event-id>")?;
+    let events_file = flag_value(args, "--events").ok_or("Missing --events <file>")?;
+    let format: ReportFormat = flag_value(args, "--format").unwrap_or("markdown").parse()?;
+
+    let events = event::load_events(events_file).await?;
+    let event = events
+        .iter()
+        .rev()
+        .find(|event| event.id == event_id)
+        .ok_or_else(|| format!("Event {} not found in {}", event_id, events_file))?;
+
+    let mut artifacts = Vec::new();
+    for path in &event.forensic_artifacts {
+        match fs::read(path).await {
+            Ok(content) => artifacts.push(Artifact {
+                path: path.clone(),
+                content: String::from_utf8_lossy(&content).into_owned(),
+            }),
+            Err(e) => eprintln!("Skipping forensic file {}: {}", path, e),
+        }
+    }
+
+    print!("{}", report::render(event, &artifacts, format));
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn diff(args: &[String]) -> Result<ExitCode, Box<dyn Error>> {
+    let container_id = args.first().filter(|a| !a.starts_with("--")).ok_or("Missing <container-id>")?;
+    let state_file = flag_value(args, "--state-file").ok_or("Missing --state-file <file>")?;
+
+    let baselines = state::load_baselines(state_file).await?;
+    let baseline = baselines
+        .iter()
+        .find(|b| b.container_id.starts_with(container_id.as_str()))
+        .ok_or_else(|| format!("No baseline for container {} in {}", container_id, state_file))?;
+    let current = cgroup::read_procs(&baseline.procs_path).await?;
+    let saved: HashSet<i32> = baseline.processes.iter().map(|p| p.pid).collect();
+
+    let missing: Vec<&BaselineProcess> = baseline.processes.iter().filter(|p| !current.contains(&p.pid)).collect();
+    let mut new: Vec<i32> = current.difference(&saved).copied().collect();
+    new.sort_unstable();
+    if missing.is_empty() && new.is_empty() {
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    println!("--- baseline {}", baseline.container_id);
+    println!("+++ current {}", baseline.container_id);
+    for process in missing {
+        println!("-{}", describe(process));
+    }
+    for pid in new {
+        println!("+{}", describe(&BaselineProcess::read(pid).await));
+    }
+    Ok(ExitCode::from(1))
+}
+
+fn describe(process: &BaselineProcess) -> String {
+    format!(
+        "{}\t{}\t{}",
+        process.pid,
+        process.exe.as_deref().unwrap_or("unknown"),
+        process.cmdline.as_deref().unwrap_or("")
+    )
+}
+
+async fn containers(args: &[String]) -> Result<ExitCode, Box<dyn Error>> {
+    let socket = flag_value(args, "--socket").unwrap_or(DEFAULT_CONTROL_SOCKET);
+    let json = args.iter().any(|a| a == "--json");
+    let watch = args.iter().any(|a| a == "--watch");
+
+    loop {
+        let response = control::request(socket, "containers").await?;
+        if json {
+            println!("{}", response);
+        } else {
+            let statuses: Vec<ContainerStatus> = response
+                .as_array()
+                .map(|v| v.iter().filter_map(ContainerStatus::from_json).collect())
+                .unwrap_or_default();
+            if watch {
+                // Clear the screen and move the cursor home, like watch(1).
+                print!("\x1b[2J\x1b[H");
+            }
+            print!("{}", containers_table(&statuses));
+        }
+        if !watch {
+            return Ok(ExitCode::SUCCESS);
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+// One monitoring task as reported by the daemon's "tasks" command.
+struct TaskSample {
+    container_id: String,
+    name: Option<String>,
+    state: String,
+    tid: Option<i64>,
+    polls: u64,
+    cpu_ticks: Option<u64>,
+    numa_node: Option<i64>,
+}
+
+impl TaskSample {
+    fn from_json(value: &Value) -> Option<TaskSample> {
+        let string = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+        let number = |key: &str| value.get(key).and_then(Value::as_i64);
+        Some(TaskSample {
+            container_id: string("container_id")?,
+            name: string("name"),
+            state: string("state").unwrap_or_default(),
+            tid: number("tid"),
+            polls: number("polls").unwrap_or(0) as u64,
+            cpu_ticks: number("cpu_ticks").map(|t| t as u64),
+            numa_node: number("numa_node"),
+        })
+    }
+}
+
+struct TaskRow {
+    sample: TaskSample,
+    cpu_percent: Option<f64>,
+    poll_rate: Option<f64>,
+}
+
+async fn top(args: &[String]) -> Result<ExitCode, Box<dyn Error>> {
+    let socket = flag_value(args, "--socket").unwrap_or(DEFAULT_CONTROL_SOCKET);
+    let mut previous: HashMap<String, (u64, Option<u64>)> = HashMap::new();
+    let mut last_sample: Option<Instant> = None;
+
+    loop {
+        let response = control::request(socket, "tasks").await?;
+        let now = Instant::now();
+        let elapsed = last_sample.map(|at| now.duration_since(at).as_secs_f64());
+        last_sample = Some(now);
+        let clock_ticks = response.get("clock_ticks").and_then(Value::as_i64).unwrap_or(100) as f64;
+
+        // Rates need two samples, so the first screen shows them as "-".
+        let mut rows: Vec<TaskRow> = response
+            .get("tasks")
+            .and_then(Value::as_array)
+            .map(|tasks| tasks.iter().filter_map(TaskSample::from_json).collect::<Vec<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|sample| {
+                let before = previous.get(&sample.container_id);
+                let cpu_percent = match (elapsed, before.and_then(|b| b.1), sample.cpu_ticks) {
+                    (Some(secs), Some(before), Some(after)) => {
+                        Some(after.saturating_sub(before) as f64 / clock_ticks / secs * 100.0)
+                    }
+                    _ => None,
+                };
+                let poll_rate = match (elapsed, before) {
+                    (Some(secs), Some(before)) => Some(sample.polls.saturating_sub(before.0) as f64 / secs),
+                    _ => None,
+                };
+                TaskRow { sample, cpu_percent, poll_rate }
+            })
+            .collect();
+        previous = rows
+            .iter()
+            .map(|row| (row.sample.container_id.clone(), (row.sample.polls, row.sample.cpu_ticks)))
+            .collect();
+        rows.sort_by(|a, b| {
+            b.cpu_percent
+                .unwrap_or(0.0)
+                .total_cmp(&a.cpu_percent.unwrap_or(0.0))
+                .then_with(|| a.sample.container_id.cmp(&b.sample.container_id))
+        });
+
+        let rss = response.get("rss_kb").and_then(Value::as_i64);
+        print!("\x1b[2J\x1b[H");
+        println!(
+            "cnpd: {} monitoring task(s), RSS {}\n",
+            rows.len(),
+            rss.map(|kb| format!("{:.1} MiB", kb as f64 / 1024.0)).unwrap_or_else(|| "-".to_string())
+        );
+        print!("{}", top_table(&rows));
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+fn top_table(rows: &[TaskRow]) -> String {
+    let header = ["CONTAINER ID", "NAME", "STATE", "TID", "NODE", "CPU%", "POLLS/S"];
+    let cells: Vec<[String; 7]> = rows
+        .iter()
+        .map(|row| {
+            let dash = || "-".to_string();
+            [
+                docker::short_id(&row.sample.container_id).to_string(),
+                row.sample.name.clone().unwrap_or_else(dash),
+                row.sample.state.clone(),
+                row.sample.tid.map(|tid| tid.to_string()).unwrap_or_else(dash),
+                row.sample.numa_node.map(|node| node.to_string()).unwrap_or_else(dash),
+                row.cpu_percent.map(|c| format!("{:.1}", c)).unwrap_or_else(dash),
+                row.poll_rate.map(|r| format!("{:.1}", r)).unwrap_or_else(dash),
+            ]
+        })
+        .collect();
+    render_table(&header, &cells)
+}
+
+async fn export_baseline(args: &[String]) -> Result<ExitCode, Box<dyn Error>> {
+    let path = args.first().filter(|a| !a.starts_with("--")).ok_or("export-baseline needs an output file")?;
+    let socket = flag_value(args, "--socket").unwrap_or(DEFAULT_CONTROL_SOCKET);
+
+    let response = control::request(socket, "known-pids").await?;
+    let mut baselines = Vec::new();
+    for container in response.as_array().map(Vec::as_slice).unwrap_or_default() {
+        let Some(container_id) = container.get("container_id").and_then(Value::as_str) else {
+            continue;
+        };
+        let pids: Vec<i32> = container
+            .get("pids")
+            .and_then(Value::as_array)
+            .map(|pids| pids.iter().filter_map(Value::as_i64).map(|pid| pid as i32).collect())
+            .unwrap_or_default();
+        baselines.push(PortableBaseline::resolve(container_id, &pids).await);
+    }
+
+    fs::write(path, baseline::to_toml(&baselines)).await?;
+    let processes: usize = baselines.iter().map(|b| b.processes.len()).sum();
+    println!("Exported {} process(es) from {} container(s) to {}", processes, baselines.len(), path);
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn whitelist(args: &[String]) -> Result<ExitCode, Box<dyn Error>> {
+    if args.first().map(String::as_str) != Some("add") {
+        return Err("Usage: cnpd-ctl whitelist add <container-id> <pid>...".into());
+    }
+    let socket = flag_value(args, "--socket").unwrap_or(DEFAULT_CONTROL_SOCKET);
+    let mut operands = Vec::new();
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        if arg == "--socket" {
+            rest.next();
+        } else {
+            operands.push(arg.as_str());
+        }
+    }
+    let (container, pids) = operands.split_first().ok_or("whitelist add needs a container ID")?;
+    if pids.is_empty() {
+        return Err("whitelist add needs one or more PIDs".into());
+    }
+    for pid in pids {
+        pid.parse::<i32>().map_err(|_| format!("Invalid PID: {}", pid))?;
+    }
+
+    let response = control::request(socket, &format!("whitelist-add {} {}", container, pids.join(" "))).await?;
+    let container_id = response.get("container_id").and_then(Value::as_str).unwrap_or(container);
+    println!("Whitelisting {} in {}", pids.join(", "), docker::short_id(container_id));
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn suppressed_events(args: &[String]) -> Result<ExitCode, Box<dyn Error>> {
+    let socket = flag_value(args, "--socket").unwrap_or(DEFAULT_CONTROL_SOCKET);
+    let response = control::request(socket, "suppressed-events").await?;
+    if args.iter().any(|a| a == "--json") {
+        println!("{}", response);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let list = |key: &str| response.get(key).and_then(Value::as_array).cloned().unwrap_or_default();
+    let string = |value: &Value, key: &str| value.get(key).and_then(Value::as_str).unwrap_or("-").to_string();
+    for count in list("counts") {
+        let tenant = count.get("tenant").and_then(Value::as_str).map(|t| format!(" (tenant {})", t));
+        println!(
+            "{} suppressed by {}{}",
+            count.get("count").and_then(Value::as_i64).unwrap_or(0),
+            string(&count, "rule"),
+            tenant.unwrap_or_default()
+        );
+    }
+    for event in list("events") {
+        println!(
+            "{}\t{}\t{}\t{}",
+            string(&event, "detected_at"),
+            docker::short_id(&string(&event, "container_id")),
+            event.get("pid").and_then(Value::as_i64).unwrap_or(0),
+            string(&event, "exe")
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn test_webhook(args: &[String]) -> Result<ExitCode, Box<dyn Error>> {
+    let url = args.first().filter(|a| !a.starts_with("--")).ok_or("Missing <url>")?;
+    let event = DetectionEvent::synthetic(chrono::Local::now());
+
+    let response = match webhook::post_json(url, &event.to_json().to_string()).await {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("Failed to send test event to {}: {}", url, e);
+            return Ok(ExitCode::from(1));
+        }
+    };
+    println!("HTTP {}", response.status);
+    for (name, value) in &response.headers {
+        println!("{}: {}", name, value);
+    }
+    println!();
+    let mut end = response.body.len().min(MAX_BODY_PRINTED);
+    while !response.body.is_char_boundary(end) {
+        end -= 1;
+    }
+    println!("{}", &response.body[..end]);
+    if end < response.body.len() {
+        println!("... ({} more bytes)", response.body.len() - end);
+    }
+    Ok(if response.is_success() { ExitCode::SUCCESS } else { ExitCode::from(1) })
+}
+
+async fn query(args: &[String]) -> Result<ExitCode, Box<dyn Error>> {
+    let database = args.first().filter(|a| !a.starts_with("--")).ok_or("Missing <database>")?;
+    let since = match flag_value(args, "--since") {
+        Some(since) => Some(match config::parse_duration(since) {
+            Ok(ago) => (Local::now() - chrono::Duration::from_std(ago)?).format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            Err(_) => since.to_string(),
+        }),
+        None => None,
+    };
+    let query = EventQuery {
+        container: flag_value(args, "--container").map(str::to_string),
+        since,
+        action: flag_value(args, "--action").map(str::to_string),
+    };
+
+    let path = database.clone();
+    let events = tokio::task::spawn_blocking(move || query.run(&path)).await??;
+    if args.iter().any(|a| a == "--json") {
+        println!("{}", Value::Array(events));
+    } else {
+        print!("{}", events_table(&events));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn events_table(events: &[Value]) -> String {
+    let header = ["ID", "CONTAINER ID", "PID", "EXE", "DETECTED AT", "ACTION"];
+    let rows: Vec<[String; 6]> = events
+        .iter()
+        .map(|event| {
+            let column = |key: &str| match event.get(key) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Number(n)) => n.to_string(),
+                _ => "-".to_string(),
+            };
+            [
+                column("id"),
+                docker::short_id(&column("container_id")).to_string(),
+                column("pid"),
+                column("exe_path"),
+                column("detected_at"),
+                column("action_taken"),
+            ]
+        })
+        .collect();
+    render_table(&header, &rows)
+}
+
+fn containers_table(statuses: &[ContainerStatus]) -> String {
+    let header = ["CONTAINER ID", "NAME", "STATE", "PIDS", "DETECTIONS", "LAST DETECTION", "UPTIME", "HEALTH"];
+    let rows: Vec<[String; 8]> = statuses
+        .iter()
+        .map(|s| {
+            [
+                docker::short_id(&s.container_id).to_string(),
+                s.name.clone().unwrap_or_else(|| "-".to_string()),
+                s.state.to_string(),
+                s.whitelist_pids.to_string(),
+                s.detections.to_string(),
+                s.last_detection.clone().unwrap_or_else(|| "-".to_string()),
+                format_uptime(s.uptime.as_secs()),
+                s.health_score.to_string(),
+            ]
+        })
+        .collect();
+    render_table(&header, &rows)
+}
+
+fn render_table<const N: usize>(header: &[&str; N], rows: &[[String; N]]) -> String {
+    let mut widths = header.map(str::len);
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut out = String::new();
+    let mut push_row = |cells: Vec<&str>| {
+        let line: Vec<String> = cells.iter().zip(widths).map(|(c, w)| format!("{:<w$}", c, w = w)).collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    };
+    push_row(header.to_vec());
+    for row in rows {
+        push_row(row.iter().map(String::as_str).collect());
+    }
+    out
+}
+
+fn format_uptime(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+#[tokio::main]
+async fn main() -> Result<ExitCode, Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("simulate-policy") => simulate_policy(&args[1..]).await,
+        Some("report") => report(&args[1..]).await,
+        Some("diff") => diff(&args[1..]).await,
+        Some("containers") => containers(&args[1..]).await,
+        Some("top") => top(&args[1..]).await,
+        Some("export-baseline") => export_baseline(&args[1..]).await,
+        Some("whitelist") => whitelist(&args[1..]).await,
+        Some("suppressed-events") => suppressed_events(&args[1..]).await,
+        Some("query") => query(&args[1..]).await,
+        Some("test-webhook") => test_webhook(&args[1..]).await,
+        _ => {
+            eprintln!("{}", USAGE);
+            Ok(ExitCode::from(2))
+        }
+    }
+}
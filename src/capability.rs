@@ -0,0 +1,233 @@
+use std::fmt;
+
+use crate::config::Config;
+use crate::policy::Action;
+
+// The Linux capabilities some modes need, with their bit numbers from linux/capability.h.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    // Signals to processes of another user, as container processes often are.
+    Kill,
+    // ptrace attach for core dumps, and /proc/<pid>/ns links of other users' processes.
+    SysPtrace,
+    // fanotify, the cgroup freezer and loading eBPF programs.
+    SysAdmin,
+    AuditControl,
+    AuditRead,
+}
+
+impl Capability {
+    fn bit(&self) -> u32 {
+        match self {
+            Capability::Kill => 5,
+            Capability::SysPtrace => 19,
+            Capability::SysAdmin => 21,
+            Capability::AuditControl => 30,
+            Capability::AuditRead => 37,
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Capability::Kill => "CAP_KILL",
+            Capability::SysPtrace => "CAP_SYS_PTRACE",
+            Capability::SysAdmin => "CAP_SYS_ADMIN",
+            Capability::AuditControl => "CAP_AUDIT_CONTROL",
+            Capability::AuditRead => "CAP_AUDIT_READ",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// A capability the configuration needs but the process does not have, with the
+// option that needs it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingCapability {
+    pub capability: Capability,
+    pub needed_by: &'static str,
+}
+
+impl fmt::Display for MissingCapability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (needed by {})", self.capability, self.needed_by)
+    }
+}
+
+pub struct CapabilityChecker;
+
+impl CapabilityChecker {
+    // The capabilities the configured mode needs, each with the option needing it.
+    // Actions set in a policy file are not known yet and are not checked.
+    pub fn required(config: &Config) -> Vec<(Capability, &'static str)> {
+        let kills = |action: Action| matches!(action, Action::Kill | Action::KillAndCommit);
+        let mut required = Vec::new();
+        if config.kill_suspicious_process {
+            required.push((Capability::Kill, "--kill-suspicious-process"));
+        }
+        if config.default_action.is_some_and(kills) {
+            required.push((Capability::Kill, "--action"));
+        }
+        if config.alert_on_process_exit && kills(config.exit_action) {
+            required.push((Capability::Kill, "--exit-action"));
+        }
+        if config.alert_on_privilege_escalation && kills(config.privilege_escalation_action) {
+            required.push((Capability::Kill, "--privilege-escalation-action"));
+        }
+        if config.risk_threshold.is_some() && kills(config.high_risk_action) {
+            required.push((Capability::Kill, "--high-risk-action"));
+        }
+        if !config.block_file_writes.is_empty() {
+            required.push((Capability::Kill, "--block-file-writes"));
+        }
+        if config.core_dump_on_detection {
+            required.push((Capability::SysPtrace, "--core-dump-on-detection"));
+        }
+        if config.watch_user_namespaces {
+            required.push((Capability::SysPtrace, "--watch-user-namespaces"));
+        }
+        if config.detect_file_writes {
+            required.push((Capability::SysAdmin, "--detect-file-writes"));
+        }
+        if config.freeze_on_detection {
+            required.push((Capability::SysAdmin, "--freeze-on-detection"));
+        }
+        if config.trace_tcp_connect {
+            required.push((Capability::SysAdmin, "--trace-tcp-connect"));
+        }
+        if config.detect_ptrace_usage {
+            required.push((Capability::SysAdmin, "--detect-ptrace-usage"));
+        }
+        if config.use_bcc.is_some() {
+            required.push((Capability::SysAdmin, "--use-bcc"));
+        }
+        if config.audit_exec {
+            required.push((Capability::AuditControl, "--audit-exec"));
+            required.push((Capability::AuditRead, "--audit-exec"));
+        }
+        if config.watch_seccomp_violations {
+            required.push((Capability::AuditRead, "--watch-seccomp-violations"));
+        }
+        required
+    }
+
+    // The required capabilities missing from the effective set in /proc/self/status.
+    // Nothing is reported when the set cannot be read.
+    pub fn check_required(config: &Config) -> Vec<MissingCapability> {
+        match std::fs::read_to_string("/proc/self/status").ok().as_deref().and_then(effective_set) {
+            Some(effective) => CapabilityChecker::missing(config, effective),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn missing(config: &Config, effective: u64) -> Vec<MissingCapability> {
+        CapabilityChecker::required(config)
+            .into_iter()
+            .filter(|(capability, _)| effective & (1 << capability.bit()) == 0)
+            .map(|(capability, needed_by)| MissingCapability { capability, needed_by })
+            .collect()
+    }
+}
+
+// Every capability of linux/capability.h, indexed by bit number.
+pub const CAPABILITY_NAMES: [&str; 41] = [
+    "CAP_CHOWN",
+    "CAP_DAC_OVERRIDE",
+    "CAP_DAC_READ_SEARCH",
+    "CAP_FOWNER",
+    "CAP_FSETID",
+    "CAP_KILL",
+    "CAP_SETGID",
+    "CAP_SETUID",
+    "CAP_SETPCAP",
+    "CAP_LINUX_IMMUTABLE",
+    "CAP_NET_BIND_SERVICE",
+    "CAP_NET_BROADCAST",
+    "CAP_NET_ADMIN",
+    "CAP_NET_RAW",
+    "CAP_IPC_LOCK",
+    "CAP_IPC_OWNER",
+    "CAP_SYS_MODULE",
+    "CAP_SYS_RAWIO",
+    "CAP_SYS_CHROOT",
+    "CAP_SYS_PTRACE",
+    "CAP_SYS_PACCT",
+    "CAP_SYS_ADMIN",
+    "CAP_SYS_BOOT",
+    "CAP_SYS_NICE",
+    "CAP_SYS_RESOURCE",
+    "CAP_SYS_TIME",
+    "CAP_SYS_TTY_CONFIG",
+    "CAP_MKNOD",
+    "CAP_LEASE",
+    "CAP_AUDIT_WRITE",
+    "CAP_AUDIT_CONTROL",
+    "CAP_SETFCAP",
+    "CAP_MAC_OVERRIDE",
+    "CAP_MAC_ADMIN",
+    "CAP_SYSLOG",
+    "CAP_WAKE_ALARM",
+    "CAP_BLOCK_SUSPEND",
+    "CAP_AUDIT_READ",
+    "CAP_PERFMON",
+    "CAP_BPF",
+    "CAP_CHECKPOINT_RESTORE",
+];
+
+// The names of the capabilities set in a mask, lowest bit first. Bits newer kernels may
+// add are shown by number.
+pub fn capability_names(mask: u64) -> Vec<String> {
+    (0..64)
+        .filter(|bit| mask & (1 << bit) != 0)
+        .map(|bit| match CAPABILITY_NAMES.get(bit) {
+            Some(name) => name.to_string(),
+            None => format!("CAP_{}", bit),
+        })
+        .collect()
+}
+
+// A capability set line of /proc/<pid>/status (CapInh, CapPrm, CapEff, ...), a hex bitmask.
+pub fn capability_set(status: &str, field: &str) -> Option<u64> {
+    let line = status.lines().find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))?;
+    u64::from_str_radix(line.trim(), 16).ok()
+}
+
+pub fn effective_set(status: &str) -> Option<u64> {
+    capability_set(status, "CapEff")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_capabilities_missing_for_the_mode() {
+        let status = "Name:\tcnpd\nCapInh:\t0000000000000000\nCapEff:\t00000000a80425fb\n";
+        let effective = effective_set(status).unwrap();
+        assert_eq!(effective, 0xa80425fb);
+
+        let config = Config {
+            kill_suspicious_process: true,
+            detect_file_writes: true,
+            ..Config::default()
+        };
+        // Docker's default set has CAP_KILL but not CAP_SYS_ADMIN.
+        let missing = CapabilityChecker::missing(&config, effective);
+        assert_eq!(
+            missing,
+            [MissingCapability {
+                capability: Capability::SysAdmin,
+                needed_by: "--detect-file-writes",
+            }]
+        );
+        assert!(CapabilityChecker::missing(&Config::default(), 0).is_empty());
+        assert_eq!(capability_set(status, "CapInh"), Some(0));
+    }
+
+    #[test]
+    fn names_capability_bits() {
+        let mask = (1 << 21) | (1 << 40) | (1 << 45);
+        assert_eq!(capability_names(mask), ["CAP_SYS_ADMIN", "CAP_CHECKPOINT_RESTORE", "CAP_45"]);
+    }
+}
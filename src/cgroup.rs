@@ -0,0 +1,256 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, SystemTime};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::log;
+use crate::runtime::Runtime;
+
+pub const DEFAULT_CGROUP_PATH: &str = "/sys/fs/cgroup/system.slice/";
+const EMPTY_PROCS_POLL: Duration = Duration::from_millis(10);
+
+// A container's cgroup directory and the hierarchy it was found in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerCgroup {
+    pub root: String,
+    pub name: String,
+    // memory.max in bytes, read when the whitelist is built. None means no limit.
+    pub memory_limit: Option<u64>,
+}
+
+impl ContainerCgroup {
+    pub fn path(&self) -> String {
+        format!("{}/{}", self.root.trim_end_matches('/'), self.name)
+    }
+
+    pub fn procs_path(&self) -> String {
+        format!("{}/cgroup.procs", self.path())
+    }
+
+    pub fn container_id(&self) -> String {
+        let name = Runtime::ALL
+            .iter()
+            .find_map(|runtime| self.name.strip_prefix(runtime.scope_prefix()))
+            .unwrap_or(&self.name);
+        name.replace(".scope", "")
+    }
+}
+
+// The container a process belongs to, from its /proc/<pid>/cgroup.
+pub fn container_id_of(proc_cgroup: &str) -> Option<String> {
+    let scope = proc_cgroup.lines().flat_map(|line| line.split('/')).find(|part| is_container_scope(part))?;
+    let container = ContainerCgroup {
+        root: String::new(),
+        name: scope.to_string(),
+        memory_limit: None,
+    };
+    Some(container.container_id())
+}
+
+// Whether a cgroup directory name is the scope of a container of any known runtime.
+pub fn is_container_scope(name: &str) -> bool {
+    Runtime::ALL.iter().any(|runtime| name.starts_with(runtime.scope_prefix()))
+}
+
+pub async fn get_docker_directories(cgroup_paths: &[String]) -> Result<Vec<ContainerCgroup>, Box<dyn Error>> {
+    let mut docker_list: Vec<ContainerCgroup> = Vec::new();
+
+    for cgroup_path in cgroup_paths {
+        let entries = fs::read_dir(cgroup_path).await?;
+        tokio::pin!(entries); // Pin the iterator to enable async operations
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if entry.file_type().await?.is_dir() {
+                if let Some(dir_name) = path.file_name().and_then(|s| s.to_str()) {
+                    if is_container_scope(dir_name) {
+                        docker_list.push(ContainerCgroup {
+                            root: cgroup_path.clone(),
+                            name: dir_name.to_string(),
+                            memory_limit: None,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    let mut seen: HashMap<String, &str> = HashMap::new();
+    for container in &docker_list {
+        if let Some(first) = seen.insert(container.container_id(), &container.root) {
+            eprintln!(
+                "Warning: container {} found in both {} and {}",
+                log::id(&container.container_id()),
+                first,
+                container.root
+            );
+        }
+    }
+
+    Ok(docker_list)
+}
+
+// O_NOATIME keeps every poll from updating the file's access time. The kernel only
+// allows it for the file's owner or with CAP_FOWNER, so fall back to a plain open.
+async fn open_noatime(path: &str) -> std::io::Result<File> {
+    match OpenOptions::new().read(true).custom_flags(libc::O_NOATIME).open(path).await {
+        Err(e) if e.raw_os_error() == Some(libc::EPERM) => File::open(path).await,
+        result => result,
+    }
+}
+
+// Parsed line by line through a small reused buffer, as containers with thousands of
+// threads make cgroup.procs large and it is read on every poll.
+pub async fn read_procs(procs_path: &str) -> Result<HashSet<i32>, Box<dyn Error>> {
+    let mut reader = BufReader::new(open_noatime(procs_path).await?);
+    let mut procs = HashSet::new();
+    let mut line = String::new();
+    while reader.read_line(&mut line).await? > 0 {
+        if let Ok(pid) = line.trim_end().parse() {
+            procs.insert(pid);
+        }
+        line.clear();
+    }
+    Ok(procs)
+}
+
+// A restarted container's cgroup.procs is empty until its new init process joins.
+// Reads it every EMPTY_PROCS_POLL until it is not, or `limit` has passed, in which case
+// the empty set is returned.
+pub async fn wait_for_procs(procs_path: &str, limit: Duration) -> Result<HashSet<i32>, Box<dyn Error>> {
+    let deadline = tokio::time::Instant::now() + limit;
+    loop {
+        let procs = read_procs(procs_path).await?;
+        if !procs.is_empty() || tokio::time::Instant::now() >= deadline {
+            return Ok(procs);
+        }
+        tokio::time::sleep(EMPTY_PROCS_POLL).await;
+    }
+}
+
+// With --retry-whitelist-on-empty, how often an empty cgroup.procs is read again before
+// the baseline is taken. The wait doubles after every attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmptyProcsRetry {
+    pub attempts: u32,
+    pub backoff: Duration,
+}
+
+pub async fn get_whitelist(
+    docker_list: &[ContainerCgroup],
+    retry: Option<EmptyProcsRetry>,
+) -> Result<Vec<(ContainerCgroup, HashSet<i32>)>, Box<dyn Error>> {
+    let mut whitelist = Vec::new();
+
+    for container in docker_list {
+        let procs_path = container.procs_path();
+        if fs::try_exists(&procs_path).await.unwrap_or(false) {
+            let mut container = container.clone();
+            container.memory_limit = read_memory_limit(&container).await;
+            let mut procs = read_procs(&procs_path).await?;
+            // A container being created has no init process yet; a baseline taken now
+            // would report it as new on the first poll.
+            if let Some(retry) = retry.filter(|_| procs.is_empty()) {
+                let mut backoff = retry.backoff;
+                for _ in 0..retry.attempts {
+                    tokio::time::sleep(backoff).await;
+                    procs = read_procs(&procs_path).await?;
+                    if !procs.is_empty() {
+                        break;
+                    }
+                    backoff *= 2;
+                }
+                if procs.is_empty() {
+                    eprintln!(
+                        "Warning: cgroup.procs of {} still empty after {} retries",
+                        log::id(&container.container_id()),
+                        retry.attempts
+                    );
+                }
+            }
+            whitelist.push((container, procs));
+        }
+    }
+
+    Ok(whitelist)
+}
+
+async fn read_memory_value(container: &ContainerCgroup, file: &str) -> Option<u64> {
+    let content = fs::read_to_string(format!("{}/{}", container.path(), file)).await.ok()?;
+    content.trim().parse().ok()
+}
+
+// memory.max holds "max" when the container has no limit, which fails to parse.
+pub async fn read_memory_limit(container: &ContainerCgroup) -> Option<u64> {
+    read_memory_value(container, "memory.max").await
+}
+
+pub async fn read_memory_usage(container: &ContainerCgroup) -> Option<u64> {
+    read_memory_value(container, "memory.current").await
+}
+
+// `populated 0` in cgroup.events means no process is left in the cgroup or below it,
+// as for a stopped container whose cgroup has not been removed yet. None when the file
+// cannot be read, as under cgroup v1.
+pub async fn is_populated(container: &ContainerCgroup) -> Option<bool> {
+    let events = fs::read_to_string(format!("{}/cgroup.events", container.path())).await.ok()?;
+    events.lines().find_map(|line| line.strip_prefix("populated ")).map(|value| value != "0")
+}
+
+// Freezes or thaws every process in the container with the cgroup v2 freezer, then
+// waits until cgroup.events reports the new state. Freezing can take long when a task
+// sits in the kernel holding locks, so callers bound this with a timeout.
+pub async fn set_frozen(container: &ContainerCgroup, frozen: bool) -> std::io::Result<()> {
+    let value = if frozen { "1" } else { "0" };
+    fs::write(format!("{}/cgroup.freeze", container.path()), value).await?;
+    let expected = format!("frozen {}", value);
+    loop {
+        let events = fs::read_to_string(format!("{}/cgroup.events", container.path())).await?;
+        if events.lines().any(|line| line == expected) {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+}
+
+// Sends SIGKILL to `pid` only if it is still a member of the cgroup. The process is
+// pinned with a pidfd before the membership check, so the PID cannot be recycled for
+// an unrelated process between the check and the signal. Returns Ok(false) when the
+// process is no longer in the cgroup.
+pub async fn kill_process(procs_path: &str, pid: i32) -> std::io::Result<bool> {
+    let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+    if pidfd < 0 {
+        let err = std::io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::ESRCH) => Ok(false),
+            _ => Err(err),
+        };
+    }
+    let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd as i32) };
+
+    let members = read_procs(procs_path).await.map_err(|e| std::io::Error::other(e.to_string()))?;
+    if !members.contains(&pid) {
+        return Ok(false);
+    }
+
+    let sent = unsafe {
+        libc::syscall(libc::SYS_pidfd_send_signal, pidfd.as_raw_fd(), libc::SIGKILL, std::ptr::null::<libc::siginfo_t>(), 0)
+    };
+    if sent < 0 {
+        let err = std::io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::ESRCH) => Ok(false),
+            _ => Err(err),
+        };
+    }
+    Ok(true)
+}
+
+// Time since the container's cgroup directory was created, taken from its mtime
+// (cgroupfs does not report a birth time).
+pub async fn cgroup_age(container: &ContainerCgroup) -> Option<Duration> {
+    let modified = fs::metadata(container.path()).await.ok()?.modified().ok()?;
+    SystemTime::now().duration_since(modified).ok()
+}
@@ -0,0 +1,596 @@
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::docker;
+use crate::event::DetectionEvent;
+use crate::info;
+use crate::json::{self, Value};
+use crate::sha256;
+
+// PutLogEvents takes at most 1 MiB per call, counting 26 bytes of overhead per event,
+// and at most 10,000 events.
+const MAX_BATCH_BYTES: usize = 1_048_576;
+const EVENT_OVERHEAD: usize = 26;
+const MAX_BATCH_EVENTS: usize = 10_000;
+// Log streams used to accept 5 PutLogEvents calls per second.
+const MIN_PUT_INTERVAL: Duration = Duration::from_millis(200);
+const MAX_QUEUED: usize = 10_000;
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+const BATCH_WINDOW: Duration = Duration::from_millis(20);
+// Credentials from instance metadata are refreshed this long before they expire.
+const REFRESH_MARGIN: Duration = Duration::from_secs(300);
+const INSTANCE_METADATA: &str = "169.254.169.254:80";
+const CONTAINER_CREDENTIALS: &str = "169.254.170.2:80";
+const SERVICE: &str = "logs";
+const TARGET_PREFIX: &str = "Logs_20140328";
+
+// Where to send events, from --cloudwatch-log-group, --cloudwatch-region and
+// --cloudwatch-endpoint. Every container writes to its own stream of the group.
+#[derive(Debug, Clone)]
+pub struct CloudWatchConfig {
+    pub log_group: String,
+    pub region: Option<String>,
+    // The regional endpoint unless set, e.g. a TLS-terminating proxy in front of it.
+    pub endpoint: Option<String>,
+}
+
+impl CloudWatchConfig {
+    pub fn new(log_group: String) -> CloudWatchConfig {
+        CloudWatchConfig {
+            log_group,
+            region: None,
+            endpoint: None,
+        }
+    }
+
+    // --cloudwatch-region, or the region the AWS CLI and SDKs would use.
+    pub fn region(&self) -> Option<String> {
+        self.region
+            .clone()
+            .or_else(|| std::env::var("AWS_REGION").ok())
+            .or_else(|| std::env::var("AWS_DEFAULT_REGION").ok())
+            .filter(|region| !region.is_empty())
+    }
+}
+
+// An AWS access key, found the way the SDKs' default credential chain finds one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Credentials {
+    // Environment variables, then the shared credentials file, then the ECS task role,
+    // then the EC2 instance role.
+    pub fn resolve() -> Result<Credentials, String> {
+        if let Some(credentials) = Credentials::from_env() {
+            return Ok(credentials);
+        }
+        let path = std::env::var("AWS_SHARED_CREDENTIALS_FILE")
+            .ok()
+            .or_else(|| std::env::var("HOME").ok().map(|home| format!("{}/.aws/credentials", home)));
+        let profile = std::env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string());
+        if let Some(content) = path.and_then(|path| std::fs::read_to_string(path).ok()) {
+            if let Some(credentials) = Credentials::from_profile(&content, &profile) {
+                return Ok(credentials);
+            }
+        }
+        if let Ok(uri) = std::env::var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI") {
+            return Credentials::from_role_json(&read_metadata(CONTAINER_CREDENTIALS, "GET", &uri, &[])?);
+        }
+        Credentials::from_instance_metadata()
+    }
+
+    fn from_env() -> Option<Credentials> {
+        Some(Credentials {
+            access_key_id: std::env::var("AWS_ACCESS_KEY_ID").ok().filter(|v| !v.is_empty())?,
+            secret_access_key: std::env::var("AWS_SECRET_ACCESS_KEY").ok().filter(|v| !v.is_empty())?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok().filter(|v| !v.is_empty()),
+            expires_at: None,
+        })
+    }
+
+    // The `[profile]` section of ~/.aws/credentials.
+    pub fn from_profile(content: &str, profile: &str) -> Option<Credentials> {
+        let mut section = None;
+        let mut keys = HashMap::new();
+        for line in content.lines().map(str::trim) {
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = Some(name.trim());
+            } else if let (Some(current), Some((key, value))) = (section, line.split_once('=')) {
+                if current == profile && !line.starts_with(['#', ';']) {
+                    keys.insert(key.trim(), value.trim());
+                }
+            }
+        }
+        Some(Credentials {
+            access_key_id: keys.get("aws_access_key_id")?.to_string(),
+            secret_access_key: keys.get("aws_secret_access_key")?.to_string(),
+            session_token: keys.get("aws_session_token").map(|token| token.to_string()),
+            expires_at: None,
+        })
+    }
+
+    // IMDSv2: a session token first, then the role's credentials.
+    fn from_instance_metadata() -> Result<Credentials, String> {
+        let token = read_metadata(
+            INSTANCE_METADATA,
+            "PUT",
+            "/latest/api/token",
+            &[("X-aws-ec2-metadata-token-ttl-seconds".to_string(), "21600".to_string())],
+        )
+        .map_err(|e| format!("no credentials in the environment, ~/.aws/credentials or instance metadata ({})", e))?;
+        let header = [("X-aws-ec2-metadata-token".to_string(), token)];
+        let path = "/latest/meta-data/iam/security-credentials/";
+        let role = read_metadata(INSTANCE_METADATA, "GET", path, &header)?;
+        let role = role.lines().next().unwrap_or_default().trim();
+        if role.is_empty() {
+            return Err("the instance has no IAM role".to_string());
+        }
+        Credentials::from_role_json(&read_metadata(INSTANCE_METADATA, "GET", &format!("{}{}", path, role), &header)?)
+    }
+
+    fn from_role_json(body: &str) -> Result<Credentials, String> {
+        let doc = json::parse(body).map_err(|e| format!("invalid role credentials: {}", e))?;
+        let string = |key: &str| doc.get(key).and_then(Value::as_str).map(str::to_string);
+        Ok(Credentials {
+            access_key_id: string("AccessKeyId").ok_or("role credentials have no AccessKeyId")?,
+            secret_access_key: string("SecretAccessKey").ok_or("role credentials have no SecretAccessKey")?,
+            session_token: string("Token"),
+            expires_at: string("Expiration").and_then(|at| at.parse().ok()),
+        })
+    }
+
+    fn expiring(&self) -> bool {
+        self.expires_at
+            .is_some_and(|at| at - chrono::Duration::from_std(REFRESH_MARGIN).unwrap_or_default() <= Utc::now())
+    }
+}
+
+// The Signature Version 4 key for one day, region and service.
+pub fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> [u8; 32] {
+    let key = sha256::hmac(format!("AWS4{}", secret_access_key).as_bytes(), date.as_bytes());
+    let key = sha256::hmac(&key, region.as_bytes());
+    let key = sha256::hmac(&key, service.as_bytes());
+    sha256::hmac(&key, b"aws4_request")
+}
+
+// The headers of a signed CloudWatch Logs API call, Authorization included.
+pub fn signed_headers(
+    credentials: &Credentials,
+    region: &str,
+    host: &str,
+    action: &str,
+    body: &str,
+    now: DateTime<Utc>,
+) -> Vec<(String, String)> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = &amz_date[..8];
+    // Sorted by name, as the canonical request needs them.
+    let mut headers = vec![
+        ("content-type".to_string(), "application/x-amz-json-1.1".to_string()),
+        ("host".to_string(), host.to_string()),
+        ("x-amz-date".to_string(), amz_date.clone()),
+    ];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token".to_string(), token.clone()));
+    }
+    headers.push(("x-amz-target".to_string(), format!("{}.{}", TARGET_PREFIX, action)));
+
+    let names: Vec<&str> = headers.iter().map(|(name, _)| name.as_str()).collect();
+    let names = names.join(";");
+    let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value)).collect();
+    let canonical_request = format!(
+        "POST\n/\n\n{}\n{}\n{}",
+        canonical_headers,
+        names,
+        sha256::hex_digest(body.as_bytes())
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, region, SERVICE);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        sha256::hex_digest(canonical_request.as_bytes())
+    );
+    let key = signing_key(&credentials.secret_access_key, date, region, SERVICE);
+    let signature = sha256::hex(&sha256::hmac(&key, string_to_sign.as_bytes()));
+    headers.push((
+        "authorization".to_string(),
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, names, signature
+        ),
+    ));
+    headers
+}
+
+// One log line of a stream, with its time in milliseconds since the epoch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEvent {
+    pub timestamp: i64,
+    pub message: String,
+}
+
+// Splits a stream's events, oldest first, into PutLogEvents calls within the size and
+// count limits. An event too large for any call is left out.
+pub fn batches(mut events: Vec<LogEvent>) -> Vec<Vec<LogEvent>> {
+    events.sort_by_key(|e| e.timestamp);
+    let mut batches = Vec::new();
+    let mut batch: Vec<LogEvent> = Vec::new();
+    let mut size = 0;
+    for event in events {
+        let event_size = event.message.len() + EVENT_OVERHEAD;
+        if event_size > MAX_BATCH_BYTES {
+            eprintln!("Warning: dropping a {} byte event, over the CloudWatch Logs limit", event.message.len());
+            continue;
+        }
+        if size + event_size > MAX_BATCH_BYTES || batch.len() >= MAX_BATCH_EVENTS {
+            batches.push(std::mem::take(&mut batch));
+            size = 0;
+        }
+        size += event_size;
+        batch.push(event);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+struct Response {
+    status: u16,
+    body: String,
+}
+
+// A plain-HTTP request; the body is read up to the server closing the connection.
+fn request(addr: &str, method: &str, path: &str, headers: &[(String, String)], body: &str) -> Result<Response, String> {
+    let host = addr.strip_suffix(":80").unwrap_or(addr);
+    let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\n", method, path, host);
+    for (name, value) in headers.iter().filter(|(name, _)| name != "host") {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", body.len()));
+
+    let socket_addr = std::net::ToSocketAddrs::to_socket_addrs(addr)
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("{} did not resolve", addr))?;
+    let mut stream = TcpStream::connect_timeout(&socket_addr, IO_TIMEOUT).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.write_all(head.as_bytes()).map_err(|e| e.to_string())?;
+    stream.write_all(body.as_bytes()).map_err(|e| e.to_string())?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).map_err(|e| e.to_string())?;
+
+    let response = String::from_utf8_lossy(&response);
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status = head
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| format!("{} sent an invalid response", addr))?;
+    Ok(Response { status, body: body.to_string() })
+}
+
+// The body of a metadata request, which must succeed.
+fn read_metadata(addr: &str, method: &str, path: &str, headers: &[(String, String)]) -> Result<String, String> {
+    let response = request(addr, method, path, headers, "")?;
+    match response.status {
+        200..=299 => Ok(response.body),
+        status => Err(format!("{}{} answered {}", addr, path, status)),
+    }
+}
+
+// A failed API call: the exception name without its namespace, and the whole error.
+struct ApiError {
+    kind: String,
+    doc: Value,
+}
+
+#[derive(Default)]
+struct Queue {
+    events: VecDeque<(String, LogEvent)>,
+    shutdown: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    ready: Condvar,
+}
+
+// Sends events to CloudWatch Logs from a background thread, one stream per container,
+// named by its short ID. Events that cannot be sent are written to the local log.
+pub struct CloudWatchSink {
+    shared: Arc<Shared>,
+    thread: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl CloudWatchSink {
+    pub fn start(config: &CloudWatchConfig) -> Result<CloudWatchSink, String> {
+        let region = config
+            .region()
+            .ok_or("--cloudwatch-log-group needs --cloudwatch-region, AWS_REGION or AWS_DEFAULT_REGION")?;
+        let endpoint = config
+            .endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://logs.{}.amazonaws.com", region));
+        // Never send signed requests in plain text to AWS itself.
+        let host = endpoint.strip_prefix("http://").ok_or_else(|| {
+            format!(
+                "CloudWatch endpoint {} needs TLS, which this build has no client for; \
+                 set --cloudwatch-endpoint to an http:// proxy in front of it",
+                endpoint
+            )
+        })?;
+        let host = host.trim_end_matches('/').to_string();
+        let client = Client {
+            log_group: config.log_group.clone(),
+            region,
+            addr: if host.contains(':') { host.clone() } else { format!("{}:80", host) },
+            host,
+            credentials: None,
+            group_created: false,
+            tokens: HashMap::new(),
+            last_put: HashMap::new(),
+        };
+
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            ready: Condvar::new(),
+        });
+        let worker = shared.clone();
+        let thread = thread::Builder::new()
+            .name("cnpd-cloudwatch".to_string())
+            .spawn(move || run(client, &worker))
+            .map_err(|e| e.to_string())?;
+        info!("Sending events to CloudWatch Logs group {}", config.log_group);
+        Ok(CloudWatchSink {
+            shared,
+            thread: Mutex::new(Some(thread)),
+        })
+    }
+
+    pub fn send(&self, event: &DetectionEvent) {
+        let stream = match event.container_id.as_str() {
+            "" => "cnpd".to_string(),
+            id => docker::short_id(id).to_string(),
+        };
+        let log_event = LogEvent {
+            timestamp: Utc::now().timestamp_millis(),
+            message: event.to_json().to_string(),
+        };
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.events.len() >= MAX_QUEUED {
+            queue.events.pop_front();
+            eprintln!("Warning: CloudWatch queue is full, dropping the oldest event");
+        }
+        queue.events.push_back((stream, log_event));
+        self.shared.ready.notify_one();
+    }
+
+    // Waits up to `timeout` for queued events to be sent.
+    pub fn shutdown(&self, timeout: Duration) {
+        self.shared.queue.lock().unwrap().shutdown = true;
+        self.shared.ready.notify_one();
+
+        let deadline = Instant::now() + timeout;
+        let Some(thread) = self.thread.lock().unwrap().take() else {
+            return;
+        };
+        while !thread.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+fn next_batch(shared: &Shared) -> Option<Vec<(String, LogEvent)>> {
+    let mut queue = shared.queue.lock().unwrap();
+    while queue.events.is_empty() && !queue.shutdown {
+        queue = shared.ready.wait(queue).unwrap();
+    }
+    if queue.events.is_empty() {
+        return None;
+    }
+    if !queue.shutdown {
+        queue = shared.ready.wait_timeout(queue, BATCH_WINDOW).unwrap().0;
+    }
+    Some(queue.events.drain(..).collect())
+}
+
+fn run(mut client: Client, shared: &Shared) {
+    while let Some(queued) = next_batch(shared) {
+        let mut streams: HashMap<String, Vec<LogEvent>> = HashMap::new();
+        for (stream, event) in queued {
+            streams.entry(stream).or_default().push(event);
+        }
+        for (stream, events) in streams {
+            for batch in batches(events) {
+                if let Err(e) = client.put(&stream, &batch) {
+                    eprintln!("Failed to send {} events to CloudWatch Logs: {}", batch.len(), e);
+                    for event in batch {
+                        eprintln!("CloudWatch unavailable, event: {}", event.message);
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Client {
+    log_group: String,
+    region: String,
+    host: String,
+    addr: String,
+    credentials: Option<Credentials>,
+    group_created: bool,
+    // The sequence token of each stream's next PutLogEvents, once one is known.
+    tokens: HashMap<String, Option<String>>,
+    last_put: HashMap<String, Instant>,
+}
+
+impl Client {
+    fn call(&mut self, action: &str, body: Value) -> Result<Result<Value, ApiError>, String> {
+        if self.credentials.as_ref().is_none_or(Credentials::expiring) {
+            self.credentials = Some(Credentials::resolve()?);
+        }
+        let credentials = self.credentials.as_ref().expect("credentials were just resolved");
+        let body = body.to_string();
+        let headers = signed_headers(credentials, &self.region, &self.host, action, &body, Utc::now());
+        let response = request(&self.addr, "POST", "/", &headers, &body)?;
+        let doc = json::parse(&response.body).unwrap_or(Value::Null);
+        if (200..300).contains(&response.status) {
+            return Ok(Ok(doc));
+        }
+        let kind = doc
+            .get("__type")
+            .and_then(Value::as_str)
+            .map(|kind| kind.rsplit('#').next().unwrap_or(kind).to_string());
+        match kind {
+            Some(kind) => Ok(Err(ApiError { kind, doc })),
+            None => Err(format!("{} answered {}: {}", action, response.status, response.body.trim())),
+        }
+    }
+
+    // Creates the group or stream, which may well exist already.
+    fn create(&mut self, action: &str, body: Value) -> Result<(), String> {
+        match self.call(action, body)? {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind == "ResourceAlreadyExistsException" => Ok(()),
+            Err(e) => Err(format!("{} failed: {}", action, e.doc)),
+        }
+    }
+
+    fn put(&mut self, stream: &str, events: &[LogEvent]) -> Result<(), String> {
+        let group: Value = self.log_group.as_str().into();
+        if !self.group_created {
+            self.create("CreateLogGroup", Value::Object(vec![("logGroupName".to_string(), group.clone())]))?;
+            self.group_created = true;
+        }
+        if !self.tokens.contains_key(stream) {
+            self.create(
+                "CreateLogStream",
+                Value::Object(vec![
+                    ("logGroupName".to_string(), group.clone()),
+                    ("logStreamName".to_string(), stream.into()),
+                ]),
+            )?;
+            self.tokens.insert(stream.to_string(), None);
+        }
+        if let Some(wait) = self.last_put.get(stream).and_then(|at| MIN_PUT_INTERVAL.checked_sub(at.elapsed())) {
+            thread::sleep(wait);
+        }
+
+        let log_events: Vec<Value> = events
+            .iter()
+            .map(|e| {
+                Value::Object(vec![
+                    ("timestamp".to_string(), Value::Number(e.timestamp as f64)),
+                    ("message".to_string(), e.message.as_str().into()),
+                ])
+            })
+            .collect();
+        // A rejected sequence token comes back with the expected one, which one retry uses.
+        for _ in 0..2 {
+            let mut body = vec![
+                ("logGroupName".to_string(), group.clone()),
+                ("logStreamName".to_string(), stream.into()),
+                ("logEvents".to_string(), Value::Array(log_events.clone())),
+            ];
+            if let Some(Some(token)) = self.tokens.get(stream) {
+                body.push(("sequenceToken".to_string(), token.as_str().into()));
+            }
+            self.last_put.insert(stream.to_string(), Instant::now());
+            let result = self.call("PutLogEvents", Value::Object(body))?;
+            let expected = |doc: &Value| doc.get("expectedSequenceToken").and_then(Value::as_str).map(str::to_string);
+            match result {
+                Ok(doc) => {
+                    let next = doc.get("nextSequenceToken").and_then(Value::as_str).map(str::to_string);
+                    self.tokens.insert(stream.to_string(), next);
+                    return Ok(());
+                }
+                Err(e) if e.kind == "DataAlreadyAcceptedException" => {
+                    self.tokens.insert(stream.to_string(), expected(&e.doc));
+                    return Ok(());
+                }
+                Err(e) if e.kind == "InvalidSequenceTokenException" => {
+                    self.tokens.insert(stream.to_string(), expected(&e.doc));
+                }
+                // Deleted from under us: created again on the next batch.
+                Err(e) if e.kind == "ResourceNotFoundException" => {
+                    self.group_created = false;
+                    self.tokens.remove(stream);
+                    return Err(format!("PutLogEvents failed: {}", e.doc));
+                }
+                Err(e) => return Err(format!("PutLogEvents failed: {}", e.doc)),
+            }
+        }
+        Err("PutLogEvents kept rejecting the sequence token".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_the_documented_signing_key() {
+        // The example from the AWS Signature Version 4 documentation.
+        let key = signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20120215", "us-east-1", "iam");
+        assert_eq!(sha256::hex(&key), "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d");
+
+        let credentials = Credentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: Some("token".to_string()),
+            expires_at: None,
+        };
+        let now = "2026-03-02T10:00:00Z".parse().unwrap();
+        let headers = signed_headers(&credentials, "eu-west-1", "logs.eu-west-1.amazonaws.com", "PutLogEvents", "{}", now);
+        let authorization = &headers.last().unwrap().1;
+        assert!(authorization.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20260302/eu-west-1/logs/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date;x-amz-security-token;x-amz-target, Signature="
+        ));
+    }
+
+    #[test]
+    fn reads_credentials_of_the_profile() {
+        let content = "\
+[default]
+aws_access_key_id = AKIADEFAULT
+aws_secret_access_key = default-secret
+
+[cnpd]
+aws_access_key_id=AKIACNPD
+# aws_session_token = old
+aws_secret_access_key=cnpd-secret
+";
+        let credentials = Credentials::from_profile(content, "cnpd").unwrap();
+        assert_eq!(credentials.access_key_id, "AKIACNPD");
+        assert_eq!(credentials.secret_access_key, "cnpd-secret");
+        assert_eq!(credentials.session_token, None);
+        assert!(Credentials::from_profile(content, "missing").is_none());
+    }
+
+    #[test]
+    fn splits_batches_at_the_size_limit() {
+        let event = |timestamp| LogEvent {
+            timestamp,
+            message: "x".repeat(400_000),
+        };
+        let split = batches(vec![event(3), event(1), event(2)]);
+        assert_eq!(split.iter().map(Vec::len).collect::<Vec<_>>(), [2, 1]);
+        assert_eq!(split[0][0].timestamp, 1);
+    }
+}
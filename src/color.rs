@@ -0,0 +1,41 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static STDOUT_COLOR: AtomicBool = AtomicBool::new(false);
+static STDERR_COLOR: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy)]
+pub enum Color {
+    Red = 31,
+    Yellow = 33,
+    Cyan = 36,
+}
+
+// Colors are only used on streams attached to a terminal, and never when --no-color
+// or the NO_COLOR environment variable is set, so log files and pipes stay plain.
+pub fn init(no_color: bool) {
+    let allowed = !no_color && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty());
+    let is_tty = |fd| unsafe { libc::isatty(fd) } == 1;
+    // Log lines painted with stdout() end up on stderr when it carries them.
+    let log_fd = if crate::log::log_to_stderr() { libc::STDERR_FILENO } else { libc::STDOUT_FILENO };
+    STDOUT_COLOR.store(allowed && is_tty(log_fd), Ordering::Relaxed);
+    STDERR_COLOR.store(allowed && is_tty(libc::STDERR_FILENO), Ordering::Relaxed);
+}
+
+fn paint(enabled: &AtomicBool, color: Color, text: impl Display) -> String {
+    if enabled.load(Ordering::Relaxed) {
+        format!("\x1b[{}m{}\x1b[0m", color as u8, text)
+    } else {
+        text.to_string()
+    }
+}
+
+// For text printed with println! or info!.
+pub fn stdout(color: Color, text: impl Display) -> String {
+    paint(&STDOUT_COLOR, color, text)
+}
+
+// For text printed with eprintln!.
+pub fn stderr(color: Color, text: impl Display) -> String {
+    paint(&STDERR_COLOR, color, text)
+}
@@ -0,0 +1,836 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::anomaly;
+use crate::cgroup::{EmptyProcsRetry, DEFAULT_CGROUP_PATH};
+use crate::control::DEFAULT_CONTROL_SOCKET;
+use crate::discovery::DiscoveryMethod;
+use crate::event::OutputFormat;
+use crate::eventbuffer::{self, BufferOverflow};
+use crate::forensics::{DEFAULT_CORE_DIR, DEFAULT_EVIDENCE_DIR};
+use crate::metrics::DEFAULT_FINAL_METRICS_FILE;
+use crate::group::ContainerGroup;
+use crate::json::Value;
+use crate::policy::{glob_match, Action};
+use crate::journald::LogOutput;
+use crate::redis::RedisConfig;
+use crate::cloudwatch::CloudWatchConfig;
+use crate::router::{Route, SinkConfig};
+use crate::subsystem::CgroupSubsystem;
+use crate::summary::Notifier;
+use crate::tenant::Tenant;
+use crate::toml;
+use crate::upgrade::DEFAULT_UPGRADE_STATE_FILE;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub latency_warn_ms: Option<u64>,
+    pub metrics_addr: Option<SocketAddr>,
+    // Path under which the metrics server also answers /healthz and /readyz.
+    pub healthcheck_endpoint: Option<String>,
+    pub stats_interval: Duration,
+    pub policy_file: Option<String>,
+    pub output_file: Option<String>,
+    pub max_proc_read_time: Duration,
+    pub cgroup_paths: Vec<String>,
+    pub trivy_scan: bool,
+    pub max_known_pids: usize,
+    pub container_start_delay: Option<Duration>,
+    pub forensics_dir: Option<String>,
+    pub plugins: Vec<String>,
+    pub require_seccomp: bool,
+    pub exempt_containers: Vec<String>,
+    pub watchdog_restart: bool,
+    // How long a monitoring task may go without polling before it is reported as stuck.
+    // Actions pause the watchdog, so this only has to cover a poll.
+    pub watchdog_timeout: Duration,
+    pub memory_pressure_discount: u8,
+    pub state_file: Option<String>,
+    pub no_color: bool,
+    pub alert_on_process_exit: bool,
+    pub exit_action: Action,
+    pub syslog_addr: Option<String>,
+    // This build has no TLS, so RFC 5425 is not available: events, cmdlines included,
+    // would leave the host in the clear, which has to be acknowledged with
+    // --syslog-allow-plaintext.
+    pub syslog_allow_plaintext: bool,
+    pub kill_suspicious_process: bool,
+    // None keeps to the containers found at startup.
+    pub discovery_method: Option<DiscoveryMethod>,
+    pub checkpoint: bool,
+    pub checkpoint_dir: Option<String>,
+    pub control_socket: String,
+    pub debug: bool,
+    pub config_file: Option<String>,
+    pub sandbox: bool,
+    pub report_format: OutputFormat,
+    // Containers found at startup are monitored this many to a task.
+    pub containers_per_task: usize,
+    // CPU cores the runtime threads are pinned to; empty leaves scheduling to the kernel.
+    pub bind_cpus: Vec<usize>,
+    // Detections using less CPU than this are only logged; 0 acts on all of them.
+    pub alert_threshold_cpu_percent: f64,
+    // Log full 64-character container IDs instead of the 12-character short form.
+    pub full_container_ids: bool,
+    pub watch_user_namespaces: bool,
+    // Freeze the container while forensic data is collected for a detection.
+    pub freeze_on_detection: bool,
+    // Freeze it with `docker pause` instead of the cgroup v2 freezer.
+    pub pause_on_detection: bool,
+    // From the `[[tenants]]` section of the --config file.
+    pub tenants: Vec<Tenant>,
+    pub verify_signatures: bool,
+    // Public key for cosign; keyless signatures are accepted without one.
+    pub cosign_key: Option<String>,
+    // Transparency log to verify against instead of cosign's default.
+    pub rekor_url: Option<String>,
+    // Capture outbound TCP connections with eBPF as they are made.
+    pub trace_tcp_connect: bool,
+    // Skip PIDs in cgroup.procs whose /proc/<pid>/exe does not resolve.
+    pub ignore_kernel_threads: bool,
+    // From the `[[groups]]` section of the --config file.
+    pub groups: Vec<ContainerGroup>,
+    pub alert_on_privilege_escalation: bool,
+    pub privilege_escalation_action: Action,
+    // Dump the memory of detected processes with gcore before acting on them.
+    pub core_dump_on_detection: bool,
+    pub core_dump_dir: String,
+    pub core_dump_timeout: Duration,
+    // Keep --output-file open and reopen it on SIGHUP.
+    pub log_rotate_on_sighup: bool,
+    // Alternate procfs mount to read process information from, instead of /proc.
+    pub bind_mount_proc: Option<String>,
+    // Take argv and exe from kernel audit execve records.
+    pub audit_exec: bool,
+    // Processes that exited younger than this are not reported; 0 disables the check.
+    pub min_process_age_ms: u64,
+    // Warn about containers in the host PID namespace and only log their detections,
+    // unless the action is explicitly allowed.
+    pub host_pid_namespace_check: bool,
+    pub allow_host_pid_ns_action: bool,
+    // Publish events to a Redis pub/sub channel.
+    pub redis: Option<RedisConfig>,
+    // Send events to a CloudWatch Logs group.
+    pub cloudwatch: Option<CloudWatchConfig>,
+    // Longest a stop or restart may take before the container's init process is killed.
+    pub max_restart_duration: Duration,
+    // Also store events in this SQLite database.
+    pub write_events_to_sqlite: Option<String>,
+    // Outputs events are written to besides stdout and the configured sinks.
+    pub log_outputs: Vec<LogOutput>,
+    // Move a process into this container once it is monitored and exit with 0 if it
+    // is detected and acted on within simulate_timeout, 1 otherwise.
+    pub simulate_attack: Option<String>,
+    // The process to move; by default a sleep started for the purpose.
+    pub simulate_pid: Option<i32>,
+    pub simulate_timeout: Duration,
+    // Compare an archived container against its baseline from state_file, report the
+    // processes outside it and exit, without monitoring anything.
+    pub forensics_mode: bool,
+    pub container_archive: Option<String>,
+    // Write a Grafana dashboard for the /metrics series there and exit.
+    pub export_grafana_dashboard: Option<String>,
+    // Send a summary of every container's detections this often, to report_notifiers.
+    pub report_interval: Option<Duration>,
+    pub report_notifiers: Vec<Notifier>,
+    // Containers whose ContainerRisk score reaches this get at least high_risk_action.
+    pub risk_threshold: Option<u32>,
+    pub high_risk_action: Action,
+    pub retry_whitelist_on_empty: bool,
+    pub whitelist_retries: u32,
+    pub whitelist_retry_backoff: Duration,
+    pub ignore_stopped_containers: bool,
+    // Spread monitoring tasks over the NUMA nodes, each pinned to its node's CPUs.
+    pub numa_aware: bool,
+    // Only containers of this Docker Compose project are monitored.
+    pub container_namespace: Option<String>,
+    // Overload events once a container runs more processes than this, or when their
+    // number grows faster than this many PIDs per second.
+    pub alert_on_large_proc_set: Option<usize>,
+    pub alert_on_proc_set_growth_rate: Option<f64>,
+    // Keep a rolling window of process_count_window samples of every container's process
+    // count in this file, and alert when a count is more than process_count_stddevs
+    // standard deviations above the window's mean.
+    pub process_count_baseline_file: Option<String>,
+    pub process_count_window: usize,
+    pub process_count_stddevs: f64,
+    // Script run before a detected process is killed or its container stopped.
+    pub pre_action_hook: Option<String>,
+    pub pre_action_hook_timeout: Duration,
+    // Script run before a container found after startup is monitored; the container is
+    // skipped unless it succeeds.
+    pub container_start_hook: Option<String>,
+    // Write a last Prometheus snapshot to final_metrics_file when exiting.
+    pub export_metrics_on_exit: bool,
+    pub final_metrics_file: String,
+    // How long after restarting a container its new cgroup.procs is waited for before
+    // it becomes the whitelist, and with no_act_in_grace_window_on_restart, how long
+    // after the restart detections are only logged.
+    pub post_restart_grace_ms: u64,
+    pub no_act_in_grace_window_on_restart: bool,
+    // Report files written in containers, and kill the writer of any whose path inside
+    // the container matches one of block_file_writes.
+    pub detect_file_writes: bool,
+    pub block_file_writes: Vec<String>,
+    // Stop the container once the process with this PID in the container's PID
+    // namespace exits, for containers meant to run a single process.
+    pub kill_container_on_exit_of_pid: Option<i32>,
+    // falcosidekick endpoint events are also sent to as Falco alerts.
+    pub report_to_falco: Option<String>,
+    // Replaces the policy's default_action, like --kill-suspicious-process does with kill.
+    pub default_action: Option<Action>,
+    // From the `[[sinks]]` and `[[routes]]` sections of the --config file.
+    pub sinks: Vec<SinkConfig>,
+    pub routes: Vec<Route>,
+    // Add the processes running at the time to the whitelist this often.
+    pub baseline_refresh_interval: Option<Duration>,
+    // Detections of a container within this long of the first are recorded as one
+    // correlated event, with its container acted on once.
+    pub event_correlation_window: Option<Duration>,
+    // Report AUDIT_SECCOMP records of container processes.
+    pub watch_seccomp_violations: bool,
+    // Report new processes with effective capabilities the container's init process lacks.
+    pub detect_capabilities_abuse: bool,
+    // Report mounts added in a container after its whitelist was taken, and whether they
+    // shadow one of sensitive_mount_paths.
+    pub detect_mount_namespace_changes: bool,
+    pub sensitive_mount_paths: Vec<String>,
+    // Mark new processes appearing at hours their container had none at over the past
+    // week, learning from the --write-events-to-sqlite database.
+    pub detect_time_of_day_anomalies: bool,
+    // Disconnect containers from their networks before stopping or restarting them, and
+    // reconnect them afterwards, whatever the action.
+    pub isolate_on_detection: bool,
+    // Roll back containers that would be restarted instead, for stateless services.
+    pub rollback_on_detection: bool,
+    // Record the working directory of new processes, marking those under one of
+    // sensitive_cwd_paths.
+    pub track_process_working_directory: bool,
+    pub sensitive_cwd_paths: Vec<String>,
+    // Report processes attaching to others with PTRACE_ATTACH or PTRACE_SEIZE, traced
+    // with eBPF.
+    pub detect_ptrace_usage: bool,
+    // Copy the Docker labels of containers into their events, only those starting with
+    // one of include_label_prefixes when there are any.
+    pub container_labels_in_events: bool,
+    pub include_label_prefixes: Vec<String>,
+    // Events detected but not yet written to the sinks, and what happens to more.
+    pub events_buffer_size: usize,
+    pub events_buffer_overflow: BufferOverflow,
+    // A BCC Python script started for every container with its cgroup ID, whose stdout
+    // events go through the same actions as detected new processes.
+    pub use_bcc: Option<String>,
+    // Report the unlinked files new processes hold open.
+    pub detect_hidden_files: bool,
+    // Copy files out of containers before stopping or restarting them: evidence_paths,
+    // or every file written since the container started when there are none.
+    pub preserve_evidence_on_restart: bool,
+    pub evidence_dir: String,
+    pub evidence_paths: Vec<String>,
+    // Forget containers whose cgroup directory is gone, in the state file, the metrics
+    // and the inventory, and end their monitoring task.
+    pub cleanup_dead_containers: bool,
+    // Cgroup controllers whose files are watched besides cgroup.procs.
+    pub monitor_cgroup_subsystems: Vec<CgroupSubsystem>,
+    // Where the state is handed to a new binary installed over the running one, which
+    // SIGHUP execs with --resume-from-state-file.
+    pub upgrade_state_file: String,
+    pub resume_from_state_file: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            latency_warn_ms: None,
+            metrics_addr: None,
+            healthcheck_endpoint: None,
+            stats_interval: Duration::from_secs(60),
+            policy_file: None,
+            output_file: None,
+            max_proc_read_time: Duration::from_millis(50),
+            cgroup_paths: vec![DEFAULT_CGROUP_PATH.to_string()],
+            trivy_scan: false,
+            max_known_pids: 10_000,
+            container_start_delay: None,
+            forensics_dir: None,
+            plugins: Vec::new(),
+            require_seccomp: false,
+            exempt_containers: Vec::new(),
+            watchdog_restart: false,
+            watchdog_timeout: Duration::from_secs(120),
+            memory_pressure_discount: 1,
+            state_file: None,
+            no_color: false,
+            alert_on_process_exit: false,
+            exit_action: Action::LogOnly,
+            syslog_addr: None,
+            syslog_allow_plaintext: false,
+            kill_suspicious_process: false,
+            discovery_method: None,
+            checkpoint: false,
+            checkpoint_dir: None,
+            control_socket: DEFAULT_CONTROL_SOCKET.to_string(),
+            debug: false,
+            config_file: None,
+            sandbox: false,
+            report_format: OutputFormat::Text,
+            containers_per_task: 10,
+            bind_cpus: Vec::new(),
+            alert_threshold_cpu_percent: 0.0,
+            full_container_ids: false,
+            watch_user_namespaces: false,
+            freeze_on_detection: false,
+            pause_on_detection: false,
+            tenants: Vec::new(),
+            verify_signatures: false,
+            cosign_key: None,
+            rekor_url: None,
+            trace_tcp_connect: false,
+            ignore_kernel_threads: false,
+            groups: Vec::new(),
+            alert_on_privilege_escalation: false,
+            privilege_escalation_action: Action::Stop,
+            core_dump_on_detection: false,
+            core_dump_dir: DEFAULT_CORE_DIR.to_string(),
+            core_dump_timeout: Duration::from_secs(30),
+            log_rotate_on_sighup: false,
+            bind_mount_proc: None,
+            audit_exec: false,
+            min_process_age_ms: 0,
+            host_pid_namespace_check: false,
+            allow_host_pid_ns_action: false,
+            redis: None,
+            cloudwatch: None,
+            max_restart_duration: Duration::from_secs(30),
+            write_events_to_sqlite: None,
+            log_outputs: Vec::new(),
+            simulate_attack: None,
+            simulate_pid: None,
+            simulate_timeout: Duration::from_secs(30),
+            forensics_mode: false,
+            container_archive: None,
+            export_grafana_dashboard: None,
+            report_interval: None,
+            report_notifiers: Vec::new(),
+            risk_threshold: None,
+            high_risk_action: Action::Kill,
+            retry_whitelist_on_empty: false,
+            whitelist_retries: 5,
+            whitelist_retry_backoff: Duration::from_millis(100),
+            ignore_stopped_containers: false,
+            numa_aware: false,
+            container_namespace: None,
+            alert_on_large_proc_set: None,
+            alert_on_proc_set_growth_rate: None,
+            process_count_baseline_file: None,
+            process_count_window: 360,
+            process_count_stddevs: 3.0,
+            pre_action_hook: None,
+            pre_action_hook_timeout: Duration::from_millis(5000),
+            container_start_hook: None,
+            export_metrics_on_exit: false,
+            final_metrics_file: DEFAULT_FINAL_METRICS_FILE.to_string(),
+            post_restart_grace_ms: 5000,
+            no_act_in_grace_window_on_restart: false,
+            detect_file_writes: false,
+            block_file_writes: Vec::new(),
+            kill_container_on_exit_of_pid: None,
+            report_to_falco: None,
+            default_action: None,
+            sinks: Vec::new(),
+            routes: Vec::new(),
+            baseline_refresh_interval: None,
+            event_correlation_window: None,
+            watch_seccomp_violations: false,
+            detect_capabilities_abuse: false,
+            detect_mount_namespace_changes: false,
+            sensitive_mount_paths: ["/etc", "/proc", "/sys", "/root"].map(str::to_string).to_vec(),
+            detect_time_of_day_anomalies: false,
+            isolate_on_detection: false,
+            rollback_on_detection: false,
+            track_process_working_directory: false,
+            sensitive_cwd_paths: ["/proc", "/sys", "/host"].map(str::to_string).to_vec(),
+            detect_ptrace_usage: false,
+            container_labels_in_events: false,
+            include_label_prefixes: Vec::new(),
+            events_buffer_size: eventbuffer::DEFAULT_CAPACITY,
+            events_buffer_overflow: BufferOverflow::Drop,
+            use_bcc: None,
+            detect_hidden_files: false,
+            preserve_evidence_on_restart: false,
+            evidence_dir: DEFAULT_EVIDENCE_DIR.to_string(),
+            evidence_paths: Vec::new(),
+            cleanup_dead_containers: false,
+            monitor_cgroup_subsystems: Vec::new(),
+            upgrade_state_file: DEFAULT_UPGRADE_STATE_FILE.to_string(),
+            resume_from_state_file: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Config, Box<dyn Error>> {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+        let mut cgroup_paths = Vec::new();
+        let mut redis_channel = None;
+        let (mut cloudwatch_region, mut cloudwatch_endpoint) = (None, None);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--latency-warn-ms" => {
+                    config.latency_warn_ms = Some(next_value(&arg, &mut args)?.parse()?);
+                }
+                "--metrics-addr" => {
+                    config.metrics_addr = Some(next_value(&arg, &mut args)?.parse()?);
+                }
+                "--healthcheck-endpoint" => {
+                    let path = next_value(&arg, &mut args)?;
+                    if !path.starts_with('/') {
+                        return Err(format!("--healthcheck-endpoint must be an absolute path, got {}", path).into());
+                    }
+                    config.healthcheck_endpoint = Some(path);
+                }
+                "--stats-interval" => {
+                    config.stats_interval = parse_duration(&next_value(&arg, &mut args)?)?;
+                }
+                "--policy" => config.policy_file = Some(next_value(&arg, &mut args)?),
+                "--output-file" => config.output_file = Some(next_value(&arg, &mut args)?),
+                "--max-proc-read-time" => {
+                    config.max_proc_read_time = parse_duration(&next_value(&arg, &mut args)?)?;
+                }
+                "--host-pid-namespace-check" => config.host_pid_namespace_check = true,
+                "--allow-host-pid-ns-action" => config.allow_host_pid_ns_action = true,
+                "--min-process-age-ms" => config.min_process_age_ms = next_value(&arg, &mut args)?.parse()?,
+                "--max-known-pids" => config.max_known_pids = next_value(&arg, &mut args)?.parse()?,
+                "--container-start-delay" => {
+                    config.container_start_delay = Some(parse_duration(&next_value(&arg, &mut args)?)?);
+                }
+                "--forensics-dir" => config.forensics_dir = Some(next_value(&arg, &mut args)?),
+                "--trivy-scan" => config.trivy_scan = true,
+                "--require-seccomp" => config.require_seccomp = true,
+                "--exempt-containers" => config
+                    .exempt_containers
+                    .extend(next_value(&arg, &mut args)?.split(',').map(str::to_string)),
+                "--watchdog-restart" => config.watchdog_restart = true,
+                "--watchdog-timeout" => config.watchdog_timeout = parse_duration(&next_value(&arg, &mut args)?)?,
+                "--memory-pressure-discount" => {
+                    config.memory_pressure_discount = next_value(&arg, &mut args)?.parse()?;
+                }
+                "--state-file" => config.state_file = Some(next_value(&arg, &mut args)?),
+                "--no-color" => config.no_color = true,
+                "--alert-on-process-exit" => config.alert_on_process_exit = true,
+                "--exit-action" => config.exit_action = next_value(&arg, &mut args)?.parse()?,
+                "--max-restart-duration" => {
+                    config.max_restart_duration = parse_duration(&next_value(&arg, &mut args)?)?;
+                }
+                "--redis" => config.redis = Some(RedisConfig::new(next_value(&arg, &mut args)?)),
+                "--redis-channel" => redis_channel = Some(next_value(&arg, &mut args)?),
+                "--cloudwatch-log-group" => config.cloudwatch = Some(CloudWatchConfig::new(next_value(&arg, &mut args)?)),
+                "--cloudwatch-region" => cloudwatch_region = Some(next_value(&arg, &mut args)?),
+                "--cloudwatch-endpoint" => cloudwatch_endpoint = Some(next_value(&arg, &mut args)?),
+                "--report-interval" => config.report_interval = Some(parse_duration(&next_value(&arg, &mut args)?)?),
+                "--report-notify" => config.report_notifiers.push(next_value(&arg, &mut args)?.parse()?),
+                "--risk-threshold" => config.risk_threshold = Some(next_value(&arg, &mut args)?.parse()?),
+                "--high-risk-action" => config.high_risk_action = next_value(&arg, &mut args)?.parse()?,
+                "--retry-whitelist-on-empty" => config.retry_whitelist_on_empty = true,
+                "--whitelist-retries" => config.whitelist_retries = next_value(&arg, &mut args)?.parse()?,
+                "--whitelist-retry-backoff" => {
+                    config.whitelist_retry_backoff = parse_duration(&next_value(&arg, &mut args)?)?;
+                }
+                "--ignore-stopped-containers" => config.ignore_stopped_containers = true,
+                "--numa-aware" => config.numa_aware = true,
+                "--container-namespace" => config.container_namespace = Some(next_value(&arg, &mut args)?),
+                "--alert-on-large-proc-set" => {
+                    config.alert_on_large_proc_set = Some(next_value(&arg, &mut args)?.parse()?);
+                }
+                "--alert-on-proc-set-growth-rate" => {
+                    let value = next_value(&arg, &mut args)?;
+                    let rate = value.strip_suffix("/s").unwrap_or(&value);
+                    config.alert_on_proc_set_growth_rate =
+                        Some(rate.parse().map_err(|_| format!("Invalid growth rate: {} (expected <N>/s)", value))?);
+                }
+                "--pre-action-hook" => config.pre_action_hook = Some(next_value(&arg, &mut args)?),
+                "--process-count-baseline-file" => config.process_count_baseline_file = Some(next_value(&arg, &mut args)?),
+                "--process-count-window" => config.process_count_window = next_value(&arg, &mut args)?.parse()?,
+                "--process-count-stddevs" => config.process_count_stddevs = next_value(&arg, &mut args)?.parse()?,
+                "--container-start-hook" => config.container_start_hook = Some(next_value(&arg, &mut args)?),
+                "--pre-action-hook-timeout-ms" => {
+                    config.pre_action_hook_timeout = Duration::from_millis(next_value(&arg, &mut args)?.parse()?)
+                }
+                "--export-metrics-on-exit" => config.export_metrics_on_exit = true,
+                "--final-metrics-file" => config.final_metrics_file = next_value(&arg, &mut args)?,
+                "--post-restart-grace-ms" => config.post_restart_grace_ms = next_value(&arg, &mut args)?.parse()?,
+                "--no-act-in-grace-window-on-restart" => config.no_act_in_grace_window_on_restart = true,
+                "--detect-file-writes" => config.detect_file_writes = true,
+                "--watch-seccomp-violations" => config.watch_seccomp_violations = true,
+                "--detect-capabilities-abuse" => config.detect_capabilities_abuse = true,
+                "--detect-mount-namespace-changes" => config.detect_mount_namespace_changes = true,
+                "--detect-time-of-day-anomalies" => config.detect_time_of_day_anomalies = true,
+                "--isolate-on-detection" => config.isolate_on_detection = true,
+                "--rollback-on-detection" => config.rollback_on_detection = true,
+                "--track-process-working-directory" => config.track_process_working_directory = true,
+                "--detect-ptrace-usage" => config.detect_ptrace_usage = true,
+                "--events-buffer-size" => config.events_buffer_size = next_value(&arg, &mut args)?.parse()?,
+                "--events-buffer-overflow" => config.events_buffer_overflow = next_value(&arg, &mut args)?.parse()?,
+                "--preserve-evidence-on-restart" => config.preserve_evidence_on_restart = true,
+                "--evidence-dir" => config.evidence_dir = next_value(&arg, &mut args)?,
+                "--evidence-paths" => {
+                    config.evidence_paths = next_value(&arg, &mut args)?.split(',').map(str::to_string).collect();
+                }
+                "--detect-hidden-files" => config.detect_hidden_files = true,
+                "--cleanup-dead-containers" => config.cleanup_dead_containers = true,
+                "--upgrade-state-file" => config.upgrade_state_file = next_value(&arg, &mut args)?,
+                "--resume-from-state-file" => config.resume_from_state_file = Some(next_value(&arg, &mut args)?),
+                "--monitor-cgroup-subsystems" => {
+                    config.monitor_cgroup_subsystems = next_value(&arg, &mut args)?
+                        .split(',')
+                        .map(|subsystem| subsystem.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--use-bcc" => config.use_bcc = Some(next_value(&arg, &mut args)?),
+                "--container-labels-in-events" => config.container_labels_in_events = true,
+                "--include-label-prefixes" => {
+                    config.include_label_prefixes = next_value(&arg, &mut args)?.split(',').map(str::to_string).collect();
+                }
+                "--sensitive-cwd-paths" => {
+                    config.sensitive_cwd_paths = next_value(&arg, &mut args)?.split(',').map(str::to_string).collect();
+                }
+                "--sensitive-mount-paths" => {
+                    config.sensitive_mount_paths = next_value(&arg, &mut args)?.split(',').map(str::to_string).collect();
+                }
+                "--block-file-writes" => config
+                    .block_file_writes
+                    .extend(next_value(&arg, &mut args)?.split(',').map(str::to_string)),
+                "--kill-container-on-exit-of-pid" => {
+                    config.kill_container_on_exit_of_pid = Some(next_value(&arg, &mut args)?.parse()?);
+                }
+                "--report-to-falco" => config.report_to_falco = Some(next_value(&arg, &mut args)?),
+                "--action" => config.default_action = Some(next_value(&arg, &mut args)?.parse()?),
+                "--baseline-refresh-interval" => {
+                    config.baseline_refresh_interval = Some(parse_duration(&next_value(&arg, &mut args)?)?);
+                }
+                "--event-correlation-window" => {
+                    config.event_correlation_window = Some(parse_duration(&next_value(&arg, &mut args)?)?);
+                }
+                "--forensics-mode" => config.forensics_mode = true,
+                "--container-archive" => config.container_archive = Some(next_value(&arg, &mut args)?),
+                "--export-grafana-dashboard" => config.export_grafana_dashboard = Some(next_value(&arg, &mut args)?),
+                "--simulate-attack" => config.simulate_attack = Some(next_value(&arg, &mut args)?),
+                "--simulate-pid" => config.simulate_pid = Some(next_value(&arg, &mut args)?.parse()?),
+                "--simulate-timeout" => config.simulate_timeout = parse_duration(&next_value(&arg, &mut args)?)?,
+                "--log-output" => config.log_outputs.push(next_value(&arg, &mut args)?.parse()?),
+                "--write-events-to-sqlite" => config.write_events_to_sqlite = Some(next_value(&arg, &mut args)?),
+                "--syslog-tcp" => config.syslog_addr = Some(next_value(&arg, &mut args)?),
+                "--syslog-allow-plaintext" => config.syslog_allow_plaintext = true,
+                "--kill-suspicious-process" => config.kill_suspicious_process = true,
+                "--discovery-method" => config.discovery_method = Some(next_value(&arg, &mut args)?.parse()?),
+                "--checkpoint" => config.checkpoint = true,
+                "--checkpoint-dir" => config.checkpoint_dir = Some(next_value(&arg, &mut args)?),
+                "--control-socket" => config.control_socket = next_value(&arg, &mut args)?,
+                "--debug" => config.debug = true,
+                "--sandbox" => config.sandbox = true,
+                "--containers-per-task" => config.containers_per_task = next_value(&arg, &mut args)?.parse()?,
+                "--bind-cpu" => {
+                    config.bind_cpus = next_value(&arg, &mut args)?
+                        .split(',')
+                        .map(|cpu| cpu.trim().parse().map_err(|_| format!("Invalid CPU: {}", cpu)))
+                        .collect::<Result<_, _>>()?;
+                }
+                "--alert-threshold-cpu-percent" => {
+                    config.alert_threshold_cpu_percent = next_value(&arg, &mut args)?.parse()?;
+                }
+                "--full-container-ids" => config.full_container_ids = true,
+                "--watch-user-namespaces" => config.watch_user_namespaces = true,
+                "--freeze-on-detection" => config.freeze_on_detection = true,
+                "--pause-on-detection" => config.pause_on_detection = true,
+                "--verify-signatures" => config.verify_signatures = true,
+                "--trace-tcp-connect" => config.trace_tcp_connect = true,
+                "--ignore-kernel-threads" => config.ignore_kernel_threads = true,
+                "--alert-on-privilege-escalation" => config.alert_on_privilege_escalation = true,
+                "--log-rotate-on-sighup" => config.log_rotate_on_sighup = true,
+                "--audit-exec" => config.audit_exec = true,
+                "--bind-mount-proc" => config.bind_mount_proc = Some(next_value(&arg, &mut args)?),
+                "--core-dump-on-detection" => config.core_dump_on_detection = true,
+                "--core-dump-dir" => config.core_dump_dir = next_value(&arg, &mut args)?,
+                "--core-dump-timeout" => config.core_dump_timeout = parse_duration(&next_value(&arg, &mut args)?)?,
+                "--privilege-escalation-action" => config.privilege_escalation_action = next_value(&arg, &mut args)?.parse()?,
+                "--cosign-key" => config.cosign_key = Some(next_value(&arg, &mut args)?),
+                "--rekor-url" => config.rekor_url = Some(next_value(&arg, &mut args)?),
+                "--report-format" => config.report_format = next_value(&arg, &mut args)?.parse()?,
+                "--config" => {
+                    let path = next_value(&arg, &mut args)?;
+                    config.apply_file(&path)?;
+                    config.config_file = Some(path);
+                }
+                "--cgroup-path" => cgroup_paths.push(next_value(&arg, &mut args)?),
+                _ => return Err(format!("Unknown argument: {}", arg).into()),
+            }
+        }
+
+        if !cgroup_paths.is_empty() {
+            config.cgroup_paths = cgroup_paths;
+        }
+        if let Some(channel) = redis_channel {
+            config.redis.as_mut().ok_or("--redis-channel requires --redis or a [redis] config section")?.channel = channel;
+        }
+        if cloudwatch_region.is_some() || cloudwatch_endpoint.is_some() {
+            let cloudwatch = config
+                .cloudwatch
+                .as_mut()
+                .ok_or("--cloudwatch-region and --cloudwatch-endpoint require --cloudwatch-log-group")?;
+            cloudwatch.region = cloudwatch_region;
+            cloudwatch.endpoint = cloudwatch_endpoint;
+        }
+        if config.syslog_addr.is_some() && !config.syslog_allow_plaintext {
+            return Err("--syslog-tcp sends events unencrypted, as this build has no TLS syslog (RFC 5425); \
+                        add --syslog-allow-plaintext to send them anyway"
+                .into());
+        }
+        if config.syslog_allow_plaintext && config.syslog_addr.is_none() {
+            return Err("--syslog-allow-plaintext requires --syslog-tcp".into());
+        }
+        if config.trace_tcp_connect && !cfg!(feature = "ebpf") {
+            return Err("--trace-tcp-connect needs a build with the ebpf feature".into());
+        }
+        if config.detect_ptrace_usage && !cfg!(feature = "ebpf") {
+            return Err("--detect-ptrace-usage needs a build with the ebpf feature".into());
+        }
+        if config.numa_aware && !config.bind_cpus.is_empty() {
+            return Err("--numa-aware cannot be combined with --bind-cpu".into());
+        }
+        if !config.report_notifiers.is_empty() && config.report_interval.is_none() {
+            return Err("--report-notify requires --report-interval".into());
+        }
+        if config.stats_interval.is_zero() {
+            return Err("--stats-interval must be greater than zero".into());
+        }
+        if config.report_interval.is_some_and(|interval| interval.is_zero()) {
+            return Err("--report-interval must be greater than zero".into());
+        }
+        if config.forensics_mode && (config.container_archive.is_none() || config.state_file.is_none()) {
+            return Err("--forensics-mode requires --container-archive and --state-file".into());
+        }
+        if config.container_archive.is_some() && !config.forensics_mode {
+            return Err("--container-archive requires --forensics-mode".into());
+        }
+        if config.simulate_pid.is_some() && config.simulate_attack.is_none() {
+            return Err("--simulate-pid requires --simulate-attack".into());
+        }
+        if config.log_rotate_on_sighup && config.output_file.is_none() {
+            return Err("--log-rotate-on-sighup requires --output-file".into());
+        }
+        if config.core_dump_on_detection && config.sandbox {
+            return Err("--core-dump-on-detection cannot be used with --sandbox, whose seccomp filter denies ptrace()".into());
+        }
+        if config.trace_tcp_connect && config.sandbox {
+            return Err("--trace-tcp-connect cannot be used with --sandbox, whose seccomp filter denies bpf()".into());
+        }
+        if config.detect_ptrace_usage && config.sandbox {
+            return Err("--detect-ptrace-usage cannot be used with --sandbox, whose seccomp filter denies bpf()".into());
+        }
+        if config.use_bcc.is_some() && config.sandbox {
+            return Err("--use-bcc cannot be used with --sandbox, whose seccomp filter denies bpf()".into());
+        }
+        if config.final_metrics_file != DEFAULT_FINAL_METRICS_FILE && !config.export_metrics_on_exit {
+            return Err("--final-metrics-file requires --export-metrics-on-exit".into());
+        }
+        if config.pre_action_hook.is_some() && config.sandbox {
+            return Err("--pre-action-hook cannot be used with --sandbox, whose restrictions the script would inherit".into());
+        }
+        if config.container_start_hook.is_some() && config.sandbox {
+            return Err("--container-start-hook cannot be used with --sandbox, whose restrictions the script would inherit".into());
+        }
+        if config.freeze_on_detection && config.pause_on_detection {
+            return Err("--freeze-on-detection and --pause-on-detection are alternatives, pick one".into());
+        }
+        if !config.block_file_writes.is_empty() && !config.detect_file_writes {
+            return Err("--block-file-writes requires --detect-file-writes".into());
+        }
+        if config.kill_suspicious_process && config.default_action.is_some() {
+            return Err("--kill-suspicious-process and --action both set the default action, pick one".into());
+        }
+        if config.baseline_refresh_interval.is_some_and(|interval| interval.is_zero()) {
+            return Err("--baseline-refresh-interval must be greater than zero".into());
+        }
+        if config.healthcheck_endpoint.is_some() && config.metrics_addr.is_none() {
+            return Err("--healthcheck-endpoint is served by the metrics server and requires --metrics-addr".into());
+        }
+        if config.event_correlation_window.is_some_and(|window| window.is_zero()) {
+            return Err("--event-correlation-window must be greater than zero".into());
+        }
+        if config.process_count_window < anomaly::MIN_SAMPLES {
+            return Err(format!("--process-count-window must be at least {} samples", anomaly::MIN_SAMPLES).into());
+        }
+        if !config.process_count_stddevs.is_finite() || config.process_count_stddevs <= 0.0 {
+            return Err("--process-count-stddevs must be greater than zero".into());
+        }
+        if config.detect_time_of_day_anomalies && config.write_events_to_sqlite.is_none() {
+            return Err("--detect-time-of-day-anomalies keeps its history in the event store and requires --write-events-to-sqlite".into());
+        }
+        if config.containers_per_task == 0 {
+            return Err("--containers-per-task must be at least 1".into());
+        }
+        if (config.evidence_dir != DEFAULT_EVIDENCE_DIR || !config.evidence_paths.is_empty()) && !config.preserve_evidence_on_restart {
+            return Err("--evidence-dir and --evidence-paths require --preserve-evidence-on-restart".into());
+        }
+        if config.events_buffer_size == 0 {
+            return Err("--events-buffer-size must be at least 1".into());
+        }
+        if config.watchdog_timeout.is_zero() {
+            return Err("--watchdog-timeout must be greater than zero".into());
+        }
+
+        Ok(config)
+    }
+
+    // Settings that do not fit on the command line come from a TOML config file:
+    //
+    //     plugins = ["/etc/cnpd/plugins/ticket_filer.so"]
+    //     pre_action_hook = "/etc/cnpd/hooks/capture.sh"
+    //     pre_action_hook_timeout_ms = 5000
+    //     container_start_hook = "/etc/cnpd/hooks/fetch-policy.sh"
+    //     include_label_prefixes = ["com.example.", "app.kubernetes.io/"]
+    pub fn apply_file(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)?;
+        let doc = toml::parse(&content).map_err(|e| format!("{}: {}", path, e))?;
+
+        if let Some(plugins) = doc.get("plugins") {
+            self.plugins = string_list(plugins).ok_or_else(|| format!("{}: plugins must be a list of paths", path))?;
+        }
+        if let Some(tenants) = doc.get("tenants") {
+            self.tenants = tenants
+                .as_array()
+                .ok_or_else(|| format!("{}: tenants must be an array of tables", path))?
+                .iter()
+                .map(Tenant::from_toml)
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("{}: {}", path, e))?;
+        }
+        if let Some(redis) = doc.get("redis") {
+            self.redis = Some(RedisConfig::from_toml(redis).map_err(|e| format!("{}: {}", path, e))?);
+        }
+        if let Some(hook) = doc.get("pre_action_hook") {
+            let hook = hook.as_str().ok_or_else(|| format!("{}: pre_action_hook must be a path", path))?;
+            self.pre_action_hook = Some(hook.to_string());
+        }
+        if let Some(limit) = doc.get("pre_action_hook_timeout_ms") {
+            let limit = limit
+                .as_i64()
+                .and_then(|ms| u64::try_from(ms).ok())
+                .ok_or_else(|| format!("{}: pre_action_hook_timeout_ms must be a number of milliseconds", path))?;
+            self.pre_action_hook_timeout = Duration::from_millis(limit);
+        }
+        if let Some(hook) = doc.get("container_start_hook") {
+            let hook = hook.as_str().ok_or_else(|| format!("{}: container_start_hook must be a path", path))?;
+            self.container_start_hook = Some(hook.to_string());
+        }
+        if let Some(prefixes) = doc.get("include_label_prefixes") {
+            self.include_label_prefixes = string_list(prefixes)
+                .ok_or_else(|| format!("{}: include_label_prefixes must be a list of strings", path))?;
+        }
+        if let Some(sinks) = doc.get("sinks") {
+            self.sinks = sinks
+                .as_array()
+                .ok_or_else(|| format!("{}: sinks must be an array of tables", path))?
+                .iter()
+                .map(SinkConfig::from_toml)
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("{}: {}", path, e))?;
+        }
+        if let Some(routes) = doc.get("routes") {
+            self.routes = routes
+                .as_array()
+                .ok_or_else(|| format!("{}: routes must be an array of tables", path))?
+                .iter()
+                .map(Route::from_toml)
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("{}: {}", path, e))?;
+        }
+        if let Some(groups) = doc.get("groups") {
+            self.groups = groups
+                .as_array()
+                .ok_or_else(|| format!("{}: groups must be an array of tables", path))?
+                .iter()
+                .map(ContainerGroup::from_toml)
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("{}: {}", path, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn string_list(value: &Value) -> Option<Vec<String>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|item| item.as_str().map(str::to_string))
+        .collect()
+}
+
+impl Config {
+    pub fn whitelist_retry(&self) -> Option<EmptyProcsRetry> {
+        self.retry_whitelist_on_empty.then_some(EmptyProcsRetry {
+            attempts: self.whitelist_retries,
+            backoff: self.whitelist_retry_backoff,
+        })
+    }
+
+    pub fn blocks_file_write(&self, path: &str) -> bool {
+        self.block_file_writes.iter().any(|pattern| glob_match(pattern, path))
+    }
+
+    // Labels are free-form and may carry anything, so only chosen ones go into events.
+    pub fn includes_label(&self, key: &str) -> bool {
+        self.include_label_prefixes.is_empty() || self.include_label_prefixes.iter().any(|prefix| key.starts_with(prefix.as_str()))
+    }
+
+    pub fn is_exempt(&self, container_id: &str, name: Option<&str>) -> bool {
+        self.exempt_containers.iter().any(|pattern| {
+            glob_match(pattern, container_id) || name.is_some_and(|name| glob_match(pattern, name))
+        })
+    }
+}
+
+fn next_value<I: Iterator<Item = String>>(flag: &str, args: &mut I) -> Result<String, Box<dyn Error>> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for {}", flag).into())
+}
+
+// Accepts durations such as "50ms", "5s", "10m" or "1h"; a bare number is read as seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, Box<dyn Error>> {
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration: {}", value))?;
+
+    let seconds = match unit {
+        "ms" => return Ok(Duration::from_millis(number)),
+        "" | "s" => Some(number),
+        "m" => number.checked_mul(60),
+        "h" => number.checked_mul(3600),
+        _ => return Err(format!("Invalid duration unit in: {}", value).into()),
+    };
+    seconds
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("Invalid duration: {}", value).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations_without_overflowing() {
+        assert_eq!(parse_duration("50ms").unwrap(), Duration::from_millis(50));
+        assert_eq!(parse_duration("5").unwrap(), Duration::from_secs(5));
+        assert_eq!(parse_duration("10m").unwrap(), Duration::from_secs(600));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+        assert!(parse_duration(&format!("{}m", u64::MAX / 60 + 1)).is_err());
+        assert!(parse_duration(&format!("{}h", u64::MAX)).is_err());
+        assert!(parse_duration("5d").is_err());
+    }
+}
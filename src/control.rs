@@ -0,0 +1,200 @@
+use std::error::Error;
+use std::os::unix::fs::PermissionsExt;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::filter::EventFilter;
+use crate::inventory::Inventory;
+use crate::info;
+use crate::json::{self, Value};
+use crate::procfs;
+use crate::tenant::{Access, Tenants};
+use crate::whitelist::ProcessWhitelistStore;
+
+pub const DEFAULT_CONTROL_SOCKET: &str = "/run/cnpd.sock";
+
+fn error(message: String) -> Value {
+    Value::Object(vec![("error".to_string(), message.into())])
+}
+
+// Line-based protocol: the client sends a command name, the daemon answers with one
+// line of JSON and closes the connection. Clients only see the containers `access`
+// allows.
+async fn handle(
+    command: &str,
+    inventory: &Inventory,
+    whitelists: &ProcessWhitelistStore,
+    filter: &EventFilter,
+    access: &Access,
+) -> Value {
+    if *access == Access::Tenants(Vec::new()) {
+        return error("Permission denied: this user belongs to no tenant".to_string());
+    }
+    if let Some(args) = command.strip_prefix("whitelist-add ") {
+        return whitelist_add(args, inventory, whitelists, access);
+    }
+    match command {
+        "containers" => Value::Array(
+            inventory
+                .snapshot()
+                .iter()
+                .filter(|s| access.allows(s.tenant.as_deref()))
+                .map(|s| s.to_json())
+                .collect(),
+        ),
+        "known-pids" => Value::Array(
+            whitelists
+                .known_pids()
+                .await
+                .into_iter()
+                .filter(|(container_id, _)| access.allows(inventory.tenant(container_id).as_deref()))
+                .map(|(container_id, pids)| {
+                    Value::Object(vec![
+                        ("container_id".to_string(), container_id.into()),
+                        ("pids".to_string(), Value::Array(pids.into_iter().map(Value::from).collect())),
+                    ])
+                })
+                .collect(),
+        ),
+        "suppressed-events" => filter.to_json(access),
+        "tasks" => tasks(inventory, access).await,
+        _ => error(format!("Unknown command: {}", command)),
+    }
+}
+
+// `whitelist-add <container> <pid>...`: the PIDs are whitelisted by the container's
+// monitoring task on its next poll.
+fn whitelist_add(args: &str, inventory: &Inventory, whitelists: &ProcessWhitelistStore, access: &Access) -> Value {
+    let mut args = args.split_whitespace();
+    let Some(container) = args.next() else {
+        return error("whitelist-add needs a container ID".to_string());
+    };
+    let pids = match args.map(str::parse).collect::<Result<Vec<i32>, _>>() {
+        Ok(pids) if !pids.is_empty() => pids,
+        _ => return error("whitelist-add needs one or more PIDs".to_string()),
+    };
+    // The prefix is resolved first and access checked on the container it names, which
+    // may belong to another tenant than a stopped container sharing the prefix.
+    let container_id = match whitelists.resolve(container) {
+        Ok(container_id) if access.allows(inventory.tenant(&container_id).as_deref()) => container_id,
+        Ok(_) => return error(format!("Container {} is not being monitored", container)),
+        Err(e) => return error(e),
+    };
+    match whitelists.add(&container_id, &pids) {
+        Ok(()) => Value::Object(vec![
+            ("container_id".to_string(), container_id.into()),
+            ("pids".to_string(), Value::Array(pids.into_iter().map(Value::from).collect())),
+        ]),
+        Err(e) => error(e),
+    }
+}
+
+// CPU time of the thread that last ran each monitoring task, for cnpd-ctl top. Tasks
+// move between runtime threads, so this is the thread's total rather than the task's
+// own share, and tasks that ran on the same thread report the same ticks.
+async fn tasks(inventory: &Inventory, access: &Access) -> Value {
+    let mut tasks = Vec::new();
+    for status in inventory.snapshot().iter().filter(|s| access.allows(s.tenant.as_deref())) {
+        let cpu_ticks = match status.tid {
+            Some(tid) => procfs::read_thread_cpu_ticks(tid).await,
+            None => None,
+        };
+        tasks.push(Value::Object(vec![
+            ("container_id".to_string(), status.container_id.as_str().into()),
+            ("name".to_string(), status.name.clone().into()),
+            ("state".to_string(), status.state.to_string().into()),
+            ("tid".to_string(), status.tid.into()),
+            ("polls".to_string(), status.polls.into()),
+            ("cpu_ticks".to_string(), cpu_ticks.into()),
+            ("numa_node".to_string(), status.numa_node.map(|node| node as u64).into()),
+        ]));
+    }
+    Value::Object(vec![
+        ("clock_ticks".to_string(), procfs::clock_ticks_per_second().into()),
+        ("rss_kb".to_string(), procfs::self_rss_kb().await.into()),
+        ("tasks".to_string(), Value::Array(tasks)),
+    ])
+}
+
+pub async fn serve_control(
+    path: &str,
+    inventory: Arc<Inventory>,
+    whitelists: Arc<ProcessWhitelistStore>,
+    filter: Arc<EventFilter>,
+    tenants: Arc<Tenants>,
+) -> Result<(), Box<dyn Error>> {
+    // A socket left behind by a previous run would make bind fail.
+    let _ = tokio::fs::remove_file(path).await;
+    let listener = UnixListener::bind(path)?;
+    // Tenants connect as their own users; what each one sees is then decided from the
+    // peer credentials rather than by the file mode.
+    let mode = if tenants.is_empty() { 0o600 } else { 0o666 };
+    tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).await?;
+    info!("Control socket listening on {}", path);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let inventory = inventory.clone();
+        let whitelists = whitelists.clone();
+        let filter = filter.clone();
+        let access = match stream.peer_cred() {
+            Ok(cred) => tenants.access(cred.uid()),
+            Err(_) => Access::Tenants(Vec::new()),
+        };
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut command = String::new();
+            if BufReader::new(reader).read_line(&mut command).await.is_err() {
+                return;
+            }
+            let response = handle(command.trim(), &inventory, &whitelists, &filter, &access).await;
+            let _ = writer.write_all(format!("{}\n", response).as_bytes()).await;
+        });
+    }
+}
+
+pub async fn request(path: &str, command: &str) -> Result<Value, Box<dyn Error>> {
+    let stream = UnixStream::connect(path)
+        .await
+        .map_err(|e| format!("Failed to connect to {}: {}", path, e))?;
+    let (reader, mut writer) = stream.into_split();
+    writer.write_all(format!("{}\n", command).as_bytes()).await?;
+
+    let mut response = String::new();
+    BufReader::new(reader).read_line(&mut response).await?;
+    let response = json::parse(&response)?;
+    if let Some(error) = response.get("error").and_then(Value::as_str) {
+        return Err(error.to_string().into());
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inventory::MonitorState;
+
+    #[test]
+    fn whitelist_add_checks_the_tenant_of_the_resolved_container() {
+        let inventory = Inventory::default();
+        let whitelists = ProcessWhitelistStore::default();
+        // Tenant a's container has stopped, leaving tenant b's as the only one monitored.
+        inventory.register("a1", MonitorState::Stopped, 0);
+        inventory.set_tenant("a1", "a".to_string());
+        drop(whitelists.register("a1"));
+        inventory.register("a2", MonitorState::Monitoring, 0);
+        inventory.set_tenant("a2", "b".to_string());
+        let b_whitelist = whitelists.register("a2");
+
+        let tenant_a = Access::Tenants(vec!["a".to_string()]);
+        let response = whitelist_add("a 42", &inventory, &whitelists, &tenant_a);
+        assert_eq!(response.get("error").and_then(Value::as_str), Some("Container a is not being monitored"));
+        assert_eq!(b_whitelist.take_pending(), None);
+
+        let tenant_b = Access::Tenants(vec!["b".to_string()]);
+        let response = whitelist_add("a 42", &inventory, &whitelists, &tenant_b);
+        assert_eq!(response.get("container_id").and_then(Value::as_str), Some("a2"));
+        assert_eq!(b_whitelist.take_pending(), Some(vec![42]));
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::HashSet;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+
+use crate::cgroup::{self, ContainerCgroup};
+use crate::inotify::Inotify;
+use crate::log;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// The start event can arrive just before the container's cgroup shows up.
+const CGROUP_LOOKUP_RETRIES: u32 = 10;
+
+// How containers started after the initial scan are found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoveryMethod {
+    // `docker events` start/die notifications.
+    DockerEvents,
+    // IN_CREATE/IN_DELETE on the cgroup directories.
+    Inotify,
+    // Rescanning the cgroup directories every second.
+    Poll,
+}
+
+impl FromStr for DiscoveryMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "docker-events" => Ok(DiscoveryMethod::DockerEvents),
+            "inotify" => Ok(DiscoveryMethod::Inotify),
+            "poll" => Ok(DiscoveryMethod::Poll),
+            _ => Err(format!("Unknown discovery method: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ContainerChange {
+    Started(ContainerCgroup),
+    // Carries the container id.
+    Stopped(String),
+}
+
+// Starts discovery in a background task. `known` are the containers found by the
+// initial scan, which are not reported again.
+pub fn spawn(
+    method: DiscoveryMethod,
+    cgroup_paths: Vec<String>,
+    known: &[ContainerCgroup],
+) -> mpsc::UnboundedReceiver<ContainerChange> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let known: HashSet<String> = known.iter().map(ContainerCgroup::container_id).collect();
+    tokio::spawn(async move {
+        let result = match method {
+            DiscoveryMethod::DockerEvents => DockerEventListener::new(cgroup_paths).run(&tx).await,
+            DiscoveryMethod::Inotify => watch_directories(&cgroup_paths, &tx).await,
+            DiscoveryMethod::Poll => poll_directories(&cgroup_paths, known, &tx).await,
+        };
+        if let Err(e) = result {
+            eprintln!("Container discovery stopped: {}", e);
+        }
+    });
+    rx
+}
+
+pub struct DockerEventListener {
+    cgroup_paths: Vec<String>,
+}
+
+impl DockerEventListener {
+    pub fn new(cgroup_paths: Vec<String>) -> Self {
+        DockerEventListener { cgroup_paths }
+    }
+
+    pub async fn run(&self, tx: &mpsc::UnboundedSender<ContainerChange>) -> Result<(), String> {
+        let mut child = Command::new("docker")
+            .args(["events", "--filter", "type=container", "--filter", "event=start", "--filter", "event=die"])
+            .args(["--format", "{{.Action}} {{.Actor.ID}}"])
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to run docker events: {}", e))?;
+        let stdout = child.stdout.take().ok_or("docker events has no stdout")?;
+        let mut lines = BufReader::new(stdout).lines();
+
+        while let Some(line) = lines.next_line().await.map_err(|e| e.to_string())? {
+            let Some((action, id)) = line.split_once(' ') else {
+                continue;
+            };
+            let change = match action {
+                "start" => match self.find_cgroup(id).await {
+                    Some(container) => ContainerChange::Started(container),
+                    None => {
+                        eprintln!("No cgroup found for started container {}", log::id(id));
+                        continue;
+                    }
+                },
+                "die" => ContainerChange::Stopped(id.to_string()),
+                _ => continue,
+            };
+            if tx.send(change).is_err() {
+                break;
+            }
+        }
+        Err("docker events exited".to_string())
+    }
+
+    async fn find_cgroup(&self, container_id: &str) -> Option<ContainerCgroup> {
+        for _ in 0..CGROUP_LOOKUP_RETRIES {
+            if let Ok(containers) = cgroup::get_docker_directories(&self.cgroup_paths).await {
+                if let Some(container) = containers.into_iter().find(|c| c.container_id() == container_id) {
+                    return Some(container);
+                }
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        None
+    }
+}
+
+async fn watch_directories(cgroup_paths: &[String], tx: &mpsc::UnboundedSender<ContainerChange>) -> Result<(), String> {
+    let inotify = Inotify::new().map_err(|e| e.to_string())?;
+    let mut roots = Vec::new();
+    for path in cgroup_paths {
+        let wd = inotify
+            .add_watch(path, libc::IN_CREATE | libc::IN_DELETE | libc::IN_ONLYDIR)
+            .map_err(|e| format!("Failed to watch {}: {}", path, e))?;
+        roots.push((wd, path.clone()));
+    }
+
+    loop {
+        for event in inotify.read_events().await.map_err(|e| e.to_string())? {
+            let (Some(name), Some((_, root))) = (event.name, roots.iter().find(|(wd, _)| *wd == event.wd)) else {
+                continue;
+            };
+            if !cgroup::is_container_scope(&name) {
+                continue;
+            }
+            let container = ContainerCgroup {
+                root: root.clone(),
+                name,
+                memory_limit: None,
+            };
+            let change = if event.mask & libc::IN_CREATE != 0 {
+                ContainerChange::Started(container)
+            } else {
+                ContainerChange::Stopped(container.container_id())
+            };
+            if tx.send(change).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+async fn poll_directories(
+    cgroup_paths: &[String],
+    mut known: HashSet<String>,
+    tx: &mpsc::UnboundedSender<ContainerChange>,
+) -> Result<(), String> {
+    loop {
+        sleep(POLL_INTERVAL).await;
+        let containers = match cgroup::get_docker_directories(cgroup_paths).await {
+            Ok(containers) => containers,
+            Err(e) => {
+                eprintln!("Failed to scan cgroup directories: {}", e);
+                continue;
+            }
+        };
+
+        let current: HashSet<String> = containers.iter().map(ContainerCgroup::container_id).collect();
+        for id in known.difference(&current) {
+            if tx.send(ContainerChange::Stopped(id.clone())).is_err() {
+                return Ok(());
+            }
+        }
+        for container in containers {
+            if !known.contains(&container.container_id()) && tx.send(ContainerChange::Started(container)).is_err() {
+                return Ok(());
+            }
+        }
+        known = current;
+    }
+}
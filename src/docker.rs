@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::error::Error;
+use tokio::process::Command;
+
+use crate::json;
+use crate::procfs;
+
+// The 12-character form Docker itself prints.
+pub fn short_id(full_id: &str) -> &str {
+    full_id.get(..12).unwrap_or(full_id)
+}
+
+pub async fn stop_container(container_id: &str) -> Result<bool, Box<dyn Error>> {
+    let output = Command::new("docker")
+        .arg("stop")
+        .arg(container_id)
+        .output()
+        .await?;
+    Ok(output.status.success())
+}
+
+pub async fn start_container(container_id: &str) -> Result<bool, Box<dyn Error>> {
+    let output = Command::new("docker")
+        .arg("start")
+        .arg(container_id)
+        .output()
+        .await?;
+    Ok(output.status.success())
+}
+
+// Paths reported by `docker diff` as added (A) or changed (C).
+pub async fn changed_paths(container_id: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let output = Command::new("docker")
+        .arg("diff")
+        .arg(container_id)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string().into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .filter(|(kind, _)| *kind == "A" || *kind == "C")
+        .map(|(_, path)| path.to_string())
+        .collect())
+}
+
+// `docker cp <container>:<path> <target>`, which works whether or not the container runs.
+pub async fn copy_from(container_id: &str, path: &str, target: &str) -> Result<(), Box<dyn Error>> {
+    let output = Command::new("docker")
+        .arg("cp")
+        .arg(format!("{}:{}", container_id, path))
+        .arg(target)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string().into());
+    }
+    Ok(())
+}
+
+pub async fn commit_container(container_id: &str, image: &str) -> Result<bool, Box<dyn Error>> {
+    let output = Command::new("docker")
+        .arg("commit")
+        .arg(container_id)
+        .arg(image)
+        .output()
+        .await?;
+    Ok(output.status.success())
+}
+
+pub async fn save_image(image: &str, archive: &str) -> Result<bool, Box<dyn Error>> {
+    let output = Command::new("docker")
+        .arg("save")
+        .arg("-o")
+        .arg(archive)
+        .arg(image)
+        .output()
+        .await?;
+    Ok(output.status.success())
+}
+
+pub async fn remove_image(image: &str) -> Result<bool, Box<dyn Error>> {
+    let output = Command::new("docker")
+        .arg("rmi")
+        .arg(image)
+        .output()
+        .await?;
+    Ok(output.status.success())
+}
+
+// Runs `docker inspect --format <template>` and returns the trimmed output.
+pub async fn inspect(container_id: &str, template: &str) -> Result<String, Box<dyn Error>> {
+    let output = Command::new("docker")
+        .arg("inspect")
+        .arg("--format")
+        .arg(template)
+        .arg(container_id)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string().into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+pub async fn container_name(container_id: &str) -> Result<String, Box<dyn Error>> {
+    Ok(inspect(container_id, "{{.Name}}").await?.trim_start_matches('/').to_string())
+}
+
+// The Docker Compose project that created the container, from its labels.
+pub async fn compose_project(container_id: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let project = inspect(container_id, "{{index .Config.Labels \"com.docker.compose.project\"}}").await?;
+    // `index` prints "<no value>" for a missing label.
+    Ok(Some(project).filter(|p| !p.is_empty() && p != "<no value>"))
+}
+
+// The container's labels; `null` when it has none.
+pub async fn labels(container_id: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    match json::parse(&inspect(container_id, "{{json .Config.Labels}}").await?)? {
+        json::Value::Object(labels) => Ok(labels
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value.as_str()?.to_string())))
+            .collect()),
+        _ => Ok(HashMap::new()),
+    }
+}
+
+// Names of the networks the container is connected to.
+pub async fn networks(container_id: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    match json::parse(&inspect(container_id, "{{json .NetworkSettings.Networks}}").await?)? {
+        json::Value::Object(networks) => Ok(networks.into_iter().map(|(name, _)| name).collect()),
+        _ => Ok(Vec::new()),
+    }
+}
+
+// `docker network connect` or `docker network disconnect`.
+pub async fn set_connected(network: &str, container_id: &str, connected: bool) -> Result<(), Box<dyn Error>> {
+    let command = if connected { "connect" } else { "disconnect" };
+    let output = Command::new("docker")
+        .args(["network", command, network, container_id])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string().into());
+    }
+    Ok(())
+}
+
+// The whole `docker inspect` object of the container.
+pub async fn inspect_container(container_id: &str) -> Result<json::Value, Box<dyn Error>> {
+    Ok(json::parse(&inspect(container_id, "{{json .}}").await?)?)
+}
+
+pub async fn rename_container(container_id: &str, name: &str) -> Result<(), Box<dyn Error>> {
+    let output = Command::new("docker").args(["rename", container_id, name]).output().await?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string().into());
+    }
+    Ok(())
+}
+
+pub async fn remove_container(container_id: &str) -> Result<(), Box<dyn Error>> {
+    let output = Command::new("docker").args(["rm", "--force", container_id]).output().await?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string().into());
+    }
+    Ok(())
+}
+
+// `docker create <args>`. Returns the ID of the new container.
+pub async fn create_container(args: &[String]) -> Result<String, Box<dyn Error>> {
+    let output = Command::new("docker").arg("create").args(args).output().await?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string().into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+pub async fn is_running(container_id: &str) -> Result<bool, Box<dyn Error>> {
+    Ok(inspect(container_id, "{{.State.Running}}").await? == "true")
+}
+
+// The image reference the container was created from.
+pub async fn container_image(container_id: &str) -> Result<String, Box<dyn Error>> {
+    inspect(container_id, "{{.Config.Image}}").await
+}
+
+pub async fn security_opts(container_id: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let opts = json::parse(&inspect(container_id, "{{json .HostConfig.SecurityOpt}}").await?)?;
+    Ok(opts
+        .as_array()
+        .map(|opts| opts.iter().filter_map(|o| o.as_str().map(str::to_string)).collect())
+        .unwrap_or_default())
+}
+
+// An explicit `seccomp=<profile>` option is a profile, `seccomp=unconfined` is not.
+// Without either Docker applies its default profile, so fall back to the kernel's view
+// of one of the container's processes.
+pub async fn has_seccomp_profile(container_id: &str, pid: Option<i32>) -> Result<bool, Box<dyn Error>> {
+    let opts = security_opts(container_id).await?;
+    if let Some(profile) = opts.iter().find_map(|opt| opt.strip_prefix("seccomp")) {
+        let profile = profile.trim_start_matches(['=', ':']);
+        return Ok(profile != "unconfined");
+    }
+    match pid {
+        Some(pid) => Ok(procfs::seccomp_mode(pid).await.is_some_and(|mode| mode != 0)),
+        None => Ok(false),
+    }
+}
+
+// `docker pause` or `docker unpause`, which freeze the container with whatever
+// freezer its runtime uses, cgroup v1 included.
+pub async fn set_paused(container_id: &str, paused: bool) -> Result<(), Box<dyn Error>> {
+    let command = if paused { "pause" } else { "unpause" };
+    let output = Command::new("docker").arg(command).arg(container_id).output().await?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string().into());
+    }
+    Ok(())
+}
+
+// Checkpoints the container with CRIU, leaving it running so the regular stop/restart
+// still applies. Without a directory Docker stores it under the container's own state.
+pub async fn checkpoint_container(
+    container_id: &str,
+    checkpoint_id: &str,
+    dir: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let mut command = Command::new("docker");
+    command.args(["checkpoint", "create", "--leave-running"]);
+    if let Some(dir) = dir {
+        command.arg(format!("--checkpoint-dir={}", dir));
+    }
+    let output = command.arg(container_id).arg(checkpoint_id).output().await?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string().into());
+    }
+    Ok(())
+}
@@ -0,0 +1,117 @@
+// Traces outbound TCP connections with a bpftrace kprobe on tcp_connect, which runs in
+// the context of the connecting process once the local port has been assigned. This
+// catches connections from processes that exit before /proc/<pid>/net can be read.
+// Also traces ptrace() attaches for --detect-ptrace-usage.
+
+use std::error::Error;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+use crate::netsock::{ConnectionLog, NetworkEvent};
+
+// Prints "<pid> <local ip> <local port> <remote ip> <remote port>" per connection.
+// skc_dport is in network byte order, skc_num in host order.
+const TCP_CONNECT_PROGRAM: &str = r#"
+kprobe:tcp_connect {
+    $sk = (struct sock *)arg0;
+    $dport = $sk->__sk_common.skc_dport;
+    $dport = (($dport & 0xff) << 8) | ($dport >> 8);
+    if ($sk->__sk_common.skc_family == 2) {
+        printf("%d %s %d %s %d\n", pid, ntop(2, $sk->__sk_common.skc_rcv_saddr),
+            $sk->__sk_common.skc_num, ntop(2, $sk->__sk_common.skc_daddr), $dport);
+    } else if ($sk->__sk_common.skc_family == 10) {
+        printf("%d %s %d %s %d\n", pid, ntop(10, $sk->__sk_common.skc_v6_rcv_saddr.in6_u.u6_addr8),
+            $sk->__sk_common.skc_num, ntop(10, $sk->__sk_common.skc_v6_daddr.in6_u.u6_addr8), $dport);
+    }
+}
+"#;
+
+fn parse_connect_line(line: &str) -> Option<NetworkEvent> {
+    let mut fields = line.split_whitespace();
+    Some(NetworkEvent {
+        pid: fields.next()?.parse().ok()?,
+        local_ip: fields.next()?.parse().ok()?,
+        local_port: fields.next()?.parse().ok()?,
+        remote_ip: fields.next()?.parse().ok()?,
+        remote_port: fields.next()?.parse().ok()?,
+        seen_at: Instant::now(),
+    })
+}
+
+// Prints "<tracer pid> <tracee pid> <request>" for PTRACE_ATTACH (16) and PTRACE_SEIZE
+// (0x4206), the requests that attach to a running process.
+const PTRACE_PROGRAM: &str = r#"
+tracepoint:syscalls:sys_enter_ptrace /args->request == 16 || args->request == 0x4206/ {
+    printf("%d %d %d\n", pid, args->pid, args->request);
+}
+"#;
+
+// A process attaching to another with ptrace().
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PtraceCall {
+    pub tracer_pid: i32,
+    pub tracee_pid: i32,
+    pub request: i64,
+}
+
+fn parse_ptrace_line(line: &str) -> Option<PtraceCall> {
+    let mut fields = line.split_whitespace();
+    Some(PtraceCall {
+        tracer_pid: fields.next()?.parse().ok()?,
+        tracee_pid: fields.next()?.parse().ok()?,
+        request: fields.next()?.parse().ok()?,
+    })
+}
+
+// Starts bpftrace and sends every attach it reports to `tx` from a background task.
+// Calls from every process on the host are traced; the receiver ties them to containers.
+pub fn trace_ptrace(tx: mpsc::UnboundedSender<PtraceCall>) -> Result<(), Box<dyn Error>> {
+    let mut child = Command::new("bpftrace")
+        .args(["-q", "-e", PTRACE_PROGRAM])
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to run bpftrace: {}", e))?;
+    let stdout = child.stdout.take().ok_or("bpftrace has no stdout")?;
+
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(call) = parse_ptrace_line(&line) {
+                if tx.send(call).is_err() {
+                    break;
+                }
+            }
+        }
+        let status = child.wait().await.map(|s| s.to_string()).unwrap_or_else(|e| e.to_string());
+        eprintln!("ptrace tracing stopped: bpftrace exited ({})", status);
+    });
+    Ok(())
+}
+
+// Starts bpftrace and records every connection it reports in `log` from a background task.
+pub fn trace_tcp_connects(log: Arc<ConnectionLog>) -> Result<(), Box<dyn Error>> {
+    let mut child = Command::new("bpftrace")
+        .args(["-q", "-e", TCP_CONNECT_PROGRAM])
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to run bpftrace: {}", e))?;
+    let stdout = child.stdout.take().ok_or("bpftrace has no stdout")?;
+
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(event) = parse_connect_line(&line) {
+                log.record(event);
+            }
+        }
+        let status = child.wait().await.map(|s| s.to_string()).unwrap_or_else(|e| e.to_string());
+        eprintln!("TCP connect tracing stopped: bpftrace exited ({})", status);
+    });
+    Ok(())
+}
@@ -0,0 +1,786 @@
+use chrono::{DateTime, Local};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::docker;
+use crate::json::{self, Value};
+use crate::lineage::ProcessInfo;
+use crate::mountinfo::MountEntry;
+use crate::procfs::DeletedFile;
+use crate::netsock::NetSocket;
+use crate::policy::Action;
+use crate::scan::Vulnerability;
+use crate::signature::SignatureStatus;
+use crate::subsystem::SubsystemChange;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    NewProcess,
+    // A whitelisted process disappeared from the container.
+    ProcessExit,
+    // A process is in a different user namespace than the container's init process.
+    NamespaceEscape,
+    // A process running as root was started by a parent that was not.
+    PrivilegeEscalation,
+    // Stopping or restarting the container took longer than --max-restart-duration.
+    ActionTimeout,
+    // The container runs more processes than --alert-on-large-proc-set allows, or their
+    // number grows faster than --alert-on-proc-set-growth-rate.
+    Overload,
+    // A file in the container was written, with --detect-file-writes.
+    FileWrite,
+    // Detections of one container within --event-correlation-window of each other.
+    Correlated,
+    // A syscall blocked by the process's seccomp profile, with --watch-seccomp-violations.
+    SeccompViolation,
+    // A process has capabilities the container's init process lacks, e.g. from file
+    // capabilities set with setcap, with --detect-capabilities-abuse.
+    CapabilityAbuse,
+    // A mount was added in the container, with --detect-mount-namespace-changes.
+    MountChange,
+    // A process in the container attached to another with ptrace(), with --detect-ptrace-usage.
+    Ptrace,
+    // A new process holds open a file that was unlinked, as malware hiding its binary
+    // does, with --detect-hidden-files.
+    HiddenFile,
+    // The container's monitoring task scored below the degraded health score.
+    MonitoringDegraded,
+    // The container's CPU usage or I/O went far above its usual rate, with
+    // --monitor-cgroup-subsystems cpu or io.
+    CpuSpike,
+    IoSpike,
+    // The container's net_cls.classid changed, which moves its traffic out of the class
+    // network policies match, with --monitor-cgroup-subsystems net_cls.
+    NetClassChange,
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EventKind::NewProcess => "new-process",
+            EventKind::ProcessExit => "process-exit",
+            EventKind::NamespaceEscape => "namespace-escape",
+            EventKind::PrivilegeEscalation => "privilege-escalation",
+            EventKind::ActionTimeout => "action-timeout",
+            EventKind::Overload => "overload",
+            EventKind::FileWrite => "file-write",
+            EventKind::Correlated => "correlated",
+            EventKind::SeccompViolation => "seccomp-violation",
+            EventKind::CapabilityAbuse => "capability-abuse",
+            EventKind::MountChange => "mount-change",
+            EventKind::Ptrace => "ptrace",
+            EventKind::HiddenFile => "hidden-file",
+            EventKind::MonitoringDegraded => "monitoring-degraded",
+            EventKind::CpuSpike => "cpu-spike",
+            EventKind::IoSpike => "io-spike",
+            EventKind::NetClassChange => "net-class-change",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for EventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "new-process" => Ok(EventKind::NewProcess),
+            "process-exit" => Ok(EventKind::ProcessExit),
+            "namespace-escape" => Ok(EventKind::NamespaceEscape),
+            "privilege-escalation" => Ok(EventKind::PrivilegeEscalation),
+            "action-timeout" => Ok(EventKind::ActionTimeout),
+            "overload" => Ok(EventKind::Overload),
+            "file-write" => Ok(EventKind::FileWrite),
+            "correlated" => Ok(EventKind::Correlated),
+            "seccomp-violation" => Ok(EventKind::SeccompViolation),
+            "capability-abuse" => Ok(EventKind::CapabilityAbuse),
+            "mount-change" => Ok(EventKind::MountChange),
+            "ptrace" => Ok(EventKind::Ptrace),
+            "hidden-file" => Ok(EventKind::HiddenFile),
+            "monitoring-degraded" => Ok(EventKind::MonitoringDegraded),
+            "cpu-spike" => Ok(EventKind::CpuSpike),
+            "io-spike" => Ok(EventKind::IoSpike),
+            "net-class-change" => Ok(EventKind::NetClassChange),
+            _ => Err(format!("Unknown event kind: {}", s)),
+        }
+    }
+}
+
+// How events are written to stdout by the daemon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    // Human-readable log lines.
+    Text,
+    // One DetectionEvent JSON object per line; log lines move to stderr.
+    Ndjson,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _ => Err(format!("Unknown report format: {}", s)),
+        }
+    }
+}
+
+// Exit events carry the same fields as detections and differ only in `kind`.
+pub type ProcessExitEvent = DetectionEvent;
+pub type NamespaceEscapeEvent = DetectionEvent;
+pub type PrivilegeEscalationEvent = DetectionEvent;
+pub type ActionTimeoutEvent = DetectionEvent;
+pub type OverloadEvent = DetectionEvent;
+pub type FileWriteEvent = DetectionEvent;
+pub type CorrelatedDetectionEvent = DetectionEvent;
+pub type SeccompViolationEvent = DetectionEvent;
+pub type CapabilityAbuseEvent = DetectionEvent;
+pub type MountChangeEvent = DetectionEvent;
+pub type PtraceEvent = DetectionEvent;
+pub type HiddenFileEvent = DetectionEvent;
+pub type MonitoringDegradedEvent = DetectionEvent;
+pub type CpuSpikeEvent = DetectionEvent;
+pub type IoSpikeEvent = DetectionEvent;
+pub type NetClassChangeEvent = DetectionEvent;
+
+#[derive(Debug, Clone)]
+pub struct DetectionEvent {
+    pub id: String,
+    pub kind: EventKind,
+    pub detected_at: String,
+    pub container_id: String,
+    pub pid: i32,
+    pub exe: Option<String>,
+    pub cmdline: Option<String>,
+    pub detection_latency_ms: Option<f64>,
+    pub action: Action,
+    // HIGH/CRITICAL findings from the Trivy scan, when one was run.
+    pub vulnerabilities: Option<Vec<Vulnerability>>,
+    // Files written while investigating the detection.
+    pub forensic_artifacts: Vec<String>,
+    pub network_connections: Vec<NetSocket>,
+    pub open_fds: u32,
+    // Ancestors of the process, nearest first.
+    pub process_lineage: Vec<ProcessInfo>,
+    // Container memory usage was above 80% of memory.max when the process appeared.
+    pub memory_pressure: bool,
+    // The process appeared shortly after an OOM kill in the container.
+    pub after_oom_kill: bool,
+    // Networks the container was disconnected from while it was acted on, with
+    // --isolate-on-detection or the isolate action.
+    pub isolated_networks: Vec<String>,
+    // The container that replaced this one, after a rollback.
+    pub rolled_back_to: Option<String>,
+    // With --track-process-working-directory, the working directory of the process and
+    // whether it is under one of --sensitive-cwd-paths.
+    pub process_cwd: Option<PathBuf>,
+    pub suspicious_cwd: bool,
+    // Where the CRIU checkpoint taken before stopping the container was stored.
+    pub checkpoint_dir: Option<String>,
+    // CPU usage sampled over 100ms, when --alert-threshold-cpu-percent is set.
+    pub cpu_percent: Option<f64>,
+    // User namespace inodes of the process and of the container's init process,
+    // set on namespace-escape events.
+    pub user_namespace: Option<u64>,
+    pub container_user_namespace: Option<u64>,
+    // The container was frozen while the details above were collected.
+    pub frozen: bool,
+    // How long the container stayed frozen or paused for this detection.
+    pub pause_duration_ms: Option<u64>,
+    // The tenant owning the container, in multi-tenant mode.
+    pub tenant: Option<String>,
+    // Result of --verify-signatures for the container's image.
+    pub image_signature_status: Option<SignatureStatus>,
+    // Sent by `cnpd-ctl test-webhook`; describes no real process.
+    pub is_test: bool,
+    // The container group the container belongs to, and the group's other
+    // containers being monitored when the event happened.
+    pub group_name: Option<String>,
+    pub group_peer_containers: Vec<String>,
+    // Effective UIDs of the process and its parent, set on privilege-escalation events.
+    pub effective_uid: Option<u32>,
+    pub parent_effective_uid: Option<u32>,
+    // Effective capabilities of the process that the container's init process does not
+    // have, set on capability-abuse events.
+    pub extra_capabilities: Vec<String>,
+    // The container's risk assessment from when its monitoring started.
+    pub risk_score: Option<u32>,
+    pub risk_factors: Vec<String>,
+    // Processes in the container, and how fast their number grew in PIDs per second,
+    // set on overload events.
+    pub process_count: Option<u32>,
+    pub process_growth_rate: Option<f64>,
+    // With --process-count-baseline-file, the container's usual process count and its
+    // standard deviation that process_count was far above.
+    pub process_count_mean: Option<f64>,
+    pub process_count_stddev: Option<f64>,
+    // The written file inside the container, and where it is stored in the overlay
+    // upperdir on the host, set on file-write events. Hidden-file events set the path the
+    // unlinked file had.
+    pub file_path: Option<String>,
+    pub file_host_path: Option<String>,
+    // The grouped detections, in the order they happened, and how many there were,
+    // set on correlated events.
+    pub correlated_events: Vec<DetectionEvent>,
+    pub correlated_count: Option<u32>,
+    // The blocked syscall by name (or number, when unknown), the seccomp action taken on
+    // it, and the ID of the new-process detection of the same PID, if there was one,
+    // set on seccomp-violation events (and the related ID on ptrace and hidden-file events).
+    pub syscall: Option<String>,
+    pub seccomp_action: Option<String>,
+    pub related_event_id: Option<String>,
+    // The new mount's source (with the bound subtree for bind mounts), where it was
+    // mounted, its options, and whether it hides a --sensitive-mount-paths entry, set on
+    // mount-change events.
+    pub new_source: Option<String>,
+    pub new_target: Option<String>,
+    pub mount_flags: Option<String>,
+    pub shadows_sensitive_path: Option<bool>,
+    // With --detect-time-of-day-anomalies, the process appeared at an hour of the day
+    // the container had no new processes at over the past week.
+    pub high_anomaly: bool,
+    // ptrace events; the tracer is also `pid`.
+    pub tracer_pid: Option<i32>,
+    pub tracee_pid: Option<i32>,
+    pub ptrace_request: Option<String>,
+    // The fd and size of the unlinked file, whose path is file_path, set on hidden-file
+    // events.
+    pub hidden_fd: Option<i32>,
+    pub hidden_file_size: Option<u64>,
+    // The task's health score, set on monitoring-degraded events.
+    pub health_score: Option<u32>,
+    // CPU microseconds or I/O bytes per second and the usual rate, set on cpu-spike and
+    // io-spike events.
+    pub usage_rate: Option<f64>,
+    pub usage_baseline: Option<f64>,
+    // The class before and after, set on net-class-change events.
+    pub previous_net_classid: Option<u32>,
+    pub net_classid: Option<u32>,
+    // Docker labels of the container matching --include-label-prefixes, with
+    // --container-labels-in-events.
+    pub container_labels: HashMap<String, String>,
+}
+
+impl DetectionEvent {
+    pub fn new(container_id: &str, pid: i32, detected_at: DateTime<Local>) -> Self {
+        DetectionEvent {
+            id: format!("{}-{}-{}", docker::short_id(container_id), pid, detected_at.timestamp_millis()),
+            kind: EventKind::NewProcess,
+            detected_at: detected_at.format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            container_id: container_id.to_string(),
+            pid,
+            exe: None,
+            cmdline: None,
+            detection_latency_ms: None,
+            action: Action::LogOnly,
+            vulnerabilities: None,
+            forensic_artifacts: Vec::new(),
+            network_connections: Vec::new(),
+            open_fds: 0,
+            process_lineage: Vec::new(),
+            memory_pressure: false,
+            after_oom_kill: false,
+            checkpoint_dir: None,
+            cpu_percent: None,
+            user_namespace: None,
+            container_user_namespace: None,
+            frozen: false,
+            pause_duration_ms: None,
+            tenant: None,
+            image_signature_status: None,
+            is_test: false,
+            group_name: None,
+            group_peer_containers: Vec::new(),
+            effective_uid: None,
+            parent_effective_uid: None,
+            extra_capabilities: Vec::new(),
+            isolated_networks: Vec::new(),
+            rolled_back_to: None,
+            process_cwd: None,
+            suspicious_cwd: false,
+            risk_score: None,
+            risk_factors: Vec::new(),
+            process_count: None,
+            process_growth_rate: None,
+            process_count_mean: None,
+            process_count_stddev: None,
+            file_path: None,
+            file_host_path: None,
+            correlated_events: Vec::new(),
+            correlated_count: None,
+            syscall: None,
+            seccomp_action: None,
+            related_event_id: None,
+            new_source: None,
+            new_target: None,
+            mount_flags: None,
+            shadows_sensitive_path: None,
+            high_anomaly: false,
+            tracer_pid: None,
+            tracee_pid: None,
+            ptrace_request: None,
+            hidden_fd: None,
+            hidden_file_size: None,
+            health_score: None,
+            usage_rate: None,
+            usage_baseline: None,
+            previous_net_classid: None,
+            net_classid: None,
+            container_labels: HashMap::new(),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("id".to_string(), self.id.as_str().into()),
+            ("kind".to_string(), self.kind.to_string().into()),
+            ("detected_at".to_string(), self.detected_at.as_str().into()),
+            ("container_id".to_string(), self.container_id.as_str().into()),
+            ("container_id_short".to_string(), docker::short_id(&self.container_id).into()),
+            ("pid".to_string(), self.pid.into()),
+            ("exe".to_string(), self.exe.clone().into()),
+            ("cmdline".to_string(), self.cmdline.clone().into()),
+            ("detection_latency_ms".to_string(), self.detection_latency_ms.into()),
+            ("action".to_string(), self.action.to_string().into()),
+            (
+                "vulnerabilities".to_string(),
+                self.vulnerabilities
+                    .as_ref()
+                    .map(|v| Value::Array(v.iter().map(Vulnerability::to_json).collect()))
+                    .unwrap_or(Value::Null),
+            ),
+            ("forensic_artifacts".to_string(), self.forensic_artifacts.clone().into()),
+            (
+                "network_connections".to_string(),
+                Value::Array(self.network_connections.iter().map(NetSocket::to_json).collect()),
+            ),
+            ("open_fds".to_string(), self.open_fds.into()),
+            (
+                "process_lineage".to_string(),
+                Value::Array(self.process_lineage.iter().map(ProcessInfo::to_json).collect()),
+            ),
+            ("memory_pressure".to_string(), self.memory_pressure.into()),
+            ("after_oom_kill".to_string(), self.after_oom_kill.into()),
+            ("checkpoint_dir".to_string(), self.checkpoint_dir.clone().into()),
+            ("cpu_percent".to_string(), self.cpu_percent.into()),
+            ("user_namespace".to_string(), self.user_namespace.into()),
+            ("container_user_namespace".to_string(), self.container_user_namespace.into()),
+            ("frozen".to_string(), self.frozen.into()),
+            ("pause_duration_ms".to_string(), self.pause_duration_ms.into()),
+            ("tenant".to_string(), self.tenant.clone().into()),
+            (
+                "image_signature_status".to_string(),
+                self.image_signature_status.map(|s| s.to_string()).into(),
+            ),
+            ("is_test".to_string(), self.is_test.into()),
+            ("group_name".to_string(), self.group_name.clone().into()),
+            ("group_peer_containers".to_string(), self.group_peer_containers.clone().into()),
+            ("effective_uid".to_string(), self.effective_uid.into()),
+            ("parent_effective_uid".to_string(), self.parent_effective_uid.into()),
+            ("extra_capabilities".to_string(), self.extra_capabilities.clone().into()),
+            ("isolated_networks".to_string(), self.isolated_networks.clone().into()),
+            ("rolled_back_to".to_string(), self.rolled_back_to.clone().into()),
+            (
+                "process_cwd".to_string(),
+                self.process_cwd.as_ref().map(|cwd| cwd.to_string_lossy().into_owned()).into(),
+            ),
+            ("suspicious_cwd".to_string(), self.suspicious_cwd.into()),
+            ("risk_score".to_string(), self.risk_score.into()),
+            ("risk_factors".to_string(), self.risk_factors.clone().into()),
+            ("process_count".to_string(), self.process_count.into()),
+            ("process_growth_rate".to_string(), self.process_growth_rate.into()),
+            ("process_count_mean".to_string(), self.process_count_mean.into()),
+            ("process_count_stddev".to_string(), self.process_count_stddev.into()),
+            ("file_path".to_string(), self.file_path.clone().into()),
+            ("file_host_path".to_string(), self.file_host_path.clone().into()),
+            (
+                "correlated_events".to_string(),
+                Value::Array(self.correlated_events.iter().map(DetectionEvent::to_json).collect()),
+            ),
+            ("correlated_count".to_string(), self.correlated_count.into()),
+            ("syscall".to_string(), self.syscall.clone().into()),
+            ("seccomp_action".to_string(), self.seccomp_action.clone().into()),
+            ("related_event_id".to_string(), self.related_event_id.clone().into()),
+            ("new_source".to_string(), self.new_source.clone().into()),
+            ("new_target".to_string(), self.new_target.clone().into()),
+            ("mount_flags".to_string(), self.mount_flags.clone().into()),
+            ("shadows_sensitive_path".to_string(), self.shadows_sensitive_path.into()),
+            ("high_anomaly".to_string(), self.high_anomaly.into()),
+            ("tracer_pid".to_string(), self.tracer_pid.into()),
+            ("tracee_pid".to_string(), self.tracee_pid.into()),
+            ("ptrace_request".to_string(), self.ptrace_request.clone().into()),
+            ("hidden_fd".to_string(), self.hidden_fd.into()),
+            ("hidden_file_size".to_string(), self.hidden_file_size.into()),
+            ("health_score".to_string(), self.health_score.into()),
+            ("usage_rate".to_string(), self.usage_rate.into()),
+            ("usage_baseline".to_string(), self.usage_baseline.into()),
+            ("previous_net_classid".to_string(), self.previous_net_classid.into()),
+            ("net_classid".to_string(), self.net_classid.into()),
+            ("container_labels".to_string(), {
+                let mut labels: Vec<(String, Value)> =
+                    self.container_labels.iter().map(|(key, value)| (key.clone(), value.as_str().into())).collect();
+                labels.sort_by(|a, b| a.0.cmp(&b.0));
+                Value::Object(labels)
+            }),
+        ])
+    }
+
+    pub fn from_json(value: &Value) -> Result<DetectionEvent, String> {
+        let string = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+
+        Ok(DetectionEvent {
+            id: string("id").unwrap_or_default(),
+            kind: match string("kind") {
+                Some(kind) => kind.parse()?,
+                None => EventKind::NewProcess,
+            },
+            detected_at: string("detected_at").unwrap_or_default(),
+            container_id: string("container_id").ok_or("Missing container_id")?,
+            pid: value.get("pid").and_then(Value::as_i64).ok_or("Missing pid")? as i32,
+            exe: string("exe"),
+            cmdline: string("cmdline"),
+            detection_latency_ms: value.get("detection_latency_ms").and_then(Value::as_f64),
+            action: string("action").ok_or("Missing action")?.parse()?,
+            vulnerabilities: value
+                .get("vulnerabilities")
+                .and_then(Value::as_array)
+                .map(|v| v.iter().filter_map(Vulnerability::from_json).collect()),
+            forensic_artifacts: value
+                .get("forensic_artifacts")
+                .and_then(Value::as_array)
+                .map(|v| v.iter().filter_map(Value::as_str).map(str::to_string).collect())
+                .unwrap_or_default(),
+            network_connections: value
+                .get("network_connections")
+                .and_then(Value::as_array)
+                .map(|v| v.iter().filter_map(NetSocket::from_json).collect())
+                .unwrap_or_default(),
+            open_fds: value.get("open_fds").and_then(Value::as_i64).unwrap_or(0) as u32,
+            process_lineage: value
+                .get("process_lineage")
+                .and_then(Value::as_array)
+                .map(|v| v.iter().filter_map(ProcessInfo::from_json).collect())
+                .unwrap_or_default(),
+            memory_pressure: value.get("memory_pressure").and_then(Value::as_bool).unwrap_or(false),
+            after_oom_kill: value.get("after_oom_kill").and_then(Value::as_bool).unwrap_or(false),
+            checkpoint_dir: string("checkpoint_dir"),
+            cpu_percent: value.get("cpu_percent").and_then(Value::as_f64),
+            user_namespace: value.get("user_namespace").and_then(Value::as_i64).map(|v| v as u64),
+            container_user_namespace: value
+                .get("container_user_namespace")
+                .and_then(Value::as_i64)
+                .map(|v| v as u64),
+            frozen: value.get("frozen").and_then(Value::as_bool).unwrap_or(false),
+            pause_duration_ms: value.get("pause_duration_ms").and_then(Value::as_i64).map(|v| v as u64),
+            tenant: string("tenant"),
+            image_signature_status: string("image_signature_status").and_then(|s| s.parse().ok()),
+            is_test: value.get("is_test").and_then(Value::as_bool).unwrap_or(false),
+            group_name: string("group_name"),
+            group_peer_containers: value
+                .get("group_peer_containers")
+                .and_then(Value::as_array)
+                .map(|v| v.iter().filter_map(Value::as_str).map(str::to_string).collect())
+                .unwrap_or_default(),
+            effective_uid: value.get("effective_uid").and_then(Value::as_i64).map(|v| v as u32),
+            parent_effective_uid: value.get("parent_effective_uid").and_then(Value::as_i64).map(|v| v as u32),
+            extra_capabilities: value
+                .get("extra_capabilities")
+                .and_then(Value::as_array)
+                .map(|v| v.iter().filter_map(Value::as_str).map(str::to_string).collect())
+                .unwrap_or_default(),
+            rolled_back_to: string("rolled_back_to"),
+            isolated_networks: value
+                .get("isolated_networks")
+                .and_then(Value::as_array)
+                .map(|v| v.iter().filter_map(Value::as_str).map(str::to_string).collect())
+                .unwrap_or_default(),
+            process_cwd: string("process_cwd").map(PathBuf::from),
+            suspicious_cwd: value.get("suspicious_cwd").and_then(Value::as_bool).unwrap_or(false),
+            risk_score: value.get("risk_score").and_then(Value::as_i64).map(|v| v as u32),
+            risk_factors: value
+                .get("risk_factors")
+                .and_then(Value::as_array)
+                .map(|v| v.iter().filter_map(Value::as_str).map(str::to_string).collect())
+                .unwrap_or_default(),
+            process_count: value.get("process_count").and_then(Value::as_i64).map(|v| v as u32),
+            process_growth_rate: value.get("process_growth_rate").and_then(Value::as_f64),
+            process_count_mean: value.get("process_count_mean").and_then(Value::as_f64),
+            process_count_stddev: value.get("process_count_stddev").and_then(Value::as_f64),
+            file_path: string("file_path"),
+            file_host_path: string("file_host_path"),
+            correlated_events: match value.get("correlated_events").and_then(Value::as_array) {
+                Some(events) => events.iter().map(DetectionEvent::from_json).collect::<Result<_, _>>()?,
+                None => Vec::new(),
+            },
+            correlated_count: value.get("correlated_count").and_then(Value::as_i64).map(|v| v as u32),
+            syscall: string("syscall"),
+            seccomp_action: string("seccomp_action"),
+            related_event_id: string("related_event_id"),
+            new_source: string("new_source"),
+            new_target: string("new_target"),
+            mount_flags: string("mount_flags"),
+            shadows_sensitive_path: value.get("shadows_sensitive_path").and_then(Value::as_bool),
+            high_anomaly: value.get("high_anomaly").and_then(Value::as_bool).unwrap_or(false),
+            tracer_pid: value.get("tracer_pid").and_then(Value::as_i64).map(|v| v as i32),
+            tracee_pid: value.get("tracee_pid").and_then(Value::as_i64).map(|v| v as i32),
+            ptrace_request: string("ptrace_request"),
+            hidden_fd: value.get("hidden_fd").and_then(Value::as_i64).map(|fd| fd as i32),
+            hidden_file_size: value.get("hidden_file_size").and_then(Value::as_i64).map(|size| size as u64),
+            health_score: value.get("health_score").and_then(Value::as_i64).map(|score| score as u32),
+            usage_rate: value.get("usage_rate").and_then(Value::as_f64),
+            usage_baseline: value.get("usage_baseline").and_then(Value::as_f64),
+            previous_net_classid: value.get("previous_net_classid").and_then(Value::as_i64).map(|id| id as u32),
+            net_classid: value.get("net_classid").and_then(Value::as_i64).map(|id| id as u32),
+            container_labels: match value.get("container_labels") {
+                Some(Value::Object(labels)) => labels
+                    .iter()
+                    .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+                    .collect(),
+                _ => HashMap::new(),
+            },
+        })
+    }
+
+    // An obviously fake detection for checking that an event receiver is reachable.
+    pub fn synthetic(detected_at: DateTime<Local>) -> DetectionEvent {
+        let mut event = DetectionEvent::new("test-container-cnpd", 99999, detected_at);
+        event.exe = Some("/usr/bin/cnpd-test".to_string());
+        event.cmdline = Some("cnpd-test --synthetic-event".to_string());
+        event.is_test = true;
+        event
+    }
+
+    // Reported against the container's init process; only ever logged.
+    pub fn overload(container_id: &str, init_pid: i32, process_count: usize, detected_at: DateTime<Local>) -> OverloadEvent {
+        let mut event = DetectionEvent::new(container_id, init_pid, detected_at);
+        event.id.push_str("-overload");
+        event.kind = EventKind::Overload;
+        event.process_count = Some(process_count as u32);
+        event
+    }
+
+    // Reported against the writing process.
+    pub fn file_write(container_id: &str, pid: i32, path: &str, detected_at: DateTime<Local>) -> FileWriteEvent {
+        let mut event = DetectionEvent::new(container_id, pid, detected_at);
+        event.id.push_str("-write");
+        event.kind = EventKind::FileWrite;
+        event.file_path = Some(path.to_string());
+        event
+    }
+
+    pub fn seccomp_violation(
+        container_id: &str,
+        pid: i32,
+        syscall: &str,
+        seccomp_action: &str,
+        detected_at: DateTime<Local>,
+    ) -> SeccompViolationEvent {
+        let mut event = DetectionEvent::new(container_id, pid, detected_at);
+        event.id.push_str("-seccomp");
+        event.kind = EventKind::SeccompViolation;
+        event.syscall = Some(syscall.to_string());
+        event.seccomp_action = Some(seccomp_action.to_string());
+        event
+    }
+
+    pub fn ptrace(container_id: &str, tracer_pid: i32, tracee_pid: i32, request: &str, detected_at: DateTime<Local>) -> PtraceEvent {
+        let mut event = DetectionEvent::new(container_id, tracer_pid, detected_at);
+        event.id.push_str(&format!("-ptrace{}", tracee_pid));
+        event.kind = EventKind::Ptrace;
+        event.tracer_pid = Some(tracer_pid);
+        event.tracee_pid = Some(tracee_pid);
+        event.ptrace_request = Some(request.to_string());
+        event
+    }
+
+    // Reported against the container's init process; only ever logged.
+    pub fn monitoring_degraded(container_id: &str, init_pid: i32, score: u32, detected_at: DateTime<Local>) -> MonitoringDegradedEvent {
+        let mut event = DetectionEvent::new(container_id, init_pid, detected_at);
+        event.id.push_str("-degraded");
+        event.kind = EventKind::MonitoringDegraded;
+        event.health_score = Some(score);
+        event
+    }
+
+    // Reported against the container's init process, like overload events.
+    pub fn subsystem_change(container_id: &str, init_pid: i32, change: &SubsystemChange, detected_at: DateTime<Local>) -> DetectionEvent {
+        let mut event = DetectionEvent::new(container_id, init_pid, detected_at);
+        let (kind, suffix) = match change {
+            SubsystemChange::CpuSpike { .. } => (EventKind::CpuSpike, "-cpu"),
+            SubsystemChange::IoSpike { .. } => (EventKind::IoSpike, "-io"),
+            SubsystemChange::NetClassChange { .. } => (EventKind::NetClassChange, "-netcls"),
+        };
+        event.kind = kind;
+        event.id.push_str(suffix);
+        match *change {
+            SubsystemChange::CpuSpike { rate, baseline } | SubsystemChange::IoSpike { rate, baseline } => {
+                event.usage_rate = Some(rate);
+                event.usage_baseline = Some(baseline);
+            }
+            SubsystemChange::NetClassChange { from, to } => {
+                event.previous_net_classid = Some(from);
+                event.net_classid = Some(to);
+            }
+        }
+        event
+    }
+
+    // Reported against the process holding the file open.
+    pub fn hidden_file(container_id: &str, pid: i32, file: &DeletedFile, detected_at: DateTime<Local>) -> HiddenFileEvent {
+        let mut event = DetectionEvent::new(container_id, pid, detected_at);
+        event.id.push_str(&format!("-hidden{}", file.fd));
+        event.kind = EventKind::HiddenFile;
+        event.file_path = Some(file.path.clone());
+        event.hidden_fd = Some(file.fd);
+        event.hidden_file_size = file.size;
+        event
+    }
+
+    // Reported against the container's init process, whose mount namespace changed.
+    pub fn mount_change(container_id: &str, init_pid: i32, mount: &MountEntry, shadows: bool, detected_at: DateTime<Local>) -> MountChangeEvent {
+        let mut event = DetectionEvent::new(container_id, init_pid, detected_at);
+        event.id.push_str(&format!("-mount{}", mount.mount_id));
+        event.kind = EventKind::MountChange;
+        event.new_source = Some(mount.describe_source());
+        event.new_target = Some(mount.target.clone());
+        event.mount_flags = Some(mount.options.clone());
+        event.shadows_sensitive_path = Some(shadows);
+        event
+    }
+
+    // Groups a burst of detections, which must not be empty, under the first of them.
+    // `action` is the one taken for the whole burst.
+    pub fn correlated(events: Vec<DetectionEvent>, action: Action) -> CorrelatedDetectionEvent {
+        let first = &events[0];
+        let mut event = DetectionEvent {
+            id: format!("{}-correlated", first.id),
+            kind: EventKind::Correlated,
+            action,
+            correlated_count: Some(events.len() as u32),
+            ..DetectionEvent::new(&first.container_id, first.pid, Local::now())
+        };
+        event.detected_at = first.detected_at.clone();
+        event.exe = first.exe.clone();
+        event.cmdline = first.cmdline.clone();
+        event.tenant = first.tenant.clone();
+        event.group_name = first.group_name.clone();
+        event.risk_score = first.risk_score;
+        event.risk_factors = first.risk_factors.clone();
+        event.correlated_events = events;
+        event
+    }
+
+    pub fn process_exit(container_id: &str, pid: i32, exited_at: DateTime<Local>) -> ProcessExitEvent {
+        let mut event = DetectionEvent::new(container_id, pid, exited_at);
+        event.id.push_str("-exit");
+        event.kind = EventKind::ProcessExit;
+        event
+    }
+
+    // Turns a detection into a namespace escape, which is always acted on with the
+    // most severe action whatever the policy says.
+    pub fn into_namespace_escape(mut self, user_namespace: u64, container_user_namespace: u64) -> NamespaceEscapeEvent {
+        self.id.push_str("-userns");
+        self.kind = EventKind::NamespaceEscape;
+        self.action = Action::Stop;
+        self.user_namespace = Some(user_namespace);
+        self.container_user_namespace = Some(container_user_namespace);
+        self
+    }
+
+    // Turns a detection into a privilege escalation, acted on with `action` whatever
+    // the policy says.
+    pub fn into_privilege_escalation(mut self, effective_uid: u32, parent_effective_uid: u32, action: Action) -> PrivilegeEscalationEvent {
+        self.id.push_str("-privesc");
+        self.kind = EventKind::PrivilegeEscalation;
+        self.action = action;
+        self.effective_uid = Some(effective_uid);
+        self.parent_effective_uid = Some(parent_effective_uid);
+        self
+    }
+
+    // Keeps the action the policy chose for the process.
+    pub fn into_capability_abuse(mut self, extra_capabilities: Vec<String>) -> CapabilityAbuseEvent {
+        self.id.push_str("-capabuse");
+        self.kind = EventKind::CapabilityAbuse;
+        self.extra_capabilities = extra_capabilities;
+        self
+    }
+
+    // Records that acting on this event timed out and `init_pid` was killed instead.
+    pub fn into_action_timeout(mut self, init_pid: i32) -> ActionTimeoutEvent {
+        self.id.push_str("-timeout");
+        self.kind = EventKind::ActionTimeout;
+        self.action = Action::Kill;
+        self.pid = init_pid;
+        self
+    }
+
+    pub fn parse_line(line: &str) -> Result<DetectionEvent, String> {
+        DetectionEvent::from_json(&json::parse(line)?)
+    }
+}
+
+// The --output-file log held open between events, for --log-rotate-on-sighup.
+pub struct EventLog {
+    path: String,
+    file: Mutex<File>,
+}
+
+impl EventLog {
+    pub async fn open(path: &str) -> std::io::Result<EventLog> {
+        Ok(EventLog {
+            path: path.to_string(),
+            file: Mutex::new(open_append(path).await?),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub async fn append(&self, event: &DetectionEvent) -> std::io::Result<()> {
+        let line = format!("{}\n", event.to_json());
+        self.file.lock().await.write_all(line.as_bytes()).await
+    }
+
+    // Switches to a new file at the same path once logrotate has moved the old one
+    // away. Events arriving meanwhile wait on the lock and go to the new file; if
+    // the new file cannot be opened, the old one stays in use.
+    pub async fn reopen(&self) -> std::io::Result<()> {
+        let mut file = self.file.lock().await;
+        file.flush().await?;
+        *file = open_append(&self.path).await?;
+        Ok(())
+    }
+}
+
+async fn open_append(path: &str) -> std::io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path).await
+}
+
+pub async fn append_event(path: &str, event: &DetectionEvent) -> std::io::Result<()> {
+    let mut file = open_append(path).await?;
+    file.write_all(format!("{}\n", event.to_json()).as_bytes()).await
+}
+
+pub async fn load_events(path: &str) -> Result<Vec<DetectionEvent>, Box<dyn std::error::Error>> {
+    let content = tokio::fs::read_to_string(path).await?;
+    let mut events = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match DetectionEvent::parse_line(line) {
+            Ok(event) => events.push(event),
+            Err(e) => eprintln!("Skipping {} line {}: {}", path, number + 1, e),
+        }
+    }
+    Ok(events)
+}
@@ -0,0 +1,147 @@
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Instant};
+
+use crate::metrics::Metrics;
+
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+// What a full buffer does with a new event, from --events-buffer-overflow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferOverflow {
+    // The event is lost and counted in cnpd_events_dropped_total.
+    Drop,
+    // The monitoring task waits for room, and polls the container later.
+    Backpressure,
+}
+
+impl FromStr for BufferOverflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop" => Ok(BufferOverflow::Drop),
+            "backpressure" => Ok(BufferOverflow::Backpressure),
+            _ => Err(format!("Unknown events buffer overflow behavior: {}", s)),
+        }
+    }
+}
+
+// The bounded queue between the monitoring tasks that detect events and the task
+// writing them to the sinks, so that a slow sink under a burst of detections fills at
+// most --events-buffer-size events of memory.
+pub struct EventBuffer<T> {
+    tx: mpsc::Sender<T>,
+    overflow: BufferOverflow,
+    metrics: Arc<Metrics>,
+    // Events queued or being delivered.
+    pending: Arc<AtomicUsize>,
+}
+
+pub struct EventReceiver<T> {
+    rx: mpsc::Receiver<T>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl<T> EventBuffer<T> {
+    pub fn new(capacity: usize, overflow: BufferOverflow, metrics: Arc<Metrics>) -> (EventBuffer<T>, EventReceiver<T>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let pending = Arc::new(AtomicUsize::new(0));
+        let buffer = EventBuffer {
+            tx,
+            overflow,
+            metrics,
+            pending: pending.clone(),
+        };
+        (buffer, EventReceiver { rx, pending })
+    }
+
+    pub async fn push(&self, event: T) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        let sent = match self.overflow {
+            BufferOverflow::Drop => match self.tx.try_send(event) {
+                Ok(()) => true,
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    self.metrics.record_dropped_event();
+                    false
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => false,
+            },
+            BufferOverflow::Backpressure => self.tx.send(event).await.is_ok(),
+        };
+        if !sent {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    // Waits up to `timeout` for every queued event to be delivered, on shutdown.
+    // Returns whether the buffer emptied.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.pending.load(Ordering::SeqCst) > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        true
+    }
+}
+
+impl<T> EventReceiver<T> {
+    // Hands the events to `deliver` one at a time, in the order they were pushed,
+    // until every EventBuffer is gone.
+    pub async fn run<F, Fut>(mut self, mut deliver: F)
+    where
+        F: FnMut(T) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        while let Some(event) = self.rx.recv().await {
+            deliver(event).await;
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn drops_events_when_full() {
+        let metrics = Arc::new(Metrics::default());
+        let (buffer, receiver) = EventBuffer::new(2, BufferOverflow::Drop, metrics.clone());
+        for event in 0..5 {
+            buffer.push(event).await;
+        }
+        assert_eq!(metrics.events_dropped_total(), 3);
+
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        let sink = delivered.clone();
+        tokio::spawn(receiver.run(move |event| {
+            sink.lock().unwrap().push(event);
+            async {}
+        }));
+        assert!(buffer.drain(Duration::from_secs(5)).await);
+        assert_eq!(*delivered.lock().unwrap(), [0, 1]);
+    }
+
+    #[tokio::test]
+    async fn backpressure_waits_for_room() {
+        let metrics = Arc::new(Metrics::default());
+        let (buffer, receiver) = EventBuffer::new(1, BufferOverflow::Backpressure, metrics.clone());
+        tokio::spawn(receiver.run(|_| sleep(Duration::from_millis(20))));
+        let started = Instant::now();
+        for event in 0..4 {
+            buffer.push(event).await;
+        }
+        assert!(started.elapsed() >= Duration::from_millis(40));
+        assert!(buffer.drain(Duration::from_secs(5)).await);
+        assert_eq!(metrics.events_dropped_total(), 0);
+    }
+}
@@ -0,0 +1,73 @@
+use chrono::{SecondsFormat, Utc};
+
+use crate::event::DetectionEvent;
+use crate::json::Value;
+use crate::syslog;
+use crate::webhook;
+
+// Falco priorities, indexed by syslog severity.
+const PRIORITIES: [&str; 8] = ["Emergency", "Alert", "Critical", "Error", "Warning", "Notice", "Informational", "Debug"];
+
+// Sends events to Falco's alert stream, for --report-to-falco. Falco's gRPC API only
+// streams its own alerts out and has no call for submitting them, so events go to a
+// falcosidekick HTTP endpoint instead, in the JSON Falco itself writes with
+// json_output. They then reach the same outputs as Falco's own detections.
+pub struct FalcoReporter {
+    url: String,
+    hostname: String,
+}
+
+impl FalcoReporter {
+    pub fn new(url: &str) -> FalcoReporter {
+        FalcoReporter {
+            url: url.to_string(),
+            hostname: syslog::hostname(),
+        }
+    }
+
+    // Posts from a separate task, like the tenant webhooks; failures are only logged.
+    pub fn send(&self, event: &DetectionEvent) {
+        let url = self.url.clone();
+        let body = format_alert(event, &self.hostname).to_string();
+        tokio::spawn(async move {
+            match webhook::post_json(&url, &body).await.map_err(|e| e.to_string()) {
+                Ok(response) if response.is_success() => {}
+                Ok(response) => eprintln!("Falco endpoint {} answered with HTTP {}", url, response.status),
+                Err(e) => eprintln!("Failed to send event to Falco: {}", e),
+            }
+        });
+    }
+}
+
+pub fn format_alert(event: &DetectionEvent, hostname: &str) -> Value {
+    let output = format!(
+        "cnpd {} (container_id={} proc.pid={} proc.exepath={} proc.cmdline={} action={})",
+        event.kind,
+        event.container_id,
+        event.pid,
+        event.exe.as_deref().unwrap_or("<NA>"),
+        event.cmdline.as_deref().unwrap_or("<NA>"),
+        event.action
+    );
+    Value::Object(vec![
+        ("hostname".to_string(), hostname.into()),
+        ("output".to_string(), output.into()),
+        ("priority".to_string(), PRIORITIES[syslog::severity(event) as usize].into()),
+        ("rule".to_string(), format!("cnpd {}", event.kind).into()),
+        ("source".to_string(), "cnpd".into()),
+        ("tags".to_string(), vec!["container", "process", "cnpd"].into()),
+        ("time".to_string(), Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true).into()),
+        (
+            "output_fields".to_string(),
+            Value::Object(vec![
+                ("container.id".to_string(), event.container_id.as_str().into()),
+                ("proc.pid".to_string(), event.pid.into()),
+                ("proc.exepath".to_string(), event.exe.clone().into()),
+                ("proc.cmdline".to_string(), event.cmdline.clone().into()),
+                ("cnpd.event_id".to_string(), event.id.as_str().into()),
+                ("cnpd.action".to_string(), event.action.to_string().into()),
+                ("cnpd.event".to_string(), event.to_json()),
+            ]),
+        ),
+    ])
+}
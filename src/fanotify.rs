@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+
+// A file in the container that was closed after being opened for writing.
+#[derive(Debug, Clone)]
+pub struct FileWrite {
+    // The writing process, in the host PID namespace.
+    pub pid: i32,
+    // Path of the file inside the container.
+    pub path: String,
+}
+
+// The upperdir of the overlay mounted as the container's root, from the
+// /proc/<pid>/mounts of one of its processes.
+pub fn overlay_upperdir(mounts: &str) -> Option<String> {
+    mounts.lines().find_map(|line| {
+        let mut fields = line.split(' ');
+        let (_, target, fstype, options) = (fields.next()?, fields.next()?, fields.next()?, fields.next()?);
+        if target != "/" || fstype != "overlay" {
+            return None;
+        }
+        options.split(',').find_map(|option| option.strip_prefix("upperdir=")).map(unescape_mount_path)
+    })
+}
+
+// /proc/<pid>/mounts writes spaces, tabs, newlines and backslashes in paths as octal escapes.
+pub(crate) fn unescape_mount_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(i) = rest.find('\\') {
+        out.push_str(&rest[..i]);
+        match rest.get(i + 1..i + 4).and_then(|octal| u8::from_str_radix(octal, 8).ok()) {
+            Some(byte) => {
+                out.push(byte as char);
+                rest = &rest[i + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+pub async fn read_upperdir(pid: i32) -> Option<String> {
+    overlay_upperdir(&tokio::fs::read_to_string(format!("/proc/{}/mounts", pid)).await.ok()?)
+}
+
+// FAN_CLOSE_WRITE on the overlay mount that is the container's root. Overlayfs opens
+// the files in its upperdir without notifications, so a mark on the upperdir itself
+// would miss every write made through the container; the overlay mount is reached
+// through /proc/<init_pid>/root instead. Needs CAP_SYS_ADMIN.
+pub struct FileWriteWatcher {
+    fd: AsyncFd<OwnedFd>,
+    pending: VecDeque<FileWrite>,
+}
+
+impl FileWriteWatcher {
+    pub fn new(init_pid: i32) -> io::Result<FileWriteWatcher> {
+        let fd = unsafe {
+            libc::fanotify_init(
+                libc::FAN_CLASS_NOTIF | libc::FAN_NONBLOCK | libc::FAN_CLOEXEC,
+                (libc::O_RDONLY | libc::O_CLOEXEC | libc::O_LARGEFILE) as u32,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let root = CString::new(format!("/proc/{}/root", init_pid)).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let marked = unsafe {
+            libc::fanotify_mark(
+                fd.as_raw_fd(),
+                libc::FAN_MARK_ADD | libc::FAN_MARK_MOUNT,
+                libc::FAN_CLOSE_WRITE,
+                libc::AT_FDCWD,
+                root.as_ptr(),
+            )
+        };
+        if marked < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(FileWriteWatcher {
+            fd: AsyncFd::with_interest(fd, Interest::READABLE)?,
+            pending: VecDeque::new(),
+        })
+    }
+
+    // Waits for the next write. Writes by the detector itself are skipped.
+    pub async fn next(&mut self) -> io::Result<FileWrite> {
+        let own_pid = std::process::id() as i32;
+        loop {
+            if let Some(write) = self.pending.pop_front() {
+                return Ok(write);
+            }
+            let mut buf = [0u8; 4096];
+            let mut guard = self.fd.readable().await?;
+            let n = unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+            if n < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::WouldBlock {
+                    guard.clear_ready();
+                    continue;
+                }
+                return Err(err);
+            }
+            self.pending.extend(parse_events(&buf[..n as usize]).into_iter().filter(|write| write.pid != own_pid));
+        }
+    }
+}
+
+// Each event carries an open descriptor of the written file, closed here once its
+// path has been read back from /proc/self/fd.
+fn parse_events(mut buf: &[u8]) -> Vec<FileWrite> {
+    const HEADER: usize = mem::size_of::<libc::fanotify_event_metadata>();
+    let mut writes = Vec::new();
+    while buf.len() >= HEADER {
+        let event: libc::fanotify_event_metadata = unsafe { std::ptr::read_unaligned(buf.as_ptr().cast()) };
+        let len = (event.event_len as usize).clamp(HEADER, buf.len());
+        if event.fd >= 0 {
+            let fd = unsafe { OwnedFd::from_raw_fd(event.fd) };
+            if let Ok(path) = std::fs::read_link(format!("/proc/self/fd/{}", fd.as_raw_fd())) {
+                writes.push(FileWrite {
+                    pid: event.pid,
+                    path: path.to_string_lossy().into_owned(),
+                });
+            }
+        }
+        buf = &buf[len..];
+    }
+    writes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_upperdir_of_root_overlay() {
+        let mounts = "\
+overlay / overlay rw,relatime,lowerdir=/var/lib/docker/overlay2/l/A:/var/lib/docker/overlay2/l/B,upperdir=/var/lib/docker/overlay2/3f2a/diff,workdir=/var/lib/docker/overlay2/3f2a/work 0 0
+proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+overlay /data overlay rw,upperdir=/srv/other/diff,workdir=/srv/other/work 0 0
+";
+        assert_eq!(overlay_upperdir(mounts).as_deref(), Some("/var/lib/docker/overlay2/3f2a/diff"));
+        assert_eq!(overlay_upperdir("/dev/sda1 / ext4 rw 0 0\n"), None);
+        assert_eq!(unescape_mount_path("/srv/my\\040disk/diff"), "/srv/my disk/diff");
+    }
+}
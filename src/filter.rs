@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::event::DetectionEvent;
+use crate::json::Value;
+use crate::policy::SuppressRule;
+use crate::tenant::Access;
+
+// How many suppressed events are kept for `cnpd-ctl suppressed-events`.
+const MAX_SUPPRESSED_EVENTS: usize = 1000;
+
+struct RuleCount {
+    tenant: Option<String>,
+    rule: String,
+    count: u64,
+}
+
+struct Suppressed {
+    rule: String,
+    event: DetectionEvent,
+}
+
+// Keeps an audit trail of the detections that policy suppression rules silenced.
+// Counts cover every suppressed event; only the most recent events are kept in full.
+#[derive(Default)]
+pub struct EventFilter {
+    counts: Mutex<Vec<RuleCount>>,
+    recent: Mutex<VecDeque<Suppressed>>,
+}
+
+impl EventFilter {
+    pub fn record(&self, rule: &SuppressRule, event: DetectionEvent) {
+        let rule = rule.to_string();
+        let mut counts = self.counts.lock().unwrap();
+        match counts.iter_mut().find(|c| c.rule == rule && c.tenant == event.tenant) {
+            Some(c) => c.count += 1,
+            None => counts.push(RuleCount { tenant: event.tenant.clone(), rule: rule.clone(), count: 1 }),
+        }
+        drop(counts);
+
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == MAX_SUPPRESSED_EVENTS {
+            recent.pop_front();
+        }
+        recent.push_back(Suppressed { rule, event });
+    }
+
+    pub fn to_json(&self, access: &Access) -> Value {
+        let counts = self
+            .counts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|c| access.allows(c.tenant.as_deref()))
+            .map(|c| {
+                Value::Object(vec![
+                    ("rule".to_string(), c.rule.as_str().into()),
+                    ("tenant".to_string(), c.tenant.clone().into()),
+                    ("count".to_string(), c.count.into()),
+                ])
+            })
+            .collect();
+        let events = self
+            .recent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|s| access.allows(s.event.tenant.as_deref()))
+            .map(|s| {
+                let mut event = s.event.to_json();
+                if let Value::Object(fields) = &mut event {
+                    fields.push(("suppressed_by".to_string(), s.rule.as_str().into()));
+                }
+                event
+            })
+            .collect();
+        Value::Object(vec![
+            ("counts".to_string(), Value::Array(counts)),
+            ("events".to_string(), Value::Array(events)),
+        ])
+    }
+}
@@ -0,0 +1,152 @@
+use std::path::Path;
+use std::time::Duration;
+use chrono::Local;
+use tokio::fs;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use crate::{docker, procfs};
+
+pub const PROC_FILES: [&str; 3] = ["cmdline", "environ", "maps"];
+pub const DEFAULT_CORE_DIR: &str = "/var/lib/cnpd/cores";
+pub const DEFAULT_EVIDENCE_DIR: &str = "/var/lib/cnpd/evidence";
+
+// Copies /proc/<pid>/{cmdline,environ,maps} into <dir>/<event_id>/ and returns the
+// paths written. NUL separators are turned into newlines to keep the files readable.
+pub async fn collect(dir: &str, event_id: &str, pid: i32, limit: Duration) -> Vec<String> {
+    let event_dir = Path::new(dir).join(event_id);
+    if let Err(e) = fs::create_dir_all(&event_dir).await {
+        eprintln!("Failed to create forensics directory {}: {}", event_dir.display(), e);
+        return Vec::new();
+    }
+
+    let mut artifacts = Vec::new();
+    for file in PROC_FILES {
+        let content = match procfs::read_with_timeout(limit, pid, file, procfs::read_proc_file(pid, file)).await {
+            Ok(Some(content)) => content,
+            _ => continue,
+        };
+        let content: Vec<u8> = content
+            .into_iter()
+            .map(|b| if b == 0 { b'\n' } else { b })
+            .collect();
+
+        let path = event_dir.join(file);
+        match fs::write(&path, content).await {
+            Ok(()) => artifacts.push(path.to_string_lossy().into_owned()),
+            Err(e) => eprintln!("Failed to write {}: {}", path.display(), e),
+        }
+    }
+
+    artifacts
+}
+
+// Dumps the process's memory with `gcore -o <dir>/<container>-<pid>-<timestamp>` and
+// returns the core file's path. gcore appends the PID to the prefix it is given. A
+// missing gcore or a dump that outlasts `limit` only produces a warning.
+pub async fn core_dump(dir: &str, container_id: &str, pid: i32, limit: Duration) -> Option<String> {
+    if let Err(e) = fs::create_dir_all(dir).await {
+        eprintln!("Warning: failed to create core dump directory {}: {}", dir, e);
+        return None;
+    }
+    let prefix = Path::new(dir).join(format!(
+        "{}-{}-{}",
+        docker::short_id(container_id),
+        pid,
+        Local::now().format("%Y%m%dT%H%M%S")
+    ));
+    let child = Command::new("gcore")
+        .arg("-o")
+        .arg(&prefix)
+        .arg(pid.to_string())
+        .kill_on_drop(true)
+        .output();
+
+    match timeout(limit, child).await {
+        Ok(Ok(output)) if output.status.success() => Some(format!("{}.{}", prefix.display(), pid)),
+        Ok(Ok(output)) => {
+            eprintln!(
+                "Warning: gcore failed for PID {}: {}",
+                pid,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            None
+        }
+        Ok(Err(e)) => {
+            eprintln!("Warning: failed to run gcore for PID {}: {}", pid, e);
+            None
+        }
+        Err(_) => {
+            eprintln!("Warning: gcore for PID {} did not finish within {} ms", pid, limit.as_millis());
+            None
+        }
+    }
+}
+
+// The entries of `docker diff` nothing else was added below: the files written since
+// the container started, without the directories above them, which docker diff lists as
+// changed too.
+pub fn written_files(changed: &[String]) -> Vec<String> {
+    changed
+        .iter()
+        .filter(|path| {
+            let dir = format!("{}/", path.trim_end_matches('/'));
+            !changed.iter().any(|other| other.starts_with(&dir))
+        })
+        .cloned()
+        .collect()
+}
+
+// Copies files out of the container with `docker cp` into
+// <dir>/<container>-<timestamp>/, under their paths in the container, before a restart
+// discards its filesystem changes. Without `paths`, the files written since the
+// container started are copied. Returns the evidence directory, unless nothing could
+// be copied.
+pub async fn preserve_evidence(dir: &str, container_id: &str, paths: &[String]) -> Option<String> {
+    let paths = match paths.is_empty() {
+        true => match docker::changed_paths(container_id).await {
+            Ok(changed) => written_files(&changed),
+            Err(e) => {
+                eprintln!("Warning: failed to list the files written in {}: {}", docker::short_id(container_id), e);
+                return None;
+            }
+        },
+        false => paths.to_vec(),
+    };
+    let evidence_dir = Path::new(dir).join(format!(
+        "{}-{}",
+        docker::short_id(container_id),
+        Local::now().format("%Y%m%dT%H%M%S")
+    ));
+    let mut copied = 0;
+    for path in &paths {
+        let target = evidence_dir.join(path.trim_start_matches('/'));
+        if let Some(parent) = target.parent() {
+            if let Err(e) = fs::create_dir_all(parent).await {
+                eprintln!("Warning: failed to create evidence directory {}: {}", parent.display(), e);
+                return None;
+            }
+        }
+        match docker::copy_from(container_id, path, &target.to_string_lossy()).await {
+            Ok(()) => copied += 1,
+            Err(e) => eprintln!("Warning: failed to copy {} out of {}: {}", path, docker::short_id(container_id), e),
+        }
+    }
+    (copied > 0).then(|| evidence_dir.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_only_the_deepest_written_paths() {
+        let changed: Vec<String> = ["/tmp", "/tmp/.x", "/tmp/.x/miner", "/root", "/root/.bash_history", "/etc/passwd"]
+            .map(str::to_string)
+            .to_vec();
+        assert_eq!(written_files(&changed), ["/tmp/.x/miner", "/root/.bash_history", "/etc/passwd"]);
+        // A sibling sharing a prefix is not below the path.
+        let changed = vec!["/app".to_string(), "/app2".to_string()];
+        assert_eq!(written_files(&changed), ["/app", "/app2"]);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::json::Value;
+
+// The Prometheus data source is chosen when the dashboard is imported.
+const DATASOURCE: &str = "${DS_PROMETHEUS}";
+// Dashboard JSON model version of Grafana 8.
+const SCHEMA_VERSION: u32 = 30;
+
+fn object(fields: Vec<(&str, Value)>) -> Value {
+    Value::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+}
+
+fn datasource() -> Value {
+    object(vec![("type", "prometheus".into()), ("uid", DATASOURCE.into())])
+}
+
+// A range query drawn over time, one series per `legend`.
+fn range_target(expr: &str, legend: &str) -> Value {
+    object(vec![
+        ("datasource", datasource()),
+        ("expr", expr.into()),
+        ("legendFormat", legend.into()),
+        ("refId", "A".into()),
+    ])
+}
+
+// A single evaluation over the dashboard's time range, as a table with one row per series.
+fn instant_target(expr: &str) -> Value {
+    object(vec![
+        ("datasource", datasource()),
+        ("expr", expr.into()),
+        ("format", "table".into()),
+        ("instant", true.into()),
+        ("refId", "A".into()),
+    ])
+}
+
+struct Panel {
+    kind: &'static str,
+    title: &'static str,
+    // x, y, width and height on Grafana's 24 column grid.
+    grid: (u32, u32, u32, u32),
+    unit: &'static str,
+    target: Value,
+    options: Vec<(&'static str, Value)>,
+}
+
+impl Panel {
+    fn to_json(&self, id: u32) -> Value {
+        let (x, y, w, h) = self.grid;
+        let mut fields = vec![
+            ("id", id.into()),
+            ("type", self.kind.into()),
+            ("title", self.title.into()),
+            ("datasource", datasource()),
+            (
+                "gridPos",
+                object(vec![("x", x.into()), ("y", y.into()), ("w", w.into()), ("h", h.into())]),
+            ),
+            (
+                "fieldConfig",
+                object(vec![
+                    ("defaults", object(vec![("unit", self.unit.into())])),
+                    ("overrides", Value::Array(Vec::new())),
+                ]),
+            ),
+            ("targets", Value::Array(vec![self.target.clone()])),
+        ];
+        fields.extend(self.options.iter().cloned());
+        object(fields)
+    }
+}
+
+fn panels() -> Vec<Panel> {
+    let hidden_legend = || {
+        (
+            "options",
+            object(vec![(
+                "legend",
+                object(vec![("displayMode", "hidden".into()), ("placement", "bottom".into())]),
+            )]),
+        )
+    };
+    vec![
+        Panel {
+            kind: "timeseries",
+            title: "Detections",
+            grid: (0, 0, 12, 8),
+            unit: "short",
+            target: range_target("sum(increase(cnpd_detections_total[$__rate_interval]))", "detections"),
+            options: Vec::new(),
+        },
+        Panel {
+            kind: "timeseries",
+            title: "Detection rate",
+            grid: (12, 0, 8, 8),
+            unit: "short",
+            target: range_target("sum(rate(cnpd_detections_total[$__rate_interval])) * 60", "detections / min"),
+            options: Vec::new(),
+        },
+        Panel {
+            kind: "stat",
+            title: "Monitored containers",
+            grid: (20, 0, 4, 8),
+            unit: "short",
+            target: range_target("sum(cnpd_monitored_containers)", "containers"),
+            options: Vec::new(),
+        },
+        Panel {
+            kind: "barchart",
+            title: "Detections by container",
+            grid: (0, 8, 12, 10),
+            unit: "short",
+            target: instant_target(
+                "sort_desc(sum by (container_id) (increase(cnpd_container_detections_total[$__range])))",
+            ),
+            options: vec![hidden_legend()],
+        },
+        Panel {
+            kind: "table",
+            title: "Top 10 containers",
+            grid: (12, 8, 12, 10),
+            unit: "short",
+            target: instant_target(
+                "topk(10, sum by (container_id) (increase(cnpd_container_detections_total[$__range])))",
+            ),
+            options: vec![(
+                "transformations",
+                Value::Array(vec![object(vec![
+                    ("id", "organize".into()),
+                    (
+                        "options",
+                        object(vec![
+                            ("excludeByName", object(vec![("Time", true.into())])),
+                            ("renameByName", object(vec![("Value", "Detections".into())])),
+                        ]),
+                    ),
+                ])]),
+            )],
+        },
+        // A summary rather than buckets, so each quantile is its own series.
+        Panel {
+            kind: "timeseries",
+            title: "Stop/restart duration",
+            grid: (0, 18, 24, 8),
+            unit: "s",
+            target: range_target("max by (quantile) (cnpd_restart_duration_seconds)", "p{{quantile}}"),
+            options: Vec::new(),
+        },
+    ]
+}
+
+// The dashboard written by --export-grafana-dashboard, for Grafana 8's Import dashboard page.
+pub fn dashboard() -> Value {
+    let panels = panels()
+        .iter()
+        .enumerate()
+        .map(|(index, panel)| panel.to_json(index as u32 + 1))
+        .collect();
+    object(vec![
+        (
+            "__inputs",
+            Value::Array(vec![object(vec![
+                ("name", "DS_PROMETHEUS".into()),
+                ("label", "Prometheus".into()),
+                ("type", "datasource".into()),
+                ("pluginId", "prometheus".into()),
+                ("pluginName", "Prometheus".into()),
+            ])]),
+        ),
+        (
+            "__requires",
+            Value::Array(vec![object(vec![
+                ("type", "grafana".into()),
+                ("id", "grafana".into()),
+                ("name", "Grafana".into()),
+                ("version", "8.0.0".into()),
+            ])]),
+        ),
+        ("uid", "cnpd".into()),
+        ("title", "Container new process detector".into()),
+        ("tags", vec!["cnpd"].into()),
+        ("editable", true.into()),
+        ("schemaVersion", SCHEMA_VERSION.into()),
+        ("refresh", "30s".into()),
+        ("time", object(vec![("from", "now-6h".into()), ("to", "now".into())])),
+        ("templating", object(vec![("list", Value::Array(Vec::new()))])),
+        ("panels", Value::Array(panels)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+    use crate::metrics::Metrics;
+
+    #[test]
+    fn queries_only_exported_metrics() {
+        let doc = json::parse(&dashboard().to_string()).unwrap();
+        let panels = doc.get("panels").and_then(Value::as_array).unwrap();
+        assert_eq!(panels.len(), 6);
+
+        let exported = Metrics::default().render();
+        for panel in panels {
+            let expr = panel.get("targets").and_then(Value::as_array).unwrap()[0]
+                .get("expr")
+                .and_then(Value::as_str)
+                .unwrap();
+            let start = expr.find("cnpd_").unwrap();
+            let name: String = expr[start..].chars().take_while(|c| c.is_ascii_alphanumeric() || *c == '_').collect();
+            assert!(exported.contains(&format!("# TYPE {} ", name)), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::json::Value;
+use crate::policy::glob_match;
+
+// One `[[groups]]` entry of the config file, for containers deployed together:
+//
+//     [[groups]]
+//     name = "checkout"
+//     containers = ["checkout-*", "payments-api"]
+//     group_action = "stop-all"
+#[derive(Debug, Clone)]
+pub struct ContainerGroup {
+    pub name: String,
+    // Globs matched against the container name or ID.
+    pub containers: Vec<String>,
+    // Stop every member when a detection in one of them is acted on.
+    pub stop_all: bool,
+}
+
+impl ContainerGroup {
+    pub fn from_toml(value: &Value) -> Result<ContainerGroup, String> {
+        let name = value
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or("Container group is missing a name")?
+            .to_string();
+        let containers = value
+            .get("containers")
+            .and_then(Value::as_array)
+            .and_then(|v| v.iter().map(|v| v.as_str().map(str::to_string)).collect::<Option<Vec<_>>>())
+            .ok_or_else(|| format!("Container group {}: containers must be a list of patterns", name))?;
+        let stop_all = match value.get("group_action").and_then(Value::as_str) {
+            None => false,
+            Some("stop-all") => true,
+            Some(action) => return Err(format!("Container group {}: unknown group_action {}", name, action)),
+        };
+        Ok(ContainerGroup { name, containers, stop_all })
+    }
+
+    pub fn owns(&self, container_id: &str, name: Option<&str>) -> bool {
+        self.containers.iter().any(|pattern| {
+            glob_match(pattern, container_id) || name.is_some_and(|name| glob_match(pattern, name))
+        })
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::inventory::{ContainerStatus, Inventory, MonitorState};
+use crate::json::Value;
+
+// How far back errors and detection latencies count towards a task's health score.
+pub const SCORE_WINDOW: Duration = Duration::from_secs(5 * 60);
+// Tasks scoring below this report a monitoring-degraded event.
+pub const DEGRADED_SCORE: u32 = 50;
+// How often a healthy task polls at the least. A cycle reads cgroup.procs and /proc of
+// new processes, so it takes far longer than the loop's own sleep between cycles.
+pub const EXPECTED_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const ERROR_PENALTY: u32 = 10;
+const SLOW_DETECTION: Duration = Duration::from_secs(1);
+const SLOW_DETECTION_PENALTY: u32 = 20;
+const STALLED_PENALTY: u32 = 30;
+
+// What a monitoring task's health score is computed from: the errors it logged and
+// kept going after, how long its detections took, and when it last polled. A task that
+// fails this way never shows up as Failed.
+#[derive(Debug, Clone)]
+pub struct TaskHealth {
+    errors: VecDeque<Instant>,
+    latencies: VecDeque<(Instant, Duration)>,
+    last_poll: Instant,
+}
+
+impl TaskHealth {
+    pub fn new(now: Instant) -> TaskHealth {
+        TaskHealth {
+            errors: VecDeque::new(),
+            latencies: VecDeque::new(),
+            last_poll: now,
+        }
+    }
+
+    pub fn record_error(&mut self, now: Instant) {
+        self.errors.push_back(now);
+        self.prune(now);
+    }
+
+    pub fn record_latency(&mut self, now: Instant, latency: Duration) {
+        self.latencies.push_back((now, latency));
+        self.prune(now);
+    }
+
+    pub fn record_poll(&mut self, now: Instant) {
+        self.last_poll = now;
+    }
+
+    fn prune(&mut self, now: Instant) {
+        let recent = |at: &Instant| now.saturating_duration_since(*at) <= SCORE_WINDOW;
+        while self.errors.front().is_some_and(|at| !recent(at)) {
+            self.errors.pop_front();
+        }
+        while self.latencies.front().is_some_and(|(at, _)| !recent(at)) {
+            self.latencies.pop_front();
+        }
+    }
+
+    // From 100 down to 0: 10 points off per error in the last SCORE_WINDOW, 20 when
+    // detections took over a second on average, 30 when the last poll is more than
+    // twice EXPECTED_POLL_INTERVAL ago.
+    pub fn score(&self, now: Instant) -> u32 {
+        let recent = |at: &Instant| now.saturating_duration_since(*at) <= SCORE_WINDOW;
+        let errors = self.errors.iter().filter(|at| recent(at)).count() as u32;
+        let latencies: Vec<Duration> = self.latencies.iter().filter(|(at, _)| recent(at)).map(|(_, l)| *l).collect();
+        let mut penalty = errors.saturating_mul(ERROR_PENALTY);
+        if !latencies.is_empty() && latencies.iter().sum::<Duration>() / latencies.len() as u32 > SLOW_DETECTION {
+            penalty += SLOW_DETECTION_PENALTY;
+        }
+        if now.saturating_duration_since(self.last_poll) > 2 * EXPECTED_POLL_INTERVAL {
+            penalty += STALLED_PENALTY;
+        }
+        100u32.saturating_sub(penalty)
+    }
+}
+
+// The /healthz and /readyz endpoints served next to /metrics with --healthcheck-endpoint,
+// for Kubernetes liveness and readiness probes.
+pub struct HealthCheck {
+    prefix: String,
+    inventory: Arc<Inventory>,
+    started_at: Instant,
+}
+
+// What a probe is answered with; `ok` decides between 200 and 503.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthReport {
+    pub ok: bool,
+    pub status: &'static str,
+    pub monitored_containers: usize,
+    pub failed_containers: usize,
+    pub uptime_seconds: u64,
+    // The health score of every monitored container's task. Degraded tasks still
+    // monitor, so they leave `ok` alone.
+    pub task_scores: Vec<(String, u32)>,
+}
+
+impl HealthReport {
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("status".to_string(), self.status.into()),
+            ("monitored_containers".to_string(), (self.monitored_containers as u64).into()),
+            ("failed_containers".to_string(), (self.failed_containers as u64).into()),
+            ("uptime_seconds".to_string(), self.uptime_seconds.into()),
+            (
+                "degraded_containers".to_string(),
+                (self.task_scores.iter().filter(|(_, score)| *score < DEGRADED_SCORE).count() as u64).into(),
+            ),
+            (
+                "tasks".to_string(),
+                Value::Array(
+                    self.task_scores
+                        .iter()
+                        .map(|(container_id, score)| {
+                            Value::Object(vec![
+                                ("container_id".to_string(), container_id.as_str().into()),
+                                ("health_score".to_string(), (*score).into()),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+}
+
+impl HealthCheck {
+    // `prefix` is the path the endpoints are served under, "/" for /healthz itself.
+    pub fn new(prefix: &str, inventory: Arc<Inventory>, started_at: Instant) -> HealthCheck {
+        HealthCheck {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            inventory,
+            started_at,
+        }
+    }
+
+    // The report for a request path, or None when it is not one of the endpoints.
+    pub fn handle(&self, path: &str) -> Option<HealthReport> {
+        let statuses = self.inventory.snapshot();
+        match path.strip_prefix(self.prefix.as_str())? {
+            "/healthz" => Some(self.report(&statuses, false)),
+            "/readyz" => Some(self.report(&statuses, true)),
+            _ => None,
+        }
+    }
+
+    // Live while no monitoring task has failed. Ready once, on top of that, every container
+    // has its whitelist and has been polled at least once; the probe server only starts
+    // after the initial whitelist has been taken.
+    fn report(&self, statuses: &[ContainerStatus], readiness: bool) -> HealthReport {
+        let failed = statuses.iter().filter(|s| s.state == MonitorState::Failed).count();
+        let monitored = statuses
+            .iter()
+            .filter(|s| matches!(s.state, MonitorState::Monitoring | MonitorState::GracePeriod))
+            .count();
+        let polling = statuses
+            .iter()
+            .all(|s| s.state != MonitorState::GracePeriod && (s.state != MonitorState::Monitoring || s.polls > 0));
+        let status = if failed > 0 {
+            "failed"
+        } else if readiness && !polling {
+            "starting"
+        } else {
+            "ok"
+        };
+        HealthReport {
+            ok: status == "ok",
+            status,
+            monitored_containers: monitored,
+            failed_containers: failed,
+            uptime_seconds: self.started_at.elapsed().as_secs(),
+            task_scores: statuses
+                .iter()
+                .filter(|s| s.state == MonitorState::Monitoring)
+                .map(|s| (s.container_id.clone(), s.health_score))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_errors_latency_and_stalls() {
+        let start = Instant::now();
+        let mut health = TaskHealth::new(start);
+        assert_eq!(health.score(start), 100);
+
+        health.record_error(start);
+        health.record_error(start);
+        health.record_latency(start, Duration::from_millis(1500));
+        health.record_latency(start, Duration::from_millis(900));
+        assert_eq!(health.score(start), 60);
+        // Three seconds without a poll.
+        assert_eq!(health.score(start + Duration::from_secs(3)), 30);
+
+        let later = start + SCORE_WINDOW + Duration::from_secs(1);
+        health.record_poll(later);
+        assert_eq!(health.score(later), 100);
+    }
+
+    #[test]
+    fn fails_probes_once_a_task_fails() {
+        let inventory = Arc::new(Inventory::default());
+        let health = HealthCheck::new("/", inventory.clone(), Instant::now());
+        inventory.register("4f1c2a", MonitorState::Monitoring, 3);
+        assert!(health.handle("/healthz").unwrap().ok);
+        assert_eq!(health.handle("/readyz").unwrap().status, "starting");
+
+        inventory.record_poll("4f1c2a");
+        assert!(health.handle("/readyz").unwrap().ok);
+
+        inventory.register("9b7e11", MonitorState::Failed, 0);
+        let report = health.handle("/healthz").unwrap();
+        assert_eq!((report.ok, report.monitored_containers, report.failed_containers), (false, 1, 1));
+        assert!(health.handle("/metrics").is_none());
+    }
+}
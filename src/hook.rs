@@ -0,0 +1,123 @@
+use std::path::Path;
+use std::time::Duration;
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use crate::event::DetectionEvent;
+use crate::info;
+use crate::policy::Action;
+
+pub const OUTPUT_FILE: &str = "pre-action-hook.log";
+// A start hook still running after this long counts as failed.
+pub const START_HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Runs --pre-action-hook before `action` is taken on the event's container, with the
+// daemon's privileges, so that it can still capture network traffic or fork a debugger
+// while the container is up. A failing or hanging script only produces a warning: the
+// action is taken regardless. Returns the path of the forensic file the script's output
+// was appended to, if --forensics-dir is set; otherwise the output is logged.
+pub async fn run_pre_action(
+    script: &str,
+    limit: Duration,
+    event: &DetectionEvent,
+    action: Action,
+    forensics_dir: Option<&str>,
+) -> Option<String> {
+    let child = Command::new(script)
+        .env("CNPD_CONTAINER_ID", &event.container_id)
+        .env("CNPD_PID", event.pid.to_string())
+        .env("CNPD_ACTION", action.to_string())
+        .env("CNPD_EVENT_JSON", event.to_json().to_string())
+        .kill_on_drop(true)
+        .output();
+
+    let output = match timeout(limit, child).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => {
+            eprintln!("Warning: failed to run pre-action hook {}: {}", script, e);
+            return None;
+        }
+        Err(_) => {
+            eprintln!(
+                "Warning: pre-action hook {} did not finish within {} ms, taking action {}",
+                script,
+                limit.as_millis(),
+                action
+            );
+            return None;
+        }
+    };
+    if !output.status.success() {
+        eprintln!("Warning: pre-action hook {} exited with {}, taking action {}", script, output.status, action);
+    }
+
+    let Some(dir) = forensics_dir else {
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            info!("Pre-action hook: {}", line);
+        }
+        for line in String::from_utf8_lossy(&output.stderr).lines() {
+            info!("Pre-action hook (stderr): {}", line);
+        }
+        return None;
+    };
+    let path = Path::new(dir).join(&event.id).join(OUTPUT_FILE);
+    match append_output(&path, &output.stdout, &output.stderr).await {
+        Ok(()) => Some(path.to_string_lossy().into_owned()),
+        Err(e) => {
+            eprintln!("Failed to write {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+// Runs --container-start-hook for a container found after startup, before it is
+// monitored, e.g. to set up firewall rules or fetch its policy. Returns whether the
+// container should be monitored: a script that fails, cannot be run or times out
+// keeps it from being monitored.
+pub async fn run_container_start(script: &str, container_id: &str, cgroup_path: &str) -> bool {
+    let child = Command::new(script)
+        .env("CNPD_CONTAINER_ID", container_id)
+        .env("CNPD_CGROUP_PATH", cgroup_path)
+        .kill_on_drop(true)
+        .output();
+
+    let output = match timeout(START_HOOK_TIMEOUT, child).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => {
+            eprintln!("Warning: failed to run container start hook {}: {}", script, e);
+            return false;
+        }
+        Err(_) => {
+            eprintln!(
+                "Warning: container start hook {} did not finish within {} s",
+                script,
+                START_HOOK_TIMEOUT.as_secs()
+            );
+            return false;
+        }
+    };
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        info!("Container start hook: {}", line);
+    }
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        info!("Container start hook (stderr): {}", line);
+    }
+    if !output.status.success() {
+        eprintln!("Warning: container start hook {} exited with {}", script, output.status);
+    }
+    output.status.success()
+}
+
+async fn append_output(path: &Path, stdout: &[u8], stderr: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path).await?;
+    file.write_all(b"--- stdout\n").await?;
+    file.write_all(stdout).await?;
+    file.write_all(b"--- stderr\n").await?;
+    file.write_all(stderr).await?;
+    file.flush().await
+}
@@ -0,0 +1,80 @@
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+
+// One event read from the inotify descriptor.
+#[derive(Debug, Clone)]
+pub struct InotifyEvent {
+    pub wd: i32,
+    pub mask: u32,
+    // Name of the entry inside a watched directory, if any.
+    pub name: Option<String>,
+}
+
+// A non-blocking inotify descriptor driven by the Tokio reactor.
+pub struct Inotify {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl Inotify {
+    pub fn new() -> io::Result<Inotify> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        Ok(Inotify {
+            fd: AsyncFd::with_interest(fd, Interest::READABLE)?,
+        })
+    }
+
+    pub fn add_watch(&self, path: &str, mask: u32) -> io::Result<i32> {
+        let c_path = CString::new(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), c_path.as_ptr(), mask) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(wd)
+    }
+
+    // Waits until at least one event is available and returns everything read.
+    pub async fn read_events(&self) -> io::Result<Vec<InotifyEvent>> {
+        let mut buf = [0u8; 4096];
+        loop {
+            let mut guard = self.fd.readable().await?;
+            let n = unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+            if n < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::WouldBlock {
+                    guard.clear_ready();
+                    continue;
+                }
+                return Err(err);
+            }
+            return Ok(parse_events(&buf[..n as usize]));
+        }
+    }
+}
+
+fn parse_events(mut buf: &[u8]) -> Vec<InotifyEvent> {
+    const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+    let mut events = Vec::new();
+    while buf.len() >= HEADER {
+        let event: libc::inotify_event = unsafe { std::ptr::read_unaligned(buf.as_ptr().cast()) };
+        let end = (HEADER + event.len as usize).min(buf.len());
+        let name = buf[HEADER..end]
+            .split(|b| *b == 0)
+            .next()
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned());
+        events.push(InotifyEvent {
+            wd: event.wd,
+            mask: event.mask,
+            name,
+        });
+        buf = &buf[end..];
+    }
+    events
+}
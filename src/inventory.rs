@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::health::TaskHealth;
+use crate::json::Value;
+use crate::numa;
+use crate::risk::ContainerRisk;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonitorState {
+    Monitoring,
+    Stopped,
+    Failed,
+    // Waiting out --container-start-delay before taking the snapshot.
+    GracePeriod,
+}
+
+impl fmt::Display for MonitorState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MonitorState::Monitoring => "Monitoring",
+            MonitorState::Stopped => "Stopped",
+            MonitorState::Failed => "Failed",
+            MonitorState::GracePeriod => "Grace Period",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for MonitorState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Monitoring" => Ok(MonitorState::Monitoring),
+            "Stopped" => Ok(MonitorState::Stopped),
+            "Failed" => Ok(MonitorState::Failed),
+            "Grace Period" => Ok(MonitorState::GracePeriod),
+            _ => Err(format!("Unknown monitor state: {}", s)),
+        }
+    }
+}
+
+// A snapshot of one container's monitoring status.
+#[derive(Debug, Clone)]
+pub struct ContainerStatus {
+    pub container_id: String,
+    pub name: Option<String>,
+    pub state: MonitorState,
+    pub whitelist_pids: usize,
+    pub detections: u64,
+    pub last_detection: Option<String>,
+    pub uptime: Duration,
+    pub tenant: Option<String>,
+    pub group: Option<String>,
+    // Runtime thread that ran the task's latest poll cycle, and how many cycles it ran.
+    pub tid: Option<i32>,
+    pub polls: u64,
+    // With --numa-aware, the NUMA node the task runs on.
+    pub numa_node: Option<usize>,
+    pub risk: Option<ContainerRisk>,
+    // 0 to 100, from the task's recent errors, detection latency and last poll.
+    pub health_score: u32,
+}
+
+impl ContainerStatus {
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("container_id".to_string(), self.container_id.as_str().into()),
+            ("name".to_string(), self.name.clone().into()),
+            ("state".to_string(), self.state.to_string().into()),
+            ("whitelist_pids".to_string(), (self.whitelist_pids as u64).into()),
+            ("detections".to_string(), self.detections.into()),
+            ("last_detection".to_string(), self.last_detection.clone().into()),
+            ("uptime_secs".to_string(), self.uptime.as_secs().into()),
+            ("tenant".to_string(), self.tenant.clone().into()),
+            ("group".to_string(), self.group.clone().into()),
+            ("tid".to_string(), self.tid.into()),
+            ("polls".to_string(), self.polls.into()),
+            ("numa_node".to_string(), self.numa_node.map(|node| node as u64).into()),
+            ("risk_score".to_string(), self.risk.as_ref().map(|risk| risk.score).into()),
+            (
+                "risk_factors".to_string(),
+                self.risk.as_ref().map(|risk| risk.factors.clone()).unwrap_or_default().into(),
+            ),
+            ("health_score".to_string(), self.health_score.into()),
+        ])
+    }
+
+    pub fn from_json(value: &Value) -> Option<ContainerStatus> {
+        let string = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+        let number = |key: &str| value.get(key).and_then(Value::as_i64).unwrap_or(0) as u64;
+        Some(ContainerStatus {
+            container_id: string("container_id")?,
+            name: string("name"),
+            state: string("state")?.parse().ok()?,
+            whitelist_pids: number("whitelist_pids") as usize,
+            detections: number("detections"),
+            last_detection: string("last_detection"),
+            uptime: Duration::from_secs(number("uptime_secs")),
+            tenant: string("tenant"),
+            group: string("group"),
+            tid: value.get("tid").and_then(Value::as_i64).map(|tid| tid as i32),
+            polls: number("polls"),
+            numa_node: value.get("numa_node").and_then(Value::as_i64).map(|node| node as usize),
+            risk: value.get("risk_score").and_then(Value::as_i64).map(|score| ContainerRisk {
+                score: score as u32,
+                factors: value
+                    .get("risk_factors")
+                    .and_then(Value::as_array)
+                    .map(|v| v.iter().filter_map(Value::as_str).map(str::to_string).collect())
+                    .unwrap_or_default(),
+            }),
+            health_score: value.get("health_score").and_then(Value::as_i64).map_or(100, |score| score as u32),
+        })
+    }
+}
+
+struct Entry {
+    status: ContainerStatus,
+    started_at: Instant,
+    // Docker labels copied into events, with --container-labels-in-events.
+    labels: HashMap<String, String>,
+    health: TaskHealth,
+}
+
+// Monitoring status of every container the daemon has seen, served to cnpd-ctl.
+#[derive(Default)]
+pub struct Inventory {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl Inventory {
+    // Called when a monitoring task starts; resets the task uptime.
+    pub fn register(&self, container_id: &str, state: MonitorState, whitelist_pids: usize) {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.entry(container_id.to_string()).or_insert_with(|| Entry {
+            status: ContainerStatus {
+                container_id: container_id.to_string(),
+                name: None,
+                state,
+                whitelist_pids,
+                detections: 0,
+                last_detection: None,
+                uptime: Duration::ZERO,
+                tenant: None,
+                group: None,
+                tid: None,
+                polls: 0,
+                numa_node: None,
+                risk: None,
+                health_score: 100,
+            },
+            started_at: Instant::now(),
+            labels: HashMap::new(),
+            health: TaskHealth::new(Instant::now()),
+        });
+        entry.status.state = state;
+        entry.status.whitelist_pids = whitelist_pids;
+        entry.started_at = Instant::now();
+        entry.health = TaskHealth::new(Instant::now());
+    }
+
+    fn update<F: FnOnce(&mut ContainerStatus)>(&self, container_id: &str, f: F) {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(container_id) {
+            f(&mut entry.status);
+        }
+    }
+
+    // Forgets a container whose cgroup is gone, with --cleanup-dead-containers.
+    pub fn remove(&self, container_id: &str) {
+        self.entries.lock().unwrap().remove(container_id);
+    }
+
+    pub fn contains(&self, container_id: &str) -> bool {
+        self.entries.lock().unwrap().contains_key(container_id)
+    }
+
+    pub fn set_name(&self, container_id: &str, name: String) {
+        self.update(container_id, |status| status.name = Some(name));
+    }
+
+    pub fn name(&self, container_id: &str) -> Option<String> {
+        self.entries.lock().unwrap().get(container_id).and_then(|entry| entry.status.name.clone())
+    }
+
+    pub fn set_labels(&self, container_id: &str, labels: HashMap<String, String>) {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(container_id) {
+            entry.labels = labels;
+        }
+    }
+
+    pub fn labels(&self, container_id: &str) -> HashMap<String, String> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(container_id)
+            .map(|entry| entry.labels.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set_tenant(&self, container_id: &str, tenant: String) {
+        self.update(container_id, |status| status.tenant = Some(tenant));
+    }
+
+    pub fn tenant(&self, container_id: &str) -> Option<String> {
+        self.entries.lock().unwrap().get(container_id).and_then(|entry| entry.status.tenant.clone())
+    }
+
+    pub fn set_group(&self, container_id: &str, group: String) {
+        self.update(container_id, |status| status.group = Some(group));
+    }
+
+    pub fn group(&self, container_id: &str) -> Option<String> {
+        self.entries.lock().unwrap().get(container_id).and_then(|entry| entry.status.group.clone())
+    }
+
+    // Containers of the group that are still running, sorted by ID.
+    pub fn group_members(&self, group: &str) -> Vec<String> {
+        let mut members: Vec<String> = self
+            .entries
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|entry| entry.status.group.as_deref() == Some(group) && entry.status.state != MonitorState::Stopped)
+            .map(|entry| entry.status.container_id.clone())
+            .collect();
+        members.sort();
+        members
+    }
+
+    pub fn set_risk(&self, container_id: &str, risk: ContainerRisk) {
+        self.update(container_id, |status| status.risk = Some(risk));
+    }
+
+    pub fn risk(&self, container_id: &str) -> Option<ContainerRisk> {
+        self.entries.lock().unwrap().get(container_id).and_then(|entry| entry.status.risk.clone())
+    }
+
+    pub fn set_state(&self, container_id: &str, state: MonitorState) {
+        self.update(container_id, |status| status.state = state);
+    }
+
+    // Called at the start of every poll cycle, on whichever runtime thread runs it.
+    pub fn record_poll(&self, container_id: &str) {
+        let tid = unsafe { libc::gettid() };
+        let node = numa::current_node();
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(container_id) {
+            entry.status.tid = Some(tid);
+            entry.status.numa_node = node;
+            entry.status.polls += 1;
+            entry.health.record_poll(Instant::now());
+        }
+    }
+
+    // An error the task logged and carried on after.
+    pub fn record_error(&self, container_id: &str) {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(container_id) {
+            entry.health.record_error(Instant::now());
+        }
+    }
+
+    pub fn record_latency(&self, container_id: &str, latency: Duration) {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(container_id) {
+            entry.health.record_latency(Instant::now(), latency);
+        }
+    }
+
+    pub fn health_score(&self, container_id: &str) -> Option<u32> {
+        self.entries.lock().unwrap().get(container_id).map(|entry| entry.health.score(Instant::now()))
+    }
+
+    pub fn record_detection(&self, container_id: &str, detected_at: &str) {
+        self.update(container_id, |status| {
+            status.detections += 1;
+            status.last_detection = Some(detected_at.to_string());
+        });
+    }
+
+    pub fn set_whitelist_pids(&self, container_id: &str, whitelist_pids: usize) {
+        self.update(container_id, |status| status.whitelist_pids = whitelist_pids);
+    }
+
+    pub fn snapshot(&self) -> Vec<ContainerStatus> {
+        let mut statuses: Vec<ContainerStatus> = self
+            .entries
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| {
+                let mut status = entry.status.clone();
+                status.uptime = entry.started_at.elapsed();
+                status.health_score = entry.health.score(Instant::now());
+                status
+            })
+            .collect();
+        statuses.sort_by(|a, b| a.container_id.cmp(&b.container_id));
+        statuses
+    }
+}
@@ -0,0 +1,92 @@
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::str::FromStr;
+
+use crate::event::DetectionEvent;
+use crate::log;
+use crate::syslog;
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+// Where events are logged, from --log-output. Stdout always is; `stdout` is accepted
+// so that scripts can spell out the default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogOutput {
+    Stdout,
+    Journald,
+}
+
+impl FromStr for LogOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stdout" => Ok(LogOutput::Stdout),
+            "journald" => Ok(LogOutput::Journald),
+            _ => Err(format!("Unknown log output: {}", s)),
+        }
+    }
+}
+
+// Writes events to the systemd journal over its native protocol, one datagram per
+// event, with the key fields as CNPD_* journal fields so that they can be matched
+// with `journalctl CNPD_CONTAINER_ID=...`.
+pub struct JournaldLogger {
+    socket: UnixDatagram,
+}
+
+impl JournaldLogger {
+    pub fn connect() -> io::Result<JournaldLogger> {
+        let socket = UnixDatagram::unbound()?;
+        socket
+            .connect(JOURNAL_SOCKET)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", JOURNAL_SOCKET, e)))?;
+        Ok(JournaldLogger { socket })
+    }
+
+    pub fn send(&self, event: &DetectionEvent) {
+        if let Err(e) = self.socket.send(&format_entry(event)) {
+            eprintln!("Failed to write event {} to the journal: {}", event.id, e);
+        }
+    }
+}
+
+fn format_entry(event: &DetectionEvent) -> Vec<u8> {
+    let message = format!(
+        "{} in container {}: PID {} ({}), action {}",
+        event.kind,
+        log::id(&event.container_id),
+        event.pid,
+        event.exe.as_deref().unwrap_or("unknown"),
+        event.action
+    );
+    let mut entry = Vec::new();
+    for (name, value) in [
+        ("MESSAGE", message),
+        ("PRIORITY", syslog::severity(event).to_string()),
+        ("SYSLOG_IDENTIFIER", "cnpd".to_string()),
+        ("CNPD_EVENT_ID", event.id.clone()),
+        ("CNPD_KIND", event.kind.to_string()),
+        ("CNPD_CONTAINER_ID", event.container_id.clone()),
+        ("CNPD_PID", event.pid.to_string()),
+        ("CNPD_ACTION", event.action.to_string()),
+        ("CNPD_SCORE", event.action.score().to_string()),
+        ("CNPD_EVENT", event.to_json().to_string()),
+    ] {
+        push_field(&mut entry, name, &value);
+    }
+    entry
+}
+
+// Values containing a newline, which a command line can, need the length-prefixed form.
+fn push_field(entry: &mut Vec<u8>, name: &str, value: &str) {
+    entry.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
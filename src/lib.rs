@@ -0,0 +1,71 @@
+pub mod affinity;
+pub mod anomaly;
+pub mod archive;
+pub mod asyncset;
+pub mod audit;
+pub mod baseline;
+pub mod bcc;
+pub mod capability;
+pub mod cgroup;
+pub mod cloudwatch;
+pub mod color;
+pub mod config;
+pub mod control;
+pub mod discovery;
+pub mod docker;
+#[cfg(feature = "ebpf")]
+pub mod ebpf;
+pub mod event;
+pub mod eventbuffer;
+pub mod falco;
+pub mod fanotify;
+pub mod filter;
+pub mod forensics;
+pub mod grafana;
+pub mod group;
+pub mod health;
+pub mod hook;
+pub mod inotify;
+pub mod inventory;
+pub mod journald;
+pub mod json;
+pub mod lineage;
+pub mod log;
+pub mod metrics;
+pub mod mountinfo;
+pub mod netsock;
+pub mod numa;
+pub mod oom;
+pub mod plugin;
+pub mod policy;
+pub mod procfs;
+pub mod redis;
+pub mod regex;
+pub mod report;
+#[cfg(feature = "ebpf")]
+pub mod ringbuf;
+pub mod risk;
+pub mod rollback;
+pub mod router;
+pub mod runtime;
+pub mod sandbox;
+pub mod scan;
+pub mod sha256;
+pub mod signature;
+#[cfg(feature = "simd")]
+pub mod simd;
+pub mod sqlite;
+pub mod state;
+pub mod subsystem;
+pub mod summary;
+pub mod syscalls;
+pub mod syslog;
+pub mod tenant;
+pub mod timeofday;
+pub mod toml;
+pub mod upgrade;
+#[cfg(feature = "io-uring")]
+pub mod uring;
+pub mod watchdog;
+pub mod webhook;
+pub mod whitelist;
@@ -0,0 +1,91 @@
+use crate::json::Value;
+use crate::procfs::{self, ProcCache};
+
+// Upper bound on the walk, in case PIDs are reused while it runs.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessInfo {
+    pub pid: i32,
+    pub ppid: Option<i32>,
+    pub name: Option<String>,
+    pub exe: Option<String>,
+    // Set on the ancestor where the chain leaves the detected process's cgroup.
+    pub outside_container: bool,
+}
+
+impl ProcessInfo {
+    async fn read(pid: i32) -> ProcessInfo {
+        ProcessInfo {
+            pid,
+            ppid: procfs::read_ppid(pid).await,
+            name: procfs::read_status_field(pid, "Name").await,
+            exe: procfs::read_exe(pid).await,
+            outside_container: false,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("pid".to_string(), self.pid.into()),
+            ("ppid".to_string(), self.ppid.into()),
+            ("name".to_string(), self.name.clone().into()),
+            ("exe".to_string(), self.exe.clone().into()),
+            ("outside_container".to_string(), self.outside_container.into()),
+        ])
+    }
+
+    pub fn from_json(value: &Value) -> Option<ProcessInfo> {
+        let string = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+        Some(ProcessInfo {
+            pid: value.get("pid")?.as_i64()? as i32,
+            ppid: value.get("ppid").and_then(Value::as_i64).map(|p| p as i32),
+            name: string("name"),
+            exe: string("exe"),
+            outside_container: value.get("outside_container").and_then(Value::as_bool).unwrap_or(false),
+        })
+    }
+}
+
+impl std::fmt::Display for ProcessInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "PID {}", self.pid)?;
+        if let Some(name) = &self.name {
+            write!(f, " ({})", name)?;
+        }
+        if let Some(exe) = &self.exe {
+            write!(f, " {}", exe)?;
+        }
+        if self.outside_container {
+            write!(f, " [outside container]")?;
+        }
+        Ok(())
+    }
+}
+
+pub struct ProcessLineage;
+
+impl ProcessLineage {
+    // Ancestors of `pid`, nearest first, following PPid up to PID 1. The walk also
+    // stops at the first ancestor in a different cgroup than `pid`, which is included
+    // and flagged since a fork chain crossing the container boundary is suspicious.
+    // Ancestors are read through `cache`, as PIDs detected together often share them.
+    pub async fn for_pid(pid: i32, cache: &ProcCache) -> Vec<ProcessInfo> {
+        let mut chain = Vec::new();
+        let cgroup = cache.cgroup(pid).await;
+        let mut next = procfs::read_ppid(pid).await;
+
+        while let Some(ppid) = next.filter(|&p| p > 0 && chain.len() < MAX_DEPTH) {
+            let mut info = match cache.proc_info(ppid).await {
+                Ok(Some(info)) => info,
+                _ => ProcessInfo::read(ppid).await,
+            };
+            let parent_cgroup = cache.cgroup(ppid).await;
+            info.outside_container = cgroup.is_some() && parent_cgroup != cgroup;
+
+            next = if ppid == 1 || info.outside_container { None } else { info.ppid };
+            chain.push(info);
+        }
+        chain
+    }
+}
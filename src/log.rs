@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static DEBUG: AtomicBool = AtomicBool::new(false);
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+static FULL_IDS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_debug(enabled: bool) {
+    DEBUG.store(enabled, Ordering::Relaxed);
+}
+
+pub fn debug_enabled() -> bool {
+    DEBUG.load(Ordering::Relaxed)
+}
+
+// Sends log lines to stderr, leaving stdout to machine-readable output.
+pub fn set_log_to_stderr(enabled: bool) {
+    TO_STDERR.store(enabled, Ordering::Relaxed);
+}
+
+pub fn log_to_stderr() -> bool {
+    TO_STDERR.load(Ordering::Relaxed)
+}
+
+pub fn set_full_container_ids(enabled: bool) {
+    FULL_IDS.store(enabled, Ordering::Relaxed);
+}
+
+// A container ID as it should appear in log output: the 12-character short form,
+// unless --full-container-ids was given.
+pub fn id(container_id: &str) -> &str {
+    if FULL_IDS.load(Ordering::Relaxed) {
+        container_id
+    } else {
+        crate::docker::short_id(container_id)
+    }
+}
+
+// Informational log line, on stdout unless set_log_to_stderr() was called.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::log_to_stderr() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+// Prints only when the daemon runs with --debug.
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::log::debug_enabled() {
+            $crate::info!("[DEBUG] {}", format_args!($($arg)*));
+        }
+    };
+}
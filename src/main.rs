@@ -1,128 +1,2846 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::path::Path;
-use tokio::fs;
+use std::future::Future;
+use std::pin::Pin;
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::Poll;
+use std::time::Instant;
+use tokio::runtime::{self, Runtime};
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::process::Command;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinSet;
 use tokio::time::{sleep, Duration};
-use chrono::{Local, Utc};
+use chrono::{DateTime, Local, Utc};
+use container_new_process_detector::anomaly::{ProcessCountAnomaly, ProcessCountBaselines};
+use container_new_process_detector::archive::ContainerArchive;
+use container_new_process_detector::audit::{self, ExecLog, SeccompRecord};
+use container_new_process_detector::bcc::BccProgram;
+use container_new_process_detector::capability::{self, CapabilityChecker};
+use container_new_process_detector::cgroup::{self, ContainerCgroup};
+use container_new_process_detector::color::{self, Color};
+use container_new_process_detector::config::Config;
+use container_new_process_detector::control;
+use container_new_process_detector::discovery::{self, ContainerChange};
+use container_new_process_detector::event::{self, DetectionEvent, EventKind, EventLog, OutputFormat, ProcessExitEvent};
+use container_new_process_detector::eventbuffer::EventBuffer;
+use container_new_process_detector::falco::FalcoReporter;
+use container_new_process_detector::fanotify::{self, FileWrite, FileWriteWatcher};
+use container_new_process_detector::filter::EventFilter;
+use container_new_process_detector::health::{HealthCheck, DEGRADED_SCORE};
+use container_new_process_detector::inventory::{Inventory, MonitorState};
+use container_new_process_detector::journald::{JournaldLogger, LogOutput};
+use container_new_process_detector::lineage::ProcessLineage;
+use container_new_process_detector::metrics::{self, ExitReason, Metrics};
+use container_new_process_detector::mountinfo::{self, MountEntry};
+use container_new_process_detector::netsock::ConnectionLog;
+use container_new_process_detector::numa::{self, NumaAwareScheduler};
+use container_new_process_detector::oom::OomWatcher;
+use container_new_process_detector::plugin::{self, CnpdPlugin};
+use container_new_process_detector::policy::{Action, Policy, PolicyEngine};
+use container_new_process_detector::procfs::{CapabilitySets, ProcCache};
+use container_new_process_detector::signature::{self, SignatureStatus};
+use container_new_process_detector::state::{self, Baseline, BaselineProcess, StateStore};
+use container_new_process_detector::redis::RedisPublisher;
+use container_new_process_detector::cloudwatch::CloudWatchSink;
+use container_new_process_detector::risk::ContainerRisk;
+use container_new_process_detector::rollback::ContainerSpec;
+use container_new_process_detector::router::DetectionEventRouter;
+use container_new_process_detector::runtime::ContainerRuntimeDetector;
+use container_new_process_detector::sqlite::SqliteEventStore;
+use container_new_process_detector::timeofday::TimeAnomalyDetector;
+use container_new_process_detector::subsystem::{SubsystemChange, SubsystemMonitor};
+use container_new_process_detector::summary::SummaryReport;
+use container_new_process_detector::syslog::SyslogTcpSink;
+use container_new_process_detector::syscalls;
+use container_new_process_detector::tenant::Tenants;
+use container_new_process_detector::upgrade::{ResumeState, ResumedContainer, RunningBinary};
+use container_new_process_detector::whitelist::{distinct_pids, ContainerWhitelist, ProcessWhitelistStore};
+use container_new_process_detector::watchdog::{StuckHandler, WatchdogTimer};
+use container_new_process_detector::{affinity, debug, docker, forensics, grafana, hook, info, log, netsock, procfs, sandbox, scan, webhook};
 
-async fn get_docker_directories() -> Result<Vec<String>, Box<dyn Error>> {
-    let cgroup_path = "/sys/fs/cgroup/system.slice/";
-    let mut docker_list = Vec::new();
+const POLL_INTERVAL: Duration = Duration::from_nanos(1);
+const FD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const NS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const MEMORY_PRESSURE_RATIO: f64 = 0.8;
+// How long a new process is watched when --alert-threshold-cpu-percent is set.
+const CPU_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+// Bound on freezing or thawing a container, which can hang on tasks stuck in the kernel.
+const FREEZE_TIMEOUT: Duration = Duration::from_secs(2);
+// A new PID this soon after an OOM kill may be an attacker re-executing after covering tracks.
+const OOM_REEXEC_WINDOW: Duration = Duration::from_millis(500);
+// How often the process count is sampled for --alert-on-proc-set-growth-rate.
+const GROWTH_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+const MOUNT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const SUBSYSTEM_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+// How often --process-count-baseline-file samples process counts, and saves them.
+const PROCESS_COUNT_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+const PROCESS_COUNT_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+// Detection IDs kept for --watch-seccomp-violations before starting over.
+const MAX_DETECTION_IDS: usize = 10_000;
+
+// State shared by all monitoring tasks.
+struct Context {
+    config: Config,
+    metrics: Arc<Metrics>,
+    engine: PolicyEngine,
+    tenants: Arc<Tenants>,
+    plugins: Vec<Arc<dyn CnpdPlugin>>,
+    watchdog: WatchdogTimer,
+    // With --state-file, through which every change to the file is made.
+    state: Option<StateStore>,
+    syslog: Option<SyslogTcpSink>,
+    redis: Option<RedisPublisher>,
+    cloudwatch: Option<CloudWatchSink>,
+    sqlite: Option<SqliteEventStore>,
+    // With --detect-time-of-day-anomalies, the hours each container usually starts processes at.
+    time_anomalies: Option<TimeAnomalyDetector>,
+    journald: Option<JournaldLogger>,
+    falco: Option<FalcoReporter>,
+    // Per-container sinks from the [[routes]] of the --config file.
+    router: Option<Arc<DetectionEventRouter>>,
+    // Receives every recorded event while --simulate-attack waits for its detection.
+    simulation: Option<mpsc::UnboundedSender<DetectionEvent>>,
+    inventory: Arc<Inventory>,
+    whitelists: Arc<ProcessWhitelistStore>,
+    // With --numa-aware on a host with several nodes, runs the monitoring tasks.
+    numa: Option<NumaAwareScheduler>,
+    filter: Arc<EventFilter>,
+    // Filled by --trace-tcp-connect.
+    connections: Arc<ConnectionLog>,
+    // Filled by --audit-exec.
+    execs: Arc<ExecLog>,
+    // The open --output-file, with --log-rotate-on-sighup.
+    event_log: Option<Arc<EventLog>>,
+    // With --process-count-baseline-file, the usual process count of every container.
+    process_counts: Option<Arc<ProcessCountBaselines>>,
+    // IDs of new-process detections by container and PID, which seccomp violations and
+    // ptrace attaches of the same process refer to.
+    detection_ids: Mutex<HashMap<(String, i32), String>>,
+    // Recorded events on their way to the sinks below, written by deliver_event.
+    events: EventBuffer<DetectionEvent>,
+}
+
+async fn build_event(
+    ctx: &Context,
+    cache: &ProcCache,
+    container_id: &str,
+    pid: i32,
+    detected_at: DateTime<Local>,
+) -> DetectionEvent {
+    let limit = ctx.config.max_proc_read_time;
+    // A read that timed out counts against the task's health score.
+    let timed_out = || {
+        ctx.inventory.record_error(container_id);
+        Some(procfs::TIMEOUT_MARKER.to_string())
+    };
+
+    let latency = procfs::read_with_timeout(limit, pid, "stat", procfs::process_age(pid))
+        .await
+        .unwrap_or(None);
+    ctx.metrics.record_detection(container_id, latency);
+    if let Some(latency) = latency {
+        ctx.inventory.record_latency(container_id, latency);
+    }
+    if let (Some(latency), Some(warn_ms)) = (latency, ctx.config.latency_warn_ms) {
+        if latency.as_millis() > warn_ms as u128 {
+            eprintln!(
+                "Warning: detection latency for PID {} in {} was {} ms (threshold {} ms)",
+                pid,
+                log::id(container_id),
+                latency.as_millis(),
+                warn_ms
+            );
+        }
+    }
+
+    let mut event = DetectionEvent::new(container_id, pid, detected_at);
+    event.exe = match procfs::read_with_timeout(limit, pid, "exe", async { Some(cache.proc_info(pid).await) }).await {
+        Ok(Some(Ok(info))) => info.and_then(|info| info.exe),
+        Ok(Some(Err(e))) => {
+            eprintln!("Warning: {}", e);
+            ctx.inventory.record_error(container_id);
+            None
+        }
+        Ok(None) => None,
+        Err(_) => timed_out(),
+    };
+    event.cmdline = procfs::read_with_timeout(limit, pid, "cmdline", procfs::read_cmdline(pid))
+        .await
+        .unwrap_or_else(|_| timed_out());
+    // Audit reports argv at exec time, before the process can exit or overwrite it.
+    if let Some(exec) = ctx.execs.latest_for_pid(pid) {
+        event.cmdline = Some(exec.argv.join(" "));
+        if event.exe.is_none() {
+            event.exe = exec.exe;
+        }
+    }
+    event.detection_latency_ms = latency.map(|l| l.as_secs_f64() * 1000.0);
+    if ctx.config.track_process_working_directory {
+        event.process_cwd = procfs::read_with_timeout(limit, pid, "cwd", procfs::read_cwd(pid))
+            .await
+            .ok()
+            .flatten();
+        event.suspicious_cwd = event
+            .process_cwd
+            .as_deref()
+            .is_some_and(|cwd| procfs::is_sensitive_cwd(cwd, &ctx.config.sensitive_cwd_paths));
+    }
+    event.open_fds = procfs::read_with_timeout(limit, pid, "fd", procfs::count_fds(pid))
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(0);
+    event.process_lineage = procfs::read_with_timeout(limit, pid, "status", async {
+        Some(ProcessLineage::for_pid(pid, cache).await)
+    })
+    .await
+    .ok()
+    .flatten()
+    .unwrap_or_default();
+    event.network_connections = procfs::read_with_timeout(limit, pid, "net/tcp6", netsock::read_process_sockets(pid))
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    // Traced connections also cover sockets already closed, or a process that has exited.
+    for socket in ctx.connections.for_pid(pid).iter().map(|c| c.to_socket()) {
+        let known = event.network_connections.iter().any(|s| {
+            (s.local_port, s.remote_ip, s.remote_port) == (socket.local_port, socket.remote_ip, socket.remote_port)
+        });
+        if !known {
+            event.network_connections.push(socket);
+        }
+    }
+    if let Some(dir) = &ctx.config.forensics_dir {
+        event.forensic_artifacts = forensics::collect(dir, &event.id, pid, limit).await;
+    }
+    event.tenant = ctx.inventory.tenant(container_id);
+    event.group_name = ctx.inventory.group(container_id);
+    event.container_labels = ctx.inventory.labels(container_id);
+    if let Some(group) = &event.group_name {
+        event.group_peer_containers = ctx.inventory.group_members(group).into_iter().filter(|id| id != container_id).collect();
+    }
+    set_risk(ctx, &mut event);
+    event.action = policy_engine(ctx, event.tenant.as_deref()).evaluate(&event);
+    event
+}
+
+fn set_risk(ctx: &Context, event: &mut DetectionEvent) {
+    if let Some(risk) = ctx.inventory.risk(&event.container_id) {
+        event.risk_score = Some(risk.score);
+        event.risk_factors = risk.factors;
+    }
+}
+
+// The age of a process that already exited younger than --min-process-age-ms. Such a
+// process finished before anything could be collected from it. One that exited before
+// even its start time could be read is no older than the time since the previous poll,
+// as it was not in cgroup.procs then.
+async fn short_lived(min_age_ms: u64, (pid, start_ticks): (i32, u64), since_last_read: Duration) -> Option<Duration> {
+    if min_age_ms == 0 || procfs::read_start_time(pid).await.is_some_and(|start| start == start_ticks) {
+        return None;
+    }
+    let age = match start_ticks {
+        0 => since_last_read,
+        _ => procfs::age_since_start(start_ticks),
+    };
+    (age < Duration::from_millis(min_age_ms)).then_some(age)
+}
+
+// Containers started with --pid host see every host process in their cgroup's view,
+// which shows as the init process sharing PID 1's namespace.
+async fn shares_host_pid_namespace(init_pid: Option<i32>) -> bool {
+    let (Some(pid), Some(host)) = (init_pid, procfs::read_ns_inode(1, "pid").await) else {
+        return false;
+    };
+    procfs::read_ns_inode(pid, "pid").await == Some(host)
+}
+
+// Containers owned by a tenant with its own policy are judged by that policy.
+fn policy_engine<'a>(ctx: &'a Context, tenant: Option<&str>) -> &'a PolicyEngine {
+    tenant.and_then(|t| ctx.tenants.engine(t)).unwrap_or(&ctx.engine)
+}
+
+// --pre-action-hook, for the actions that end processes.
+async fn pre_action_hook(ctx: &Context, action: Action, event: &mut DetectionEvent) {
+    let Some(script) = &ctx.config.pre_action_hook else {
+        return;
+    };
+    if !action.blocks() {
+        return;
+    }
+    let limit = ctx.config.pre_action_hook_timeout;
+    if let Some(output) = hook::run_pre_action(script, limit, event, action, ctx.config.forensics_dir.as_deref()).await {
+        event.forensic_artifacts.push(output);
+    }
+}
+
+// Returns whether the container was restarted, which leaves it with new processes.
+// Artifacts the action leaves behind are added to the event.
+async fn apply_action(ctx: &Context, container: &ContainerCgroup, event: &mut DetectionEvent) -> Result<bool, Box<dyn Error>> {
+    let container_id = &container.container_id();
+    let (pid, action) = (event.pid, event.action);
+    match action {
+        Action::Restart | Action::Stop | Action::KillAndCommit | Action::Isolate | Action::Rollback => {
+            let rolls_back = action == Action::Rollback || (ctx.config.rollback_on_detection && action != Action::Stop);
+            // Copied while the container is up, before anything can change its files.
+            if ctx.config.preserve_evidence_on_restart {
+                let dir = &ctx.config.evidence_dir;
+                if let Some(evidence) = forensics::preserve_evidence(dir, container_id, &ctx.config.evidence_paths).await {
+                    info!("Evidence from {} copied to {}", log::id(container_id), evidence);
+                    event.forensic_artifacts.push(evidence);
+                }
+            }
+            // Read before isolate() takes the container off the networks it is recreated on.
+            let spec = match rolls_back {
+                true => Some(rollback_spec(container_id).await?),
+                false => None,
+            };
+            // Cut off first, so the process cannot send anything out while docker stop
+            // waits for the container to exit.
+            if action == Action::Isolate || ctx.config.isolate_on_detection {
+                event.isolated_networks = isolate(container_id).await;
+            }
+            if action == Action::KillAndCommit {
+                // Killed first so the snapshot holds what the process wrote, not what it
+                // goes on to write while the commit runs; the commit must finish before
+                // the restart, which would otherwise race it for the filesystem.
+                kill_process(container, pid).await;
+                if let Some(image) = forensic_commit(container_id).await {
+                    event.forensic_artifacts.push(image);
+                }
+            }
+            let mut rolled_back_to = None;
+            let stop = async {
+                match &spec {
+                    // The replacement is a new container, which discovery monitors afresh.
+                    Some(spec) => rollback(container_id, spec).await.map(|id| {
+                        rolled_back_to = Some(id);
+                        None
+                    }),
+                    None => stop_or_restart(container, action).await,
+                }
+                .map_err(|e| e.to_string())
+            };
+            let restarted = match tokio::time::timeout(ctx.config.max_restart_duration, stop).await {
+                Ok(result) => result,
+                Err(_) => {
+                    action_timed_out(ctx, container, event, action).await;
+                    Ok(None)
+                }
+            };
+            event.rolled_back_to = rolled_back_to;
+            // A stopped container is reconnected too, for when it is started again.
+            if event.rolled_back_to.is_none() {
+                reconnect(container_id, &event.isolated_networks).await;
+            }
+            if let Some(duration) = restarted? {
+                ctx.metrics.record_restart(duration);
+                return Ok(true);
+            }
+        }
+        Action::Kill => kill_process(container, pid).await,
+        Action::LogOnly => {
+            info!("Policy allows process {} in {}, no action taken", pid, log::id(container_id));
+        }
+    }
+    Ok(false)
+}
+
+// Disconnects the container from every network it is on. Returns the ones it was
+// disconnected from, which reconnect() takes it back to.
+async fn isolate(container_id: &str) -> Vec<String> {
+    let networks = match docker::networks(container_id).await {
+        Ok(networks) => networks,
+        Err(e) => {
+            eprintln!("Warning: failed to list the networks of {}, not isolating it: {}", log::id(container_id), e);
+            return Vec::new();
+        }
+    };
+    let mut isolated = Vec::new();
+    for network in networks {
+        match docker::set_connected(&network, container_id, false).await {
+            Ok(()) => isolated.push(network),
+            // The host and none networks cannot be disconnected from.
+            Err(e) => eprintln!("Warning: failed to disconnect {} from {}: {}", log::id(container_id), network, e),
+        }
+    }
+    if !isolated.is_empty() {
+        info!(
+            "{}",
+            color::stdout(
+                Color::Cyan,
+                format!("Disconnected {} from networks {}", log::id(container_id), isolated.join(", "))
+            )
+        );
+    }
+    isolated
+}
+
+async fn reconnect(container_id: &str, networks: &[String]) {
+    for network in networks {
+        match docker::set_connected(network, container_id, true).await {
+            Ok(()) => info!("Reconnected {} to network {}", log::id(container_id), network),
+            Err(e) => eprintln!(
+                "{}",
+                color::stderr(Color::Red, format!("Failed to reconnect {} to {}: {}", log::id(container_id), network, e))
+            ),
+        }
+    }
+}
+
+async fn kill_process(container: &ContainerCgroup, pid: i32) {
+    let container_id = &container.container_id();
+    match cgroup::kill_process(&container.procs_path(), pid).await {
+        Ok(true) => info!(
+            "{}",
+            color::stdout(Color::Cyan, format!("Killed process {} in {}", pid, log::id(container_id)))
+        ),
+        Ok(false) => info!("Process {} already left {}, not killing it", pid, log::id(container_id)),
+        Err(e) => eprintln!(
+            "{}",
+            color::stderr(Color::Red, format!("Failed to kill process {} in {}: {}", pid, log::id(container_id), e))
+        ),
+    }
+}
+
+// Snapshots the container as cnpd-forensics/<name>:<timestamp> for kill-and-commit.
+// Returns the image, or None (with a warning) if the commit failed, in which case the
+// container is restarted all the same.
+async fn forensic_commit(container_id: &str) -> Option<String> {
+    // Image repositories must be lowercase, which container names need not be.
+    let name = docker::container_name(container_id)
+        .await
+        .map(|name| name.to_lowercase())
+        .unwrap_or_else(|_| docker::short_id(container_id).to_string());
+    let image = format!("cnpd-forensics/{}:{}", name, Utc::now().format("%Y%m%d%H%M%S"));
+    match docker::commit_container(container_id, &image).await.map_err(|e| e.to_string()) {
+        Ok(true) => {
+            info!("Committed {} as forensic image {}", log::id(container_id), image);
+            Some(image)
+        }
+        Ok(false) => {
+            eprintln!("Warning: failed to commit {} as {}, restarting it anyway", log::id(container_id), image);
+            None
+        }
+        Err(e) => {
+            eprintln!("Warning: failed to commit {}: {}, restarting it anyway", log::id(container_id), e);
+            None
+        }
+    }
+}
+
+// What the container would be recreated from, read before anything is done to it.
+async fn rollback_spec(container_id: &str) -> Result<ContainerSpec, Box<dyn Error>> {
+    let inspect = docker::inspect_container(container_id).await?;
+    // `docker inspect` prints an array, of the one container here.
+    let inspect = inspect.as_array().and_then(|containers| containers.first()).unwrap_or(&inspect);
+    Ok(ContainerSpec::from_inspect(inspect).map_err(|e| format!("Cannot roll back {}: {}", log::id(container_id), e))?)
+}
+
+// Stops the container and replaces it with one created from `spec`. The old container
+// is only removed once the new one exists, so a failed docker create leaves it stopped
+// instead of gone. Returns the ID of the new container.
+async fn rollback(container_id: &str, spec: &ContainerSpec) -> Result<String, Box<dyn Error>> {
+    if !docker::stop_container(container_id).await? {
+        return Err(format!("Failed to stop Docker container: {}", log::id(container_id)).into());
+    }
+    info!("{}", color::stdout(Color::Cyan, format!("Docker container stopped: {}", log::id(container_id))));
+    let aside = format!("{}-rolled-back-{}", spec.name, docker::short_id(container_id));
+    docker::rename_container(container_id, &aside).await?;
+    let new_id = match docker::create_container(&spec.create_args).await.map_err(|e| e.to_string()) {
+        Ok(id) => id,
+        Err(e) => {
+            if let Err(e) = docker::rename_container(container_id, &spec.name).await {
+                eprintln!("Warning: failed to rename {} back to {}: {}", log::id(container_id), spec.name, e);
+            }
+            return Err(format!("Failed to recreate {} from {}: {}", spec.name, spec.image, e).into());
+        }
+    };
+    for network in &spec.networks {
+        if let Err(e) = docker::set_connected(network, &new_id, true).await {
+            eprintln!("Warning: failed to connect {} to {}: {}", log::id(&new_id), network, e);
+        }
+    }
+    if let Err(e) = docker::remove_container(container_id).await {
+        eprintln!("Warning: failed to remove the rolled back container {}: {}", aside, e);
+    }
+    if !docker::start_container(&new_id).await? {
+        return Err(format!("Failed to start Docker container: {}", log::id(&new_id)).into());
+    }
+    info!(
+        "{}",
+        color::stdout(
+            Color::Cyan,
+            format!("Rolled back {} to {} from image {}", log::id(container_id), log::id(&new_id), spec.image)
+        )
+    );
+    Ok(new_id)
+}
+
+// Returns how long a successful restart took.
+async fn stop_or_restart(container: &ContainerCgroup, action: Action) -> Result<Option<Duration>, Box<dyn Error>> {
+    let container_id = &container.container_id();
+    match action {
+        Action::Restart | Action::KillAndCommit | Action::Isolate => {
+            // Stop the Docker container
+            let stop_start = Utc::now();
+            if !docker::stop_container(container_id).await? {
+                eprintln!("{}", color::stderr(Color::Red, format!("Failed to stop Docker container: {}", log::id(container_id))));
+            } else {
+                info!("{}", color::stdout(Color::Cyan, format!("Docker container stopped: {}", log::id(container_id))));
+
+                // Start the Docker container
+                let started = docker::start_container(container_id).await?;
+
+                let stop_end = Utc::now();
+                let duration = stop_end - stop_start;
+                if !started {
+                    eprintln!("{}", color::stderr(Color::Red, format!("Failed to start Docker container: {}", log::id(container_id))));
+                } else {
+                    info!("{}", color::stdout(Color::Cyan, format!("Docker container started: {}", log::id(container_id))));
+                    info!("Time taken from stop to start: {} ms", duration.num_milliseconds());
+                    return Ok(duration.to_std().ok());
+                }
+            }
+        }
+        Action::Stop => {
+            if !docker::stop_container(container_id).await? {
+                eprintln!("{}", color::stderr(Color::Red, format!("Failed to stop Docker container: {}", log::id(container_id))));
+            } else {
+                info!("{}", color::stdout(Color::Cyan, format!("Docker container stopped: {}", log::id(container_id))));
+            }
+        }
+        _ => {}
+    }
+    Ok(None)
+}
+
+// docker stop waits out the stop timeout of a container that ignores SIGTERM, while the
+// detected process keeps running. Killing the init process ends the whole container.
+async fn action_timed_out(ctx: &Context, container: &ContainerCgroup, event: &DetectionEvent, action: Action) {
+    let container_id = container.container_id();
+    let procs_path = container.procs_path();
+    eprintln!(
+        "{}",
+        color::stderr(
+            Color::Red,
+            format!(
+                "Error: {} of {} took longer than {} ms, killing its init process",
+                if action == Action::Stop { "stop" } else { "restart" },
+                log::id(&container_id),
+                ctx.config.max_restart_duration.as_millis()
+            )
+        )
+    );
+    let init_pid = cgroup::read_procs(&procs_path).await.ok().and_then(|procs| procs.into_iter().min());
+    if let Some(init_pid) = init_pid {
+        match cgroup::kill_process(&procs_path, init_pid).await {
+            Ok(true) => info!("Killed init process {} of {}", init_pid, log::id(&container_id)),
+            Ok(false) => info!("Init process {} already left {}", init_pid, log::id(&container_id)),
+            Err(e) => eprintln!("Failed to kill init process {} of {}: {}", init_pid, log::id(&container_id), e),
+        }
+    }
+    record_event(ctx, &event.clone().into_action_timeout(init_pid.unwrap_or(event.pid))).await;
+}
+
+// Returns whether the container is now in the requested state. Failures only warn:
+// a container that cannot be frozen is still handled, just without the freeze.
+async fn freeze(ctx: &Context, container: &ContainerCgroup, frozen: bool) -> bool {
+    let (verb, result) = if ctx.config.pause_on_detection {
+        let verb = if frozen { "pause" } else { "unpause" };
+        let pause = async { docker::set_paused(&container.container_id(), frozen).await.map_err(|e| e.to_string()) };
+        (verb, tokio::time::timeout(FREEZE_TIMEOUT, pause).await)
+    } else {
+        let verb = if frozen { "freeze" } else { "thaw" };
+        let freeze = async { cgroup::set_frozen(container, frozen).await.map_err(|e| e.to_string()) };
+        (verb, tokio::time::timeout(FREEZE_TIMEOUT, freeze).await)
+    };
+    match result {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            eprintln!("Warning: failed to {} {}: {}", verb, log::id(&container.container_id()), e);
+            false
+        }
+        Err(_) => {
+            eprintln!(
+                "Warning: failed to {} {} within {} ms",
+                verb,
+                log::id(&container.container_id()),
+                FREEZE_TIMEOUT.as_millis()
+            );
+            false
+        }
+    }
+}
+
+// Thaws a container frozen for this detection, if it still is, and records how long
+// it stayed frozen.
+async fn thaw(ctx: &Context, container: &ContainerCgroup, frozen_at: &mut Option<Instant>, event: &mut DetectionEvent) {
+    if let Some(since) = frozen_at.take() {
+        freeze(ctx, container, false).await;
+        event.pause_duration_ms = Some(since.elapsed().as_millis() as u64);
+    }
+}
+
+// Returns where the checkpoint was stored, or None (with a warning) if CRIU failed.
+async fn checkpoint(ctx: &Context, container_id: &str) -> Option<String> {
+    let checkpoint_id = format!("cnpd-{}-{}", docker::short_id(container_id), Local::now().timestamp());
+    let dir = ctx.config.checkpoint_dir.as_deref();
+
+    match docker::checkpoint_container(container_id, &checkpoint_id, dir).await {
+        Ok(()) => {
+            let path = match dir {
+                Some(dir) => format!("{}/{}", dir.trim_end_matches('/'), checkpoint_id),
+                None => format!("/var/lib/docker/containers/{}/checkpoints/{}", container_id, checkpoint_id),
+            };
+            info!("Checkpointed {} to {}", log::id(container_id), path);
+            Some(path)
+        }
+        Err(e) => {
+            eprintln!("Warning: failed to checkpoint {}, stopping it anyway: {}", log::id(container_id), e);
+            None
+        }
+    }
+}
+
+async fn record_event(ctx: &Context, event: &DetectionEvent) {
+    for detection in std::iter::once(event).chain(&event.correlated_events) {
+        if detection.kind == EventKind::NewProcess {
+            ctx.metrics.record_outcome(detection.action.blocks());
+        }
+        if detection.kind == EventKind::NewProcess && (ctx.config.watch_seccomp_violations || ctx.config.detect_ptrace_usage) {
+            let mut ids = ctx.detection_ids.lock().unwrap();
+            if ids.len() >= MAX_DETECTION_IDS {
+                ids.clear();
+            }
+            ids.insert((detection.container_id.clone(), detection.pid), detection.id.clone());
+        }
+    }
+    ctx.events.push(event.clone()).await;
+}
+
+// Writes a recorded event to every configured output, from the task draining ctx.events.
+async fn deliver_event(ctx: &Context, event: &DetectionEvent) {
+    if ctx.config.report_format == OutputFormat::Ndjson {
+        println!("{}", event.to_json());
+    }
+    if let Some(sink) = &ctx.syslog {
+        sink.send(event);
+    }
+    if let Some(publisher) = &ctx.redis {
+        publisher.send(event);
+    }
+    if let Some(sink) = &ctx.cloudwatch {
+        sink.send(event);
+    }
+    if let Some(store) = &ctx.sqlite {
+        store.send(event);
+    }
+    if let Some(journal) = &ctx.journald {
+        journal.send(event);
+    }
+    if let Some(falco) = &ctx.falco {
+        falco.send(event);
+    }
+    if let Some(router) = ctx.router.clone() {
+        let name = ctx.inventory.name(&event.container_id);
+        let event = event.clone();
+        tokio::spawn(async move {
+            for sent in router.route(&event, name.as_deref()).await {
+                match sent.result {
+                    Ok(()) => debug!("Event {} sent to sink {}", event.id, sent.sink),
+                    Err(e) => eprintln!("Failed to send event {} to sink {}: {}", event.id, sent.sink, e),
+                }
+            }
+        });
+    }
+    if let Some(simulation) = &ctx.simulation {
+        let _ = simulation.send(event.clone());
+        for detection in &event.correlated_events {
+            let _ = simulation.send(detection.clone());
+        }
+    }
+    if let Some(log) = &ctx.event_log {
+        if let Err(e) = log.append(event).await {
+            eprintln!("Failed to write event to {}: {}", log.path(), e);
+        }
+    } else if let Some(path) = &ctx.config.output_file {
+        if let Err(e) = event::append_event(path, event).await {
+            eprintln!("Failed to write event to {}: {}", path, e);
+        }
+    }
+    // The outputs above are the admin view with every event; tenants also get their own.
+    let Some(tenant) = event.tenant.as_deref().and_then(|t| ctx.tenants.get(t)) else {
+        return;
+    };
+    if let Some(path) = &tenant.log_file {
+        if let Err(e) = event::append_event(path, event).await {
+            eprintln!("Failed to write event to {}: {}", path, e);
+        }
+    }
+    if let Some(url) = tenant.webhook.clone() {
+        let body = event.to_json().to_string();
+        tokio::spawn(async move {
+            match webhook::post_json(&url, &body).await.map_err(|e| e.to_string()) {
+                Ok(response) if response.is_success() => {}
+                Ok(response) => eprintln!("Webhook {} answered with HTTP {}", url, response.status),
+                Err(e) => eprintln!("Failed to send event to webhook: {}", e),
+            }
+        });
+    }
+}
+
+// Logs OOM kills in the container from a separate task and publishes when the last one happened.
+async fn watch_oom_kills(container: &ContainerCgroup) -> watch::Receiver<Option<Instant>> {
+    let (tx, rx) = watch::channel(None);
+    let mut watcher = match OomWatcher::new(container).await {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("Not watching OOM kills in {}: {}", log::id(&container.container_id()), e);
+            return rx;
+        }
+    };
+    tokio::spawn(async move {
+        loop {
+            match watcher.next().await {
+                Ok(event) => {
+                    let _ = tx.send(Some(Instant::now()));
+                    eprintln!(
+                        "{}",
+                        color::stderr(
+                            Color::Red,
+                            format!(
+                                "[{}] \t OOM kill detected - \t {} \t {} kill(s), {} total",
+                                event.detected_at.format("%Y-%m-%d %H:%M:%S%.3f"),
+                                log::id(&event.container_id),
+                                event.kills,
+                                event.total
+                            )
+                        )
+                    );
+                }
+                Err(e) => {
+                    eprintln!("Stopped watching OOM kills: {}", e);
+                    return;
+                }
+            }
+            if tx.is_closed() {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+// With --detect-file-writes, reports files written in the container from a separate
+// task, which ends once the returned sender is dropped. A container whose writes cannot
+// be watched, e.g. without CAP_SYS_ADMIN, is still monitored for new processes.
+async fn watch_file_writes(ctx: &Arc<Context>, container: &ContainerCgroup, init_pid: i32) -> Option<oneshot::Sender<()>> {
+    let container_id = container.container_id();
+    let mut watcher = match FileWriteWatcher::new(init_pid) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            let hint = if e.raw_os_error() == Some(libc::EPERM) { ", it needs CAP_SYS_ADMIN" } else { "" };
+            eprintln!("Warning: not watching file writes in {}: {}{}", log::id(&container_id), e, hint);
+            return None;
+        }
+    };
+    let upperdir = fanotify::read_upperdir(init_pid).await;
+    let (stop_tx, mut stop_rx) = oneshot::channel();
+    let (ctx, container) = (ctx.clone(), container.clone());
+    tokio::spawn(async move {
+        loop {
+            let write = tokio::select! {
+                write = watcher.next() => write,
+                _ = &mut stop_rx => return,
+            };
+            match write {
+                Ok(write) => report_file_write(&ctx, &container, upperdir.as_deref(), write).await,
+                Err(e) => {
+                    eprintln!("Stopped watching file writes in {}: {}", log::id(&container_id), e);
+                    return;
+                }
+            }
+        }
+    });
+    Some(stop_tx)
+}
+
+// With --detect-mount-namespace-changes, re-reads the mount table of the container's
+// init process every MOUNT_POLL_INTERVAL from a separate task and reports the mounts
+// that were not there when it started. The task ends once the returned sender is dropped.
+async fn watch_mounts(ctx: &Arc<Context>, container_id: &str, init_pid: i32) -> Option<oneshot::Sender<()>> {
+    let Some(mounts) = mountinfo::read_mountinfo(init_pid).await else {
+        eprintln!("Warning: not watching mounts in {}: cannot read the mount table of PID {}", log::id(container_id), init_pid);
+        return None;
+    };
+    // Mount IDs are reused once freed, so a mount is told apart by its target as well.
+    let mut known: HashSet<(u32, String)> = mounts.into_iter().map(|m| (m.mount_id, m.target)).collect();
+    let (stop_tx, mut stop_rx) = oneshot::channel();
+    let (ctx, container_id) = (ctx.clone(), container_id.to_string());
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = sleep(MOUNT_POLL_INTERVAL) => {}
+                _ = &mut stop_rx => return,
+            }
+            // The init process is gone with its container; the restart starts a new task.
+            let Some(mounts) = mountinfo::read_mountinfo(init_pid).await else {
+                return;
+            };
+            let mut current = HashSet::new();
+            for mount in mounts {
+                let key = (mount.mount_id, mount.target.clone());
+                if !known.contains(&key) {
+                    report_mount_change(&ctx, &container_id, init_pid, &mount).await;
+                }
+                current.insert(key);
+            }
+            known = current;
+        }
+    });
+    Some(stop_tx)
+}
+
+// With --use-bcc, the script running for the container. One that cannot be started
+// leaves the container monitored for new processes only.
+fn start_bcc(ctx: &Context, container: &ContainerCgroup) -> Option<BccProgram> {
+    let script = ctx.config.use_bcc.as_deref()?;
+    match BccProgram::start(script, container) {
+        Ok(program) => Some(program),
+        Err(e) => {
+            eprintln!("Warning: no BCC detection in {}: {}", log::id(&container.container_id()), e);
+            None
+        }
+    }
+}
+
+// An event of the --use-bcc script, acted on as a detected new process would be: with
+// the container's tenant, group and risk, and the action of the policy. Returns whether
+// the container was restarted.
+async fn report_bcc_event(
+    ctx: &Context,
+    container: &ContainerCgroup,
+    mut event: DetectionEvent,
+    log_only: bool,
+) -> Result<bool, Box<dyn Error>> {
+    let container_id = container.container_id();
+    eprintln!(
+        "{}",
+        color::stderr(
+            Color::Red,
+            format!(
+                "[{}] \t BCC detection - \t {} \t {} \t {} \t {}",
+                event.detected_at,
+                log::id(&container_id),
+                event.pid,
+                event.kind,
+                event.exe.as_deref().unwrap_or("-")
+            )
+        )
+    );
+    ctx.metrics.record_detection(&container_id, None);
+    event.tenant = ctx.inventory.tenant(&container_id);
+    event.group_name = ctx.inventory.group(&container_id);
+    event.container_labels = ctx.inventory.labels(&container_id);
+    if let Some(group) = &event.group_name {
+        event.group_peer_containers = ctx.inventory.group_members(group).into_iter().filter(|id| *id != container_id).collect();
+    }
+    set_risk(ctx, &mut event);
+    event.action = match log_only {
+        true => Action::LogOnly,
+        false => policy_engine(ctx, event.tenant.as_deref()).evaluate(&event),
+    };
+    ctx.inventory.record_detection(&container_id, &event.detected_at);
+    pre_action_hook(ctx, event.action, &mut event).await;
+    plugin::run_plugins(&ctx.plugins, &event).await;
+    let restarted = apply_action(ctx, container, &mut event).await?;
+    stop_group_peers(ctx, &event).await;
+    record_event(ctx, &event).await;
+    Ok(restarted)
+}
+
+// Unlinked files a new process holds open, read before any action ends the process.
+// Only logged, with the ID of the new-process detection they belong to.
+async fn report_hidden_files(ctx: &Context, detection: &DetectionEvent) {
+    let container_id = &detection.container_id;
+    for file in procfs::read_deleted_files(detection.pid).await {
+        let detected_at = Local::now();
+        eprintln!(
+            "{}",
+            color::stderr(
+                Color::Red,
+                format!(
+                    "[{}] \t Hidden file - \t {} \t {} \t fd {} {} (deleted, {} bytes)",
+                    detected_at.format("%Y-%m-%d %H:%M:%S%.3f"),
+                    log::id(container_id),
+                    detection.pid,
+                    file.fd,
+                    file.path,
+                    file.size.map_or("?".to_string(), |size| size.to_string())
+                )
+            )
+        );
+        let mut event = DetectionEvent::hidden_file(container_id, detection.pid, &file, detected_at);
+        event.exe = detection.exe.clone();
+        event.cmdline = detection.cmdline.clone();
+        event.tenant = detection.tenant.clone();
+        event.related_event_id = Some(detection.id.clone());
+        set_risk(ctx, &mut event);
+        ctx.inventory.record_detection(container_id, &event.detected_at);
+        plugin::run_plugins(&ctx.plugins, &event).await;
+        record_event(ctx, &event).await;
+    }
+}
+
+async fn report_mount_change(ctx: &Context, container_id: &str, init_pid: i32, mount: &MountEntry) {
+    let shadows = mount.shadows(&ctx.config.sensitive_mount_paths);
+    let detected_at = Local::now();
+    let message = format!(
+        "[{}] \t New mount - \t {} \t {} on {} ({}){}",
+        detected_at.format("%Y-%m-%d %H:%M:%S%.3f"),
+        log::id(container_id),
+        mount.describe_source(),
+        mount.target,
+        mount.options,
+        if shadows { ", shadows a sensitive path" } else { "" }
+    );
+    if shadows {
+        eprintln!("{}", color::stderr(Color::Red, message));
+    } else {
+        info!("{}", color::stdout(Color::Yellow, message));
+    }
+    let mut event = DetectionEvent::mount_change(container_id, init_pid, mount, shadows, detected_at);
+    event.tenant = ctx.inventory.tenant(container_id);
+    set_risk(ctx, &mut event);
+    ctx.inventory.record_detection(container_id, &event.detected_at);
+    plugin::run_plugins(&ctx.plugins, &event).await;
+    record_event(ctx, &event).await;
+}
+
+// fanotify reports the write once the file is closed, when it can no longer be
+// prevented, so a write matching --block-file-writes kills the writer instead.
+async fn report_file_write(ctx: &Context, container: &ContainerCgroup, upperdir: Option<&str>, write: FileWrite) {
+    let container_id = container.container_id();
+    let detected_at = Local::now();
+    info!(
+        "{}",
+        color::stdout(
+            Color::Yellow,
+            format!(
+                "[{}] \t File written - \t {} \t {} \t {}",
+                detected_at.format("%Y-%m-%d %H:%M:%S%.3f"),
+                log::id(&container_id),
+                write.pid,
+                write.path
+            )
+        )
+    );
+    let mut event = DetectionEvent::file_write(&container_id, write.pid, &write.path, detected_at);
+    event.file_host_path = upperdir.map(|dir| format!("{}{}", dir.trim_end_matches('/'), write.path));
+    let process = BaselineProcess::read(write.pid).await;
+    event.exe = process.exe;
+    event.cmdline = process.cmdline;
+    event.tenant = ctx.inventory.tenant(&container_id);
+    set_risk(ctx, &mut event);
+    let blocked = ctx.config.blocks_file_write(&write.path);
+    if blocked {
+        event.action = Action::Kill;
+        pre_action_hook(ctx, event.action, &mut event).await;
+    }
+    plugin::run_plugins(&ctx.plugins, &event).await;
+    if blocked {
+        if let Err(e) = apply_action(ctx, container, &mut event).await {
+            eprintln!("Failed to act on the write to {} in {}: {}", write.path, log::id(&container_id), e);
+            ctx.inventory.record_error(&container_id);
+        }
+    }
+    record_event(ctx, &event).await;
+}
+
+async fn save_process_counts(baselines: &ProcessCountBaselines) {
+    if let Err(e) = baselines.save().await {
+        eprintln!("Failed to write process counts to {}: {}", baselines.path(), e);
+    }
+}
+
+fn watch_seccomp_violations(ctx: &Arc<Context>) -> Result<(), Box<dyn Error>> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    audit::listen_seccomp(tx)?;
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        while let Some(record) = rx.recv().await {
+            report_seccomp_violation(&ctx, record).await;
+        }
+    });
+    Ok(())
+}
+
+// A process trying a syscall its profile blocks is likely probing or exploiting the
+// container, so the violation is reported, tied to the container by the cgroup of the
+// process. Killed processes may be gone before their cgroup is read, and cannot be tied.
+async fn report_seccomp_violation(ctx: &Context, record: SeccompRecord) {
+    let cgroup = procfs::read_proc_file(record.pid, "cgroup").await;
+    let Some(container_id) = cgroup.and_then(|cgroup| cgroup::container_id_of(&String::from_utf8_lossy(&cgroup))) else {
+        debug!("Seccomp violation by PID {} outside any known container", record.pid);
+        return;
+    };
+    if !ctx.inventory.contains(&container_id) {
+        return;
+    }
+    let syscall = match syscalls::syscall_name(record.syscall).filter(|_| record.native()) {
+        Some(name) => name.to_string(),
+        None => record.syscall.to_string(),
+    };
+    let detected_at = Local::now();
+    eprintln!(
+        "{}",
+        color::stderr(
+            Color::Red,
+            format!(
+                "[{}] \t Seccomp violation - \t {} \t {} \t {} ({})",
+                detected_at.format("%Y-%m-%d %H:%M:%S%.3f"),
+                log::id(&container_id),
+                record.pid,
+                syscall,
+                record.action()
+            )
+        )
+    );
+    let mut event = DetectionEvent::seccomp_violation(&container_id, record.pid, &syscall, record.action(), detected_at);
+    event.exe = record.exe.clone().or(procfs::read_exe(record.pid).await);
+    event.cmdline = procfs::read_cmdline(record.pid).await;
+    event.tenant = ctx.inventory.tenant(&container_id);
+    event.related_event_id = ctx.detection_ids.lock().unwrap().get(&(container_id.clone(), record.pid)).cloned();
+    set_risk(ctx, &mut event);
+    ctx.inventory.record_detection(&container_id, &event.detected_at);
+    plugin::run_plugins(&ctx.plugins, &event).await;
+    record_event(ctx, &event).await;
+}
+
+#[cfg(feature = "ebpf")]
+fn watch_ptrace(ctx: &Arc<Context>) -> Result<(), Box<dyn Error>> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    container_new_process_detector::ebpf::trace_ptrace(tx)?;
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        while let Some(call) = rx.recv().await {
+            report_ptrace(&ctx, call).await;
+        }
+    });
+    Ok(())
+}
+
+// Attaching to another process gives access to its memory, so a monitored container's
+// process doing it is reported even when the tracer itself is whitelisted.
+#[cfg(feature = "ebpf")]
+async fn report_ptrace(ctx: &Context, call: container_new_process_detector::ebpf::PtraceCall) {
+    let cgroup = procfs::read_proc_file(call.tracer_pid, "cgroup").await;
+    let Some(container_id) = cgroup.and_then(|cgroup| cgroup::container_id_of(&String::from_utf8_lossy(&cgroup))) else {
+        return;
+    };
+    if !ctx.inventory.contains(&container_id) {
+        return;
+    }
+    let request = syscalls::ptrace_request_name(call.request);
+    let detected_at = Local::now();
+    eprintln!(
+        "{}",
+        color::stderr(
+            Color::Red,
+            format!(
+                "[{}] \t ptrace attach - \t {} \t {} \t {} on PID {}",
+                detected_at.format("%Y-%m-%d %H:%M:%S%.3f"),
+                log::id(&container_id),
+                call.tracer_pid,
+                request,
+                call.tracee_pid
+            )
+        )
+    );
+    let mut event = DetectionEvent::ptrace(&container_id, call.tracer_pid, call.tracee_pid, &request, detected_at);
+    event.exe = procfs::read_exe(call.tracer_pid).await;
+    event.cmdline = procfs::read_cmdline(call.tracer_pid).await;
+    event.tenant = ctx.inventory.tenant(&container_id);
+    event.related_event_id = ctx.detection_ids.lock().unwrap().get(&(container_id.clone(), call.tracer_pid)).cloned();
+    set_risk(ctx, &mut event);
+    ctx.inventory.record_detection(&container_id, &event.detected_at);
+    plugin::run_plugins(&ctx.plugins, &event).await;
+    record_event(ctx, &event).await;
+}
+
+// Detections of one container since the first of them, for --event-correlation-window.
+// `action` is the most severe one taken on the container during the burst.
+struct Burst {
+    started: Instant,
+    action: Action,
+    events: Vec<DetectionEvent>,
+}
+
+// A burst of a single detection is recorded as it is.
+async fn record_burst(ctx: &Context, container_id: &str, mut burst: Burst) {
+    if burst.events.len() == 1 {
+        record_event(ctx, &burst.events.remove(0)).await;
+        return;
+    }
+    info!(
+        "[CORRELATED] {} detections in {} within {} ms, action {}",
+        burst.events.len(),
+        log::id(container_id),
+        burst.started.elapsed().as_millis(),
+        burst.action
+    );
+    record_event(ctx, &DetectionEvent::correlated(burst.events, burst.action)).await;
+}
+
+async fn exit_event(ctx: &Context, container_id: &str, process: BaselineProcess) -> ProcessExitEvent {
+    let exited_at = Local::now();
+    info!(
+        "{}",
+        color::stdout(
+            Color::Yellow,
+            format!(
+                "[{}] \t Whitelisted process exited - \t {} \t {}",
+                exited_at.format("%Y-%m-%d %H:%M:%S%.3f"),
+                log::id(container_id),
+                process.pid
+            )
+        )
+    );
+
+    let mut event = DetectionEvent::process_exit(container_id, process.pid, exited_at);
+    event.exe = process.exe;
+    event.cmdline = process.cmdline;
+    event.action = ctx.config.exit_action;
+    set_risk(ctx, &mut event);
+    plugin::run_plugins(&ctx.plugins, &event).await;
+    event
+}
+
+// Reports every process in `pids` whose user namespace differs from the container's
+// init process, a strong sign of a breakout. The container is stopped whatever the
+// policy says. Returns the reported PIDs, and whether the container was restarted,
+// which ends the check as the remaining PIDs are gone.
+async fn check_user_namespaces(
+    ctx: &Context,
+    cache: &ProcCache,
+    container: &ContainerCgroup,
+    container_ns: u64,
+    pids: &[i32],
+) -> Result<(Vec<i32>, bool), Box<dyn Error>> {
+    let container_id = container.container_id();
+    let mut escaped = Vec::new();
+    for &pid in pids {
+        let Some(ns) = procfs::read_ns_inode(pid, "user").await.filter(|ns| *ns != container_ns) else {
+            continue;
+        };
+        let detected_at = Local::now();
+        eprintln!(
+            "{}",
+            color::stderr(
+                Color::Red,
+                format!(
+                    "[{}] \t User namespace escape - \t {} \t {} \t user:[{}], container init user:[{}]",
+                    detected_at.format("%Y-%m-%d %H:%M:%S%.3f"),
+                    log::id(&container_id),
+                    pid,
+                    ns,
+                    container_ns
+                )
+            )
+        );
+        let mut event = build_event(ctx, cache, &container_id, pid, detected_at).await.into_namespace_escape(ns, container_ns);
+        ctx.inventory.record_detection(&container_id, &event.detected_at);
+        pre_action_hook(ctx, event.action, &mut event).await;
+        plugin::run_plugins(&ctx.plugins, &event).await;
+        let restarted = apply_action(ctx, container, &mut event).await?;
+        stop_group_peers(ctx, &event).await;
+        record_event(ctx, &event).await;
+        escaped.push(pid);
+        if restarted {
+            return Ok((escaped, true));
+        }
+    }
+    Ok((escaped, false))
+}
+
+// The effective UIDs of the process and its parent, when a process running as root
+// was started by one that was not, e.g. through a setuid binary.
+async fn privilege_escalation(pid: i32) -> Option<(u32, u32)> {
+    let (_, euid) = procfs::read_uids(pid).await?;
+    let (_, parent_euid) = procfs::read_uids(procfs::read_ppid(pid).await?).await?;
+    (euid == 0 && parent_euid != 0).then_some((euid, parent_euid))
+}
+
+// Effective capabilities of the process outside every capability set of the container's
+// init process, which it can only have gained from file capabilities or a setuid binary.
+async fn extra_capabilities(pid: i32, init: &CapabilitySets) -> Option<u64> {
+    let sets = procfs::read_capability_sets(pid).await?;
+    let extra = sets.effective & !(init.inheritable | init.permitted | init.effective);
+    (extra != 0).then_some(extra)
+}
+
+// With group_action = "stop-all", a detection that is acted on stops the rest of the
+// container's group as well, since an attacker may move on to a neighbouring service.
+async fn stop_group_peers(ctx: &Context, event: &DetectionEvent) {
+    let stop_all = event
+        .group_name
+        .as_deref()
+        .and_then(|name| ctx.config.groups.iter().find(|g| g.name == name))
+        .is_some_and(|group| group.stop_all);
+    if !stop_all || !event.action.blocks() {
+        return;
+    }
+
+    let mut stops = JoinSet::new();
+    for peer in event.group_peer_containers.clone() {
+        stops.spawn(async move {
+            let stopped = docker::stop_container(&peer).await.map_err(|e| e.to_string());
+            (peer, stopped)
+        });
+    }
+    while let Some(Ok((peer, stopped))) = stops.join_next().await {
+        match stopped {
+            Ok(true) => info!(
+                "{}",
+                color::stdout(
+                    Color::Cyan,
+                    format!("Container {} stopped with the rest of group {}", log::id(&peer), event.group_name.as_deref().unwrap_or_default())
+                )
+            ),
+            Ok(false) => eprintln!("{}", color::stderr(Color::Red, format!("Failed to stop Docker container: {}", log::id(&peer)))),
+            Err(e) => eprintln!("Failed to stop group member {}: {}", log::id(&peer), e),
+        }
+    }
+}
+
+// None when the signature could not be checked at all, e.g. cosign is not installed.
+async fn image_signature(ctx: &Context, container_id: &str) -> Option<SignatureStatus> {
+    let result = match docker::container_image(container_id).await.map_err(|e| e.to_string()) {
+        Ok(image) => signature::verify_image(&image, ctx.config.cosign_key.as_deref(), ctx.config.rekor_url.as_deref())
+            .await
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(status) => Some(status),
+        Err(e) => {
+            eprintln!("Failed to verify the image signature of {}: {}", log::id(container_id), e);
+            None
+        }
+    }
+}
+
+async fn report_overload(ctx: &Context, container_id: &str, init_pid: Option<i32>, process_count: usize, rate: Option<f64>) {
+    let mut event = DetectionEvent::overload(container_id, init_pid.unwrap_or(0), process_count, Local::now());
+    event.process_growth_rate = rate;
+    event.tenant = ctx.inventory.tenant(container_id);
+    set_risk(ctx, &mut event);
+    let reason = match rate {
+        Some(rate) => format!("grew by {:.1} processes/s to {}", rate, process_count),
+        None => format!("runs {} processes", process_count),
+    };
+    eprintln!("{}", color::stderr(Color::Yellow, format!("Warning: container {} {}", log::id(container_id), reason)));
+    record_event(ctx, &event).await;
+}
+
+async fn report_degraded(ctx: &Context, container_id: &str, init_pid: Option<i32>, score: u32) {
+    let mut event = DetectionEvent::monitoring_degraded(container_id, init_pid.unwrap_or(0), score, Local::now());
+    event.tenant = ctx.inventory.tenant(container_id);
+    eprintln!(
+        "{}",
+        color::stderr(
+            Color::Yellow,
+            format!("Warning: monitoring of {} is degraded, health score {}", log::id(container_id), score)
+        )
+    );
+    record_event(ctx, &event).await;
+}
+
+async fn report_subsystem_change(ctx: &Context, container_id: &str, init_pid: Option<i32>, change: &SubsystemChange) {
+    let mut event = DetectionEvent::subsystem_change(container_id, init_pid.unwrap_or(0), change, Local::now());
+    event.tenant = ctx.inventory.tenant(container_id);
+    set_risk(ctx, &mut event);
+    let reason = match change {
+        SubsystemChange::CpuSpike { rate, baseline } => {
+            format!("uses {:.0}% CPU, usually {:.0}%", rate / 10_000.0, baseline / 10_000.0)
+        }
+        SubsystemChange::IoSpike { rate, baseline } => format!(
+            "does {:.1} MiB/s of I/O, usually {:.1} MiB/s",
+            rate / (1024.0 * 1024.0),
+            baseline / (1024.0 * 1024.0)
+        ),
+        SubsystemChange::NetClassChange { from, to } => format!("moved from network class {:#x} to {:#x}", from, to),
+    };
+    eprintln!("{}", color::stderr(Color::Yellow, format!("Warning: container {} {}", log::id(container_id), reason)));
+    record_event(ctx, &event).await;
+}
+
+async fn report_process_count_anomaly(ctx: &Context, container_id: &str, init_pid: Option<i32>, anomaly: ProcessCountAnomaly) {
+    let mut event = DetectionEvent::overload(container_id, init_pid.unwrap_or(0), anomaly.count as usize, Local::now());
+    event.process_count_mean = Some(anomaly.mean);
+    event.process_count_stddev = Some(anomaly.stddev);
+    event.tenant = ctx.inventory.tenant(container_id);
+    set_risk(ctx, &mut event);
+    eprintln!(
+        "{}",
+        color::stderr(
+            Color::Yellow,
+            format!(
+                "Warning: container {} runs {} processes, usually {:.1} ± {:.1}",
+                log::id(container_id),
+                anomaly.count,
+                anomaly.mean,
+                anomaly.stddev
+            )
+        )
+    );
+    record_event(ctx, &event).await;
+}
+
+// Returns false when the container was stopped for running without a seccomp profile.
+async fn check_seccomp(ctx: &Context, container: &ContainerCgroup, procs: &HashSet<i32>) -> bool {
+    let container_id = container.container_id();
+    let name = docker::container_name(&container_id).await.ok();
+    if ctx.config.is_exempt(&container_id, name.as_deref()) {
+        return true;
+    }
+
+    let has_profile = docker::has_seccomp_profile(&container_id, procs.iter().min().copied())
+        .await
+        .map_err(|e| e.to_string());
+    match has_profile {
+        Ok(true) => true,
+        Ok(false) => {
+            match docker::stop_container(&container_id).await.map_err(|e| e.to_string()) {
+                Ok(true) => info!(
+                    "{}",
+                    color::stdout(Color::Cyan, format!("Container {} stopped: no seccomp profile", log::id(&container_id)))
+                ),
+                _ => eprintln!("{}", color::stderr(Color::Red, format!("Failed to stop Docker container: {}", log::id(&container_id)))),
+            }
+            false
+        }
+        Err(e) => {
+            eprintln!("Failed to check seccomp profile of {}: {}", log::id(&container_id), e);
+            true
+        }
+    }
+}
+
+// The processes a container runs once its restart completed, which replace its
+// whitelist. The new init process may take a moment to join the cgroup, so this waits up
+// to --post-restart-grace-ms for one.
+async fn restarted_procs(ctx: &Context, container: &ContainerCgroup) -> Result<HashSet<(i32, u64)>, Box<dyn Error>> {
+    let grace = Duration::from_millis(ctx.config.post_restart_grace_ms);
+    let procs = cgroup::wait_for_procs(&container.procs_path(), grace).await?;
+    if procs.is_empty() {
+        eprintln!(
+            "Warning: {} has no processes {} ms after its restart",
+            log::id(&container.container_id()),
+            grace.as_millis()
+        );
+    }
+    Ok(procfs::process_keys(&procs).await)
+}
+
+// With --kill-container-on-exit-of-pid, the process among `procs` that has the given
+// PID inside the container.
+async fn find_ns_pid(procs: &HashSet<(i32, u64)>, ns_pid: i32) -> Option<(i32, u64)> {
+    for key in procs {
+        if procfs::read_ns_pid(key.0).await == Some(ns_pid) {
+            return Some(*key);
+        }
+    }
+    None
+}
+
+// Stops the container once the process watched by --kill-container-on-exit-of-pid is
+// gone: a single-process container without it is no longer doing what it was built for.
+async fn watched_process_exited(ctx: &Context, container: &ContainerCgroup, ns_pid: i32, process: BaselineProcess) -> Result<(), Box<dyn Error>> {
+    let container_id = container.container_id();
+    eprintln!(
+        "{}",
+        color::stderr(
+            Color::Red,
+            format!("PID {} ({} in the container) of {} exited, stopping the container", process.pid, ns_pid, log::id(&container_id))
+        )
+    );
+    let mut event = exit_event(ctx, &container_id, process).await;
+    event.action = Action::Stop;
+    pre_action_hook(ctx, event.action, &mut event).await;
+    apply_action(ctx, container, &mut event).await?;
+    record_event(ctx, &event).await;
+    Ok(())
+}
+
+fn publish_whitelist(ctx: &Context, whitelist: &ContainerWhitelist, container_id: &str, known_procs: &HashSet<(i32, u64)>) {
+    whitelist.publish(known_procs);
+    ctx.inventory.set_whitelist_pids(container_id, distinct_pids(known_procs).len());
+}
+
+async fn monitor_procs(
+    container: ContainerCgroup,
+    initial_procs: HashSet<i32>,
+    ctx: Arc<Context>,
+) -> Result<(), Box<dyn Error>> {
+    let cgroup_path = container.procs_path();
+    let mut known_procs: procfs::KnownProcs = procfs::process_keys(&initial_procs).await.into();
+    let container_id = container.container_id();
+    ctx.inventory.register(&container_id, MonitorState::Monitoring, known_procs.len());
+    let whitelist = ctx.whitelists.register(&container_id);
+    publish_whitelist(&ctx, &whitelist, &container_id, &known_procs);
+    let name = docker::container_name(&container_id).await.ok();
+    if let Some(tenant) = ctx.tenants.find(&container_id, name.as_deref()) {
+        ctx.inventory.set_tenant(&container_id, tenant.name.clone());
+    }
+    if let Some(group) = ctx.config.groups.iter().find(|g| g.owns(&container_id, name.as_deref())) {
+        ctx.inventory.set_group(&container_id, group.name.clone());
+    }
+    if let Some(name) = name {
+        ctx.inventory.set_name(&container_id, name);
+    }
+    if ctx.config.container_labels_in_events {
+        match docker::labels(&container_id).await {
+            Ok(mut labels) => {
+                labels.retain(|key, _| ctx.config.includes_label(key));
+                ctx.inventory.set_labels(&container_id, labels);
+            }
+            Err(e) => eprintln!("Warning: failed to read the labels of {}: {}", log::id(&container_id), e),
+        }
+    }
+
+    // Entry-point scripts spawn many short-lived setup processes right after start,
+    // so give young containers time to settle before taking the snapshot.
+    if let Some(delay) = ctx.config.container_start_delay {
+        let age = cgroup::cgroup_age(&container).await.unwrap_or_default();
+        if let Some(remaining) = delay.checked_sub(age).filter(|d| !d.is_zero()) {
+            info!(
+                "[WAITING] {} started {} ms ago, waiting {} ms before taking the snapshot",
+                log::id(&container.container_id()),
+                age.as_millis(),
+                remaining.as_millis()
+            );
+            ctx.inventory.set_state(&container_id, MonitorState::GracePeriod);
+            sleep(remaining).await;
+            let procs = cgroup::read_procs(&cgroup_path).await?;
+            known_procs = procfs::process_keys(&procs).await.into();
+            ctx.inventory.register(&container_id, MonitorState::Monitoring, known_procs.len());
+            publish_whitelist(&ctx, &whitelist, &container_id, &known_procs);
+        }
+    }
+    // Registered after the start delay so the wait is not mistaken for a stuck task.
+    let heartbeat = ctx.watchdog.heartbeat(&container_id);
+    let mut cgroup_present = true;
+
+    // Set once known_procs outgrows --max-known-pids, or for a container in the host PID
+    // namespace; from then on detections are only logged.
+    let mut log_only = false;
+
+    let last_oom_kill = watch_oom_kills(&container).await;
+
+    // Exe and cmdline of whitelisted processes, read up front so an exit can still be described.
+    let mut whitelisted = HashMap::new();
+    if ctx.config.alert_on_process_exit {
+        for (pid, _) in known_procs.iter() {
+            whitelisted.insert(*pid, BaselineProcess::read(*pid).await);
+        }
+    }
+
+    let mut init_pid = known_procs.iter().map(|(pid, _)| *pid).min();
+    // Held while the container is monitored; replaced after a restart, whose new root
+    // mount the old watcher does not see.
+    let mut _file_writes = match init_pid {
+        Some(pid) if ctx.config.detect_file_writes => watch_file_writes(&ctx, &container, pid).await,
+        _ => None,
+    };
+    let mut _mounts = match init_pid {
+        Some(pid) if ctx.config.detect_mount_namespace_changes => watch_mounts(&ctx, &container_id, pid).await,
+        _ => None,
+    };
+    // Restarted with the container, which comes back in a new cgroup.
+    let mut bcc = start_bcc(&ctx, &container);
+    match ContainerRisk::assess(&container_id, init_pid).await.map_err(|e| e.to_string()) {
+        Ok(risk) => {
+            let high = ctx.config.risk_threshold.is_some_and(|threshold| risk.score >= threshold);
+            info!(
+                "[RISK] {} risk score {}{}",
+                log::id(&container_id),
+                risk,
+                if high { ", high risk" } else { "" }
+            );
+            ctx.inventory.set_risk(&container_id, risk);
+        }
+        Err(e) => eprintln!("Failed to assess the risk of {}: {}", log::id(&container_id), e),
+    }
+
+    // Open fd count of the container's init process, sampled every FD_POLL_INTERVAL.
+    // A warning is printed each time max_fd_count doubles, to catch fd exhaustion attacks.
+    let mut last_fd_poll = Instant::now() - FD_POLL_INTERVAL;
+    let mut max_fd_count = 0;
+    let mut fd_warn_at = 0;
+
+    // The process --kill-container-on-exit-of-pid waits for, described up front like
+    // the whitelisted ones.
+    let mut watched_process = None;
+    if let Some(ns_pid) = ctx.config.kill_container_on_exit_of_pid {
+        watched_process = match find_ns_pid(&known_procs, ns_pid).await {
+            Some(key) => Some((key, BaselineProcess::read(key.0).await)),
+            None => {
+                eprintln!("Warning: {} has no PID {} to watch for its exit", log::id(&container_id), ns_pid);
+                None
+            }
+        };
+    }
+    // With --watch-user-namespaces, new PIDs are checked as they appear and all PIDs
+    // every NS_POLL_INTERVAL, since unshare() moves an existing process too.
+    let mut container_user_ns = match init_pid {
+        Some(pid) if ctx.config.watch_user_namespaces => procfs::read_ns_inode(pid, "user").await,
+        _ => None,
+    };
+    let mut last_ns_poll = Instant::now();
+    // With --detect-capabilities-abuse, what new processes' capabilities are compared with.
+    let mut init_capabilities = match init_pid {
+        Some(pid) if ctx.config.detect_capabilities_abuse => procfs::read_capability_sets(pid).await,
+        _ => None,
+    };
+    if ctx.config.host_pid_namespace_check && shares_host_pid_namespace(init_pid).await {
+        eprintln!(
+            "{}",
+            color::stderr(
+                Color::Red,
+                format!(
+                    "Container {} shares host PID namespace — monitoring may generate false positives",
+                    log::id(&container_id)
+                )
+            )
+        );
+        if !ctx.config.allow_host_pid_ns_action {
+            log_only = true;
+        }
+    }
+    // When cgroup.procs was read by the previous poll, which no new process predates.
+    let mut last_read = Instant::now();
+    let mut key_cache = procfs::ProcessKeyCache::default();
+    // For --alert-on-large-proc-set and --alert-on-proc-set-growth-rate, which alert once
+    // when the container crosses the limit rather than on every poll spent above it.
+    let mut over_proc_limit = false;
+    let mut growing = false;
+    let mut growth_sample = (Instant::now(), known_procs.len());
+    // For --process-count-baseline-file, which also alerts once per crossing.
+    let mut last_count_sample = Instant::now();
+    let mut above_baseline = false;
+    // The health score is sampled every HEALTH_CHECK_INTERVAL and reported once per
+    // drop below DEGRADED_SCORE. A stalled task cannot report itself; cnpd-ctl
+    // containers and /healthz still show its score falling.
+    let mut last_health_check = Instant::now();
+    let mut degraded = false;
+    let mut subsystems = SubsystemMonitor::new(&ctx.config.monitor_cgroup_subsystems);
+    let mut last_subsystem_sample = Instant::now();
+    let mut escaped_pids = HashSet::new();
+    // Checked on the first detection and reused, as the image does not change.
+    let mut signature_status = None;
+    // When --baseline-refresh-interval last added the running processes to the whitelist.
+    let mut last_refresh = Instant::now();
+    // With --no-act-in-grace-window-on-restart, detections until then are only logged.
+    let mut restart_grace_until = None;
+    // With --event-correlation-window, the detections recorded together once it has passed.
+    let mut burst: Option<Burst> = None;
+    info!("Monitoring {} in {}", log::id(&container.container_id()), container.root);
+
+    let cache = ProcCache::default();
+
+    loop {
+        heartbeat.beat();
+        ctx.inventory.record_poll(&container_id);
+        if last_health_check.elapsed() >= HEALTH_CHECK_INTERVAL {
+            last_health_check = Instant::now();
+            let score = ctx.inventory.health_score(&container_id).unwrap_or(100);
+            ctx.metrics.set_task_health(&container_id, score);
+            if score < DEGRADED_SCORE && !degraded {
+                report_degraded(&ctx, &container_id, init_pid, score).await;
+            }
+            degraded = score < DEGRADED_SCORE;
+        }
+        cache.clear();
+        let window = ctx.config.event_correlation_window;
+        if let Some(done) = burst.take_if(|b| window.is_some_and(|window| b.started.elapsed() >= window)) {
+            record_burst(&ctx, &container_id, done).await;
+        }
+        let exists = tokio::fs::try_exists(&cgroup_path).await.unwrap_or(false);
+        if !exists && ctx.config.cleanup_dead_containers {
+            cleanup_dead_container(&ctx, &container_id).await;
+            return Ok(());
+        }
+        if exists != cgroup_present {
+            cgroup_present = exists;
+            let state = if exists { MonitorState::Monitoring } else { MonitorState::Stopped };
+            ctx.inventory.set_state(&container_id, state);
+        }
+        if exists {
+            let current_procs = cgroup::read_procs(&cgroup_path).await?;
+            let current = key_cache.process_keys(&current_procs).await;
+            let since_last_read = last_read.elapsed();
+            last_read = Instant::now();
+
+            let process_count = current_procs.len();
+            if let Some(limit) = ctx.config.alert_on_large_proc_set {
+                if process_count > limit && !over_proc_limit {
+                    report_overload(&ctx, &container_id, init_pid, process_count, None).await;
+                }
+                over_proc_limit = process_count > limit;
+            }
+            if let Some(max_rate) = ctx.config.alert_on_proc_set_growth_rate {
+                let elapsed = growth_sample.0.elapsed();
+                if elapsed >= GROWTH_SAMPLE_INTERVAL {
+                    let rate = (process_count as f64 - growth_sample.1 as f64) / elapsed.as_secs_f64();
+                    if rate > max_rate && !growing {
+                        report_overload(&ctx, &container_id, init_pid, process_count, Some(rate)).await;
+                    }
+                    growing = rate > max_rate;
+                    growth_sample = (Instant::now(), process_count);
+                }
+            }
+            if !ctx.config.monitor_cgroup_subsystems.is_empty() && last_subsystem_sample.elapsed() >= SUBSYSTEM_SAMPLE_INTERVAL {
+                last_subsystem_sample = Instant::now();
+                for change in subsystems.sample(&container.path()).await {
+                    report_subsystem_change(&ctx, &container_id, init_pid, &change).await;
+                }
+            }
+            if let Some(baselines) = ctx.process_counts.as_ref().filter(|_| last_count_sample.elapsed() >= PROCESS_COUNT_SAMPLE_INTERVAL) {
+                last_count_sample = Instant::now();
+                // Keyed by name where there is one, which outlives the container's ID.
+                let key = ctx.inventory.name(&container_id).unwrap_or_else(|| container_id.clone());
+                let anomaly = baselines.record(&key, process_count as u32);
+                if let Some(anomaly) = anomaly.filter(|_| !above_baseline) {
+                    report_process_count_anomaly(&ctx, &container_id, init_pid, anomaly).await;
+                }
+                above_baseline = anomaly.is_some();
+            }
+
+            if let Some(init_pid) = init_pid.filter(|_| last_fd_poll.elapsed() >= FD_POLL_INTERVAL) {
+                last_fd_poll = Instant::now();
+                if let Ok(Some(current_fd_count)) = procfs::read_with_timeout(
+                    ctx.config.max_proc_read_time,
+                    init_pid,
+                    "fd",
+                    procfs::count_fds(init_pid),
+                )
+                .await
+                {
+                    max_fd_count = max_fd_count.max(current_fd_count);
+                    if fd_warn_at == 0 {
+                        fd_warn_at = current_fd_count.max(1) * 2;
+                    } else if max_fd_count >= fd_warn_at {
+                        eprintln!(
+                            "Warning: init PID {} in {} has {} open file descriptors (max {})",
+                            init_pid,
+                            log::id(&container.container_id()),
+                            current_fd_count,
+                            max_fd_count
+                        );
+                        fd_warn_at = max_fd_count * 2;
+                    }
+                }
+            }
+
+            let known_before = known_procs.len();
+            // Set when an action restarted the container, after which nothing else in
+            // this poll applies: every process it read is gone.
+            let mut restarted = false;
+            if let Some(pids) = whitelist.take_pending() {
+                for pid in pids {
+                    match procfs::read_start_time(pid).await {
+                        Some(start) => {
+                            known_procs.insert((pid, start));
+                            info!("PID {} in {} whitelisted on request", pid, log::id(&container_id));
+                        }
+                        None => eprintln!("Not whitelisting PID {} in {}: no such process", pid, log::id(&container_id)),
+                    }
+                }
+            }
+            if ctx.config.baseline_refresh_interval.is_some_and(|interval| last_refresh.elapsed() >= interval) {
+                last_refresh = Instant::now();
+                // Only adds: entries of processes that have since exited stay whitelisted.
+                let running = procfs::new_processes(&known_procs, &current);
+                if ctx.config.alert_on_process_exit {
+                    for (pid, _) in &running {
+                        whitelisted.insert(*pid, BaselineProcess::read(*pid).await);
+                    }
+                }
+                info!(
+                    "[REBASELINE] {} whitelisted {} running processes not yet in its baseline, {} in total",
+                    log::id(&container_id),
+                    running.len(),
+                    known_procs.len() + running.len()
+                );
+                known_procs.extend(running);
+            }
+            if let Some(container_ns) = container_user_ns {
+                let sweep = last_ns_poll.elapsed() >= NS_POLL_INTERVAL;
+                if sweep {
+                    last_ns_poll = Instant::now();
+                    escaped_pids.retain(|pid| current_procs.contains(pid));
+                }
+                let keys: Vec<(i32, u64)> = current
+                    .iter()
+                    .filter(|key| (sweep || !known_procs.contains(*key)) && !escaped_pids.contains(&key.0))
+                    .copied()
+                    .collect();
+                let pids: Vec<i32> = keys.iter().map(|(pid, _)| *pid).collect();
+                let acting = heartbeat.pause();
+                let (escaped, restarted_by_escape) =
+                    check_user_namespaces(&ctx, &cache, &container, container_ns, &pids).await?;
+                drop(acting);
+                for pid in escaped {
+                    // Already handled, so it is not reported again as a new process.
+                    escaped_pids.insert(pid);
+                    known_procs.extend(keys.iter().filter(|(p, _)| *p == pid).copied());
+                }
+                restarted = restarted_by_escape;
+            }
+            while let Some(event) = bcc.as_mut().filter(|_| !restarted).and_then(BccProgram::try_next) {
+                let _acting = heartbeat.pause();
+                restarted = report_bcc_event(&ctx, &container, event, log_only).await?;
+            }
+            let new_processes = match restarted {
+                true => Vec::new(),
+                false => procfs::new_processes(&known_procs, &current),
+            };
+            for key in new_processes {
+                let proc = &key.0;
+                if ctx.config.ignore_kernel_threads && procfs::is_kernel_thread(*proc).await {
+                    debug!("Ignoring kernel thread {} in {}", proc, log::id(&container_id));
+                    known_procs.insert(key);
+                    continue;
+                }
+                if let Some(age) = short_lived(ctx.config.min_process_age_ms, key, since_last_read).await {
+                    info!("PID {} skipped (age {}ms below threshold)", proc, age.as_millis());
+                    known_procs.insert(key);
+                    continue;
+                }
+                // Up to the next process: what follows acts on the container.
+                let _acting = heartbeat.pause();
+                let detected_at = Local::now();
+                let detection_time = detected_at.format("%Y-%m-%d %H:%M:%S%.3f");
+                let cleaned_docker_dir = container.container_id();
+                info!(
+                    "{}",
+                    color::stdout(
+                        Color::Yellow,
+                        format!(
+                            "[{}] \t New process detected - \t {} \t {}",
+                            detection_time, log::id(&cleaned_docker_dir), proc
+                        )
+                    )
+                );
+
+                // Freezing first keeps the process from forking, writing files or
+                // connecting out while it is being inspected.
+                let freezes = ctx.config.freeze_on_detection || ctx.config.pause_on_detection;
+                let mut frozen_at = (freezes && freeze(&ctx, &container, true).await).then(Instant::now);
+                let mut event = build_event(&ctx, &cache, &cleaned_docker_dir, *proc, detected_at).await;
+                event.frozen = frozen_at.is_some();
+                if let Some(detector) = &ctx.time_anomalies {
+                    let key = ctx.inventory.name(&cleaned_docker_dir).unwrap_or_else(|| cleaned_docker_dir.clone());
+                    event.high_anomaly = detector.observe(&key, detected_at);
+                    if event.high_anomaly {
+                        eprintln!(
+                            "{}",
+                            color::stderr(
+                                Color::Yellow,
+                                format!(
+                                    "[{}] \t Unusual hour - \t {} \t {} \t no new processes at this hour in the past week",
+                                    detection_time, log::id(&cleaned_docker_dir), proc
+                                )
+                            )
+                        );
+                    }
+                }
+                if ctx.config.detect_hidden_files {
+                    report_hidden_files(&ctx, &event).await;
+                }
+                let escalation = match ctx.config.alert_on_privilege_escalation {
+                    true => privilege_escalation(*proc).await,
+                    false => None,
+                };
+                let abuse = match &init_capabilities {
+                    Some(init) if escalation.is_none() => extra_capabilities(*proc, init).await,
+                    _ => None,
+                };
+                if let Some((euid, parent_euid)) = escalation {
+                    eprintln!(
+                        "{}",
+                        color::stderr(
+                            Color::Red,
+                            format!(
+                                "[{}] \t Privilege escalation - \t {} \t {} \t euid {}, parent euid {}",
+                                detection_time, log::id(&cleaned_docker_dir), proc, euid, parent_euid
+                            )
+                        )
+                    );
+                    event = event.into_privilege_escalation(euid, parent_euid, ctx.config.privilege_escalation_action);
+                } else if let Some(extra) = abuse {
+                    let names = capability::capability_names(extra);
+                    eprintln!(
+                        "{}",
+                        color::stderr(
+                            Color::Red,
+                            format!(
+                                "[{}] \t Capability abuse - \t {} \t {} \t {}",
+                                detection_time, log::id(&cleaned_docker_dir), proc, names.join(",")
+                            )
+                        )
+                    );
+                    event = event.into_capability_abuse(names);
+                } else if let Some(rule) = policy_engine(&ctx, event.tenant.as_deref()).suppression(&event) {
+                    thaw(&ctx, &container, &mut frozen_at, &mut event).await;
+                    info!("Event {} suppressed by rule {}", event.id, rule);
+                    ctx.filter.record(rule, event);
+                    known_procs.insert(key);
+                    continue;
+                }
+                ctx.inventory.record_detection(&cleaned_docker_dir, &event.detected_at);
+                // Privilege escalations and capability abuse keep their own action; scoring
+                // does not apply.
+                if event.kind == EventKind::NewProcess {
+                    // Containers close to their memory limit fork extra processes on their own,
+                    // so detections there are treated as less severe.
+                    if let Some(limit) = container.memory_limit {
+                        let usage = cgroup::read_memory_usage(&container).await.unwrap_or(0);
+                        if usage as f64 > limit as f64 * MEMORY_PRESSURE_RATIO {
+                            event.memory_pressure = true;
+                            event.action = event.action.lowered(ctx.config.memory_pressure_discount);
+                        }
+                    }
+                    if last_oom_kill.borrow().is_some_and(|at| at.elapsed() <= OOM_REEXEC_WINDOW) {
+                        event.after_oom_kill = true;
+                        event.action = event.action.raised(1);
+                    }
+                    if ctx.config.verify_signatures {
+                        if signature_status.is_none() {
+                            signature_status = Some(image_signature(&ctx, &cleaned_docker_dir).await);
+                        }
+                        event.image_signature_status = signature_status.flatten();
+                        // A new process in an image nobody vouches for is more suspicious.
+                        if matches!(event.image_signature_status, Some(SignatureStatus::NoSignature | SignatureStatus::Unverified)) {
+                            event.action = event.action.raised(1);
+                        }
+                    }
+                    let high_risk = ctx.config.risk_threshold.zip(event.risk_score).is_some_and(|(threshold, score)| score >= threshold);
+                    if high_risk && event.action.score() < ctx.config.high_risk_action.score() {
+                        event.action = ctx.config.high_risk_action;
+                    }
+                    let in_restart_grace = restart_grace_until.is_some_and(|until| Instant::now() < until);
+                    if log_only || in_restart_grace {
+                        event.action = Action::LogOnly;
+                    }
+                    let threshold = ctx.config.alert_threshold_cpu_percent;
+                    if threshold > 0.0 && event.action != Action::LogOnly {
+                        // A frozen process uses no CPU, so it has to run while it is sampled.
+                        thaw(&ctx, &container, &mut frozen_at, &mut event).await;
+                        event.cpu_percent = procfs::cpu_percent(*proc, CPU_SAMPLE_INTERVAL).await;
+                        // An unmeasurable process (it exited during sampling) keeps its action.
+                        if let Some(cpu) = event.cpu_percent.filter(|cpu| *cpu < threshold) {
+                            info!(
+                                "Process {} in {} used {:.1}% CPU, below the {}% threshold",
+                                proc, log::id(&cleaned_docker_dir), cpu, threshold
+                            );
+                            event.action = Action::LogOnly;
+                        }
+                    }
+                }
+
+                let scan = match &event.exe {
+                    Some(exe) if ctx.config.trivy_scan => scan::start_scan(&cleaned_docker_dir, exe).await,
+                    _ => None,
+                };
+
+                // Within a burst the container is acted on again only for a more severe
+                // action; the offending process is still killed.
+                let repeated = burst
+                    .as_ref()
+                    .is_some_and(|b| event.action != Action::Kill && event.action.score() <= b.action.score());
+                if ctx.config.checkpoint && !repeated && matches!(event.action, Action::Restart | Action::Stop | Action::Isolate | Action::Rollback) {
+                    event.checkpoint_dir = checkpoint(&ctx, &cleaned_docker_dir).await;
+                }
+
+                if ctx.config.core_dump_on_detection && event.action.blocks() {
+                    // gcore attaches with ptrace, which would wait for a frozen process to thaw.
+                    thaw(&ctx, &container, &mut frozen_at, &mut event).await;
+                    let dir = &ctx.config.core_dump_dir;
+                    let timeout = ctx.config.core_dump_timeout;
+                    if let Some(core) = forensics::core_dump(dir, &cleaned_docker_dir, *proc, timeout).await {
+                        info!("Core dump of PID {} written to {}", proc, core);
+                        event.forensic_artifacts.push(core);
+                    }
+                }
+
+                if !repeated {
+                    pre_action_hook(&ctx, event.action, &mut event).await;
+                }
+                // SIGKILL reaches frozen processes, but docker stop needs them running.
+                if event.action != Action::Kill {
+                    thaw(&ctx, &container, &mut frozen_at, &mut event).await;
+                }
+                plugin::run_plugins(&ctx.plugins, &event).await;
+                if !repeated {
+                    restarted = apply_action(&ctx, &container, &mut event).await?;
+                }
+                thaw(&ctx, &container, &mut frozen_at, &mut event).await;
+                if !repeated {
+                    stop_group_peers(&ctx, &event).await;
+                }
+                if window.is_some() {
+                    let b = burst.get_or_insert_with(|| Burst {
+                        started: Instant::now(),
+                        action: Action::LogOnly,
+                        events: Vec::new(),
+                    });
+                    if event.action.score() > b.action.score() {
+                        b.action = event.action;
+                    }
+                }
+
+                match scan {
+                    Some(scan) => {
+                        let ctx = ctx.clone();
+                        let mut event = event;
+                        tokio::spawn(async move {
+                            event.vulnerabilities = Some(scan.await.unwrap_or_default());
+                            record_event(&ctx, &event).await;
+                        });
+                    }
+                    // Scanned events are recorded once their scan is done, outside the burst.
+                    None => match &mut burst {
+                        Some(b) => b.events.push(event),
+                        None => record_event(&ctx, &event).await,
+                    },
+                }
 
-    let entries = fs::read_dir(cgroup_path).await?;
-    tokio::pin!(entries); // Pin the iterator to enable async operations
+                if restarted {
+                    break;
+                }
+                known_procs.insert(key);
+            }
+
+            if let Some(ns_pid) = ctx.config.kill_container_on_exit_of_pid.filter(|_| !restarted) {
+                if let Some((_, process)) = watched_process.take_if(|(key, _)| !current.contains(key)) {
+                    let _acting = heartbeat.pause();
+                    watched_process_exited(&ctx, &container, ns_pid, process).await?;
+                }
+            }
+
+            let exited: Vec<i32> = whitelisted.keys().filter(|pid| !current_procs.contains(pid)).copied().collect();
+            if !exited.is_empty() && !restarted {
+                let _acting = heartbeat.pause();
+                let container_id = container.container_id();
+                let mut events = Vec::new();
+                for pid in exited {
+                    if let Some(process) = whitelisted.remove(&pid) {
+                        events.push(exit_event(&ctx, &container_id, process).await);
+                    }
+                }
+                // Several whitelisted processes usually exit together, so act once per poll.
+                pre_action_hook(&ctx, ctx.config.exit_action, &mut events[0]).await;
+                restarted = apply_action(&ctx, &container, &mut events[0]).await?;
+                for event in &events {
+                    record_event(&ctx, event).await;
+                }
+            }
+
+            if restarted {
+                // The pre-restart PIDs, the detected one included, will not come back,
+                // while every process of the new instance would otherwise look new.
+                let restarted_at = Instant::now();
+                let acting = heartbeat.pause();
+                known_procs = restarted_procs(&ctx, &container).await?.into();
+                drop(acting);
+                init_pid = known_procs.iter().map(|(pid, _)| *pid).min();
+                if ctx.config.watch_user_namespaces {
+                    container_user_ns = match init_pid {
+                        Some(pid) => procfs::read_ns_inode(pid, "user").await,
+                        None => None,
+                    };
+                }
+                if ctx.config.detect_capabilities_abuse {
+                    init_capabilities = match init_pid {
+                        Some(pid) => procfs::read_capability_sets(pid).await,
+                        None => None,
+                    };
+                }
+                if ctx.config.detect_file_writes {
+                    _file_writes = match init_pid {
+                        Some(pid) => watch_file_writes(&ctx, &container, pid).await,
+                        None => None,
+                    };
+                }
+                if ctx.config.detect_mount_namespace_changes {
+                    _mounts = match init_pid {
+                        Some(pid) => watch_mounts(&ctx, &container_id, pid).await,
+                        None => None,
+                    };
+                }
+                bcc = start_bcc(&ctx, &container);
+                whitelisted.clear();
+                if ctx.config.alert_on_process_exit {
+                    for (pid, _) in known_procs.iter() {
+                        whitelisted.insert(*pid, BaselineProcess::read(*pid).await);
+                    }
+                }
+                if let Some(ns_pid) = ctx.config.kill_container_on_exit_of_pid {
+                    if let Some(key) = find_ns_pid(&known_procs, ns_pid).await {
+                        watched_process = Some((key, BaselineProcess::read(key.0).await));
+                    }
+                }
+                escaped_pids.clear();
+                (max_fd_count, fd_warn_at) = (0, 0);
+                growth_sample = (Instant::now(), known_procs.len());
+                subsystems = SubsystemMonitor::new(&ctx.config.monitor_cgroup_subsystems);
+                if ctx.config.no_act_in_grace_window_on_restart {
+                    restart_grace_until = Some(restarted_at + Duration::from_millis(ctx.config.post_restart_grace_ms));
+                }
+                last_read = Instant::now();
+                info!(
+                    "{} restarted, whitelisting its {} new processes",
+                    log::id(&container_id),
+                    known_procs.len()
+                );
+                ctx.inventory.register(&container_id, MonitorState::Monitoring, known_procs.len());
+                publish_whitelist(&ctx, &whitelist, &container_id, &known_procs);
+                continue;
+            }
 
-    while let Some(entry) = entries.next_entry().await? {
-        let path = entry.path();
-        if path.is_dir() {
-            if let Some(dir_name) = path.file_name().and_then(|s| s.to_str()) {
-                if dir_name.starts_with("docker-") {
-                    docker_list.push(dir_name.to_string());
+            if known_procs.len() > ctx.config.max_known_pids {
+                // Forget PIDs that have exited so a PID-cycling attack cannot grow the set without bound.
+                known_procs.retain(|key| current.contains(key));
+                if !log_only {
+                    log_only = true;
+                    eprintln!(
+                        "Error: {} exceeded {} known PIDs, switching to log-only mode",
+                        log::id(&container.container_id()),
+                        ctx.config.max_known_pids
+                    );
                 }
             }
+            if known_procs.len() != known_before || whitelist.is_stale() {
+                publish_whitelist(&ctx, &whitelist, &container_id, &known_procs);
+            }
+        }
+
+        sleep(POLL_INTERVAL).await; // Monitoring interval set to 1 nanosecond
+    }
+}
+
+// With --cleanup-dead-containers, once the cgroup directory of a container is gone.
+// It is monitored anew if the cgroup comes back, as after docker start.
+async fn cleanup_dead_container(ctx: &Context, container_id: &str) {
+    if let Some(state) = &ctx.state {
+        if let Err(e) = state.remove_baseline(container_id).await {
+            eprintln!("Failed to remove {} from state file {}: {}", log::id(container_id), state.path(), e);
+        }
+    }
+    ctx.metrics.remove_container(container_id);
+    ctx.inventory.remove(container_id);
+    info!("Container {} cgroup removed, monitoring task exiting cleanly", log::id(container_id));
+}
+
+// Monitors one container until monitor_procs fails or `cancel` fires, which happens
+// when its sender in Monitors is dropped.
+async fn run_monitor(ctx: Arc<Context>, container: ContainerCgroup, procs: HashSet<i32>, cancel: oneshot::Receiver<()>) {
+    let path = container.path();
+    let container_id = container.container_id();
+    tokio::select! {
+        result = monitor_procs(container, procs, ctx.clone()) => {
+            if let Err(e) = result {
+                eprintln!("Error monitoring procs in {}: {}", path, e);
+                ctx.inventory.set_state(&container_id, MonitorState::Failed);
+            }
+        }
+        _ = cancel => {}
+    }
+    ctx.metrics.remove_task_health(&container_id);
+}
+
+// Runs the monitors of several containers in one task. Each still has its own
+// known_procs and acts only on its own container; the task ends when all are done.
+fn spawn_group(ctx: &Arc<Context>, group: Vec<(ContainerCgroup, HashSet<i32>, oneshot::Receiver<()>)>) {
+    let ids: Vec<String> = group.iter().map(|(container, _, _)| log::id(&container.container_id()).to_string()).collect();
+    let mut monitors: Vec<Pin<Box<dyn Future<Output = ()> + Send>>> = group
+        .into_iter()
+        .map(|(container, procs, cancel)| {
+            Box::pin(run_monitor(ctx.clone(), container, procs, cancel)) as Pin<Box<dyn Future<Output = ()> + Send>>
+        })
+        .collect();
+    let task = std::future::poll_fn(move |cx| {
+        monitors.retain_mut(|monitor| monitor.as_mut().poll(cx).is_pending());
+        if monitors.is_empty() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    });
+    match &ctx.numa {
+        Some(numa) => {
+            let node = numa.spawn(task);
+            debug!("Monitoring task for {} assigned to NUMA node {}", ids.join(", "), node);
+        }
+        None => {
+            tokio::spawn(task);
         }
     }
+}
 
-    Ok(docker_list)
+// Running monitors by container id. Dropping a container's sender stops its monitor
+// without affecting the other containers sharing the task.
+struct Monitors {
+    tasks: Mutex<HashMap<String, (ContainerCgroup, oneshot::Sender<()>)>>,
+    // Kept at the number of tasks for cnpd_monitored_containers.
+    metrics: Arc<Metrics>,
 }
 
-async fn get_whitelist(docker_list: &Vec<String>) -> Result<Vec<(String, HashSet<i32>)>, Box<dyn Error>> {
-    let cgroup_path = "/sys/fs/cgroup/system.slice/";
-    let mut whitelist = Vec::new();
+impl Monitors {
+    fn new(metrics: Arc<Metrics>) -> Monitors {
+        Monitors {
+            tasks: Mutex::new(HashMap::new()),
+            metrics,
+        }
+    }
 
-    for docker_dir in docker_list {
-        let procs_path = format!("{}/{}/cgroup.procs", cgroup_path, docker_dir);
-        if Path::new(&procs_path).exists() {
-            let procs_content = fs::read_to_string(&procs_path).await?;
-            let procs: HashSet<i32> = procs_content.lines().filter_map(|s| s.parse().ok()).collect();
-            whitelist.push((docker_dir.clone(), procs));
+    // Starts the containers in one task, replacing any monitor already running for them.
+    fn start_group(&self, ctx: &Arc<Context>, containers: Vec<(ContainerCgroup, HashSet<i32>)>) {
+        let mut group = Vec::new();
+        let mut tasks = self.tasks.lock().unwrap();
+        for (container, procs) in containers {
+            let (cancel_tx, cancel_rx) = oneshot::channel();
+            tasks.insert(container.container_id(), (container.clone(), cancel_tx));
+            group.push((container, procs, cancel_rx));
         }
+        self.metrics.set_monitored_containers(tasks.len());
+        spawn_group(ctx, group);
+    }
+
+    fn start(&self, ctx: &Arc<Context>, container: ContainerCgroup, procs: HashSet<i32>) {
+        self.start_group(ctx, vec![(container, procs)]);
+    }
+
+    fn stop(&self, container_id: &str) -> bool {
+        let mut tasks = self.tasks.lock().unwrap();
+        let stopped = tasks.remove(container_id).is_some();
+        self.metrics.set_monitored_containers(tasks.len());
+        stopped
+    }
+
+    // Monitors that ended on their own, like those --cleanup-dead-containers ends, are
+    // dropped first so that the container can be monitored again.
+    fn get(&self, container_id: &str) -> Option<ContainerCgroup> {
+        self.running().get(container_id).map(|(container, _)| container.clone())
     }
 
-    Ok(whitelist)
+    fn containers(&self) -> Vec<ContainerCgroup> {
+        self.running().values().map(|(container, _)| container.clone()).collect()
+    }
+
+    fn running(&self) -> MutexGuard<'_, HashMap<String, (ContainerCgroup, oneshot::Sender<()>)>> {
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.retain(|_, (_, cancel)| !cancel.is_closed());
+        self.metrics.set_monitored_containers(tasks.len());
+        tasks
+    }
 }
 
-async fn monitor_procs(docker_dir: String, initial_procs: HashSet<i32>) -> Result<(), Box<dyn Error>> {
-    let cgroup_path = format!("/sys/fs/cgroup/system.slice/{}/cgroup.procs", docker_dir);
-    let mut known_procs = initial_procs;
+// Started by an in-place upgrade: the containers the old binary monitored keep its
+// whitelist, so that processes started during the handover are still detected.
+// Containers started since are whitelisted as found. Without the state, monitoring
+// starts afresh rather than not at all.
+async fn resume(ctx: &Context, path: &str, whitelist: &mut [(ContainerCgroup, HashSet<i32>)]) {
+    let state = match ResumeState::take(path).await {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("Not resuming from the previous binary: {}", e);
+            return;
+        }
+    };
+    ctx.metrics.restore_counters(&state.counters);
+    let mut resumed = 0;
+    for (container, procs) in whitelist.iter_mut() {
+        let container_id = container.container_id();
+        if let Some(saved) = state.containers.iter().find(|saved| saved.cgroup.container_id() == container_id) {
+            *procs = saved.still_running().await;
+            resumed += 1;
+        }
+    }
+    info!("Resumed monitoring of {} of {} containers from {}", resumed, state.containers.len(), path);
+}
 
-    loop {
-        if Path::new(&cgroup_path).exists() {
-            let procs_content = fs::read_to_string(&cgroup_path).await?;
-            let current_procs: HashSet<i32> = procs_content.lines().filter_map(|s| s.parse().ok()).collect();
-
-            for proc in &current_procs {
-                if !known_procs.contains(proc) {
-                    let detection_time = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
-                    let cleaned_docker_dir = docker_dir.replace("docker-", "").replace(".scope", "");
-                    println!(
-                        "[{}] \t New process detected - \t {} \t {}",
-                        detection_time, cleaned_docker_dir, proc
-                    );
+// On SIGHUP with a new binary installed. The monitors are stopped first, so that what
+// is handed over is not changed by a poll afterwards.
+async fn hand_off(ctx: &Context, monitors: &Monitors) -> ResumeState {
+    let mut state = ResumeState::default();
+    for cgroup in monitors.containers() {
+        let container_id = cgroup.container_id();
+        monitors.stop(&container_id);
+        let known = match ctx.whitelists.get(&container_id) {
+            Some(whitelist) => whitelist.known().await.into_iter().collect(),
+            None => Vec::new(),
+        };
+        state.containers.push(ResumedContainer { cgroup, known });
+    }
+    state
+}
 
-                    // Stop the Docker container
-                    let stop_start = Utc::now();
-                    let output = Command::new("docker")
-                        .arg("stop")
-                        .arg(&cleaned_docker_dir)
-                        .output()
-                        .await?;
-
-                    if !output.status.success() {
-                        eprintln!("Failed to stop Docker container: {}", cleaned_docker_dir);
-                    } else {
-                        println!("Docker container stopped: {}", cleaned_docker_dir);
-
-                        // Start the Docker container
-                        let output = Command::new("docker")
-                            .arg("start")
-                            .arg(&cleaned_docker_dir)
-                            .output()
-                            .await?;
-
-                        let stop_end = Utc::now();
-                        let duration = stop_end - stop_start;
-                        if !output.status.success() {
-                            eprintln!("Failed to start Docker container: {}", cleaned_docker_dir);
-                        } else {
-                            println!("Docker container started: {}", cleaned_docker_dir);
-                            println!("Time taken from stop to start: {} ms", duration.num_milliseconds());
-                        }
-                    }
+// A single node has nothing to balance, so --numa-aware is ignored there.
+fn numa_scheduler(config: &Config) -> Result<Option<NumaAwareScheduler>, Box<dyn Error>> {
+    if !config.numa_aware {
+        return Ok(None);
+    }
+    let nodes = numa::nodes().map_err(|e| format!("Failed to read NUMA nodes: {}", e))?;
+    if nodes.len() < 2 {
+        info!("Only one NUMA node, --numa-aware has no effect");
+        return Ok(None);
+    }
+    for node in &nodes {
+        info!("NUMA node {}: CPUs {:?}", node.id, node.cpus);
+    }
+    Ok(Some(NumaAwareScheduler::start(nodes)?))
+}
+
+// With --ignore-stopped-containers, whether a container found at startup is still
+// running rather than stopped with its cgroup not yet removed. cgroup.events decides
+// when it can be read, Docker otherwise; a container neither can tell about is treated
+// as running. Containers discovered later are not checked, as a cgroup being created
+// is unpopulated too until the container's init process joins it.
+async fn is_running(ctx: &Context, container: &ContainerCgroup) -> bool {
+    if !ctx.config.ignore_stopped_containers {
+        return true;
+    }
+    let running = match cgroup::is_populated(container).await {
+        Some(populated) => populated,
+        None => docker::is_running(&container.container_id()).await.unwrap_or(true),
+    };
+    if !running {
+        info!("Container {} skipped (not running)", log::id(&container.container_id()));
+    }
+    running
+}
+
+// With --container-namespace, whether the container belongs to that Compose project:
+// by its com.docker.compose.project label, or for a container without one, by the
+// `<project>_` or `<project>-` prefix Compose gives container names.
+async fn in_namespace(ctx: &Context, container: &ContainerCgroup) -> bool {
+    let Some(namespace) = &ctx.config.container_namespace else {
+        return true;
+    };
+    let container_id = container.container_id();
+    let member = match docker::compose_project(&container_id).await.map_err(|e| e.to_string()) {
+        Ok(Some(project)) => project == *namespace,
+        Ok(None) => docker::container_name(&container_id).await.is_ok_and(|name| {
+            name.strip_prefix(namespace.as_str()).is_some_and(|rest| rest.starts_with(['_', '-']))
+        }),
+        Err(e) => {
+            eprintln!("Failed to read the Compose project of {}: {}", log::id(&container_id), e);
+            false
+        }
+    };
+    if !member {
+        debug!("Container {} is not in Compose project {}, skipped", log::id(&container_id), namespace);
+    }
+    member
+}
+
+// Whitelists a container found after startup and starts monitoring it.
+async fn monitor_new_container(ctx: &Arc<Context>, monitors: &Monitors, container: ContainerCgroup) {
+    if !in_namespace(ctx, &container).await {
+        return;
+    }
+    let whitelist = match cgroup::get_whitelist(&[container], ctx.config.whitelist_retry()).await {
+        Ok(whitelist) => whitelist,
+        Err(e) => {
+            eprintln!("Failed to read processes of new container: {}", e);
+            return;
+        }
+    };
+    for (container, procs) in whitelist {
+        if ctx.config.require_seccomp && !check_seccomp(ctx, &container, &procs).await {
+            continue;
+        }
+        let container_id = container.container_id();
+        if let Some(script) = &ctx.config.container_start_hook {
+            if !hook::run_container_start(script, &container_id, &container.path()).await {
+                eprintln!("Warning: not monitoring {}, its start hook failed", log::id(&container_id));
+                continue;
+            }
+        }
+        info!("New container discovered: {} with processes {:?}", log::id(&container_id), procs);
+        monitors.start(ctx, container, procs);
+    }
+}
+
+// Moves a process into the container once it is being monitored, for --simulate-attack,
+// and waits for the detection to be recorded, which happens after its action completed.
+async fn simulate_attack(
+    ctx: &Context,
+    monitors: &Monitors,
+    container_id: &str,
+    mut events: mpsc::UnboundedReceiver<DetectionEvent>,
+) -> bool {
+    let timeout = ctx.config.simulate_timeout;
+    let deadline = tokio::time::Instant::now() + timeout;
+    let ready = loop {
+        let monitored = ctx.inventory.snapshot().into_iter().find(|s| {
+            s.container_id.starts_with(container_id) && s.state == MonitorState::Monitoring && s.polls > 0
+        });
+        if let Some(container) = monitored.and_then(|s| monitors.get(&s.container_id)) {
+            break Some(container);
+        }
+        if tokio::time::Instant::now() >= deadline {
+            break None;
+        }
+        sleep(Duration::from_millis(100)).await;
+    };
+    let Some(container) = ready else {
+        eprintln!("Simulated attack failed: container {} is not being monitored", container_id);
+        return false;
+    };
+    let container_id = container.container_id();
 
-                    known_procs.insert(*proc);
+    // Held until the result is in, so the sleep is killed when it was not already.
+    let mut child = None;
+    let pid = match ctx.config.simulate_pid {
+        Some(pid) => pid,
+        None => {
+            let sleep = Command::new("sleep").arg(timeout.as_secs().max(1).to_string()).kill_on_drop(true).spawn();
+            let spawned = match sleep {
+                Ok(spawned) => spawned,
+                Err(e) => {
+                    eprintln!("Simulated attack failed: cannot start a process to inject: {}", e);
+                    return false;
                 }
+            };
+            // Only None once the child has been waited for.
+            let pid = spawned.id().unwrap_or_default() as i32;
+            child = Some(spawned);
+            pid
+        }
+    };
+    let injected_at = Instant::now();
+    if let Err(e) = tokio::fs::write(container.procs_path(), pid.to_string()).await {
+        eprintln!("Simulated attack failed: cannot write PID {} to {}: {}", pid, container.procs_path(), e);
+        return false;
+    }
+    info!("Simulated attack: moved PID {} into {}", pid, log::id(&container_id));
+
+    let detected = tokio::time::timeout_at(deadline, async {
+        while let Some(event) = events.recv().await {
+            if event.container_id == container_id && event.pid == pid {
+                return Some(event);
             }
         }
+        None
+    })
+    .await;
+    drop(child);
+    match detected {
+        Ok(Some(event)) => {
+            info!(
+                "Simulated attack detected: PID {} in {} after {} ms, action {}",
+                pid,
+                log::id(&container_id),
+                injected_at.elapsed().as_millis(),
+                event.action
+            );
+            true
+        }
+        _ => {
+            eprintln!(
+                "Simulated attack not detected: PID {} in {} within {} ms",
+                pid,
+                log::id(&container_id),
+                timeout.as_millis()
+            );
+            false
+        }
+    }
+}
+
+// --forensics-mode: lists the archived processes that were not in the container's
+// saved baseline. Exits with 1 when there are any, like `cnpd-ctl diff`.
+async fn forensics_report(archive_dir: &str, state_file: &str) -> Result<ExitCode, Box<dyn Error>> {
+    let archive = ContainerArchive::load(archive_dir).await?;
+    let baselines = state::load_baselines(state_file).await?;
+    let baseline = baselines
+        .iter()
+        .find(|b| b.container_id.starts_with(&archive.container_id) || archive.container_id.starts_with(&b.container_id))
+        .ok_or_else(|| format!("No baseline for container {} in {}", archive.container_id, state_file))?;
+
+    let unexpected = archive.unexpected(baseline);
+    println!("Forensics report for container {}", archive.container_id);
+    println!("Archive: {}", archive.dir);
+    println!("Baseline: {} ({} processes)", state_file, baseline.processes.len());
+    println!(
+        "{} archived processes, {} not in the baseline",
+        archive.processes.len(),
+        unexpected.len()
+    );
+    for process in &unexpected {
+        println!(
+            "{}",
+            color::stdout(
+                Color::Yellow,
+                format!(
+                    "+{}\t{}\t{}",
+                    process.pid,
+                    process.exe.as_deref().unwrap_or("unknown"),
+                    process.cmdline.as_deref().unwrap_or("")
+                )
+            )
+        );
+    }
+    Ok(if unexpected.is_empty() { ExitCode::SUCCESS } else { ExitCode::from(1) })
+}
 
-        sleep(Duration::from_nanos(1)).await; // Monitoring interval set to 1 nanosecond
+// Without --cgroup-path, monitors the cgroup directories of the runtimes found on this
+// host instead of only Docker's.
+fn detect_runtimes(config: &mut Config) {
+    let detected = ContainerRuntimeDetector::detect();
+    if detected.is_empty() {
+        info!("No container runtime detected");
+        return;
     }
+    let names: Vec<String> = detected.iter().map(|found| found.runtime.to_string()).collect();
+    info!("Detected runtimes: {}", names.join(", "));
+
+    let paths = ContainerRuntimeDetector::cgroup_paths(&detected);
+    if config.cgroup_paths == [cgroup::DEFAULT_CGROUP_PATH] && !paths.is_empty() {
+        config.cgroup_paths = paths;
+    }
+}
+
+fn main() -> Result<ExitCode, Box<dyn Error>> {
+    let mut config = Config::from_args(std::env::args().skip(1))?;
+    log::set_log_to_stderr(config.report_format == OutputFormat::Ndjson);
+    detect_runtimes(&mut config);
+    // Applied before the runtime starts so that every thread inherits the restrictions.
+    if config.sandbox {
+        sandbox::apply(&config)?;
+    }
+    runtime(&config.bind_cpus)?.block_on(run(config))
+}
+
+// With --bind-cpu, one worker thread per CPU, and every runtime thread (workers and
+// the blocking pool serving tokio::fs) pinned to those CPUs as it starts. Tasks
+// move between worker threads, so pinning has to happen per thread rather than per task.
+fn runtime(cpus: &[usize]) -> std::io::Result<Runtime> {
+    if cpus.is_empty() {
+        return Runtime::new();
+    }
+    let online = affinity::online_cpus();
+    if cpus.len() > online {
+        eprintln!("Warning: --bind-cpu lists {} CPUs but only {} are online", cpus.len(), online);
+    }
+    let cpus = cpus.to_vec();
+    info!("Pinning runtime threads to CPUs {:?}", cpus);
+    runtime::Builder::new_multi_thread()
+        .worker_threads(cpus.len())
+        .enable_all()
+        .on_thread_start(move || {
+            if let Err(e) = affinity::pin_current_thread(&cpus) {
+                eprintln!("Failed to pin thread to CPUs {:?}: {}", cpus, e);
+            }
+        })
+        .build()
+}
+
+async fn run(config: Config) -> Result<ExitCode, Box<dyn Error>> {
+    let metrics = Arc::new(Metrics::default());
+    let final_metrics_file = config.export_metrics_on_exit.then(|| config.final_metrics_file.clone());
+    let result = monitor(config, metrics.clone()).await;
+    if let Some(path) = final_metrics_file {
+        let reason = match &result {
+            Ok((_, reason)) => *reason,
+            Err(_) => ExitReason::Error,
+        };
+        match tokio::fs::write(&path, metrics.render_final(reason, Utc::now().timestamp())).await {
+            Ok(()) => info!("Final metrics written to {}", path),
+            Err(e) => eprintln!("Failed to write final metrics to {}: {}", path, e),
+        }
+    }
+    result.map(|(code, _)| code)
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn monitor(config: Config, metrics: Arc<Metrics>) -> Result<(ExitCode, ExitReason), Box<dyn Error>> {
+    let started_at = Instant::now();
+    color::init(config.no_color);
+    log::set_debug(config.debug);
+    log::set_full_container_ids(config.full_container_ids);
+    if let (true, Some(archive), Some(state_file)) = (config.forensics_mode, &config.container_archive, &config.state_file) {
+        return Ok((forensics_report(archive, state_file).await?, ExitReason::Graceful));
+    }
+    if let Some(path) = &config.export_grafana_dashboard {
+        tokio::fs::write(path, format!("{}\n", grafana::dashboard()))
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        info!("Grafana dashboard written to {}", path);
+        return Ok((ExitCode::SUCCESS, ExitReason::Graceful));
+    }
+    if let Some(path) = &config.bind_mount_proc {
+        if !tokio::fs::metadata(path).await.map(|m| m.is_dir()).unwrap_or(false) {
+            return Err(format!("--bind-mount-proc {} is not a directory", path).into());
+        }
+        procfs::set_proc_root(path);
+        info!("Reading process information from {}", path);
+    }
+    let missing = CapabilityChecker::check_required(&config);
+    if !missing.is_empty() {
+        let missing: Vec<String> = missing.iter().map(ToString::to_string).collect();
+        return Err(format!(
+            "Missing Linux capabilities: {}. Grant them (e.g. with docker run --cap-add) or drop the options that need them",
+            missing.join(", ")
+        )
+        .into());
+    }
+    // Overwriting a state file written by a newer version would lose what it added.
+    if let Some(path) = &config.state_file {
+        state::check_version(path).await?;
+    }
+    let mut policy = match &config.policy_file {
+        Some(path) => Policy::load(path).await?,
+        None => Policy::default(),
+    };
+    if config.kill_suspicious_process {
+        policy.default_action = Action::Kill;
+    }
+    if let Some(action) = config.default_action {
+        policy.default_action = action;
+    }
+    // The watchdog thread only reports stuck tasks; restarting them happens back on the runtime.
+    let (stuck_tx, mut stuck_rx) = mpsc::unbounded_channel::<String>();
+    let on_stuck = config.watchdog_restart.then(|| {
+        Box::new(move |container: &str| {
+            let _ = stuck_tx.send(container.to_string());
+        }) as StuckHandler
+    });
+    let (simulation_tx, simulation_rx) = mpsc::unbounded_channel();
+    let (events, event_receiver) = EventBuffer::new(config.events_buffer_size, config.events_buffer_overflow, metrics.clone());
+    let ctx = Arc::new(Context {
+        events,
+        metrics,
+        engine: PolicyEngine::new(policy),
+        tenants: Arc::new(Tenants::load(&config.tenants).await?),
+        plugins: plugin::load_plugins(&config.plugins)?,
+        inventory: Arc::new(Inventory::default()),
+        whitelists: Arc::new(ProcessWhitelistStore::default()),
+        numa: numa_scheduler(&config)?,
+        filter: Arc::new(EventFilter::default()),
+        connections: Arc::new(ConnectionLog::default()),
+        execs: Arc::new(ExecLog::default()),
+        detection_ids: Mutex::new(HashMap::new()),
+        process_counts: match &config.process_count_baseline_file {
+            Some(path) => Some(Arc::new(
+                ProcessCountBaselines::load(path, config.process_count_window, config.process_count_stddevs).await?,
+            )),
+            None => None,
+        },
+        event_log: match (&config.output_file, config.log_rotate_on_sighup) {
+            (Some(path), true) => Some(Arc::new(EventLog::open(path).await?)),
+            _ => None,
+        },
+        syslog: config.syslog_addr.as_deref().map(SyslogTcpSink::start).transpose()?,
+        redis: config.redis.as_ref().map(RedisPublisher::start).transpose()?,
+        cloudwatch: config.cloudwatch.as_ref().map(CloudWatchSink::start).transpose()?,
+        sqlite: config.write_events_to_sqlite.as_deref().map(SqliteEventStore::open).transpose()?,
+        // Opened after the event store, which switches the database to WAL.
+        time_anomalies: match (&config.write_events_to_sqlite, config.detect_time_of_day_anomalies) {
+            (Some(path), true) => Some(TimeAnomalyDetector::open(path)?),
+            _ => None,
+        },
+        journald: if config.log_outputs.contains(&LogOutput::Journald) {
+            Some(JournaldLogger::connect()?)
+        } else {
+            None
+        },
+        falco: config.report_to_falco.as_deref().map(FalcoReporter::new),
+        router: match config.routes.is_empty() {
+            true => None,
+            false => Some(Arc::new(DetectionEventRouter::from_config(&config.sinks, &config.routes)?)),
+        },
+        simulation: config.simulate_attack.is_some().then_some(simulation_tx),
+        watchdog: WatchdogTimer::start(config.watchdog_timeout, on_stuck)?,
+        state: config.state_file.as_deref().map(StateStore::new),
+        config,
+    });
+
+    tokio::spawn(event_receiver.run({
+        let ctx = ctx.clone();
+        move |event| {
+            let ctx = ctx.clone();
+            async move { deliver_event(&ctx, &event).await }
+        }
+    }));
+
+    #[cfg(feature = "ebpf")]
+    if ctx.config.trace_tcp_connect {
+        container_new_process_detector::ebpf::trace_tcp_connects(ctx.connections.clone())?;
+        info!("Tracing outbound TCP connections with bpftrace");
+    }
+    #[cfg(feature = "ebpf")]
+    if ctx.config.detect_ptrace_usage {
+        watch_ptrace(&ctx)?;
+        info!("Tracing ptrace attaches with bpftrace");
+    }
+
+    if ctx.config.audit_exec {
+        audit::listen(ctx.execs.clone())?;
+        info!("Receiving execve records from the kernel audit subsystem");
+    }
+    if let Some(baselines) = ctx.process_counts.clone() {
+        tokio::spawn(async move {
+            loop {
+                sleep(PROCESS_COUNT_SAVE_INTERVAL).await;
+                save_process_counts(&baselines).await;
+            }
+        });
+    }
+    if ctx.config.watch_seccomp_violations {
+        watch_seccomp_violations(&ctx)?;
+        info!("Receiving seccomp violations from the kernel audit subsystem");
+    }
+
     // Step 1: Retrieve docker directories
-    let docker_list = get_docker_directories().await?;
+    let found = cgroup::get_docker_directories(&ctx.config.cgroup_paths).await?;
+    let mut docker_list = Vec::new();
+    for container in &found {
+        if in_namespace(&ctx, container).await && is_running(&ctx, container).await {
+            docker_list.push(container.clone());
+        }
+    }
 
     // Step 2: Get initial whitelist of processes
-    let whitelist = get_whitelist(&docker_list).await?;
+    let mut whitelist = cgroup::get_whitelist(&docker_list, ctx.config.whitelist_retry()).await?;
+    if let Some(path) = &ctx.config.resume_from_state_file {
+        resume(&ctx, path, &mut whitelist).await;
+    }
 
     // Step 3: Print the docker directories and the whitelist
-    println!("Docker directories: {:?}", docker_list);
-    println!("Whitelist: {:?}", whitelist);
+    info!("Docker directories: {:?}", docker_list);
+    info!("Whitelist: {:?}", whitelist);
+    if let Some(state) = &ctx.state {
+        let mut baselines = Vec::new();
+        for (container, procs) in &whitelist {
+            let mut processes = Vec::new();
+            for pid in procs {
+                processes.push(BaselineProcess::read(*pid).await);
+            }
+            baselines.push(Baseline {
+                container_id: container.container_id(),
+                procs_path: container.procs_path(),
+                processes,
+            });
+        }
+        if let Err(e) = state.save_baselines(&baselines).await {
+            eprintln!("Failed to write state file {}: {}", state.path(), e);
+        }
+    }
+
+    // Step 4: Monitor the docker directories, --containers-per-task to a task
+    let monitors = Arc::new(Monitors::new(ctx.metrics.clone()));
+    let mut allowed = Vec::new();
+    for (container, procs) in whitelist {
+        if ctx.config.require_seccomp && !check_seccomp(&ctx, &container, &procs).await {
+            continue;
+        }
+        allowed.push((container, procs));
+    }
+    while !allowed.is_empty() {
+        let rest = allowed.split_off(allowed.len().min(ctx.config.containers_per_task));
+        monitors.start_group(&ctx, std::mem::replace(&mut allowed, rest));
+    }
+
+    // Pick up containers started later, and stop monitoring the ones that go away
+    if let Some(method) = ctx.config.discovery_method {
+        let mut changes = discovery::spawn(method, ctx.config.cgroup_paths.clone(), &found);
+        let ctx = ctx.clone();
+        let monitors = monitors.clone();
+        tokio::spawn(async move {
+            while let Some(change) = changes.recv().await {
+                match change {
+                    ContainerChange::Started(container) => {
+                        if monitors.get(&container.container_id()).is_none() {
+                            monitor_new_container(&ctx, &monitors, container).await;
+                        }
+                    }
+                    ContainerChange::Stopped(container_id) => {
+                        ctx.inventory.set_state(&container_id, MonitorState::Stopped);
+                        if monitors.stop(&container_id) {
+                            info!("Container {} is gone, stopped monitoring it", log::id(&container_id));
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    // Restart monitoring tasks the watchdog reports as stuck. The replacement keeps the
+    // whitelist the stuck task last published rather than taking a new snapshot, which
+    // would accept whatever started while the task was not polling.
+    if ctx.config.watchdog_restart {
+        let ctx = ctx.clone();
+        let monitors = monitors.clone();
+        tokio::spawn(async move {
+            while let Some(container_id) = stuck_rx.recv().await {
+                let Some(container) = monitors.get(&container_id) else {
+                    continue;
+                };
+                let Some(whitelist) = ctx.whitelists.get(&container_id) else {
+                    eprintln!("Failed to restart monitoring task for {}: no whitelist", log::id(&container_id));
+                    continue;
+                };
+                let known = whitelist.known().await;
+                info!("Restarting monitoring task for {}", log::id(&container_id));
+                monitors.start(&ctx, container, procfs::still_running(&known).await);
+            }
+        });
+    }
+
+    // Step 5: Expose metrics and the control socket, and print a periodic stats summary
+    let inventory = ctx.inventory.clone();
+    let whitelists = ctx.whitelists.clone();
+    let filter = ctx.filter.clone();
+    let tenants = ctx.tenants.clone();
+    let socket = ctx.config.control_socket.clone();
+    tokio::spawn(async move {
+        if let Err(e) = control::serve_control(&socket, inventory, whitelists, filter, tenants).await {
+            eprintln!("Error serving control socket {}: {}", socket, e);
+        }
+    });
+    if let Some(addr) = ctx.config.metrics_addr {
+        let metrics = ctx.metrics.clone();
+        let health = ctx
+            .config
+            .healthcheck_endpoint
+            .as_deref()
+            .map(|prefix| Arc::new(HealthCheck::new(prefix, ctx.inventory.clone(), started_at)));
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve_metrics(addr, metrics, health).await {
+                eprintln!("Error serving metrics: {}", e);
+            }
+        });
+    }
+
+    if let Some(interval) = ctx.config.report_interval {
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            loop {
+                ticks.tick().await;
+                let summary = SummaryReport::collect(&ctx.inventory, &ctx.metrics, interval);
+                info!("Summary: {}", summary.to_json());
+                for notifier in &ctx.config.report_notifiers {
+                    if let Err(e) = notifier.send(&summary).await.map_err(|e| e.to_string()) {
+                        eprintln!("Failed to send summary to {:?}: {}", notifier, e);
+                    }
+                }
+            }
+        });
+    }
 
-    // Step 4: Monitor each docker directory in a separate task
-    for (docker_dir, procs) in whitelist {
+    if let Some(log) = ctx.event_log.clone() {
+        let mut hangup = signal(SignalKind::hangup())?;
         tokio::spawn(async move {
-            if let Err(e) = monitor_procs(docker_dir, procs).await {
-                eprintln!("Error monitoring procs: {}", e);
+            while hangup.recv().await.is_some() {
+                match log.reopen().await {
+                    Ok(()) => info!("Reopened {} on SIGHUP", log.path()),
+                    Err(e) => eprintln!("Failed to reopen {}, still writing to the old file: {}", log.path(), e),
+                }
             }
         });
     }
 
-    // Keep the main function running indefinitely
+    let simulation = async {
+        match &ctx.config.simulate_attack {
+            Some(container_id) => simulate_attack(&ctx, &monitors, container_id, simulation_rx).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(simulation);
+
+    // SIGHUP also reopens the event log, from its own task.
+    let binary = match RunningBinary::current() {
+        Ok(binary) => Some(binary),
+        Err(e) => {
+            eprintln!("Warning: in-place upgrades on SIGHUP are disabled, the running binary is unknown: {}", e);
+            None
+        }
+    };
+    let mut hangup = signal(SignalKind::hangup())?;
+    let mut handover = None;
+
+    // Keep the main function running until SIGINT or SIGTERM, or the simulated attack's result
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut exit_code = ExitCode::SUCCESS;
+    let mut exit_reason = ExitReason::Graceful;
     loop {
-        sleep(Duration::from_secs(3600)).await;
+        tokio::select! {
+            _ = sleep(ctx.config.stats_interval) => info!("{}", ctx.metrics.summary()),
+            Some(()) = hangup.recv(), if binary.is_some() => {
+                let binary = binary.as_ref().unwrap();
+                match binary.replaced().await {
+                    Ok(true) => {
+                        info!("{} was replaced, handing over to the new binary", binary.path.display());
+                        handover = Some(hand_off(&ctx, &monitors).await);
+                        break;
+                    }
+                    Ok(false) => {}
+                    Err(e) => eprintln!("Failed to compare {} with the running binary: {}", binary.path.display(), e),
+                }
+            }
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => {
+                exit_reason = ExitReason::Killed;
+                break;
+            }
+            detected = &mut simulation => {
+                if !detected {
+                    exit_code = ExitCode::from(1);
+                }
+                break;
+            }
+        }
+    }
+
+    info!("Shutting down");
+    if !ctx.events.drain(Duration::from_secs(5)).await {
+        eprintln!("Warning: shutting down with events still buffered for the sinks");
+    }
+    if let Some(sink) = &ctx.syslog {
+        sink.shutdown(Duration::from_secs(5));
+    }
+    if let Some(publisher) = &ctx.redis {
+        publisher.shutdown(Duration::from_secs(5));
+    }
+    if let Some(sink) = &ctx.cloudwatch {
+        sink.shutdown(Duration::from_secs(5));
+    }
+    if let Some(store) = &ctx.sqlite {
+        store.shutdown(Duration::from_secs(5));
+    }
+    if let Some(router) = &ctx.router {
+        router.shutdown(Duration::from_secs(5));
+    }
+    if let Some(baselines) = &ctx.process_counts {
+        save_process_counts(baselines).await;
+    }
+    if let (Some(mut state), Some(binary)) = (handover, &binary) {
+        state.counters = ctx.metrics.counters();
+        let path = &ctx.config.upgrade_state_file;
+        state.save(path).await.map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        info!("Handed {} containers over in {}", state.containers.len(), path);
+        let e = binary.exec(path);
+        return Err(format!("Failed to exec {}: {}", binary.path.display(), e).into());
     }
+    Ok((exit_code, exit_reason))
 }
@@ -0,0 +1,453 @@
+use std::error::Error;
+use std::fmt::{self, Write};
+use std::net::SocketAddr;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::health::HealthCheck;
+use crate::{info, log};
+
+// Values below 2^SUB_BUCKET_BITS are stored exactly; larger values keep their top
+// SUB_BUCKET_BITS bits. Quantiles are reported as the upper bound of their bucket, at
+// most 1/16 (6.25%) above the true value.
+const SUB_BUCKET_BITS: u32 = 5;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+const HALF_SUB_BUCKETS: u64 = SUB_BUCKETS / 2;
+const BUCKETS: usize = (SUB_BUCKETS + (64 - SUB_BUCKET_BITS as u64) * HALF_SUB_BUCKETS) as usize;
+
+pub const QUANTILES: [f64; 3] = [0.5, 0.95, 0.99];
+pub const DEFAULT_FINAL_METRICS_FILE: &str = "/tmp/cnpd-final-metrics.prom";
+
+// Why the daemon exited, as the `reason` label of cnpd_exit_time_unix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    // Interrupted by an operator, or done on its own (--simulate-attack).
+    Graceful,
+    // Failed during startup or while monitoring.
+    Error,
+    // Stopped with SIGTERM, by a supervisor or a CI timeout.
+    Killed,
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ExitReason::Graceful => "graceful",
+            ExitReason::Error => "error",
+            ExitReason::Killed => "killed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    sum: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            counts: vec![0; BUCKETS],
+            total: 0,
+            sum: 0,
+            max: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, value: u64) {
+        self.counts[bucket_index(value)] += 1;
+        self.total += 1;
+        self.sum = self.sum.saturating_add(value);
+        self.max = self.max.max(value);
+    }
+
+    pub fn len(&self) -> u64 {
+        self.total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    pub fn value_at_quantile(&self, quantile: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+
+        let target = ((quantile * self.total as f64).ceil() as u64).clamp(1, self.total);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return bucket_upper_bound(index).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS {
+        return value as usize;
+    }
+    let shift = (64 - value.leading_zeros()) - SUB_BUCKET_BITS;
+    let top = value >> shift;
+    (SUB_BUCKETS + (shift as u64 - 1) * HALF_SUB_BUCKETS + (top - HALF_SUB_BUCKETS)) as usize
+}
+
+fn bucket_upper_bound(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+    let shift = (index - SUB_BUCKETS) / HALF_SUB_BUCKETS + 1;
+    let top = (index - SUB_BUCKETS) % HALF_SUB_BUCKETS + HALF_SUB_BUCKETS;
+    (top << shift) | ((1 << shift) - 1)
+}
+
+// The counters an in-place upgrade carries over to the new binary, so that they keep
+// counting up rather than resetting.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Counters {
+    pub detections_total: u64,
+    pub blocked_total: u64,
+    pub allowed_total: u64,
+    pub events_dropped_total: u64,
+    // Under the ID shown in the logs, sorted.
+    pub container_detections: Vec<(String, u64)>,
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    detections_total: AtomicU64,
+    // Detections by container, under the ID shown in the logs.
+    container_detections: Mutex<HashMap<String, u64>>,
+    // Containers with a running monitor.
+    monitored_containers: AtomicU64,
+    // Microseconds between process start and detection.
+    detection_latency: Mutex<Histogram>,
+    // New processes acted on, and the ones the policy let run.
+    blocked_total: AtomicU64,
+    allowed_total: AtomicU64,
+    // Milliseconds from docker stop to a completed docker start.
+    restart_duration: Mutex<Histogram>,
+    // Events lost to a full --events-buffer-size buffer.
+    events_dropped_total: AtomicU64,
+    // Health score of each running monitoring task, under the ID shown in the logs.
+    task_health: Mutex<HashMap<String, u32>>,
+}
+
+impl Metrics {
+    pub fn record_detection(&self, container_id: &str, latency: Option<Duration>) {
+        self.detections_total.fetch_add(1, Ordering::Relaxed);
+        *self
+            .container_detections
+            .lock()
+            .unwrap()
+            .entry(log::id(container_id).to_string())
+            .or_default() += 1;
+        if let Some(latency) = latency {
+            self.detection_latency
+                .lock()
+                .unwrap()
+                .record(latency.as_micros() as u64);
+        }
+    }
+
+    pub fn detection_latency(&self) -> Histogram {
+        self.detection_latency.lock().unwrap().clone()
+    }
+
+    pub fn set_monitored_containers(&self, count: usize) {
+        self.monitored_containers.store(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_outcome(&self, blocked: bool) {
+        let counter = if blocked { &self.blocked_total } else { &self.allowed_total };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn blocked_total(&self) -> u64 {
+        self.blocked_total.load(Ordering::Relaxed)
+    }
+
+    pub fn allowed_total(&self) -> u64 {
+        self.allowed_total.load(Ordering::Relaxed)
+    }
+
+    pub fn record_restart(&self, duration: Duration) {
+        self.restart_duration.lock().unwrap().record(duration.as_millis() as u64);
+    }
+
+    pub fn restart_duration(&self) -> Histogram {
+        self.restart_duration.lock().unwrap().clone()
+    }
+
+    pub fn record_dropped_event(&self) {
+        self.events_dropped_total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn events_dropped_total(&self) -> u64 {
+        self.events_dropped_total.load(Ordering::Relaxed)
+    }
+
+    pub fn set_task_health(&self, container_id: &str, score: u32) {
+        self.task_health.lock().unwrap().insert(log::id(container_id).to_string(), score);
+    }
+
+    pub fn remove_task_health(&self, container_id: &str) {
+        self.task_health.lock().unwrap().remove(log::id(container_id));
+    }
+
+    // Drops the per-container series of a container that is gone for good.
+    pub fn remove_container(&self, container_id: &str) {
+        self.container_detections.lock().unwrap().remove(log::id(container_id));
+        self.remove_task_health(container_id);
+    }
+
+    pub fn counters(&self) -> Counters {
+        let mut container_detections: Vec<(String, u64)> =
+            self.container_detections.lock().unwrap().iter().map(|(id, count)| (id.clone(), *count)).collect();
+        container_detections.sort();
+        Counters {
+            detections_total: self.detections_total.load(Ordering::Relaxed),
+            blocked_total: self.blocked_total.load(Ordering::Relaxed),
+            allowed_total: self.allowed_total.load(Ordering::Relaxed),
+            events_dropped_total: self.events_dropped_total.load(Ordering::Relaxed),
+            container_detections,
+        }
+    }
+
+    // Adds to what was counted so far, which at startup is nothing.
+    pub fn restore_counters(&self, counters: &Counters) {
+        self.detections_total.fetch_add(counters.detections_total, Ordering::Relaxed);
+        self.blocked_total.fetch_add(counters.blocked_total, Ordering::Relaxed);
+        self.allowed_total.fetch_add(counters.allowed_total, Ordering::Relaxed);
+        self.events_dropped_total.fetch_add(counters.events_dropped_total, Ordering::Relaxed);
+        let mut detections = self.container_detections.lock().unwrap();
+        for (id, count) in &counters.container_detections {
+            *detections.entry(id.clone()).or_default() += count;
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let latency = self.detection_latency();
+        let mut summary = format!(
+            "Stats: {} detections",
+            self.detections_total.load(Ordering::Relaxed)
+        );
+        if !latency.is_empty() {
+            let _ = write!(
+                summary,
+                ", detection latency p50 {:.3} ms, p95 {:.3} ms, p99 {:.3} ms",
+                latency.value_at_quantile(0.5) as f64 / 1000.0,
+                latency.value_at_quantile(0.95) as f64 / 1000.0,
+                latency.value_at_quantile(0.99) as f64 / 1000.0
+            );
+        }
+        summary
+    }
+
+    // Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let latency = self.detection_latency();
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# HELP cnpd_detections_total Number of new processes detected.");
+        let _ = writeln!(out, "# TYPE cnpd_detections_total counter");
+        let _ = writeln!(
+            out,
+            "cnpd_detections_total {}",
+            self.detections_total.load(Ordering::Relaxed)
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP cnpd_detection_latency_seconds Time from process start to detection."
+        );
+        let _ = writeln!(out, "# TYPE cnpd_detection_latency_seconds summary");
+        for quantile in QUANTILES {
+            let _ = writeln!(
+                out,
+                "cnpd_detection_latency_seconds{{quantile=\"{}\"}} {}",
+                quantile,
+                latency.value_at_quantile(quantile) as f64 / 1_000_000.0
+            );
+        }
+        let _ = writeln!(
+            out,
+            "cnpd_detection_latency_seconds_sum {}",
+            latency.sum() as f64 / 1_000_000.0
+        );
+        let _ = writeln!(out, "cnpd_detection_latency_seconds_count {}", latency.len());
+
+        let _ = writeln!(
+            out,
+            "# HELP cnpd_container_detections_total Number of new processes detected, by container."
+        );
+        let _ = writeln!(out, "# TYPE cnpd_container_detections_total counter");
+        let mut containers: Vec<(String, u64)> = self
+            .container_detections
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, count)| (id.clone(), *count))
+            .collect();
+        containers.sort();
+        for (id, count) in containers {
+            let _ = writeln!(out, "cnpd_container_detections_total{{container_id=\"{}\"}} {}", id, count);
+        }
+
+        let _ = writeln!(out, "# HELP cnpd_blocked_total New processes acted on.");
+        let _ = writeln!(out, "# TYPE cnpd_blocked_total counter");
+        let _ = writeln!(out, "cnpd_blocked_total {}", self.blocked_total());
+        let _ = writeln!(out, "# HELP cnpd_allowed_total New processes the policy let run.");
+        let _ = writeln!(out, "# TYPE cnpd_allowed_total counter");
+        let _ = writeln!(out, "cnpd_allowed_total {}", self.allowed_total());
+
+        let _ = writeln!(out, "# HELP cnpd_monitored_containers Containers being monitored.");
+        let _ = writeln!(out, "# TYPE cnpd_monitored_containers gauge");
+        let _ = writeln!(
+            out,
+            "cnpd_monitored_containers {}",
+            self.monitored_containers.load(Ordering::Relaxed)
+        );
+
+        let restart = self.restart_duration();
+        let _ = writeln!(
+            out,
+            "# HELP cnpd_restart_duration_seconds Time from docker stop to a completed docker start."
+        );
+        let _ = writeln!(out, "# TYPE cnpd_restart_duration_seconds summary");
+        for quantile in QUANTILES {
+            let _ = writeln!(
+                out,
+                "cnpd_restart_duration_seconds{{quantile=\"{}\"}} {}",
+                quantile,
+                restart.value_at_quantile(quantile) as f64 / 1000.0
+            );
+        }
+        let _ = writeln!(out, "cnpd_restart_duration_seconds_sum {}", restart.sum() as f64 / 1000.0);
+        let _ = writeln!(out, "cnpd_restart_duration_seconds_count {}", restart.len());
+
+        let _ = writeln!(out, "# HELP cnpd_events_dropped_total Events dropped because the events buffer was full.");
+        let _ = writeln!(out, "# TYPE cnpd_events_dropped_total counter");
+        let _ = writeln!(out, "cnpd_events_dropped_total {}", self.events_dropped_total());
+
+        let _ = writeln!(out, "# HELP cnpd_task_health_score Health score of the monitoring task, from 0 to 100.");
+        let _ = writeln!(out, "# TYPE cnpd_task_health_score gauge");
+        let mut scores: Vec<(String, u32)> = self
+            .task_health
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, score)| (id.clone(), *score))
+            .collect();
+        scores.sort();
+        for (id, score) in scores {
+            let _ = writeln!(out, "cnpd_task_health_score{{container_id=\"{}\"}} {}", id, score);
+        }
+
+        out
+    }
+
+    // The snapshot written by --export-metrics-on-exit: everything /metrics serves,
+    // plus when and why the daemon exited.
+    pub fn render_final(&self, reason: ExitReason, exit_time_unix: i64) -> String {
+        let mut out = self.render();
+        let _ = writeln!(out, "# HELP cnpd_exit_time_unix Unix time at which the daemon exited.");
+        let _ = writeln!(out, "# TYPE cnpd_exit_time_unix gauge");
+        let _ = writeln!(out, "cnpd_exit_time_unix{{reason=\"{}\"}} {}", reason, exit_time_unix);
+        out
+    }
+}
+
+// With `health`, the same server answers the Kubernetes probes too.
+pub async fn serve_metrics(addr: SocketAddr, metrics: Arc<Metrics>, health: Option<Arc<HealthCheck>>) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Metrics endpoint listening on http://{}/metrics", addr);
+
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let metrics = metrics.clone();
+        let health = health.clone();
+
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            let n = match stream.read(&mut buf).await {
+                Ok(n) => n,
+                Err(_) => return,
+            };
+            let request = String::from_utf8_lossy(&buf[..n]);
+            let path = request.split_whitespace().nth(1).unwrap_or("");
+
+            let response = if path == "/metrics" {
+                let body = metrics.render();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            } else if let Some(report) = health.as_ref().and_then(|health| health.handle(path)) {
+                let body = report.to_json().to_string();
+                let status = if report.ok { "200 OK" } else { "503 Service Unavailable" };
+                format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+            };
+
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_are_contiguous() {
+        assert_eq!(bucket_index(31), 31);
+        assert_eq!((bucket_index(32), bucket_index(33), bucket_index(34)), (32, 32, 33));
+        assert_eq!(bucket_upper_bound(32), 33);
+        for index in 0..BUCKETS - 1 {
+            let upper = bucket_upper_bound(index);
+            assert_eq!(bucket_index(upper), index);
+            assert_eq!(bucket_index(upper + 1), index + 1);
+        }
+        assert_eq!(bucket_index(u64::MAX), BUCKETS - 1);
+    }
+
+    #[test]
+    fn quantiles_are_within_a_sixteenth() {
+        let mut histogram = Histogram::default();
+        for value in 1..=100_000 {
+            histogram.record(value);
+        }
+        for (quantile, exact) in [(0.5, 50_000), (0.99, 99_000)] {
+            let reported = histogram.value_at_quantile(quantile);
+            assert!(reported >= exact, "p{} {} below {}", quantile * 100.0, reported, exact);
+            assert!((reported - exact) as f64 / exact as f64 <= 1.0 / 16.0, "p{} {} vs {}", quantile * 100.0, reported, exact);
+        }
+        assert_eq!(histogram.value_at_quantile(1.0), 100_000);
+    }
+}
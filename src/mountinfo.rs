@@ -0,0 +1,96 @@
+use crate::fanotify::unescape_mount_path;
+use crate::procfs;
+
+// One line of /proc/<pid>/mountinfo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountEntry {
+    pub mount_id: u32,
+    // The mounted subtree of the source filesystem, "/" unless it is a bind mount.
+    pub root: String,
+    pub target: String,
+    // Per-mount options such as rw,nosuid,relatime.
+    pub options: String,
+    pub fstype: String,
+    pub source: String,
+}
+
+impl MountEntry {
+    // The source as findmnt shows it, with the bound subtree in brackets:
+    // /dev/sda1[/etc/shadow].
+    pub fn describe_source(&self) -> String {
+        if self.root == "/" {
+            self.source.clone()
+        } else {
+            format!("{}[{}]", self.source, self.root)
+        }
+    }
+
+    // Whether the mount hides one of `paths` or something below it.
+    pub fn shadows(&self, paths: &[String]) -> bool {
+        paths.iter().any(|path| {
+            let path = path.trim_end_matches('/');
+            let target = self.target.trim_end_matches('/');
+            target == path || target.strip_prefix(path).is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+}
+
+// Lines that do not parse are skipped.
+pub fn parse_mountinfo(content: &str) -> Vec<MountEntry> {
+    content.lines().filter_map(parse_line).collect()
+}
+
+// `36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue`:
+// the optional fields before the `-` vary in number.
+fn parse_line(line: &str) -> Option<MountEntry> {
+    let (mount, filesystem) = line.split_once(" - ")?;
+    let mut fields = mount.split(' ');
+    let mount_id = fields.next()?.parse().ok()?;
+    let _parent_id = fields.next()?;
+    let _device = fields.next()?;
+    let root = unescape_mount_path(fields.next()?);
+    let target = unescape_mount_path(fields.next()?);
+    let options = fields.next()?.to_string();
+    let mut fields = filesystem.split(' ');
+    Some(MountEntry {
+        mount_id,
+        root,
+        target,
+        options,
+        fstype: fields.next()?.to_string(),
+        source: unescape_mount_path(fields.next()?),
+    })
+}
+
+pub async fn read_mountinfo(pid: i32) -> Option<Vec<MountEntry>> {
+    let content = procfs::read_proc_file(pid, "mountinfo").await?;
+    Some(parse_mountinfo(&String::from_utf8_lossy(&content)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bind_mounts() {
+        let content = "\
+812 700 0:52 / / rw,relatime master:305 - overlay overlay rw,lowerdir=/var/lib/docker/overlay2/l/A
+900 812 8:1 /tmp/evil\\040passwd /etc/passwd rw,relatime - ext4 /dev/sda1 rw
+bogus line
+";
+        let mounts = parse_mountinfo(content);
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[1].mount_id, 900);
+        assert_eq!(mounts[1].describe_source(), "/dev/sda1[/tmp/evil passwd]");
+        assert_eq!(mounts[1].options, "rw,relatime");
+
+        let sensitive = vec!["/etc".to_string(), "/root".to_string()];
+        assert!(mounts[1].shadows(&sensitive));
+        assert!(!mounts[0].shadows(&sensitive));
+        let etcd = MountEntry {
+            target: "/etcd".to_string(),
+            ..mounts[1].clone()
+        };
+        assert!(!etcd.shadows(&sensitive));
+    }
+}
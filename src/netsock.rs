@@ -0,0 +1,234 @@
+use std::collections::{HashSet, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::fs;
+
+use crate::json::Value;
+use crate::procfs;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetSocket {
+    pub local_ip: IpAddr,
+    pub local_port: u16,
+    pub remote_ip: IpAddr,
+    pub remote_port: u16,
+    pub state: String,
+    pub inode: u64,
+}
+
+impl NetSocket {
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("local_ip".to_string(), self.local_ip.to_string().into()),
+            ("local_port".to_string(), (self.local_port as u32).into()),
+            ("remote_ip".to_string(), self.remote_ip.to_string().into()),
+            ("remote_port".to_string(), (self.remote_port as u32).into()),
+            ("state".to_string(), self.state.as_str().into()),
+        ])
+    }
+
+    pub fn from_json(value: &Value) -> Option<NetSocket> {
+        let ip = |key: &str| value.get(key)?.as_str()?.parse().ok();
+        let port = |key: &str| value.get(key)?.as_i64().map(|p| p as u16);
+        Some(NetSocket {
+            local_ip: ip("local_ip")?,
+            local_port: port("local_port")?,
+            remote_ip: ip("remote_ip")?,
+            remote_port: port("remote_port")?,
+            state: value.get("state").and_then(Value::as_str).unwrap_or_default().to_string(),
+            inode: 0,
+        })
+    }
+}
+
+// An outbound TCP connection seen by the tcp_connect tracer as it was made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkEvent {
+    pub pid: i32,
+    pub local_ip: IpAddr,
+    pub local_port: u16,
+    pub remote_ip: IpAddr,
+    pub remote_port: u16,
+    pub seen_at: Instant,
+}
+
+impl NetworkEvent {
+    pub fn to_socket(&self) -> NetSocket {
+        NetSocket {
+            local_ip: self.local_ip,
+            local_port: self.local_port,
+            remote_ip: self.remote_ip,
+            remote_port: self.remote_port,
+            state: "SYN_SENT".to_string(),
+            inode: 0,
+        }
+    }
+}
+
+// How long traced connections are kept for detections to pick up.
+const CONNECTION_RETENTION: Duration = Duration::from_secs(60);
+const MAX_CONNECTIONS: usize = 10_000;
+
+// Recent connections from every process on the host; detections look up their PID.
+#[derive(Default)]
+pub struct ConnectionLog {
+    events: Mutex<VecDeque<NetworkEvent>>,
+}
+
+impl ConnectionLog {
+    pub fn record(&self, event: NetworkEvent) {
+        let mut events = self.events.lock().unwrap();
+        while events
+            .front()
+            .is_some_and(|e| e.seen_at.elapsed() > CONNECTION_RETENTION || events.len() >= MAX_CONNECTIONS)
+        {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    pub fn for_pid(&self, pid: i32) -> Vec<NetworkEvent> {
+        self.events.lock().unwrap().iter().filter(|e| e.pid == pid).cloned().collect()
+    }
+}
+
+fn tcp_state(code: &str) -> &'static str {
+    match code {
+        "01" => "ESTABLISHED",
+        "02" => "SYN_SENT",
+        "03" => "SYN_RECV",
+        "04" => "FIN_WAIT1",
+        "05" => "FIN_WAIT2",
+        "06" => "TIME_WAIT",
+        "07" => "CLOSE",
+        "08" => "CLOSE_WAIT",
+        "09" => "LAST_ACK",
+        "0A" => "LISTEN",
+        "0B" => "CLOSING",
+        _ => "UNKNOWN",
+    }
+}
+
+// The kernel prints addresses as 32-bit words in host byte order: 8 hex digits for
+// IPv4, 32 (four words) for IPv6. Each word is converted back to the bytes it was
+// stored as, which are in network order.
+fn parse_address(hex: &str) -> Option<(IpAddr, u16)> {
+    let (addr, port) = hex.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+
+    let mut bytes = Vec::with_capacity(16);
+    for chunk in addr.as_bytes().chunks(8) {
+        let word = u32::from_str_radix(std::str::from_utf8(chunk).ok()?, 16).ok()?;
+        bytes.extend_from_slice(&word.to_ne_bytes());
+    }
+
+    let ip = match bytes.len() {
+        4 => IpAddr::V4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3])),
+        16 => {
+            let octets: [u8; 16] = bytes.try_into().ok()?;
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return None,
+    };
+    Some((ip, port))
+}
+
+// Parses the contents of /proc/net/tcp or /proc/net/tcp6.
+pub fn parse_proc_net_tcp(content: &str) -> Vec<NetSocket> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (local_ip, local_port) = parse_address(fields.get(1)?)?;
+            let (remote_ip, remote_port) = parse_address(fields.get(2)?)?;
+            Some(NetSocket {
+                local_ip,
+                local_port,
+                remote_ip,
+                remote_port,
+                state: tcp_state(fields.get(3)?).to_string(),
+                inode: fields.get(9)?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+async fn socket_inodes(pid: i32) -> HashSet<u64> {
+    let mut inodes = HashSet::new();
+    let Ok(mut entries) = fs::read_dir(procfs::pid_path(pid, "fd")).await else {
+        return inodes;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if let Ok(target) = fs::read_link(entry.path()).await {
+            let target = target.to_string_lossy();
+            if let Some(inode) = target.strip_prefix("socket:[").and_then(|t| t.strip_suffix(']')) {
+                if let Ok(inode) = inode.parse() {
+                    inodes.insert(inode);
+                }
+            }
+        }
+    }
+    inodes
+}
+
+// TCP sockets (IPv4 and IPv6) held open by the process, read from its own network
+// namespace through /proc/<pid>/net.
+pub async fn read_process_sockets(pid: i32) -> Option<Vec<NetSocket>> {
+    let inodes = socket_inodes(pid).await;
+    let mut sockets = Vec::new();
+    for table in ["tcp", "tcp6"] {
+        if let Ok(content) = fs::read_to_string(procfs::pid_path(pid, &format!("net/{}", table))).await {
+            sockets.extend(
+                parse_proc_net_tcp(&content)
+                    .into_iter()
+                    .filter(|socket| inodes.contains(&socket.inode)),
+            );
+        }
+    }
+    Some(sockets)
+}
+
+#[cfg(all(test, target_endian = "little"))]
+mod tests {
+    use super::*;
+
+    const TCP: &str = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:0CEA 00000000:0000 0A 00000000:00000000 00:00000000 00000000   999        0 23211 1 0000000000000000 100 0 0 10 0
+   1: 0F02000A:A2C8 5DB8D822:01BB 01 00000000:00000000 02:0000052B 00000000  1000        0 56233 2 0000000000000000 20 4 30 10 -1
+";
+
+    const TCP6: &str = "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000000000000000000001000000:1F90 00000000000000000000000000000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 31337 1 0000000000000000 100 0 0 10 0
+   1: B80D0120000000000000000001000000:C350 B80D0120000000000000000002000000:01BB 01 00000000:00000000 00:00000000 00000000  1000        0 42424 1 0000000000000000 20 4 30 10 -1
+   2: 0000000000000000FFFF00000100007F:0016 0000000000000000FFFF0000A1C7A8C0:D431 01 00000000:00000000 00:00000000 00000000     0        0 51515 1 0000000000000000 20 4 30 10 -1
+";
+
+    #[test]
+    fn parses_ipv4_sockets() {
+        let sockets = parse_proc_net_tcp(TCP);
+        assert_eq!(sockets.len(), 2);
+        assert_eq!(sockets[0].local_ip, "127.0.0.1".parse::<IpAddr>().unwrap());
+        assert_eq!(sockets[0].local_port, 3306);
+        assert_eq!(sockets[0].state, "LISTEN");
+        assert_eq!(sockets[1].local_ip, "10.0.2.15".parse::<IpAddr>().unwrap());
+        assert_eq!(sockets[1].remote_ip, "34.216.184.93".parse::<IpAddr>().unwrap());
+        assert_eq!(sockets[1].remote_port, 443);
+        assert_eq!(sockets[1].inode, 56233);
+    }
+
+    #[test]
+    fn parses_ipv6_sockets() {
+        let sockets = parse_proc_net_tcp(TCP6);
+        assert_eq!(sockets.len(), 3);
+        assert_eq!(sockets[0].local_ip, "::1".parse::<IpAddr>().unwrap());
+        assert_eq!(sockets[0].local_port, 8080);
+        assert_eq!(sockets[1].local_ip, "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(sockets[1].local_port, 50000);
+        assert_eq!(sockets[1].remote_ip, "2001:db8::2".parse::<IpAddr>().unwrap());
+        assert_eq!(sockets[1].state, "ESTABLISHED");
+        assert_eq!(sockets[2].local_ip, "::ffff:127.0.0.1".parse::<IpAddr>().unwrap());
+        assert_eq!(sockets[2].remote_ip, "::ffff:192.168.199.161".parse::<IpAddr>().unwrap());
+    }
+}
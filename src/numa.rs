@@ -0,0 +1,169 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use tokio::runtime::{self, Handle};
+
+use crate::affinity;
+
+const NODE_DIR: &str = "/sys/devices/system/node";
+
+thread_local! {
+    // The node whose runtime owns this thread, if any.
+    static CURRENT_NODE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumaNode {
+    pub id: usize,
+    pub cpus: Vec<usize>,
+}
+
+// The NUMA nodes with CPUs, from the node<N>/cpulist files. Memory-only nodes have an
+// empty cpulist and cannot run anything.
+pub fn nodes() -> io::Result<Vec<NumaNode>> {
+    let mut nodes = Vec::new();
+    for entry in std::fs::read_dir(NODE_DIR)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(id) = name.to_str().and_then(|n| n.strip_prefix("node")).and_then(|n| n.parse().ok()) else {
+            continue;
+        };
+        let cpus = parse_cpulist(std::fs::read_to_string(entry.path().join("cpulist"))?.trim())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("node{}: {}", id, e)))?;
+        if !cpus.is_empty() {
+            nodes.push(NumaNode { id, cpus });
+        }
+    }
+    nodes.sort_by_key(|node| node.id);
+    Ok(nodes)
+}
+
+// A kernel CPU list such as `0-3,8-11`.
+pub fn parse_cpulist(list: &str) -> Result<Vec<usize>, String> {
+    let mut cpus = Vec::new();
+    for range in list.split(',').filter(|r| !r.is_empty()) {
+        let parse = |cpu: &str| cpu.parse::<usize>().map_err(|_| format!("Invalid CPU list: {}", list));
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(parse(first)?..=parse(last)?),
+            None => cpus.push(parse(range)?),
+        }
+    }
+    Ok(cpus)
+}
+
+// Node the calling thread is pinned to by a NumaAwareScheduler.
+pub fn current_node() -> Option<usize> {
+    CURRENT_NODE.with(Cell::get)
+}
+
+struct NodeRuntime {
+    node: NumaNode,
+    handle: Handle,
+    tasks: Arc<AtomicUsize>,
+}
+
+// Decrements a node's task count however the task ends, including by cancellation.
+struct TaskGuard(Arc<AtomicUsize>);
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Runs monitoring tasks on one single-threaded runtime per NUMA node, each with all of
+// its threads pinned to the node's CPUs, so that a task's /proc reads stay on the node
+// it runs on. Tasks never move between nodes once spawned.
+pub struct NumaAwareScheduler {
+    nodes: Vec<NodeRuntime>,
+}
+
+impl NumaAwareScheduler {
+    pub fn start(nodes: Vec<NumaNode>) -> io::Result<NumaAwareScheduler> {
+        let mut runtimes = Vec::new();
+        for node in nodes {
+            let (tx, rx) = mpsc::channel();
+            let thread_node = node.clone();
+            std::thread::Builder::new().name(format!("cnpd-numa{}", node.id)).spawn(move || {
+                let pin = move || {
+                    CURRENT_NODE.with(|current| current.set(Some(thread_node.id)));
+                    if let Err(e) = affinity::pin_current_thread(&thread_node.cpus) {
+                        eprintln!("Failed to pin thread to NUMA node {}: {}", thread_node.id, e);
+                    }
+                };
+                pin();
+                // The blocking pool serving tokio::fs is pinned to the node too.
+                let runtime = match runtime::Builder::new_current_thread().enable_all().on_thread_start(pin).build() {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                        return;
+                    }
+                };
+                let _ = tx.send(Ok(runtime.handle().clone()));
+                runtime.block_on(std::future::pending::<()>());
+            })?;
+            let handle = rx
+                .recv()
+                .map_err(|_| io::Error::other(format!("Runtime thread of NUMA node {} exited", node.id)))??;
+            runtimes.push(NodeRuntime {
+                node,
+                handle,
+                tasks: Arc::new(AtomicUsize::new(0)),
+            });
+        }
+        Ok(NumaAwareScheduler { nodes: runtimes })
+    }
+
+    // Spawns the task on the node with the fewest tasks, and returns that node's id.
+    pub fn spawn<F>(&self, task: F) -> usize
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let runtime = self
+            .nodes
+            .iter()
+            .min_by_key(|runtime| runtime.tasks.load(Ordering::Relaxed))
+            .expect("a NumaAwareScheduler has at least one node");
+        runtime.tasks.fetch_add(1, Ordering::Relaxed);
+        let guard = TaskGuard(runtime.tasks.clone());
+        runtime.handle.spawn(async move {
+            let _guard = guard;
+            task.await;
+        });
+        runtime.node.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cpulist() {
+        assert_eq!(parse_cpulist("0-3,8,10-11").unwrap(), vec![0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(parse_cpulist("").unwrap(), Vec::<usize>::new());
+        assert!(parse_cpulist("0-x").is_err());
+    }
+
+    #[test]
+    fn spreads_tasks_over_nodes() {
+        let nodes = (0..2).map(|id| NumaNode { id, cpus: vec![0] }).collect();
+        let scheduler = NumaAwareScheduler::start(nodes).unwrap();
+        let (done_tx, done_rx) = mpsc::channel();
+        let mut assigned = Vec::new();
+        for _ in 0..3 {
+            let done_tx = done_tx.clone();
+            assigned.push(scheduler.spawn(async move {
+                done_tx.send(current_node()).unwrap();
+                std::future::pending::<()>().await;
+            }));
+        }
+        assert_eq!(assigned, vec![0, 1, 0]);
+        let mut ran_on: Vec<Option<usize>> = (0..3).map(|_| done_rx.recv().unwrap()).collect();
+        ran_on.sort();
+        assert_eq!(ran_on, vec![Some(0), Some(0), Some(1)]);
+    }
+}
@@ -0,0 +1,66 @@
+use std::io;
+use chrono::{DateTime, Local};
+use tokio::fs;
+
+use crate::cgroup::ContainerCgroup;
+use crate::inotify::Inotify;
+
+// cgroup.events only carries `populated` and `frozen`; the `oom_kill` counter lives
+// next to it in memory.events, so that is the file being watched.
+pub const EVENTS_FILE: &str = "memory.events";
+
+#[derive(Debug, Clone)]
+pub struct OomKillEvent {
+    pub container_id: String,
+    pub detected_at: DateTime<Local>,
+    // Kills since the previous event, and since the cgroup was created.
+    pub kills: u64,
+    pub total: u64,
+}
+
+pub fn parse_oom_kill(events: &str) -> Option<u64> {
+    events.lines().find_map(|line| line.strip_prefix("oom_kill ")?.trim().parse().ok())
+}
+
+// Waits for IN_MODIFY on the container's memory.events through inotify.
+pub struct OomWatcher {
+    inotify: Inotify,
+    container_id: String,
+    path: String,
+    last: u64,
+}
+
+impl OomWatcher {
+    pub async fn new(container: &ContainerCgroup) -> io::Result<OomWatcher> {
+        let path = format!("{}/{}", container.path(), EVENTS_FILE);
+        let last = parse_oom_kill(&fs::read_to_string(&path).await?).unwrap_or(0);
+
+        let inotify = Inotify::new()?;
+        inotify.add_watch(&path, libc::IN_MODIFY)?;
+
+        Ok(OomWatcher {
+            inotify,
+            container_id: container.container_id(),
+            path,
+            last,
+        })
+    }
+
+    // Returns once the oom_kill counter has gone up.
+    pub async fn next(&mut self) -> io::Result<OomKillEvent> {
+        loop {
+            self.inotify.read_events().await?;
+            let total = parse_oom_kill(&fs::read_to_string(&self.path).await?).unwrap_or(self.last);
+            if total > self.last {
+                let event = OomKillEvent {
+                    container_id: self.container_id.clone(),
+                    detected_at: Local::now(),
+                    kills: total - self.last,
+                    total,
+                };
+                self.last = total;
+                return Ok(event);
+            }
+        }
+    }
+}
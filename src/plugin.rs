@@ -0,0 +1,111 @@
+use std::error::Error;
+use std::ffi::{CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+use crate::event::DetectionEvent;
+use crate::info;
+
+pub type PluginResult = Result<(), String>;
+
+// Custom detection actions live in a separate crate built as a `cdylib` that depends
+// on this crate and exports its plugin with `declare_plugin!`. Trait objects cross
+// the library boundary, so plugins must be built with the same compiler and the same
+// version of this crate as the detector.
+pub trait CnpdPlugin: Send + Sync {
+    fn name(&self) -> &str;
+    fn on_detection(&self, event: &DetectionEvent) -> PluginResult;
+}
+
+// A plugin library links its own copy of std, so the detector sees its panics as
+// foreign exceptions that catch_unwind cannot stop. `declare_plugin!` wraps the plugin
+// in this guard, which is monomorphised inside the plugin library and catches panics
+// there before they cross the boundary.
+pub struct PanicGuard<P>(pub P);
+
+impl<P: CnpdPlugin> CnpdPlugin for PanicGuard<P> {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn on_detection(&self, event: &DetectionEvent) -> PluginResult {
+        panic::catch_unwind(AssertUnwindSafe(|| self.0.on_detection(event)))
+            .unwrap_or_else(|_| Err("plugin panicked".to_string()))
+    }
+}
+
+pub const CREATE_SYMBOL: &str = "cnpd_plugin_create";
+
+type CreateFn = unsafe extern "C" fn() -> *mut Box<dyn CnpdPlugin>;
+
+#[macro_export]
+macro_rules! declare_plugin {
+    ($constructor:expr) => {
+        #[no_mangle]
+        pub extern "C" fn cnpd_plugin_create() -> *mut Box<dyn $crate::plugin::CnpdPlugin> {
+            let plugin: Box<dyn $crate::plugin::CnpdPlugin> =
+                Box::new($crate::plugin::PanicGuard($constructor));
+            Box::into_raw(Box::new(plugin))
+        }
+    };
+}
+
+fn dl_error() -> String {
+    let message = unsafe { libc::dlerror() };
+    if message.is_null() {
+        "unknown dlopen error".to_string()
+    } else {
+        unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned()
+    }
+}
+
+// The library handle is intentionally never closed: plugin code must stay mapped
+// for as long as the plugin object exists, which is the lifetime of the process.
+pub fn load_plugin(path: &str) -> Result<Arc<dyn CnpdPlugin>, Box<dyn Error>> {
+    let c_path = CString::new(path)?;
+    let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    if handle.is_null() {
+        return Err(format!("Failed to load plugin {}: {}", path, dl_error()).into());
+    }
+
+    let symbol = CString::new(CREATE_SYMBOL)?;
+    let create = unsafe { libc::dlsym(handle, symbol.as_ptr()) };
+    if create.is_null() {
+        return Err(format!("Plugin {} does not export {}: {}", path, CREATE_SYMBOL, dl_error()).into());
+    }
+
+    let create: CreateFn = unsafe { std::mem::transmute::<*mut libc::c_void, CreateFn>(create) };
+    let plugin = unsafe { Box::from_raw(create()) };
+    Ok(Arc::from(*plugin))
+}
+
+pub fn load_plugins(paths: &[String]) -> Result<Vec<Arc<dyn CnpdPlugin>>, Box<dyn Error>> {
+    let mut plugins = Vec::new();
+    for path in paths {
+        let plugin = load_plugin(path)?;
+        info!("Loaded plugin {} from {}", plugin.name(), path);
+        plugins.push(plugin);
+    }
+    Ok(plugins)
+}
+
+// Plugins run on the blocking thread pool, and a panicking plugin is reported as a
+// failure instead of taking down the monitoring task.
+pub async fn run_plugins(plugins: &[Arc<dyn CnpdPlugin>], event: &DetectionEvent) {
+    for plugin in plugins {
+        let plugin = plugin.clone();
+        let event = event.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| plugin.on_detection(&event)))
+                .unwrap_or_else(|_| Err("plugin panicked".to_string()));
+            (plugin, result)
+        })
+        .await;
+
+        match result {
+            Ok((_, Ok(()))) => {}
+            Ok((plugin, Err(e))) => eprintln!("Plugin {} failed: {}", plugin.name(), e),
+            Err(e) => eprintln!("Plugin task failed: {}", e),
+        }
+    }
+}
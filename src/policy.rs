@@ -0,0 +1,319 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::event::{DetectionEvent, EventKind};
+use crate::json::Value;
+use crate::regex::Regex;
+use crate::toml;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    // Stop the container and start it again.
+    Restart,
+    Stop,
+    // SIGKILL only the offending process and leave the container running.
+    Kill,
+    // SIGKILL the process, commit the container as a forensic image, then restart it.
+    KillAndCommit,
+    // Disconnect the container from its networks, restart it, then reconnect it.
+    Isolate,
+    // Replace the container with a new one created from the same image and settings,
+    // which leaves out whatever was written to its filesystem.
+    Rollback,
+    LogOnly,
+}
+
+impl Action {
+    // Whether the action interrupts the offending process.
+    pub fn blocks(&self) -> bool {
+        !matches!(self, Action::LogOnly)
+    }
+
+    // Severity of the action, from log-only (0) up to stop.
+    pub fn score(&self) -> u8 {
+        match self {
+            Action::LogOnly => 0,
+            Action::Kill => 1,
+            Action::Restart | Action::KillAndCommit | Action::Isolate | Action::Rollback => 2,
+            Action::Stop => 3,
+        }
+    }
+
+    fn from_score(score: u8) -> Action {
+        match score {
+            0 => Action::LogOnly,
+            1 => Action::Kill,
+            2 => Action::Restart,
+            _ => Action::Stop,
+        }
+    }
+
+    // The action `amount` severity levels below this one, bottoming out at log-only.
+    pub fn lowered(&self, amount: u8) -> Action {
+        Action::from_score(self.score().saturating_sub(amount))
+    }
+
+    // The action `amount` severity levels above this one, capped at stop.
+    pub fn raised(&self, amount: u8) -> Action {
+        Action::from_score(self.score().saturating_add(amount))
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Action::Restart => "restart",
+            Action::Stop => "stop",
+            Action::Kill => "kill",
+            Action::KillAndCommit => "kill-and-commit",
+            Action::Isolate => "isolate",
+            Action::Rollback => "rollback",
+            Action::LogOnly => "log-only",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "restart" => Ok(Action::Restart),
+            "stop" => Ok(Action::Stop),
+            "kill" => Ok(Action::Kill),
+            "kill-and-commit" => Ok(Action::KillAndCommit),
+            "isolate" => Ok(Action::Isolate),
+            "rollback" => Ok(Action::Rollback),
+            "log-only" => Ok(Action::LogOnly),
+            _ => Err(format!("Unknown action: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PolicyRule {
+    pub container: Option<String>,
+    pub exe: Option<String>,
+    pub action: Action,
+}
+
+impl PolicyRule {
+    fn matches(&self, event: &DetectionEvent) -> bool {
+        let container_matches = self
+            .container
+            .as_deref()
+            .is_none_or(|pattern| glob_match(pattern, &event.container_id));
+        let exe_matches = self.exe.as_deref().is_none_or(|pattern| {
+            event.exe.as_deref().is_some_and(|exe| glob_match(pattern, exe))
+        });
+        container_matches && exe_matches
+    }
+}
+
+// Detections matching a suppression rule are counted but neither acted on nor logged.
+#[derive(Debug, Clone)]
+pub struct SuppressRule {
+    pub container_id_pattern: Option<String>,
+    pub exe_path_regex: Option<Regex>,
+}
+
+impl SuppressRule {
+    fn from_toml(value: &Value) -> Result<SuppressRule, String> {
+        let string = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+        let rule = SuppressRule {
+            container_id_pattern: string("container_id_pattern"),
+            exe_path_regex: string("exe_path_regex").map(|r| Regex::new(&r)).transpose()?,
+        };
+        if rule.container_id_pattern.is_none() && rule.exe_path_regex.is_none() {
+            return Err("Suppression rule needs container_id_pattern or exe_path_regex".to_string());
+        }
+        Ok(rule)
+    }
+
+    fn matches(&self, event: &DetectionEvent) -> bool {
+        let container_matches = self
+            .container_id_pattern
+            .as_deref()
+            .is_none_or(|pattern| glob_match(pattern, &event.container_id));
+        let exe_matches = self.exe_path_regex.as_ref().is_none_or(|regex| {
+            event.exe.as_deref().is_some_and(|exe| regex.is_match(exe))
+        });
+        container_matches && exe_matches
+    }
+}
+
+impl fmt::Display for SuppressRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(pattern) = &self.container_id_pattern {
+            parts.push(format!("container_id_pattern = {:?}", pattern));
+        }
+        if let Some(regex) = &self.exe_path_regex {
+            parts.push(format!("exe_path_regex = {:?}", regex.as_str()));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+// Policy files look like:
+//
+//     default_action = "restart"
+//
+//     [[rule]]
+//     container = "4f1c*"
+//     exe = "/usr/bin/python*"
+//     action = "log-only"
+//
+//     [[suppress]]
+//     container_id_pattern = "test-*"
+//     exe_path_regex = ".*pytest.*"
+#[derive(Debug, Clone)]
+pub struct Policy {
+    pub default_action: Action,
+    pub rules: Vec<PolicyRule>,
+    pub suppressions: Vec<SuppressRule>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            default_action: Action::Restart,
+            rules: Vec::new(),
+            suppressions: Vec::new(),
+        }
+    }
+}
+
+impl Policy {
+    pub fn parse(input: &str) -> Result<Policy, String> {
+        let doc = toml::parse(input)?;
+        let mut policy = Policy::default();
+
+        if let Some(action) = doc.get("default_action") {
+            policy.default_action = action.as_str().ok_or("default_action must be a string")?.parse()?;
+        }
+
+        for rule in doc.get("rule").and_then(Value::as_array).into_iter().flatten() {
+            let string = |key: &str| rule.get(key).and_then(Value::as_str).map(str::to_string);
+            policy.rules.push(PolicyRule {
+                container: string("container"),
+                exe: string("exe"),
+                action: string("action").ok_or("Policy rule is missing an action")?.parse()?,
+            });
+        }
+
+        // A single [suppress] table is accepted as well as an array of them.
+        let suppressions = match doc.get("suppress") {
+            Some(Value::Array(tables)) => tables.iter().collect(),
+            Some(table) => vec![table],
+            None => Vec::new(),
+        };
+        for table in suppressions {
+            policy.suppressions.push(SuppressRule::from_toml(table)?);
+        }
+
+        Ok(policy)
+    }
+
+    pub async fn load(path: &str) -> Result<Policy, Box<dyn Error>> {
+        let content = tokio::fs::read_to_string(path).await?;
+        Policy::parse(&content).map_err(|e| format!("{}: {}", path, e).into())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PolicyEngine {
+    policy: Policy,
+}
+
+impl PolicyEngine {
+    pub fn new(policy: Policy) -> Self {
+        PolicyEngine { policy }
+    }
+
+    // The first matching rule wins.
+    pub fn evaluate(&self, event: &DetectionEvent) -> Action {
+        self.policy
+            .rules
+            .iter()
+            .find(|rule| rule.matches(event))
+            .map_or(self.policy.default_action, |rule| rule.action)
+    }
+
+    // The first suppression rule matching the event, checked after evaluate().
+    pub fn suppression(&self, event: &DetectionEvent) -> Option<&SuppressRule> {
+        self.policy.suppressions.iter().find(|rule| rule.matches(event))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimulationSummary {
+    pub blocked: usize,
+    pub allowed: usize,
+    pub changed: usize,
+}
+
+impl fmt::Display for SimulationSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} events would have been blocked, {} allowed, {} would have triggered a different action",
+            self.blocked, self.allowed, self.changed
+        )
+    }
+}
+
+// Replays recorded events against a proposed policy without acting on anything.
+pub struct PolicySimulator {
+    engine: PolicyEngine,
+}
+
+impl PolicySimulator {
+    pub fn new(engine: PolicyEngine) -> Self {
+        PolicySimulator { engine }
+    }
+
+    pub fn run<'a, I: IntoIterator<Item = &'a DetectionEvent>>(&self, events: I) -> SimulationSummary {
+        let mut summary = SimulationSummary::default();
+        for event in events.into_iter().filter(|e| e.kind == EventKind::NewProcess) {
+            let action = self.engine.evaluate(event);
+            if action.blocks() {
+                summary.blocked += 1;
+            } else {
+                summary.allowed += 1;
+            }
+            if action != event.action {
+                summary.changed += 1;
+            }
+        }
+        summary
+    }
+}
+
+// Shell-style wildcard matching: `*` matches any run of characters, `?` a single one.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
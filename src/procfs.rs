@@ -0,0 +1,648 @@
+// All /proc reads go through tokio::fs, which performs the blocking syscalls on
+// Tokio's blocking thread pool. A read stalled by a ptrace stop or kernel scheduling
+// must never block a runtime worker thread, so new forensic reads belong here too.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::ops::Deref;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::fs;
+use tokio::time::{error::Elapsed, sleep, timeout};
+
+use crate::capability;
+use crate::debug;
+use crate::lineage::ProcessInfo;
+
+static PROC_ROOT: OnceLock<String> = OnceLock::new();
+
+// --bind-mount-proc: read process information from another procfs mount instead of
+// the host /proc. The detector's own /proc/self is always the host one.
+pub fn set_proc_root(path: &str) {
+    let _ = PROC_ROOT.set(path.trim_end_matches('/').to_string());
+}
+
+// /proc/<pid>/<file> under the configured proc root; an empty file gives /proc/<pid>.
+pub fn pid_path(pid: i32, file: &str) -> String {
+    let root = PROC_ROOT.get().map(String::as_str).unwrap_or("/proc");
+    if file.is_empty() {
+        format!("{}/{}", root, pid)
+    } else {
+        format!("{}/{}/{}", root, pid, file)
+    }
+}
+
+// Stored in event fields whose /proc read did not finish in time.
+pub const TIMEOUT_MARKER: &str = "<timeout>";
+
+// Reads from /proc can block for a long time when the process is in uninterruptible
+// sleep or the kernel is under heavy I/O load, so every read in the detection path is
+// bounded by --max-proc-read-time.
+pub async fn read_with_timeout<T, F>(limit: Duration, pid: i32, file: &str, read: F) -> Result<Option<T>, Elapsed>
+where
+    F: Future<Output = Option<T>>,
+{
+    let result = timeout(limit, read).await;
+    if result.is_err() {
+        eprintln!(
+            "Warning: reading {} timed out after {} ms",
+            pid_path(pid, file),
+            limit.as_millis()
+        );
+    }
+    result
+}
+
+// Field 22 of /proc/<pid>/stat: process start time in clock ticks since boot.
+pub async fn read_start_time(pid: i32) -> Option<u64> {
+    let stat = fs::read_to_string(pid_path(pid, "stat")).await.ok()?;
+    parse_start_time(&stat)
+}
+
+// The PIDs of `known` still running under the same start time. A PID reused since
+// has a new one and is left out, to be detected as new.
+pub async fn still_running(known: impl IntoIterator<Item = &(i32, u64)>) -> HashSet<i32> {
+    let mut running = HashSet::new();
+    for (pid, start) in known {
+        if read_start_time(*pid).await == Some(*start) {
+            running.insert(*pid);
+        }
+    }
+    running
+}
+
+fn parse_start_time(stat: &str) -> Option<u64> {
+    // The command name (field 2) may contain spaces, so start after its closing parenthesis.
+    let rest = &stat[stat.rfind(')')? + 1..];
+    rest.split_whitespace().nth(19)?.parse().ok()
+}
+
+// PIDs are reused once a process exits, so processes are identified by their PID and
+// start time together. A process that exited before its start time could be read gets
+// 0, which no later process with that PID can match.
+pub async fn process_keys(pids: &HashSet<i32>) -> HashSet<(i32, u64)> {
+    #[cfg(feature = "io-uring")]
+    if let Some(reader) = crate::uring::proc_reader() {
+        let pids: Vec<i32> = pids.iter().copied().collect();
+        match reader.read_files(pids.iter().map(|pid| pid_path(*pid, "stat")).collect()).await {
+            Ok(stats) => {
+                return pids
+                    .into_iter()
+                    .zip(stats)
+                    .map(|(pid, stat)| (pid, stat.and_then(|s| parse_start_time(&String::from_utf8_lossy(&s))).unwrap_or(0)))
+                    .collect();
+            }
+            Err(e) => debug!("Batched /proc read failed, reading one file at a time: {}", e),
+        }
+    }
+    let mut keys = HashSet::with_capacity(pids.len());
+    for &pid in pids {
+        keys.insert((pid, read_start_time(pid).await.unwrap_or(0)));
+    }
+    keys
+}
+
+// Start times already read, so that a poll only reads the stat files of PIDs it has
+// not seen before. Each is kept with a pidfd opened before the read: a PID that exits
+// and is reused between two polls is told apart by its pidfd having become readable,
+// and read again. A PID whose pidfd cannot be opened, as without pidfd_open() or out
+// of file descriptors, is read on every poll.
+#[derive(Debug, Default)]
+pub struct ProcessKeyCache {
+    starts: HashMap<i32, CachedStart>,
+}
+
+#[derive(Debug)]
+struct CachedStart {
+    start: u64,
+    pidfd: OwnedFd,
+}
+
+impl ProcessKeyCache {
+    pub async fn process_keys(&mut self, pids: &HashSet<i32>) -> HashSet<(i32, u64)> {
+        self.starts.retain(|pid, _| pids.contains(pid));
+        let exited = self.exited();
+        self.starts.retain(|pid, _| !exited.contains(pid));
+
+        let unread: HashSet<i32> = pids.iter().filter(|pid| !self.starts.contains_key(pid)).copied().collect();
+        let pidfds: Vec<(i32, OwnedFd)> = unread.iter().filter_map(|pid| Some((*pid, pidfd_open(*pid)?))).collect();
+        let mut keys = process_keys(&unread).await;
+        let read: HashMap<i32, u64> = keys.iter().copied().collect();
+        // A process that exited before its start time was read is read again next time.
+        for (pid, pidfd) in pidfds {
+            if let Some(&start) = read.get(&pid).filter(|start| **start != 0) {
+                self.starts.insert(pid, CachedStart { start, pidfd });
+            }
+        }
+        keys.extend(pids.iter().filter(|pid| !unread.contains(pid)).map(|pid| (*pid, self.starts[pid].start)));
+        keys
+    }
+
+    // The cached PIDs whose process has exited, by one poll() over their pidfds. All of
+    // them if poll() fails, so that none is trusted unchecked.
+    fn exited(&self) -> HashSet<i32> {
+        let (pids, mut fds): (Vec<i32>, Vec<libc::pollfd>) = self
+            .starts
+            .iter()
+            .map(|(pid, cached)| (*pid, libc::pollfd { fd: cached.pidfd.as_raw_fd(), events: libc::POLLIN, revents: 0 }))
+            .unzip();
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, 0) } < 0 {
+            return pids.into_iter().collect();
+        }
+        pids.into_iter().zip(fds).filter(|(_, fd)| fd.revents != 0).map(|(pid, _)| pid).collect()
+    }
+}
+
+fn pidfd_open(pid: i32) -> Option<OwnedFd> {
+    let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+    (pidfd >= 0).then(|| unsafe { OwnedFd::from_raw_fd(pidfd as i32) })
+}
+
+// The processes a container's task whitelisted. With the simd feature the keys are also
+// kept laid out for simd::new_processes while there are at most simd::MAX_PIDS of them,
+// updated as processes are added rather than rebuilt on every poll.
+#[derive(Default)]
+pub struct KnownProcs {
+    keys: HashSet<(i32, u64)>,
+    #[cfg(feature = "simd")]
+    blocks: Option<crate::simd::PidBlocks>,
+}
+
+impl KnownProcs {
+    pub fn insert(&mut self, key: (i32, u64)) -> bool {
+        if !self.keys.insert(key) {
+            return false;
+        }
+        #[cfg(feature = "simd")]
+        if self.keys.len() > crate::simd::MAX_PIDS {
+            self.blocks = None;
+        } else if let Some(blocks) = &mut self.blocks {
+            blocks.insert(key);
+        }
+        true
+    }
+
+    pub fn extend<I: IntoIterator<Item = (i32, u64)>>(&mut self, keys: I) {
+        for key in keys {
+            self.insert(key);
+        }
+    }
+
+    pub fn retain<F: FnMut(&(i32, u64)) -> bool>(&mut self, keep: F) {
+        self.keys.retain(keep);
+        #[cfg(feature = "simd")]
+        {
+            self.blocks = (self.keys.len() <= crate::simd::MAX_PIDS).then(|| crate::simd::PidBlocks::new(&self.keys));
+        }
+    }
+}
+
+impl From<HashSet<(i32, u64)>> for KnownProcs {
+    fn from(keys: HashSet<(i32, u64)>) -> KnownProcs {
+        KnownProcs {
+            #[cfg(feature = "simd")]
+            blocks: (keys.len() <= crate::simd::MAX_PIDS).then(|| crate::simd::PidBlocks::new(&keys)),
+            keys,
+        }
+    }
+}
+
+impl Deref for KnownProcs {
+    type Target = HashSet<(i32, u64)>;
+
+    fn deref(&self) -> &HashSet<(i32, u64)> {
+        &self.keys
+    }
+}
+
+// Processes in `current` that are not in `known`, including ones that reuse a known PID.
+// Without a start time there is no telling a reused PID apart, so a known PID whose
+// process exited before its start time was read is not new.
+pub fn new_processes(known: &KnownProcs, current: &HashSet<(i32, u64)>) -> Vec<(i32, u64)> {
+    #[cfg(feature = "simd")]
+    if let Some(blocks) = &known.blocks {
+        return crate::simd::new_processes(blocks, current);
+    }
+    let known = &known.keys;
+    let mut new: Vec<(i32, u64)> = current
+        .difference(known)
+        .filter(|(pid, start)| *start != 0 || !known.iter().any(|(known_pid, _)| known_pid == pid))
+        .copied()
+        .collect();
+    new.sort_unstable();
+    new
+}
+
+// Fields 14 and 15 of /proc/<pid>/stat: user plus system time in clock ticks.
+pub async fn read_cpu_ticks(pid: i32) -> Option<u64> {
+    parse_cpu_ticks(&fs::read_to_string(pid_path(pid, "stat")).await.ok()?)
+}
+
+// The same for one thread of the detector itself.
+pub async fn read_thread_cpu_ticks(tid: i32) -> Option<u64> {
+    parse_cpu_ticks(&fs::read_to_string(format!("/proc/self/task/{}/stat", tid)).await.ok()?)
+}
+
+fn parse_cpu_ticks(stat: &str) -> Option<u64> {
+    let mut fields = stat[stat.rfind(')')? + 1..].split_whitespace().skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    Some(utime + stime)
+}
+
+// CPU usage of the process over `interval`, as a percentage of one core.
+pub async fn cpu_percent(pid: i32, interval: Duration) -> Option<f64> {
+    let before = read_cpu_ticks(pid).await?;
+    sleep(interval).await;
+    let after = read_cpu_ticks(pid).await?;
+    let seconds = after.saturating_sub(before) as f64 / clock_ticks_per_second() as f64;
+    Some(seconds / interval.as_secs_f64() * 100.0)
+}
+
+pub fn clock_ticks_per_second() -> u64 {
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks > 0 {
+        ticks as u64
+    } else {
+        100
+    }
+}
+
+pub fn boot_time_now() -> Duration {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut ts);
+    }
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+// How long the process has been running, measured against CLOCK_BOOTTIME.
+pub async fn process_age(pid: i32) -> Option<Duration> {
+    Some(age_since_start(read_start_time(pid).await?))
+}
+
+// Time since a process with the given start time (field 22 of stat) started.
+pub fn age_since_start(start_ticks: u64) -> Duration {
+    let ticks = clock_ticks_per_second();
+    let started = Duration::from_secs(start_ticks / ticks)
+        + Duration::from_nanos((start_ticks % ticks) * 1_000_000_000 / ticks);
+    boot_time_now().saturating_sub(started)
+}
+
+pub async fn read_proc_file(pid: i32, file: &str) -> Option<Vec<u8>> {
+    fs::read(pid_path(pid, file)).await.ok()
+}
+
+pub async fn read_exe(pid: i32) -> Option<String> {
+    let exe = fs::read_link(pid_path(pid, "exe")).await.ok()?;
+    Some(exe.to_string_lossy().into_owned())
+}
+
+// The working directory, as the process sees it inside its container.
+pub async fn read_cwd(pid: i32) -> Option<PathBuf> {
+    fs::read_link(pid_path(pid, "cwd")).await.ok()
+}
+
+// Whether `cwd` is one of `paths` or below one, compared by path component so /sysadmin
+// is not under /sys.
+pub fn is_sensitive_cwd(cwd: &Path, paths: &[String]) -> bool {
+    paths.iter().any(|path| cwd.starts_with(path))
+}
+
+// Kernel threads have an exe link that resolves to nothing. A process that has
+// already exited is not mistaken for one, as its /proc directory is gone too.
+pub async fn is_kernel_thread(pid: i32) -> bool {
+    let exe = pid_path(pid, "exe");
+    if !fs::symlink_metadata(&exe).await.is_ok_and(|m| m.file_type().is_symlink()) {
+        return false;
+    }
+    match fs::metadata(&exe).await {
+        Ok(target) => !target.is_file(),
+        Err(e) => e.kind() == std::io::ErrorKind::NotFound && fs::try_exists(pid_path(pid, "")).await.unwrap_or(false),
+    }
+}
+
+pub async fn read_cmdline(pid: i32) -> Option<String> {
+    Some(parse_cmdline(&read_proc_file(pid, "cmdline").await?))
+}
+
+// The NUL-separated arguments of a cmdline file, joined with spaces.
+pub fn parse_cmdline(raw: &[u8]) -> String {
+    let args: Vec<String> = raw
+        .split(|b| *b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+    args.join(" ")
+}
+
+// A single `Name:\tvalue` line from /proc/<pid>/status.
+pub async fn read_status_field(pid: i32, name: &str) -> Option<String> {
+    let status = fs::read_to_string(pid_path(pid, "status")).await.ok()?;
+    status.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key == name).then(|| value.trim().to_string())
+    })
+}
+
+// The CapInh, CapPrm and CapEff bitmasks of a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapabilitySets {
+    pub inheritable: u64,
+    pub permitted: u64,
+    pub effective: u64,
+}
+
+pub async fn read_capability_sets(pid: i32) -> Option<CapabilitySets> {
+    let status = fs::read_to_string(pid_path(pid, "status")).await.ok()?;
+    Some(CapabilitySets {
+        inheritable: capability::capability_set(&status, "CapInh")?,
+        permitted: capability::capability_set(&status, "CapPrm")?,
+        effective: capability::capability_set(&status, "CapEff")?,
+    })
+}
+
+// 0 disabled, 1 strict, 2 filter.
+pub async fn seccomp_mode(pid: i32) -> Option<u32> {
+    read_status_field(pid, "Seccomp").await?.parse().ok()
+}
+
+// Resident memory of the detector itself, in KiB.
+pub async fn self_rss_kb() -> Option<u64> {
+    let rss = read_status_field(std::process::id() as i32, "VmRSS").await?;
+    rss.trim_end_matches("kB").trim().parse().ok()
+}
+
+// Number of entries in /proc/<pid>/fd.
+pub async fn count_fds(pid: i32) -> Option<u32> {
+    let mut entries = fs::read_dir(pid_path(pid, "fd")).await.ok()?;
+    let mut count = 0;
+    while let Ok(Some(_)) = entries.next_entry().await {
+        count += 1;
+    }
+    Some(count)
+}
+
+// A file a process holds open whose directory entry was removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeletedFile {
+    pub fd: i32,
+    pub path: String,
+    pub size: Option<u64>,
+}
+
+// The kernel marks the fd link of an unlinked file with a " (deleted)" suffix.
+pub fn deleted_path(target: &str) -> Option<&str> {
+    target.strip_suffix(" (deleted)")
+}
+
+// The unlinked files among the process's open fds, by fd number, for --detect-hidden-files.
+// /proc/<pid>/fdinfo/<fd> has the offset and flags of an fd but not the file's size,
+// which stat() through the fd link reads from the open file itself.
+pub async fn read_deleted_files(pid: i32) -> Vec<DeletedFile> {
+    let Ok(mut entries) = fs::read_dir(pid_path(pid, "fd")).await else {
+        return Vec::new();
+    };
+    let mut files = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let Some(fd) = entry.file_name().to_str().and_then(|fd| fd.parse().ok()) else {
+            continue;
+        };
+        let Ok(target) = fs::read_link(entry.path()).await else {
+            continue;
+        };
+        if let Some(path) = deleted_path(&target.to_string_lossy()) {
+            let size = fs::metadata(entry.path()).await.ok().map(|metadata| metadata.len());
+            files.push(DeletedFile {
+                fd,
+                path: path.to_string(),
+                size,
+            });
+        }
+    }
+    files.sort_by_key(|file| file.fd);
+    files
+}
+
+// Real UID, the first of the four Uid: values.
+pub async fn read_uid(pid: i32) -> Option<u32> {
+    read_status_field(pid, "Uid").await?.split_whitespace().next()?.parse().ok()
+}
+
+// Real and effective UID, the first two of the four Uid: values.
+pub async fn read_uids(pid: i32) -> Option<(u32, u32)> {
+    let uids = read_status_field(pid, "Uid").await?;
+    let mut uids = uids.split_whitespace().map(|uid| uid.parse().ok());
+    Some((uids.next()??, uids.next()??))
+}
+
+// Inode of one of the process's namespaces, from a link such as `user:[4026531837]`.
+pub async fn read_ns_inode(pid: i32, namespace: &str) -> Option<u64> {
+    let link = fs::read_link(pid_path(pid, &format!("ns/{}", namespace))).await.ok()?;
+    let link = link.to_string_lossy();
+    link.strip_prefix(namespace)?.strip_prefix(":[")?.strip_suffix(']')?.parse().ok()
+}
+
+// The PID of the process in its own PID namespace, the last of the NSpid: values.
+pub async fn read_ns_pid(pid: i32) -> Option<i32> {
+    read_status_field(pid, "NSpid").await?.split_whitespace().last()?.parse().ok()
+}
+
+pub async fn read_ppid(pid: i32) -> Option<i32> {
+    read_status_field(pid, "PPid").await?.parse().ok()
+}
+
+// A /proc read that failed for a reason other than the process having exited.
+#[derive(Debug)]
+pub struct ProcReadError {
+    pub pid: i32,
+    pub file: &'static str,
+    pub source: io::Error,
+}
+
+impl fmt::Display for ProcReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to read {}: {}", pid_path(self.pid, self.file), self.source)
+    }
+}
+
+impl std::error::Error for ProcReadError {}
+
+// ENOENT and ESRCH both mean the process went away while it was being read.
+fn process_exited(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(libc::ENOENT) | Some(libc::ESRCH))
+}
+
+// Name, parent and executable of a process. Returns Ok(None) when the process exited
+// between being listed in cgroup.procs and being read.
+pub async fn read_proc_info(pid: i32) -> Result<Option<ProcessInfo>, ProcReadError> {
+    let error = |file, source| ProcReadError { pid, file, source };
+
+    let status = match fs::read_to_string(pid_path(pid, "status")).await {
+        Ok(status) => status,
+        Err(e) if process_exited(&e) => {
+            debug!("PID {} exited before info could be read", pid);
+            return Ok(None);
+        }
+        Err(e) => return Err(error("status", e)),
+    };
+    let field = |name: &str| {
+        status.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key == name).then(|| value.trim().to_string())
+        })
+    };
+
+    // Kernel threads have no exe link, so ENOENT here is not an exit on its own.
+    let exe = match fs::read_link(pid_path(pid, "exe")).await {
+        Ok(exe) => Some(exe.to_string_lossy().into_owned()),
+        Err(e) if process_exited(&e) => None,
+        Err(e) => return Err(error("exe", e)),
+    };
+
+    Ok(Some(ProcessInfo {
+        pid,
+        ppid: field("PPid").and_then(|p| p.parse().ok()),
+        name: field("Name"),
+        exe,
+        outside_container: false,
+    }))
+}
+
+// Process details read during one poll cycle. Several PIDs appearing together usually
+// share ancestors, whose details are then read only once. Monitoring tasks clear the
+// cache at the start of every cycle so nothing read in an earlier one is reused.
+#[derive(Default)]
+pub struct ProcCache {
+    infos: Mutex<HashMap<i32, ProcessInfo>>,
+    cgroups: Mutex<HashMap<i32, Option<String>>>,
+}
+
+impl ProcCache {
+    pub fn clear(&self) {
+        self.infos.lock().unwrap().clear();
+        self.cgroups.lock().unwrap().clear();
+    }
+
+    // read_proc_info(), with successful reads cached.
+    pub async fn proc_info(&self, pid: i32) -> Result<Option<ProcessInfo>, ProcReadError> {
+        if let Some(info) = self.infos.lock().unwrap().get(&pid) {
+            return Ok(Some(info.clone()));
+        }
+        let info = read_proc_info(pid).await?;
+        if let Some(info) = &info {
+            self.infos.lock().unwrap().insert(pid, info.clone());
+        }
+        Ok(info)
+    }
+
+    // Contents of /proc/<pid>/cgroup.
+    pub async fn cgroup(&self, pid: i32) -> Option<String> {
+        if let Some(cgroup) = self.cgroups.lock().unwrap().get(&pid) {
+            return cgroup.clone();
+        }
+        let cgroup = fs::read_to_string(pid_path(pid, "cgroup")).await.ok();
+        self.cgroups.lock().unwrap().insert(pid, cgroup.clone());
+        cgroup
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_start_time_after_command_name() {
+        let stat = "1234 (my (odd) proc) S 1 1234 1234 0 -1 4194560 100 0 0 0 5 3 0 0 20 0 1 0 98765 1000 200";
+        assert_eq!(parse_start_time(stat), Some(98765));
+    }
+
+    #[test]
+    fn reused_pid_is_a_new_process() {
+        let known: KnownProcs = HashSet::from([(1, 100), (1234, 500)]).into();
+        // PID 1234 exited and was handed to a process started later.
+        let current: HashSet<(i32, u64)> = [(1, 100), (1234, 900), (1300, 950)].into_iter().collect();
+        assert_eq!(new_processes(&known, &current), vec![(1234, 900), (1300, 950)]);
+    }
+
+    #[test]
+    fn exited_known_process_is_not_new() {
+        let known: KnownProcs = HashSet::from([(1, 100), (1234, 500)]).into();
+        // PID 1234 exited between reading cgroup.procs and its stat file.
+        let current: HashSet<(i32, u64)> = [(1, 100), (1234, 0), (1300, 0)].into_iter().collect();
+        assert_eq!(new_processes(&known, &current), vec![(1300, 0)]);
+    }
+
+    #[tokio::test]
+    async fn caches_start_times_until_the_process_exits() {
+        let own = std::process::id() as i32;
+        let start = read_start_time(own).await.unwrap();
+        let mut cache = ProcessKeyCache::default();
+        assert_eq!(cache.process_keys(&HashSet::from([own])).await, HashSet::from([(own, start)]));
+
+        // A cached start time is used without reading the stat file again.
+        cache.starts.get_mut(&own).unwrap().start = 1;
+        assert_eq!(cache.process_keys(&HashSet::from([own])).await, HashSet::from([(own, 1)]));
+        // Once the PID leaves the list it is read again when it comes back.
+        assert_eq!(cache.process_keys(&HashSet::new()).await, HashSet::new());
+        assert_eq!(cache.process_keys(&HashSet::from([own])).await, HashSet::from([(own, start)]));
+
+        // So is a PID whose process exited, as one reused since would be.
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pidfd = pidfd_open(child.id() as i32).unwrap();
+        child.wait().unwrap();
+        cache.starts.insert(own, CachedStart { start: 1, pidfd });
+        assert_eq!(cache.process_keys(&HashSet::from([own])).await, HashSet::from([(own, start)]));
+        assert_eq!(cache.starts[&own].start, start);
+    }
+
+    #[tokio::test]
+    async fn finds_unlinked_open_files() {
+        let path = std::env::temp_dir().join(format!("cnpd-hidden-{}", std::process::id()));
+        std::fs::write(&path, b"payload").unwrap();
+        let file = std::fs::File::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let deleted = read_deleted_files(std::process::id() as i32).await;
+        let found = deleted.iter().find(|f| f.path == path.to_string_lossy()).unwrap();
+        assert_eq!(found.size, Some(7));
+        drop(file);
+        assert_eq!(deleted_path("/tmp/x"), None);
+    }
+
+    #[test]
+    fn sensitive_cwd_matches_whole_components() {
+        let paths = vec!["/proc".to_string(), "/sys".to_string(), "/host".to_string()];
+        assert!(is_sensitive_cwd(Path::new("/proc/sysrq-trigger"), &paths));
+        assert!(is_sensitive_cwd(Path::new("/sys/kernel"), &paths));
+        assert!(!is_sensitive_cwd(Path::new("/sysadmin"), &paths));
+        assert!(!is_sensitive_cwd(Path::new("/app"), &paths));
+    }
+
+    #[test]
+    fn unchanged_processes_are_not_new() {
+        let known: KnownProcs = HashSet::from([(1, 100), (1234, 500)]).into();
+        let current: HashSet<(i32, u64)> = [(1234, 500)].into_iter().collect();
+        assert!(new_processes(&known, &current).is_empty());
+    }
+
+    #[test]
+    fn known_processes_grow_past_the_vectorized_size() {
+        let mut known = KnownProcs::default();
+        let current: HashSet<(i32, u64)> = (1..=300).map(|pid| (pid, pid as u64)).collect();
+        // Checked on every insert, before and after the keys stop being vectorized.
+        for pid in 1..=300 {
+            let unknown: Vec<(i32, u64)> = (pid..=300).map(|pid| (pid, pid as u64)).collect();
+            assert_eq!(new_processes(&known, &current), unknown);
+            assert!(known.insert((pid, pid as u64)));
+        }
+        assert!(!known.insert((1, 1)));
+        known.retain(|(pid, _)| *pid <= 10);
+        assert_eq!(new_processes(&known, &current).len(), 290);
+        assert_eq!(new_processes(&known, &HashSet::from([(5, 6)])), vec![(5, 6)]);
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::event::DetectionEvent;
+use crate::info;
+use crate::json::Value;
+
+pub const DEFAULT_CHANNEL: &str = "cnpd:events";
+const MAX_QUEUED: usize = 10_000;
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+// Detections of one poll cycle are recorded within a few milliseconds of each other,
+// so the worker waits this long after the first event to publish them together.
+const BATCH_WINDOW: Duration = Duration::from_millis(20);
+
+// Where to publish, from --redis and --redis-channel or a `[redis]` config section:
+//
+//     [redis]
+//     address = "redis.internal:6379"
+//     channel = "cnpd:events"
+//     username = "cnpd"
+//     password = "..."
+#[derive(Debug, Clone)]
+pub struct RedisConfig {
+    pub address: String,
+    pub channel: String,
+    // AUTH with a username needs Redis 6 ACLs; with a password only, the default user.
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl RedisConfig {
+    pub fn new(address: String) -> RedisConfig {
+        RedisConfig {
+            address,
+            channel: DEFAULT_CHANNEL.to_string(),
+            username: None,
+            password: None,
+        }
+    }
+
+    pub fn from_toml(value: &Value) -> Result<RedisConfig, String> {
+        let string = |key: &str| match value.get(key) {
+            None => Ok(None),
+            Some(v) => v.as_str().map(|s| Some(s.to_string())).ok_or(format!("redis: {} must be a string", key)),
+        };
+        let mut config = RedisConfig::new(string("address")?.ok_or("redis: missing address")?);
+        if let Some(channel) = string("channel")? {
+            config.channel = channel;
+        }
+        config.username = string("username")?;
+        config.password = string("password")?;
+        if config.username.is_some() && config.password.is_none() {
+            return Err("redis: username requires a password".to_string());
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    messages: VecDeque<Value>,
+    shutdown: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    ready: Condvar,
+}
+
+// Publishes events to a Redis pub/sub channel over one persistent connection, from a
+// background thread. Every PUBLISH carries a JSON array of the events queued since the
+// last one. Pub/sub does not keep messages for later subscribers, so events that cannot
+// be published are written to the local log instead of being queued for a retry.
+pub struct RedisPublisher {
+    shared: Arc<Shared>,
+    thread: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl RedisPublisher {
+    pub fn start(config: &RedisConfig) -> std::io::Result<RedisPublisher> {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            ready: Condvar::new(),
+        });
+        let config = config.clone();
+        let worker = shared.clone();
+        let thread = thread::Builder::new()
+            .name("cnpd-redis".to_string())
+            .spawn(move || run(&config, &worker))?;
+        Ok(RedisPublisher {
+            shared,
+            thread: Mutex::new(Some(thread)),
+        })
+    }
+
+    pub fn send(&self, event: &DetectionEvent) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.messages.len() >= MAX_QUEUED {
+            queue.messages.pop_front();
+            eprintln!("Warning: Redis queue is full, dropping the oldest event");
+        }
+        queue.messages.push_back(event.to_json());
+        self.shared.ready.notify_one();
+    }
+
+    // Waits up to `timeout` for queued events to be published, then closes the connection.
+    pub fn shutdown(&self, timeout: Duration) {
+        self.shared.queue.lock().unwrap().shutdown = true;
+        self.shared.ready.notify_one();
+
+        let deadline = Instant::now() + timeout;
+        let Some(thread) = self.thread.lock().unwrap().take() else {
+            return;
+        };
+        while !thread.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+// The next batch, or None once shut down with nothing left to publish.
+fn next_batch(shared: &Shared) -> Option<Vec<Value>> {
+    let mut queue = shared.queue.lock().unwrap();
+    while queue.messages.is_empty() && !queue.shutdown {
+        queue = shared.ready.wait(queue).unwrap();
+    }
+    if queue.messages.is_empty() {
+        return None;
+    }
+    if !queue.shutdown {
+        queue = shared.ready.wait_timeout(queue, BATCH_WINDOW).unwrap().0;
+    }
+    Some(queue.messages.drain(..).collect())
+}
+
+fn run(config: &RedisConfig, shared: &Shared) {
+    let mut connection: Option<Connection> = None;
+    let mut backoff = Duration::from_secs(1);
+    let mut next_attempt = Instant::now();
+
+    while let Some(batch) = next_batch(shared) {
+        if connection.is_none() && Instant::now() >= next_attempt {
+            match Connection::open(config) {
+                Ok(opened) => {
+                    info!("Connected to Redis {}, publishing to {}", config.address, config.channel);
+                    backoff = Duration::from_secs(1);
+                    connection = Some(opened);
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Failed to connect to Redis {}: {}, retrying in {}s",
+                        config.address,
+                        e,
+                        backoff.as_secs()
+                    );
+                    next_attempt = Instant::now() + backoff;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+
+        let payload = Value::Array(batch).to_string();
+        let published = match &mut connection {
+            Some(open) => open.command(&["PUBLISH", &config.channel, &payload]),
+            None => Err("not connected".to_string()),
+        };
+        if let Err(e) = published {
+            if connection.take().is_some() {
+                eprintln!("Lost connection to Redis {}: {}", config.address, e);
+            }
+            eprintln!("Redis unavailable, events: {}", payload);
+        }
+    }
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    fn open(config: &RedisConfig) -> Result<Connection, String> {
+        let stream = TcpStream::connect(&config.address).map_err(|e| e.to_string())?;
+        stream.set_read_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+        stream.set_write_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+        let mut connection = Connection {
+            reader: BufReader::new(stream.try_clone().map_err(|e| e.to_string())?),
+            writer: stream,
+        };
+        match (&config.username, &config.password) {
+            (Some(username), Some(password)) => connection.command(&["AUTH", username, password])?,
+            (None, Some(password)) => connection.command(&["AUTH", password])?,
+            _ => {}
+        }
+        Ok(connection)
+    }
+
+    // Sends one command as a RESP array of bulk strings and reads its reply.
+    fn command(&mut self, args: &[&str]) -> Result<(), String> {
+        let mut request = format!("*{}\r\n", args.len());
+        for arg in args {
+            request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+        }
+        self.writer.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+
+        // PUBLISH answers with an integer, AUTH with a status; both fit on one line.
+        let mut reply = String::new();
+        match self.reader.read_line(&mut reply) {
+            Ok(0) => Err("connection closed".to_string()),
+            Ok(_) => match reply.strip_prefix('-') {
+                Some(error) => Err(error.trim_end().to_string()),
+                None => Ok(()),
+            },
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+// A small backtracking regular expression matcher for the patterns in policy files.
+// Supports literals, `.`, `[...]` and `[^...]` classes, the escapes \d \w \s and
+// escaped metacharacters, `*` `+` `?` and `{m,n}` quantifiers, groups with `|`, and
+// the `^` and `$` anchors. Matching is unanchored, like `grep -E`.
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Vec<(char, char)>, bool),
+    Start,
+    End,
+    Group(Vec<Vec<Node>>),
+    Repeat(Box<Node>, usize, Option<usize>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    source: String,
+    alternatives: Vec<Vec<Node>>,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let mut parser = Parser { chars: pattern.chars().collect(), pos: 0 };
+        let alternatives = parser.alternatives()?;
+        if parser.pos < parser.chars.len() {
+            return Err(format!("Unbalanced ')' in regex {}", pattern));
+        }
+        Ok(Regex { source: pattern.to_string(), alternatives })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let group = Node::Group(self.alternatives.clone());
+        (0..=text.len()).any(|start| match_node(&group, &text, start, &mut |_| true))
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn alternatives(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Node>, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantifier(atom)?);
+        }
+        Ok(nodes)
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.next().ok_or("Unexpected end of regex")? {
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Start),
+            '$' => Ok(Node::End),
+            '(' => {
+                let alternatives = self.alternatives()?;
+                if self.next() != Some(')') {
+                    return Err("Unclosed '(' in regex".to_string());
+                }
+                Ok(Node::Group(alternatives))
+            }
+            '[' => self.class(),
+            '\\' => self.escape(),
+            c @ ('*' | '+' | '?' | '{') => Err(format!("Nothing to repeat before '{}' in regex", c)),
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    fn escape(&mut self) -> Result<Node, String> {
+        let c = self.next().ok_or("Trailing '\\' in regex")?;
+        Ok(match c {
+            'd' => Node::Class(vec![('0', '9')], false),
+            'w' => Node::Class(vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')], false),
+            's' => Node::Class(vec![(' ', ' '), ('\t', '\t'), ('\n', '\n'), ('\r', '\r')], false),
+            'n' => Node::Char('\n'),
+            't' => Node::Char('\t'),
+            c => Node::Char(c),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.next().ok_or("Unclosed '[' in regex")?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let c = if c == '\\' { self.next().ok_or("Unclosed '[' in regex")? } else { c };
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|c| *c != ']') {
+                self.pos += 1;
+                let end = self.next().ok_or("Unclosed '[' in regex")?;
+                ranges.push((c, end));
+            } else {
+                ranges.push((c, c));
+            }
+        }
+        Ok(Node::Class(ranges, negated))
+    }
+
+    fn quantifier(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => return self.counted(atom),
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        Ok(Node::Repeat(Box::new(atom), min, max))
+    }
+
+    fn counted(&mut self, atom: Node) -> Result<Node, String> {
+        let end = self.chars[self.pos..]
+            .iter()
+            .position(|c| *c == '}')
+            .ok_or("Unclosed '{' in regex")?;
+        let body: String = self.chars[self.pos + 1..self.pos + end].iter().collect();
+        self.pos += end + 1;
+        let number = |s: &str| s.trim().parse::<usize>().map_err(|_| format!("Invalid repetition {{{}}} in regex", body));
+        let (min, max) = match body.split_once(',') {
+            Some((min, "")) => (number(min)?, None),
+            Some((min, max)) => (number(min)?, Some(number(max)?)),
+            None => (number(&body)?, Some(number(&body)?)),
+        };
+        Ok(Node::Repeat(Box::new(atom), min, max))
+    }
+}
+
+// Continuation-passing backtracking: `k` is called with every position at which the
+// node can end, and the match succeeds as soon as one continuation does.
+fn match_node(node: &Node, text: &[char], pos: usize, k: &mut dyn FnMut(usize) -> bool) -> bool {
+    let single = |test: &dyn Fn(char) -> bool| pos < text.len() && test(text[pos]);
+    match node {
+        Node::Char(c) => single(&|t| t == *c) && k(pos + 1),
+        Node::Any => single(&|t| t != '\n') && k(pos + 1),
+        Node::Class(ranges, negated) => {
+            single(&|t| ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&t)) != *negated) && k(pos + 1)
+        }
+        Node::Start => pos == 0 && k(pos),
+        Node::End => pos == text.len() && k(pos),
+        Node::Group(alternatives) => alternatives.iter().any(|sequence| match_sequence(sequence, text, pos, k)),
+        Node::Repeat(inner, min, max) => match_repeat(inner, *min, *max, 0, text, pos, k),
+    }
+}
+
+fn match_sequence(nodes: &[Node], text: &[char], pos: usize, k: &mut dyn FnMut(usize) -> bool) -> bool {
+    match nodes.split_first() {
+        None => k(pos),
+        Some((first, rest)) => match_node(first, text, pos, &mut |next| match_sequence(rest, text, next, k)),
+    }
+}
+
+// Greedy: one more repetition is tried before stopping. A repetition that consumed
+// nothing is not repeated again, so patterns like (a*)* terminate.
+fn match_repeat(
+    inner: &Node,
+    min: usize,
+    max: Option<usize>,
+    count: usize,
+    text: &[char],
+    pos: usize,
+    k: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    if max.is_none_or(|max| count < max)
+        && match_node(inner, text, pos, &mut |next| {
+            (next != pos || count < min) && match_repeat(inner, min, max, count + 1, text, next, k)
+        })
+    {
+        return true;
+    }
+    count >= min && k(pos)
+}
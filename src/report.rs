@@ -0,0 +1,301 @@
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use crate::event::{DetectionEvent, EventKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" | "md" => Ok(ReportFormat::Markdown),
+            "html" => Ok(ReportFormat::Html),
+            _ => Err(format!("Unknown report format: {}", s)),
+        }
+    }
+}
+
+// A forensic file loaded for inclusion in the report.
+pub struct Artifact {
+    pub path: String,
+    pub content: String,
+}
+
+enum Block {
+    Paragraph(String),
+    Fields(Vec<(&'static str, String)>),
+    List(Vec<String>),
+    Code(String, String),
+}
+
+struct Section {
+    title: &'static str,
+    blocks: Vec<Block>,
+}
+
+fn or_unknown(value: &Option<String>) -> String {
+    value.clone().unwrap_or_else(|| "unknown".to_string())
+}
+
+fn sections(event: &DetectionEvent, artifacts: &[Artifact]) -> Vec<Section> {
+    let mut filesystem = vec![Block::Fields(vec![("Executable", or_unknown(&event.exe))])];
+    match &event.vulnerabilities {
+        Some(vulnerabilities) if vulnerabilities.is_empty() => {
+            filesystem.push(Block::Paragraph("Trivy reported no HIGH or CRITICAL vulnerabilities.".to_string()));
+        }
+        Some(vulnerabilities) => filesystem.push(Block::List(
+            vulnerabilities
+                .iter()
+                .map(|v| format!("{} {} in {}", v.severity, v.id, v.package))
+                .collect(),
+        )),
+        None => filesystem.push(Block::Paragraph("No vulnerability scan was run.".to_string())),
+    }
+    if let Some(dir) = &event.checkpoint_dir {
+        filesystem.push(Block::Fields(vec![("CRIU checkpoint", dir.clone())]));
+    }
+    for artifact in artifacts {
+        filesystem.push(Block::Code(artifact.path.clone(), artifact.content.clone()));
+    }
+
+    vec![
+        Section {
+            title: "Executive Summary",
+            blocks: vec![Block::Paragraph(format!(
+                "A {} (PID {}, {}) was detected in container {} at {}. Action taken: {}.",
+                match event.kind {
+                    EventKind::NewProcess => "new process",
+                    EventKind::ProcessExit => "whitelisted process exit",
+                    EventKind::NamespaceEscape => "user namespace escape",
+                    EventKind::PrivilegeEscalation => "privilege escalation",
+                    EventKind::ActionTimeout => "timed out container stop",
+                    EventKind::Overload => "process count overload",
+                    EventKind::FileWrite => "file write",
+                    EventKind::Correlated => "burst of detections",
+                    EventKind::SeccompViolation => "seccomp violation",
+                    EventKind::CapabilityAbuse => "capability abuse",
+                    EventKind::MountChange => "new mount",
+                    EventKind::Ptrace => "ptrace attach",
+                    EventKind::HiddenFile => "hidden file",
+                    EventKind::MonitoringDegraded => "degraded monitoring task",
+                    EventKind::CpuSpike => "CPU usage spike",
+                    EventKind::IoSpike => "I/O spike",
+                    EventKind::NetClassChange => "network class change",
+                },
+                event.pid,
+                or_unknown(&event.exe),
+                event.container_id,
+                event.detected_at,
+                event.action
+            ))],
+        },
+        Section {
+            title: "Container Context",
+            blocks: vec![Block::Fields(vec![
+                ("Container ID", event.container_id.clone()),
+                ("Event ID", event.id.clone()),
+                ("Memory pressure", event.memory_pressure.to_string()),
+                ("After OOM kill", event.after_oom_kill.to_string()),
+                ("Unusual hour", event.high_anomaly.to_string()),
+                (
+                    "Working directory",
+                    event.process_cwd.as_ref().map_or("not tracked".to_string(), |cwd| {
+                        format!("{}{}", cwd.display(), if event.suspicious_cwd { " (sensitive)" } else { "" })
+                    }),
+                ),
+                ("Labels", {
+                    let mut labels: Vec<String> =
+                        event.container_labels.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+                    labels.sort();
+                    labels.join(", ")
+                }),
+                ("Frozen during collection", event.frozen.to_string()),
+                ("Pause duration", event.pause_duration_ms.map_or("not paused".to_string(), |ms| format!("{} ms", ms))),
+                (
+                    "Image signature",
+                    event.image_signature_status.map_or("not checked".to_string(), |s| s.to_string()),
+                ),
+                ("Container group", event.group_name.clone().unwrap_or_else(|| "none".to_string())),
+                (
+                    "Group peers",
+                    if event.group_peer_containers.is_empty() {
+                        "none".to_string()
+                    } else {
+                        event.group_peer_containers.join(", ")
+                    },
+                ),
+            ])],
+        },
+        Section {
+            title: "Process Details",
+            blocks: vec![Block::Fields(vec![
+                ("PID", event.pid.to_string()),
+                ("Executable", or_unknown(&event.exe)),
+                ("Command line", or_unknown(&event.cmdline)),
+                ("Open file descriptors", event.open_fds.to_string()),
+                (
+                    "User namespace",
+                    match (event.user_namespace, event.container_user_namespace) {
+                        (Some(ns), Some(init)) => format!("{} (container init process: {})", ns, init),
+                        _ => "not checked".to_string(),
+                    },
+                ),
+                (
+                    "Effective UID",
+                    match (event.effective_uid, event.parent_effective_uid) {
+                        (Some(uid), Some(parent)) => format!("{} (parent: {})", uid, parent),
+                        _ => "not checked".to_string(),
+                    },
+                ),
+                ("CPU usage", event.cpu_percent.map_or("not sampled".to_string(), |cpu| format!("{:.1}%", cpu))),
+                (
+                    "Detection latency",
+                    event
+                        .detection_latency_ms
+                        .map_or("unknown".to_string(), |ms| format!("{:.3} ms", ms)),
+                ),
+            ])],
+        },
+        Section {
+            title: "Process Lineage",
+            blocks: vec![if event.process_lineage.is_empty() {
+                Block::Paragraph("No ancestor information was recorded for this event.".to_string())
+            } else {
+                Block::List(event.process_lineage.iter().map(|p| p.to_string()).collect())
+            }],
+        },
+        Section {
+            title: "Network Activity",
+            blocks: vec![if event.network_connections.is_empty() {
+                Block::Paragraph("No network activity was recorded for this event.".to_string())
+            } else {
+                Block::List(
+                    event
+                        .network_connections
+                        .iter()
+                        .map(|s| {
+                            format!(
+                                "{} {} -> {}",
+                                s.state,
+                                SocketAddr::new(s.local_ip, s.local_port),
+                                SocketAddr::new(s.remote_ip, s.remote_port)
+                            )
+                        })
+                        .collect(),
+                )
+            }],
+        },
+        Section {
+            title: "Filesystem Artifact",
+            blocks: filesystem,
+        },
+        Section {
+            title: "Timeline",
+            blocks: vec![Block::List(vec![
+                format!("{} - process {} detected ({})", event.detected_at, event.pid, event.kind),
+                format!("{} - action {} applied", event.detected_at, event.action),
+            ])],
+        },
+    ]
+}
+
+pub fn render(event: &DetectionEvent, artifacts: &[Artifact], format: ReportFormat) -> String {
+    let sections = sections(event, artifacts);
+    match format {
+        ReportFormat::Markdown => render_markdown(event, &sections),
+        ReportFormat::Html => render_html(event, &sections),
+    }
+}
+
+fn render_markdown(event: &DetectionEvent, sections: &[Section]) -> String {
+    let mut out = format!("# Incident Report: {}\n\n", event.id);
+    for section in sections {
+        let _ = write!(out, "## {}\n\n", section.title);
+        for block in &section.blocks {
+            match block {
+                Block::Paragraph(text) => {
+                    let _ = writeln!(out, "{}\n", text);
+                }
+                Block::Fields(fields) => {
+                    let _ = writeln!(out, "| Field | Value |\n| --- | --- |");
+                    for (name, value) in fields {
+                        let _ = writeln!(out, "| {} | `{}` |", name, value.replace('|', "\\|"));
+                    }
+                    out.push('\n');
+                }
+                Block::List(items) => {
+                    for item in items {
+                        let _ = writeln!(out, "- {}", item);
+                    }
+                    out.push('\n');
+                }
+                Block::Code(title, content) => {
+                    let _ = writeln!(out, "**{}**\n\n```\n{}\n```\n", title, content.trim_end());
+                }
+            }
+        }
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Styles are inlined so the report can be attached to an email as a single file.
+const HTML_STYLE: &str = "body{font-family:sans-serif;max-width:60em;margin:2em auto;color:#222}\
+h1{border-bottom:2px solid #c33}table{border-collapse:collapse}\
+td,th{border:1px solid #ccc;padding:.3em .6em;text-align:left}\
+pre{background:#f4f4f4;padding:.6em;overflow-x:auto}";
+
+fn render_html(event: &DetectionEvent, sections: &[Section]) -> String {
+    let title = format!("Incident Report: {}", escape_html(&event.id));
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, HTML_STYLE, title
+    );
+    for section in sections {
+        let _ = writeln!(out, "<h2>{}</h2>", section.title);
+        for block in &section.blocks {
+            match block {
+                Block::Paragraph(text) => {
+                    let _ = writeln!(out, "<p>{}</p>", escape_html(text));
+                }
+                Block::Fields(fields) => {
+                    out.push_str("<table>\n");
+                    for (name, value) in fields {
+                        let _ = writeln!(out, "<tr><th>{}</th><td><code>{}</code></td></tr>", name, escape_html(value));
+                    }
+                    out.push_str("</table>\n");
+                }
+                Block::List(items) => {
+                    out.push_str("<ul>\n");
+                    for item in items {
+                        let _ = writeln!(out, "<li>{}</li>", escape_html(item));
+                    }
+                    out.push_str("</ul>\n");
+                }
+                Block::Code(title, content) => {
+                    let _ = writeln!(
+                        out,
+                        "<h3>{}</h3>\n<pre>{}</pre>",
+                        escape_html(title),
+                        escape_html(content.trim_end())
+                    );
+                }
+            }
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
@@ -0,0 +1,399 @@
+// Consumer side of a BPF ring buffer map (BPF_MAP_TYPE_RINGBUF), for the eBPF detection
+// path. The exec tracepoint program reserves an `ExecEvent` in the map for every execve
+// and this reads them from user space without a syscall per event.
+//
+// Written against the kernel ABI directly: aya and the futures crate are not
+// dependencies, so `ExecEventBuffer` offers `poll_next` with the signature of
+// `futures::Stream::poll_next` and an async `next` on top of it.
+
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+use tokio::sync::mpsc;
+
+const BPF_MAP_CREATE: libc::c_long = 0;
+const BPF_MAP_TYPE_RINGBUF: u32 = 27;
+const BPF_RINGBUF_BUSY_BIT: u32 = 1 << 31;
+const BPF_RINGBUF_DISCARD_BIT: u32 = 1 << 30;
+const BPF_RINGBUF_HDR_SZ: usize = 8;
+
+// Events read from the map but not yet taken by the consumer. Past this, new events
+// are dropped and counted rather than left in the map, where they would make the
+// kernel side drop exec events of every container instead.
+const MAX_PENDING: usize = 4096;
+
+// Mirrors the struct the BPF program writes, field for field:
+//
+//     struct exec_event {
+//         __u64 timestamp_ns;  /* bpf_ktime_get_ns() */
+//         __u64 cgroup_id;     /* bpf_get_current_cgroup_id() */
+//         __u32 pid;
+//         __u32 ppid;
+//         __u32 uid;
+//         __u32 gid;
+//         char comm[16];
+//         char filename[256];
+//     };
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ExecEvent {
+    pub timestamp_ns: u64,
+    pub cgroup_id: u64,
+    pub pid: u32,
+    pub ppid: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub comm: [u8; 16],
+    pub filename: [u8; 256],
+}
+
+impl ExecEvent {
+    pub fn comm(&self) -> String {
+        c_string(&self.comm)
+    }
+
+    pub fn filename(&self) -> String {
+        c_string(&self.filename)
+    }
+
+    // Samples shorter than the struct come from a program built against another layout.
+    fn from_sample(sample: &[u8]) -> Option<ExecEvent> {
+        if sample.len() < std::mem::size_of::<ExecEvent>() {
+            return None;
+        }
+        Some(unsafe { ptr::read_unaligned(sample.as_ptr().cast()) })
+    }
+}
+
+impl std::fmt::Debug for ExecEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExecEvent")
+            .field("timestamp_ns", &self.timestamp_ns)
+            .field("cgroup_id", &self.cgroup_id)
+            .field("pid", &self.pid)
+            .field("ppid", &self.ppid)
+            .field("uid", &self.uid)
+            .field("gid", &self.gid)
+            .field("comm", &self.comm())
+            .field("filename", &self.filename())
+            .finish()
+    }
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+// A BPF ring buffer map mapped into this process: one writable page holding the
+// consumer position, then the producer position page and the data area, which the
+// kernel maps twice in a row so that a sample wrapping around the end stays contiguous.
+pub struct BpfRingBuffer {
+    fd: AsyncFd<OwnedFd>,
+    consumer: *mut u8,
+    producer: *mut u8,
+    data_size: usize,
+}
+
+// The mappings are owned by the buffer and only read or advanced through &mut self.
+unsafe impl Send for BpfRingBuffer {}
+
+impl BpfRingBuffer {
+    // Creates a new ring buffer map; `data_size` must be a power of two and a multiple
+    // of the page size. The BPF program that fills it references `map_fd()`.
+    pub fn create(data_size: usize) -> io::Result<BpfRingBuffer> {
+        if !data_size.is_power_of_two() || !data_size.is_multiple_of(page_size()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("ring buffer size {} is not a power of two multiple of the page size", data_size),
+            ));
+        }
+        // map_type, key_size, value_size, max_entries, then fields left at zero.
+        let mut attr = [0u32; 32];
+        attr[0] = BPF_MAP_TYPE_RINGBUF;
+        attr[3] = data_size as u32;
+        let fd = unsafe { libc::syscall(libc::SYS_bpf, BPF_MAP_CREATE, attr.as_ptr(), std::mem::size_of_val(&attr)) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+        BpfRingBuffer::from_fd(fd, data_size)
+    }
+
+    // Maps an existing ring buffer map, such as one created by the program loader.
+    pub fn from_fd(fd: OwnedFd, data_size: usize) -> io::Result<BpfRingBuffer> {
+        let map_fd = fd.as_raw_fd();
+        BpfRingBuffer::map(map_fd, fd, data_size)
+    }
+
+    // Maps the ring at `map_fd` and waits on `fd` for new samples, which for a map is
+    // the same fd. Tests lay a ring out in a memfd, which cannot be polled.
+    fn map(map_fd: RawFd, fd: OwnedFd, data_size: usize) -> io::Result<BpfRingBuffer> {
+        let page = page_size();
+        let consumer = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                page,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                map_fd,
+                0,
+            )
+        };
+        if consumer == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let producer = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                page + 2 * data_size,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                map_fd,
+                page as libc::off_t,
+            )
+        };
+        if producer == libc::MAP_FAILED {
+            let err = io::Error::last_os_error();
+            unsafe { libc::munmap(consumer, page) };
+            return Err(err);
+        }
+        Ok(BpfRingBuffer {
+            // The map fd polls readable once samples are committed.
+            fd: AsyncFd::with_interest(fd, Interest::READABLE)?,
+            consumer: consumer.cast(),
+            producer: producer.cast(),
+            data_size,
+        })
+    }
+
+    pub fn map_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    // Hands every committed sample to `f`, skipping discarded ones, and releases the
+    // space back to the producer. Returns how many samples were read.
+    pub fn drain<F: FnMut(&[u8])>(&mut self, mut f: F) -> usize {
+        // Both positions sit at the start of their pages.
+        let consumer = unsafe { &*(self.consumer as *const AtomicU64) };
+        let producer = unsafe { &*(self.producer as *const AtomicU64) };
+        let data = unsafe { self.producer.add(page_size()) };
+        let mask = self.data_size as u64 - 1;
+
+        let mut read = 0;
+        let mut position = consumer.load(Ordering::Acquire);
+        while position < producer.load(Ordering::Acquire) {
+            let header = unsafe { &*(data.add((position & mask) as usize) as *const AtomicU32) };
+            let len = header.load(Ordering::Acquire);
+            // Reserved but not yet submitted; later samples have to wait for it.
+            if len & BPF_RINGBUF_BUSY_BIT != 0 {
+                break;
+            }
+            let sample_len = (len & !BPF_RINGBUF_DISCARD_BIT) as usize;
+            if len & BPF_RINGBUF_DISCARD_BIT == 0 {
+                let start = (position & mask) as usize + BPF_RINGBUF_HDR_SZ;
+                f(unsafe { std::slice::from_raw_parts(data.add(start), sample_len) });
+                read += 1;
+            }
+            position += (sample_len + BPF_RINGBUF_HDR_SZ).next_multiple_of(8) as u64;
+            consumer.store(position, Ordering::Release);
+        }
+        read
+    }
+
+    // Waits until the kernel signals new samples.
+    pub async fn readable(&mut self) -> io::Result<()> {
+        let mut guard = self.fd.readable().await?;
+        // Cleared before draining, so a sample committed meanwhile wakes us again.
+        guard.clear_ready();
+        Ok(())
+    }
+}
+
+impl Drop for BpfRingBuffer {
+    fn drop(&mut self) {
+        let page = page_size();
+        unsafe {
+            libc::munmap(self.consumer.cast(), page);
+            libc::munmap(self.producer.cast(), page + 2 * self.data_size);
+        }
+    }
+}
+
+// Stream of exec events from the ring buffer map. A background task drains the map as
+// soon as the kernel signals new samples, so a slow consumer costs its own events,
+// counted in `dropped_events`, instead of filling the map.
+pub struct ExecEventBuffer {
+    events: mpsc::Receiver<ExecEvent>,
+    dropped: Arc<AtomicU64>,
+    map_fd: RawFd,
+}
+
+impl ExecEventBuffer {
+    pub fn new(mut ring: BpfRingBuffer) -> ExecEventBuffer {
+        let (sender, events) = mpsc::channel(MAX_PENDING);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let map_fd = ring.map_fd();
+        let counter = dropped.clone();
+        tokio::spawn(async move {
+            loop {
+                ring.drain(|sample| {
+                    let delivered = match ExecEvent::from_sample(sample) {
+                        Some(event) => !matches!(sender.try_send(event), Err(mpsc::error::TrySendError::Full(_))),
+                        None => false,
+                    };
+                    if !delivered {
+                        counter.fetch_add(1, Ordering::Relaxed);
+                    }
+                });
+                if sender.is_closed() {
+                    return;
+                }
+                if let Err(e) = ring.readable().await {
+                    eprintln!("Failed to wait on the exec event ring buffer: {}", e);
+                    return;
+                }
+            }
+        });
+        ExecEventBuffer { events, dropped, map_fd }
+    }
+
+    pub fn map_fd(&self) -> RawFd {
+        self.map_fd
+    }
+
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ExecEvent>> {
+        self.get_mut().events.poll_recv(cx)
+    }
+
+    pub async fn next(&mut self) -> Option<ExecEvent> {
+        self.events.recv().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA_SIZE: usize = 1 << 21;
+    const SAMPLE_LEN: usize = std::mem::size_of::<ExecEvent>();
+
+    // A ring laid out like the kernel's in a memfd: the consumer page, the producer
+    // page, then the data area twice over. The kernel maps one data area twice; here
+    // every write goes to both copies.
+    struct FakeRing {
+        file: OwnedFd,
+        memory: *mut u8,
+        position: u64,
+    }
+
+    impl FakeRing {
+        fn new(start: u64) -> FakeRing {
+            let file = unsafe { OwnedFd::from_raw_fd(libc::memfd_create(c"cnpd-ringbuf".as_ptr(), 0)) };
+            let len = 2 * page_size() + 2 * DATA_SIZE;
+            assert_eq!(unsafe { libc::ftruncate(file.as_raw_fd(), len as libc::off_t) }, 0);
+            let memory = unsafe {
+                libc::mmap(ptr::null_mut(), len, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, file.as_raw_fd(), 0)
+            };
+            assert_ne!(memory, libc::MAP_FAILED);
+            let mut ring = FakeRing { file, memory: memory.cast(), position: start };
+            ring.set_position(0, start);
+            ring.set_position(page_size(), start);
+            ring
+        }
+
+        fn set_position(&mut self, offset: usize, position: u64) {
+            unsafe { (*(self.memory.add(offset) as *const AtomicU64)).store(position, Ordering::Release) };
+        }
+
+        fn write(&mut self, position: u64, bytes: &[u8]) {
+            let data = unsafe { self.memory.add(2 * page_size()) };
+            for (i, byte) in bytes.iter().enumerate() {
+                let offset = (position as usize + i) % DATA_SIZE;
+                unsafe {
+                    *data.add(offset) = *byte;
+                    *data.add(offset + DATA_SIZE) = *byte;
+                }
+            }
+        }
+
+        // Reserves and fills a sample, and returns the position of its header.
+        fn push(&mut self, flags: u32, pid: u32) -> u64 {
+            let mut event = ExecEvent {
+                timestamp_ns: 0,
+                cgroup_id: 1,
+                pid,
+                ppid: 1,
+                uid: 0,
+                gid: 0,
+                comm: [0; 16],
+                filename: [0; 256],
+            };
+            event.filename[..8].copy_from_slice(b"/bin/sh\0");
+            let header = self.position;
+            let sample = unsafe { std::slice::from_raw_parts((&event as *const ExecEvent).cast::<u8>(), SAMPLE_LEN) };
+            self.write(header + BPF_RINGBUF_HDR_SZ as u64, sample);
+            self.write(header, &(SAMPLE_LEN as u32 | flags).to_ne_bytes());
+            self.position += (SAMPLE_LEN + BPF_RINGBUF_HDR_SZ).next_multiple_of(8) as u64;
+            self.set_position(page_size(), self.position);
+            header
+        }
+
+        fn consumer(&self) -> BpfRingBuffer {
+            let notify = unsafe { OwnedFd::from_raw_fd(libc::eventfd(0, libc::EFD_NONBLOCK)) };
+            BpfRingBuffer::map(self.file.as_raw_fd(), notify, DATA_SIZE).unwrap()
+        }
+    }
+
+    impl Drop for FakeRing {
+        fn drop(&mut self) {
+            unsafe { libc::munmap(self.memory.cast(), 2 * page_size() + 2 * DATA_SIZE) };
+        }
+    }
+
+    #[tokio::test]
+    async fn delivers_committed_samples_and_counts_overflow() {
+        // The first sample wraps around the end of the data area.
+        let mut ring = FakeRing::new((DATA_SIZE - 200) as u64);
+        ring.push(0, 1);
+        ring.push(BPF_RINGBUF_DISCARD_BIT, 2);
+        let overflow = 3;
+        for pid in 3..3 + (MAX_PENDING - 1 + overflow) as u32 {
+            ring.push(0, pid);
+        }
+        // Not yet submitted, so the sample after it has to wait.
+        let busy = ring.push(BPF_RINGBUF_BUSY_BIT, 10_000);
+        ring.push(0, 10_001);
+
+        let buffer = ring.consumer();
+        let notify = buffer.fd.as_raw_fd();
+        let mut events = ExecEventBuffer::new(buffer);
+        let first = events.next().await.unwrap();
+        assert_eq!((first.pid, first.filename()), (1, "/bin/sh".to_string()));
+        assert_eq!(events.dropped_events(), overflow as u64);
+        for pid in 3..2 + MAX_PENDING as u32 {
+            assert_eq!(events.next().await.unwrap().pid, pid);
+        }
+        assert!(events.events.try_recv().is_err());
+
+        ring.write(busy, &(SAMPLE_LEN as u32).to_ne_bytes());
+        assert_eq!(unsafe { libc::eventfd_write(notify, 1) }, 0);
+        assert_eq!(events.next().await.unwrap().pid, 10_000);
+        assert_eq!(events.next().await.unwrap().pid, 10_001);
+        assert_eq!(events.dropped_events(), overflow as u64);
+    }
+}
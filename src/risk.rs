@@ -0,0 +1,172 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::json::{self, Value};
+use crate::{docker, procfs};
+
+// Host paths that give a container control over the host when mounted into it. A
+// mount of a directory below one of these counts too.
+const SENSITIVE_PATHS: [&str; 10] = [
+    "/etc",
+    "/proc",
+    "/sys",
+    "/dev",
+    "/root",
+    "/boot",
+    "/var/lib/docker",
+    "/var/run/docker.sock",
+    "/run/docker.sock",
+    "/run/containerd",
+];
+
+// `HostConfig` fields that put the container in one of the host's namespaces when "host".
+const HOST_NAMESPACES: [(&str, &str); 5] = [
+    ("NetworkMode", "net"),
+    ("PidMode", "pid"),
+    ("IpcMode", "ipc"),
+    ("UTSMode", "uts"),
+    ("UsernsMode", "user"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RiskFactor {
+    Root,
+    Privileged,
+    SensitiveMount(String),
+    NoSeccomp,
+    HostNamespace(&'static str),
+}
+
+impl RiskFactor {
+    fn weight(&self) -> u32 {
+        match self {
+            RiskFactor::Root => 2,
+            RiskFactor::Privileged => 4,
+            RiskFactor::SensitiveMount(_) => 3,
+            RiskFactor::NoSeccomp => 2,
+            RiskFactor::HostNamespace(_) => 3,
+        }
+    }
+}
+
+impl fmt::Display for RiskFactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RiskFactor::Root => write!(f, "root"),
+            RiskFactor::Privileged => write!(f, "privileged"),
+            RiskFactor::SensitiveMount(path) => write!(f, "mount:{}", path),
+            RiskFactor::NoSeccomp => write!(f, "no-seccomp"),
+            RiskFactor::HostNamespace(namespace) => write!(f, "host-{}", namespace),
+        }
+    }
+}
+
+// How much damage a compromised container could do to the host, assessed once when
+// its monitoring starts. The score is the sum of the weights of the factors found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContainerRisk {
+    pub score: u32,
+    pub factors: Vec<String>,
+}
+
+impl ContainerRisk {
+    // `init_pid` is the container's init process, whose UID and seccomp mode are the
+    // actual ones; without it the configured user and security options are used.
+    pub async fn assess(container_id: &str, init_pid: Option<i32>) -> Result<ContainerRisk, Box<dyn Error>> {
+        let inspect = json::parse(&docker::inspect(container_id, "{{json .}}").await?)?;
+        let uid = match init_pid {
+            Some(pid) => procfs::read_uid(pid).await,
+            None => None,
+        };
+        let has_seccomp = docker::has_seccomp_profile(container_id, init_pid).await?;
+        Ok(ContainerRisk::from_inspect(&inspect, uid, has_seccomp))
+    }
+
+    fn from_inspect(inspect: &Value, uid: Option<u32>, has_seccomp: bool) -> ContainerRisk {
+        let host_config = inspect.get("HostConfig");
+        let host_field = |name: &str| host_config.and_then(|c| c.get(name));
+        let mut factors = Vec::new();
+
+        let user = inspect.get("Config").and_then(|c| c.get("User")).and_then(Value::as_str).unwrap_or("");
+        let configured_root = matches!(user.split(':').next(), Some("" | "root" | "0"));
+        if uid.map_or(configured_root, |uid| uid == 0) {
+            factors.push(RiskFactor::Root);
+        }
+        if host_field("Privileged").and_then(Value::as_bool).unwrap_or(false) {
+            factors.push(RiskFactor::Privileged);
+        }
+
+        // Binds are `<source>:<target>[:<options>]`; Mounts also lists volumes and tmpfs.
+        let mut sources: Vec<&str> = host_field("Binds")
+            .and_then(Value::as_array)
+            .map(|binds| binds.iter().filter_map(Value::as_str).filter_map(|b| b.split(':').next()).collect())
+            .unwrap_or_default();
+        if let Some(mounts) = inspect.get("Mounts").and_then(Value::as_array) {
+            sources.extend(mounts.iter().filter_map(|m| m.get("Source")).filter_map(Value::as_str));
+        }
+        sources.sort_unstable();
+        sources.dedup();
+        for source in sources.into_iter().filter(|source| is_sensitive(source)) {
+            factors.push(RiskFactor::SensitiveMount(source.to_string()));
+        }
+
+        if !has_seccomp {
+            factors.push(RiskFactor::NoSeccomp);
+        }
+        for (field, namespace) in HOST_NAMESPACES {
+            if host_field(field).and_then(Value::as_str) == Some("host") {
+                factors.push(RiskFactor::HostNamespace(namespace));
+            }
+        }
+
+        ContainerRisk {
+            score: factors.iter().map(RiskFactor::weight).sum(),
+            factors: factors.iter().map(RiskFactor::to_string).collect(),
+        }
+    }
+}
+
+impl fmt::Display for ContainerRisk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.factors.is_empty() {
+            return write!(f, "{}", self.score);
+        }
+        write!(f, "{} ({})", self.score, self.factors.join(", "))
+    }
+}
+
+fn is_sensitive(source: &str) -> bool {
+    let source = source.trim_end_matches('/');
+    source.is_empty()
+        || SENSITIVE_PATHS
+            .iter()
+            .any(|path| source == *path || source.strip_prefix(path).is_some_and(|rest| rest.starts_with('/')))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_privileged_root_container_with_host_mounts() {
+        let inspect = json::parse(
+            r#"{"Config": {"User": ""},
+                "HostConfig": {"Privileged": true, "Binds": ["/:/host:ro", "/srv/data:/data"], "PidMode": "host", "NetworkMode": "bridge"},
+                "Mounts": [{"Source": "/var/run/docker.sock"}, {"Source": "/srv/data"}]}"#,
+        )
+        .unwrap();
+        let risk = ContainerRisk::from_inspect(&inspect, None, false);
+        assert_eq!(
+            risk.factors,
+            vec!["root", "privileged", "mount:/", "mount:/var/run/docker.sock", "no-seccomp", "host-pid"]
+        );
+        assert_eq!(risk.score, 2 + 4 + 3 + 3 + 2 + 3);
+    }
+
+    #[test]
+    fn process_uid_overrides_configured_user() {
+        let inspect = json::parse(r#"{"Config": {"User": "1000"}, "HostConfig": {"Binds": ["/etc-backup:/backup"]}}"#).unwrap();
+        assert_eq!(ContainerRisk::from_inspect(&inspect, None, true), ContainerRisk::default());
+        assert_eq!(ContainerRisk::from_inspect(&inspect, Some(0), true).factors, vec!["root"]);
+    }
+}
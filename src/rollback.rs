@@ -0,0 +1,241 @@
+use crate::docker;
+use crate::json::Value;
+
+// What --rollback-on-detection recreates a container from, read with `docker inspect`
+// before the container is touched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContainerSpec {
+    pub name: String,
+    // The image ID, a digest of the image's content: a tag pushed again since the
+    // container was created, possibly by the attacker, does not change what it runs.
+    pub image: String,
+    // Everything `docker create` takes, the image and command included.
+    pub create_args: Vec<String>,
+    // Networks past the first, which docker create cannot attach to.
+    pub networks: Vec<String>,
+}
+
+fn str_field<'a>(value: &'a Value, path: &[&str]) -> Option<&'a str> {
+    field(value, path)?.as_str().filter(|s| !s.is_empty())
+}
+
+fn field<'a>(value: &'a Value, path: &[&str]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| value.get(key))
+}
+
+fn strings<'a>(value: &'a Value, path: &[&str]) -> impl Iterator<Item = &'a str> {
+    field(value, path)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+}
+
+fn object<'a>(value: &'a Value, path: &[&str]) -> &'a [(String, Value)] {
+    match field(value, path) {
+        Some(Value::Object(fields)) => fields,
+        _ => &[],
+    }
+}
+
+fn flag(value: &Value, path: &[&str]) -> bool {
+    field(value, path).and_then(Value::as_bool).unwrap_or(false)
+}
+
+impl ContainerSpec {
+    // From the output of `docker inspect <container>`'s object. Config holds the image
+    // defaults merged with the container's own settings, so they are all passed
+    // explicitly, which is what the original `docker create` amounted to.
+    pub fn from_inspect(inspect: &Value) -> Result<ContainerSpec, String> {
+        let id = str_field(inspect, &["Id"]).ok_or("Missing Id")?;
+        let name = str_field(inspect, &["Name"]).ok_or("Missing Name")?.trim_start_matches('/').to_string();
+        let image = str_field(inspect, &["Image"]).ok_or("Missing Image")?.to_string();
+        let mut args = vec!["--name".to_string(), name.clone()];
+        let mut option = |name: &str, value: &str| {
+            args.push(format!("--{}", name));
+            args.push(value.to_string());
+        };
+
+        // Docker names the host after the short ID unless told otherwise; the new
+        // container gets its own.
+        if let Some(hostname) = str_field(inspect, &["Config", "Hostname"]).filter(|h| *h != docker::short_id(id)) {
+            option("hostname", hostname);
+        }
+        if let Some(domain) = str_field(inspect, &["Config", "Domainname"]) {
+            option("domainname", domain);
+        }
+        if let Some(user) = str_field(inspect, &["Config", "User"]) {
+            option("user", user);
+        }
+        if let Some(dir) = str_field(inspect, &["Config", "WorkingDir"]) {
+            option("workdir", dir);
+        }
+        if let Some(signal) = str_field(inspect, &["Config", "StopSignal"]) {
+            option("stop-signal", signal);
+        }
+        for env in strings(inspect, &["Config", "Env"]) {
+            option("env", env);
+        }
+        for (key, value) in object(inspect, &["Config", "Labels"]) {
+            option("label", &format!("{}={}", key, value.as_str().unwrap_or_default()));
+        }
+        for (port, _) in object(inspect, &["Config", "ExposedPorts"]) {
+            option("expose", port);
+        }
+        // --entrypoint takes one word; the rest goes in front of the command.
+        let mut entrypoint = strings(inspect, &["Config", "Entrypoint"]);
+        option("entrypoint", entrypoint.next().unwrap_or_default());
+        let command: Vec<String> = entrypoint.chain(strings(inspect, &["Config", "Cmd"])).map(str::to_string).collect();
+
+        let network_mode = str_field(inspect, &["HostConfig", "NetworkMode"]).unwrap_or("default");
+        if network_mode != "default" {
+            option("network", network_mode);
+        }
+        for bind in strings(inspect, &["HostConfig", "Binds"]) {
+            option("volume", bind);
+        }
+        for mount in field(inspect, &["HostConfig", "Mounts"]).and_then(Value::as_array).into_iter().flatten() {
+            let mut spec = vec![format!("type={}", str_field(mount, &["Type"]).unwrap_or("volume"))];
+            if let Some(source) = str_field(mount, &["Source"]) {
+                spec.push(format!("source={}", source));
+            }
+            spec.push(format!("target={}", str_field(mount, &["Target"]).ok_or("Mount without a Target")?));
+            if flag(mount, &["ReadOnly"]) {
+                spec.push("readonly".to_string());
+            }
+            option("mount", &spec.join(","));
+        }
+        for (path, options) in object(inspect, &["HostConfig", "Tmpfs"]) {
+            match options.as_str().filter(|o| !o.is_empty()) {
+                Some(options) => option("tmpfs", &format!("{}:{}", path, options)),
+                None => option("tmpfs", path),
+            }
+        }
+        for (port, bindings) in object(inspect, &["HostConfig", "PortBindings"]) {
+            for binding in bindings.as_array().into_iter().flatten() {
+                let publish = match (str_field(binding, &["HostIp"]), str_field(binding, &["HostPort"])) {
+                    (Some(ip), host_port) => format!("{}:{}:{}", ip, host_port.unwrap_or_default(), port),
+                    (None, Some(host_port)) => format!("{}:{}", host_port, port),
+                    (None, None) => port.clone(),
+                };
+                option("publish", &publish);
+            }
+        }
+        for device in field(inspect, &["HostConfig", "Devices"]).and_then(Value::as_array).into_iter().flatten() {
+            let host = str_field(device, &["PathOnHost"]).ok_or("Device without a PathOnHost")?;
+            let target = str_field(device, &["PathInContainer"]).unwrap_or(host);
+            let permissions = str_field(device, &["CgroupPermissions"]).unwrap_or("rwm");
+            option("device", &format!("{}:{}:{}", host, target, permissions));
+        }
+        for (name, path) in [("cap-add", "CapAdd"), ("cap-drop", "CapDrop"), ("security-opt", "SecurityOpt"), ("dns", "Dns"), ("add-host", "ExtraHosts")] {
+            for value in strings(inspect, &["HostConfig", path]) {
+                option(name, value);
+            }
+        }
+        for (name, path) in [("pid", "PidMode"), ("uts", "UTSMode"), ("userns", "UsernsMode"), ("cgroupns", "CgroupnsMode")] {
+            if let Some(mode) = str_field(inspect, &["HostConfig", path]).filter(|mode| *mode != "private") {
+                option(name, mode);
+            }
+        }
+        // Containers get their own shareable IPC namespace by default.
+        if let Some(mode) = str_field(inspect, &["HostConfig", "IpcMode"]).filter(|mode| !["private", "shareable"].contains(mode)) {
+            option("ipc", mode);
+        }
+        if let Some(policy) = str_field(inspect, &["HostConfig", "RestartPolicy", "Name"]).filter(|name| *name != "no") {
+            match field(inspect, &["HostConfig", "RestartPolicy", "MaximumRetryCount"]).and_then(Value::as_i64) {
+                Some(retries) if retries > 0 => option("restart", &format!("{}:{}", policy, retries)),
+                _ => option("restart", policy),
+            }
+        }
+        if let Some(memory) = field(inspect, &["HostConfig", "Memory"]).and_then(Value::as_i64).filter(|m| *m > 0) {
+            option("memory", &memory.to_string());
+        }
+        if let Some(cpus) = field(inspect, &["HostConfig", "NanoCpus"]).and_then(Value::as_i64).filter(|n| *n > 0) {
+            option("cpus", &(cpus as f64 / 1e9).to_string());
+        }
+        if let Some(driver) = str_field(inspect, &["HostConfig", "LogConfig", "Type"]) {
+            option("log-driver", driver);
+            for (key, value) in object(inspect, &["HostConfig", "LogConfig", "Config"]) {
+                option("log-opt", &format!("{}={}", key, value.as_str().unwrap_or_default()));
+            }
+        }
+
+        for (flag_name, path) in [
+            ("--privileged", &["HostConfig", "Privileged"][..]),
+            ("--read-only", &["HostConfig", "ReadonlyRootfs"]),
+            ("--init", &["HostConfig", "Init"]),
+            ("--rm", &["HostConfig", "AutoRemove"]),
+            ("--tty", &["Config", "Tty"]),
+            ("--interactive", &["Config", "OpenStdin"]),
+        ] {
+            if flag(inspect, path) {
+                args.push(flag_name.to_string());
+            }
+        }
+        args.push(image.clone());
+        args.extend(command);
+
+        // Only bridge and user-defined networks can be joined next to another.
+        let primary = if network_mode == "default" { "bridge" } else { network_mode };
+        let networks = match network_mode {
+            "host" | "none" => Vec::new(),
+            mode if mode.starts_with("container:") => Vec::new(),
+            _ => object(inspect, &["NetworkSettings", "Networks"])
+                .iter()
+                .map(|(network, _)| network.clone())
+                .filter(|network| network != primary)
+                .collect(),
+        };
+        Ok(ContainerSpec {
+            name,
+            image,
+            create_args: args,
+            networks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+
+    #[test]
+    fn recreates_from_the_image_id() {
+        let inspect = json::parse(
+            r#"{
+                "Id": "0123456789abcdef0123456789abcdef",
+                "Name": "/web",
+                "Image": "sha256:9f1e",
+                "Config": {
+                    "Hostname": "0123456789ab",
+                    "Image": "nginx:latest",
+                    "Env": ["PATH=/usr/bin"],
+                    "Entrypoint": ["/docker-entrypoint.sh"],
+                    "Cmd": ["nginx", "-g", "daemon off;"],
+                    "Labels": {"app": "web"}
+                },
+                "HostConfig": {
+                    "NetworkMode": "frontend",
+                    "PortBindings": {"80/tcp": [{"HostIp": "", "HostPort": "8080"}]},
+                    "RestartPolicy": {"Name": "on-failure", "MaximumRetryCount": 3},
+                    "ReadonlyRootfs": true,
+                    "IpcMode": "private"
+                },
+                "NetworkSettings": {"Networks": {"frontend": {}, "backend": {}}}
+            }"#,
+        )
+        .unwrap();
+        let spec = ContainerSpec::from_inspect(&inspect).unwrap();
+        assert_eq!(spec.name, "web");
+        assert_eq!(spec.networks, ["backend"]);
+        assert_eq!(
+            spec.create_args,
+            [
+                "--name", "web", "--env", "PATH=/usr/bin", "--label", "app=web", "--entrypoint", "/docker-entrypoint.sh",
+                "--network", "frontend", "--publish", "8080:80/tcp", "--restart", "on-failure:3", "--read-only",
+                "sha256:9f1e", "nginx", "-g", "daemon off;",
+            ]
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::Duration;
+
+use crate::event::{self, DetectionEvent};
+use crate::json::Value;
+use crate::log;
+use crate::policy::glob_match;
+use crate::sqlite::SqliteEventStore;
+use crate::webhook;
+
+pub type SinkFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>>;
+
+// A destination events can be routed to.
+pub trait Sink: Send + Sync {
+    fn send<'a>(&'a self, event: &'a DetectionEvent) -> SinkFuture<'a>;
+
+    // Waits up to `timeout` for events the sink still holds to be delivered.
+    fn shutdown(&self, _timeout: Duration) {}
+}
+
+// The event JSON is POSTed as is.
+pub struct WebhookSink {
+    url: String,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str) -> WebhookSink {
+        WebhookSink { url: url.to_string() }
+    }
+}
+
+impl Sink for WebhookSink {
+    fn send<'a>(&'a self, event: &'a DetectionEvent) -> SinkFuture<'a> {
+        Box::pin(post(&self.url, event.to_json().to_string()))
+    }
+}
+
+// A Slack incoming webhook, sent a one-line summary as `{"text": ...}`.
+pub struct SlackSink {
+    url: String,
+}
+
+impl SlackSink {
+    pub fn new(url: &str) -> SlackSink {
+        SlackSink { url: url.to_string() }
+    }
+}
+
+impl Sink for SlackSink {
+    fn send<'a>(&'a self, event: &'a DetectionEvent) -> SinkFuture<'a> {
+        let text = format!(
+            "cnpd {} in container {}: PID {} ({}), action {}",
+            event.kind,
+            log::id(&event.container_id),
+            event.pid,
+            event.exe.as_deref().unwrap_or("unknown"),
+            event.action
+        );
+        Box::pin(post(&self.url, Value::Object(vec![("text".to_string(), text.into())]).to_string()))
+    }
+}
+
+async fn post(url: &str, body: String) -> Result<(), String> {
+    let response = webhook::post_json(url, &body).await.map_err(|e| e.to_string())?;
+    if !response.is_success() {
+        return Err(format!("{} answered with HTTP {}", url, response.status));
+    }
+    Ok(())
+}
+
+// Appends the event JSON to a file, one per line like --output-file.
+pub struct FileSink {
+    path: String,
+}
+
+impl FileSink {
+    pub fn new(path: &str) -> FileSink {
+        FileSink { path: path.to_string() }
+    }
+}
+
+impl Sink for FileSink {
+    fn send<'a>(&'a self, event: &'a DetectionEvent) -> SinkFuture<'a> {
+        Box::pin(async move { event::append_event(&self.path, event).await.map_err(|e| format!("{}: {}", self.path, e)) })
+    }
+}
+
+// Queues the event for a SQLite database of its own; delivery counts as done once queued.
+pub struct SqliteSink {
+    store: SqliteEventStore,
+}
+
+impl SqliteSink {
+    pub fn open(path: &str) -> Result<SqliteSink, Box<dyn Error>> {
+        Ok(SqliteSink {
+            store: SqliteEventStore::open(path)?,
+        })
+    }
+}
+
+impl Sink for SqliteSink {
+    fn send<'a>(&'a self, event: &'a DetectionEvent) -> SinkFuture<'a> {
+        self.store.send(event);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn shutdown(&self, timeout: Duration) {
+        self.store.shutdown(timeout);
+    }
+}
+
+// One `[[sinks]]` entry of the config file:
+//
+//     [[sinks]]
+//     name = "siem"
+//     kind = "webhook"
+//     url = "http://siem.internal/cnpd"
+//
+// Webhook and slack sinks take a `url`, file and sqlite sinks a `path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SinkConfig {
+    pub name: String,
+    pub kind: String,
+    pub target: String,
+}
+
+impl SinkConfig {
+    pub fn from_toml(value: &Value) -> Result<SinkConfig, String> {
+        let string = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+        let name = string("name").ok_or("Sink is missing a name")?;
+        let kind = string("kind").ok_or_else(|| format!("Sink {} is missing a kind", name))?;
+        let key = match kind.as_str() {
+            "webhook" | "slack" => "url",
+            "file" | "sqlite" => "path",
+            _ => return Err(format!("Sink {}: unknown kind {}, expected webhook, slack, file or sqlite", name, kind)),
+        };
+        let target = string(key).ok_or_else(|| format!("Sink {}: {} sinks need a {}", name, kind, key))?;
+        Ok(SinkConfig { name, kind, target })
+    }
+
+    fn open(&self) -> Result<Box<dyn Sink>, Box<dyn Error>> {
+        Ok(match self.kind.as_str() {
+            "webhook" => Box::new(WebhookSink::new(&self.target)),
+            "slack" => Box::new(SlackSink::new(&self.target)),
+            "file" => Box::new(FileSink::new(&self.target)),
+            _ => Box::new(SqliteSink::open(&self.target)?),
+        })
+    }
+}
+
+// One `[[routes]]` entry of the config file, sending the events of containers whose
+// name or ID matches `container` to the named sinks:
+//
+//     [[routes]]
+//     container = "payments-*"
+//     sinks = ["siem", "oncall"]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub container: String,
+    pub sinks: Vec<String>,
+}
+
+impl Route {
+    pub fn from_toml(value: &Value) -> Result<Route, String> {
+        let container = value
+            .get("container")
+            .and_then(Value::as_str)
+            .ok_or("Route is missing a container pattern")?
+            .to_string();
+        let sinks = value
+            .get("sinks")
+            .and_then(Value::as_array)
+            .and_then(|sinks| sinks.iter().map(|v| v.as_str().map(str::to_string)).collect::<Option<Vec<_>>>())
+            .ok_or_else(|| format!("Route {}: sinks must be a list of sink names", container))?;
+        Ok(Route { container, sinks })
+    }
+}
+
+// How delivering one event to one sink went.
+#[derive(Debug, Clone)]
+pub struct SinkResult {
+    pub sink: String,
+    pub result: Result<(), String>,
+}
+
+#[derive(Default)]
+pub struct DetectionEventRouterBuilder {
+    sinks: Vec<(String, Box<dyn Sink>)>,
+    routes: Vec<Route>,
+}
+
+impl DetectionEventRouterBuilder {
+    pub fn sink(mut self, name: &str, sink: Box<dyn Sink>) -> Self {
+        self.sinks.push((name.to_string(), sink));
+        self
+    }
+
+    pub fn route(mut self, container: &str, sinks: Vec<String>) -> Self {
+        self.routes.push(Route {
+            container: container.to_string(),
+            sinks,
+        });
+        self
+    }
+
+    // Fails on a sink registered twice or a route to a sink that was never registered.
+    pub fn build(self) -> Result<DetectionEventRouter, String> {
+        let mut sinks = HashMap::new();
+        for (name, sink) in self.sinks {
+            if sinks.insert(name.clone(), sink).is_some() {
+                return Err(format!("Sink {} is defined twice", name));
+            }
+        }
+        for route in &self.routes {
+            if let Some(unknown) = route.sinks.iter().find(|name| !sinks.contains_key(*name)) {
+                return Err(format!("Route {} names unknown sink {}", route.container, unknown));
+            }
+        }
+        Ok(DetectionEventRouter {
+            sinks,
+            routes: self.routes,
+        })
+    }
+}
+
+// Sends each event to the sinks of every route its container matches, on top of the
+// outputs every event goes to.
+pub struct DetectionEventRouter {
+    sinks: HashMap<String, Box<dyn Sink>>,
+    routes: Vec<Route>,
+}
+
+impl DetectionEventRouter {
+    pub fn builder() -> DetectionEventRouterBuilder {
+        DetectionEventRouterBuilder::default()
+    }
+
+    pub fn from_config(sinks: &[SinkConfig], routes: &[Route]) -> Result<DetectionEventRouter, Box<dyn Error>> {
+        let mut builder = DetectionEventRouter::builder();
+        for sink in sinks {
+            builder = builder.sink(&sink.name, sink.open()?);
+        }
+        for route in routes {
+            builder = builder.route(&route.container, route.sinks.clone());
+        }
+        Ok(builder.build()?)
+    }
+
+    // The sinks routed to for a container, each once however many routes name it.
+    pub fn sinks_for(&self, container_id: &str, name: Option<&str>) -> Vec<&str> {
+        let mut matched: Vec<&str> = Vec::new();
+        for route in &self.routes {
+            if !glob_match(&route.container, container_id) && !name.is_some_and(|name| glob_match(&route.container, name)) {
+                continue;
+            }
+            for sink in &route.sinks {
+                if !matched.contains(&sink.as_str()) {
+                    matched.push(sink);
+                }
+            }
+        }
+        matched
+    }
+
+    // Delivers the event to all of its sinks at once and returns their results in
+    // the order they finished.
+    pub async fn route(&self, event: &DetectionEvent, name: Option<&str>) -> Vec<SinkResult> {
+        let mut pending: Vec<(&str, SinkFuture<'_>)> = self
+            .sinks_for(&event.container_id, name)
+            .into_iter()
+            .map(|sink| (sink, self.sinks[sink].send(event)))
+            .collect();
+        let mut results = Vec::with_capacity(pending.len());
+        std::future::poll_fn(|cx| {
+            pending.retain_mut(|(sink, send)| match send.as_mut().poll(cx) {
+                Poll::Ready(result) => {
+                    results.push(SinkResult {
+                        sink: sink.to_string(),
+                        result,
+                    });
+                    false
+                }
+                Poll::Pending => true,
+            });
+            if pending.is_empty() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+        results
+    }
+
+    pub fn shutdown(&self, timeout: Duration) {
+        for sink in self.sinks.values() {
+            sink.shutdown(timeout);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_matching_containers_to_each_sink_once() {
+        let router = DetectionEventRouter::builder()
+            .sink("siem", Box::new(WebhookSink::new("http://siem.internal/cnpd")))
+            .sink("oncall", Box::new(SlackSink::new("http://hooks.slack.internal/T0")))
+            .sink("audit", Box::new(FileSink::new("/var/log/cnpd/audit.jsonl")))
+            .route("payments-*", vec!["siem".to_string(), "oncall".to_string()])
+            .route("*", vec!["siem".to_string(), "audit".to_string()])
+            .build()
+            .unwrap();
+        assert_eq!(router.sinks_for("4f1c2a", Some("payments-api")), ["siem", "oncall", "audit"]);
+        assert_eq!(router.sinks_for("4f1c2a", None), ["siem", "audit"]);
+
+        let unknown = DetectionEventRouter::builder().route("*", vec!["siem".to_string()]).build();
+        assert!(unknown.is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    Docker,
+    Containerd,
+    Podman,
+}
+
+impl Runtime {
+    pub const ALL: [Runtime; 3] = [Runtime::Docker, Runtime::Containerd, Runtime::Podman];
+
+    pub fn socket(&self) -> &'static str {
+        match self {
+            Runtime::Docker => "/var/run/docker.sock",
+            Runtime::Containerd => "/run/containerd/containerd.sock",
+            Runtime::Podman => "/run/podman/podman.sock",
+        }
+    }
+
+    // The systemd cgroup driver puts each container in a `<prefix><id>.scope` under
+    // one of these slices.
+    pub fn cgroup_dir(&self) -> &'static str {
+        match self {
+            Runtime::Docker | Runtime::Containerd => "/sys/fs/cgroup/system.slice/",
+            Runtime::Podman => "/sys/fs/cgroup/machine.slice/",
+        }
+    }
+
+    pub fn scope_prefix(&self) -> &'static str {
+        match self {
+            Runtime::Docker => "docker-",
+            Runtime::Containerd => "nerdctl-",
+            Runtime::Podman => "libpod-",
+        }
+    }
+}
+
+impl fmt::Display for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Runtime::Docker => "Docker",
+            Runtime::Containerd => "containerd",
+            Runtime::Podman => "Podman",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DetectedRuntime {
+    pub runtime: Runtime,
+    // The API socket, if it exists.
+    pub socket: Option<String>,
+    // The cgroup directory, if it holds at least one of the runtime's containers.
+    pub cgroup_dir: Option<String>,
+}
+
+impl DetectedRuntime {
+    // A socket without containers is an idle runtime, containers without a socket
+    // usually a stopped daemon whose containers still run.
+    fn rank(&self) -> u8 {
+        match (&self.socket, &self.cgroup_dir) {
+            (Some(_), Some(_)) => 0,
+            (None, Some(_)) => 1,
+            _ => 2,
+        }
+    }
+}
+
+// Finds the container runtimes on this host from their API sockets and cgroup scopes,
+// so that the right cgroup directories get monitored without --cgroup-path.
+pub struct ContainerRuntimeDetector;
+
+impl ContainerRuntimeDetector {
+    // Runtimes with a socket or running containers, those with both first.
+    pub fn detect() -> Vec<DetectedRuntime> {
+        let mut detected: Vec<DetectedRuntime> = Runtime::ALL
+            .iter()
+            .map(|runtime| DetectedRuntime {
+                runtime: *runtime,
+                socket: Some(runtime.socket())
+                    .filter(|socket| is_socket(socket))
+                    .map(str::to_string),
+                cgroup_dir: Some(runtime.cgroup_dir())
+                    .filter(|dir| has_scope(dir, runtime.scope_prefix()))
+                    .map(str::to_string),
+            })
+            .filter(|found| found.socket.is_some() || found.cgroup_dir.is_some())
+            .collect();
+        // Stable, so equally ranked runtimes keep the order of Runtime::ALL.
+        detected.sort_by_key(DetectedRuntime::rank);
+        detected
+    }
+
+    // The cgroup directories to monitor for the detected runtimes: those holding
+    // containers, and otherwise the directory a runtime with a socket will use.
+    pub fn cgroup_paths(detected: &[DetectedRuntime]) -> Vec<String> {
+        let mut paths: Vec<String> = Vec::new();
+        for runtime in detected {
+            let dir = runtime.cgroup_dir.as_deref().unwrap_or(runtime.runtime.cgroup_dir());
+            if !paths.iter().any(|path| path == dir) && Path::new(dir).is_dir() {
+                paths.push(dir.to_string());
+            }
+        }
+        paths
+    }
+}
+
+fn is_socket(path: &str) -> bool {
+    std::fs::metadata(path).is_ok_and(|meta| meta.file_type().is_socket())
+}
+
+fn has_scope(dir: &str, prefix: &str) -> bool {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return false;
+    };
+    entries
+        .flatten()
+        .any(|entry| entry.file_name().to_str().is_some_and(|name| name.starts_with(prefix)))
+}
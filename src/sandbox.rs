@@ -0,0 +1,253 @@
+// --sandbox confines the detector with Landlock and a seccomp filter. Both are
+// inherited by every thread and child process, so apply() must run before the Tokio
+// runtime starts any threads. The docker, trivy and similar tools the detector runs
+// inherit the same restrictions, which is why the system binary and library
+// directories are readable too.
+
+use std::error::Error;
+use std::ffi::CString;
+use std::io;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::info;
+
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+// Access rights of Landlock ABI v1, which has 13 in total (bits 0-12).
+const ACCESS_EXECUTE: u64 = 1 << 0;
+const ACCESS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_READ_FILE: u64 = 1 << 2;
+const ACCESS_READ_DIR: u64 = 1 << 3;
+const ACCESS_MAKE_CHAR: u64 = 1 << 6;
+const ACCESS_MAKE_BLOCK: u64 = 1 << 11;
+
+const HANDLED: u64 = (1 << 13) - 1;
+const FILE_ACCESS: u64 = ACCESS_EXECUTE | ACCESS_WRITE_FILE | ACCESS_READ_FILE;
+const READ_ONLY: u64 = ACCESS_EXECUTE | ACCESS_READ_FILE | ACCESS_READ_DIR;
+const READ_WRITE: u64 = HANDLED & !(ACCESS_MAKE_CHAR | ACCESS_MAKE_BLOCK);
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+const SYSTEM_DIRS: [&str; 6] = ["/usr", "/bin", "/sbin", "/lib", "/lib64", "/etc"];
+
+// Syscalls a process monitor never needs and an attacker would want: loading kernel
+// code, changing mounts or namespaces, and tampering with other processes. This is a
+// deny list rather than an allow list because the filter also applies to the docker
+// and trivy processes the detector runs, whose syscall use is not under our control.
+const DENIED_SYSCALLS: [libc::c_long; 22] = [
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_writev,
+    libc::SYS_kexec_load,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_reboot,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_open_by_handle_at,
+    libc::SYS_userfaultfd,
+    libc::SYS_setns,
+    libc::SYS_unshare,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_keyctl,
+];
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+pub(crate) const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+pub fn apply(config: &Config) -> Result<(), Box<dyn Error>> {
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(format!("Failed to set no_new_privs: {}", io::Error::last_os_error()).into());
+    }
+    apply_landlock(config)?;
+    apply_seccomp()?;
+    info!("Sandbox enabled: Landlock filesystem rules and seccomp filter applied");
+    Ok(())
+}
+
+fn parent_dir(path: &str) -> String {
+    match Path::new(path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_string_lossy().into_owned(),
+        _ => ".".to_string(),
+    }
+}
+
+fn read_only_paths(config: &Config) -> Vec<String> {
+    let mut paths: Vec<String> = ["/proc", "/sys/fs/cgroup"].map(str::to_string).to_vec();
+    paths.extend(SYSTEM_DIRS.map(str::to_string));
+    paths.extend(config.cgroup_paths.iter().cloned());
+    paths.extend(config.bind_mount_proc.iter().cloned());
+    paths.extend(config.plugins.iter().cloned());
+    paths.extend(config.config_file.iter().cloned());
+    paths.extend(config.policy_file.iter().cloned());
+    paths.extend(config.cosign_key.iter().cloned());
+    paths.extend(config.tenants.iter().filter_map(|t| t.policy_file.clone()));
+    // SIGHUP execs the binary again when a new one was installed.
+    if let Ok(exe) = std::env::current_exe() {
+        paths.push(parent_dir(&exe.to_string_lossy()));
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        paths.push(format!("{}/.docker", home.to_string_lossy()));
+    }
+    paths
+}
+
+// Files are written by creating and renaming them, so their parent directories
+// are what needs write access.
+fn read_write_paths(config: &Config) -> Vec<String> {
+    let mut paths = vec![std::env::temp_dir().to_string_lossy().into_owned(), "/dev/null".to_string()];
+    paths.extend([&config.output_file, &config.state_file].into_iter().flatten().map(|p| parent_dir(p)));
+    // SQLite keeps its WAL and shared-memory files next to the database.
+    paths.extend(config.write_events_to_sqlite.as_deref().map(parent_dir));
+    paths.extend(config.forensics_dir.iter().cloned());
+    paths.extend(config.tenants.iter().filter_map(|t| t.log_file.as_deref()).map(parent_dir));
+    paths.push(parent_dir(&config.control_socket));
+    paths.push(parent_dir(&config.upgrade_state_file));
+    if config.trivy_scan {
+        if let Some(home) = std::env::var_os("HOME") {
+            paths.push(format!("{}/.cache", home.to_string_lossy()));
+        }
+    }
+    paths
+}
+
+fn apply_landlock(config: &Config) -> Result<(), Box<dyn Error>> {
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<RulesetAttr>(),
+            0,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    if abi < 1 {
+        return Err(format!("Landlock is not available: {}", io::Error::last_os_error()).into());
+    }
+
+    let attr = RulesetAttr { handled_access_fs: HANDLED };
+    let ruleset = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            std::mem::size_of::<RulesetAttr>(),
+            0,
+        )
+    };
+    if ruleset < 0 {
+        return Err(format!("Failed to create Landlock ruleset: {}", io::Error::last_os_error()).into());
+    }
+    let ruleset = ruleset as libc::c_int;
+
+    let rules = read_only_paths(config)
+        .into_iter()
+        .map(|p| (p, READ_ONLY))
+        .chain(read_write_paths(config).into_iter().map(|p| (p, READ_WRITE)));
+    let result = rules
+        .into_iter()
+        .try_for_each(|(path, access)| add_rule(ruleset, &path, access))
+        .and_then(|()| {
+            if unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0) } != 0 {
+                return Err(format!("Failed to enforce Landlock ruleset: {}", io::Error::last_os_error()).into());
+            }
+            Ok(())
+        });
+    unsafe { libc::close(ruleset) };
+    result
+}
+
+// Paths that do not exist are skipped, so optional directories need no special casing.
+fn add_rule(ruleset: libc::c_int, path: &str, access: u64) -> Result<(), Box<dyn Error>> {
+    let c_path = CString::new(path)?;
+    let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Ok(());
+    }
+
+    let is_dir = Path::new(path).is_dir();
+    let rule = PathBeneathAttr {
+        allowed_access: if is_dir { access } else { access & FILE_ACCESS },
+        parent_fd: fd,
+    };
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset,
+            LANDLOCK_RULE_PATH_BENEATH,
+            &rule as *const PathBeneathAttr,
+            0,
+        )
+    };
+    let err = io::Error::last_os_error();
+    unsafe { libc::close(fd) };
+    if result != 0 {
+        return Err(format!("Failed to add Landlock rule for {}: {}", path, err).into());
+    }
+    Ok(())
+}
+
+fn statement(code: u32, jt: u8, jf: u8, k: u32) -> libc::sock_filter {
+    libc::sock_filter { code: code as u16, jt, jf, k }
+}
+
+fn apply_seccomp() -> Result<(), Box<dyn Error>> {
+    let load = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+    let jump_eq = libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K;
+    let ret = libc::BPF_RET | libc::BPF_K;
+    let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+
+    // seccomp_data: nr at offset 0, arch at offset 4.
+    let mut program = vec![
+        statement(load, 0, 0, 4),
+        statement(jump_eq, 1, 0, AUDIT_ARCH),
+        statement(ret, 0, 0, deny),
+        statement(load, 0, 0, 0),
+    ];
+    // x32 syscalls share the x86_64 audit arch but have their own numbers.
+    #[cfg(target_arch = "x86_64")]
+    program.extend([
+        statement(libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K, 0, 1, 0x4000_0000),
+        statement(ret, 0, 0, deny),
+    ]);
+    for syscall in DENIED_SYSCALLS {
+        program.push(statement(jump_eq, 0, 1, syscall as u32));
+        program.push(statement(ret, 0, 0, deny));
+    }
+    program.push(statement(ret, 0, 0, libc::SECCOMP_RET_ALLOW));
+
+    let prog = libc::sock_fprog {
+        len: program.len() as u16,
+        filter: program.as_mut_ptr(),
+    };
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            0,
+            &prog as *const libc::sock_fprog,
+        )
+    };
+    if result != 0 {
+        return Err(format!("Failed to install seccomp filter: {}", io::Error::last_os_error()).into());
+    }
+    Ok(())
+}
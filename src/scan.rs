@@ -0,0 +1,144 @@
+use std::error::Error;
+use chrono::Utc;
+use tokio::process::Command;
+use tokio::task::JoinHandle;
+
+use crate::docker;
+use crate::{info, log};
+use crate::json::{self, Value};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vulnerability {
+    pub id: String,
+    pub package: String,
+    pub severity: String,
+}
+
+impl Vulnerability {
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("id".to_string(), self.id.as_str().into()),
+            ("package".to_string(), self.package.as_str().into()),
+            ("severity".to_string(), self.severity.as_str().into()),
+        ])
+    }
+
+    pub fn from_json(value: &Value) -> Option<Vulnerability> {
+        let string = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+        Some(Vulnerability {
+            id: string("id")?,
+            package: string("package").unwrap_or_default(),
+            severity: string("severity").unwrap_or_default(),
+        })
+    }
+}
+
+// A binary is novel when `docker diff` reports its path as added or changed
+// relative to the container's image.
+pub async fn is_novel_binary(container_id: &str, exe: &str) -> Result<bool, Box<dyn Error>> {
+    let changed = docker::changed_paths(container_id).await?;
+    Ok(changed.iter().any(|path| path == exe))
+}
+
+// Snapshots the container with `docker commit` and scans it with Trivy in the
+// background, so the configured action is not delayed by the scan itself.
+pub async fn start_scan(container_id: &str, exe: &str) -> Option<JoinHandle<Vec<Vulnerability>>> {
+    match is_novel_binary(container_id, exe).await {
+        Ok(true) => {}
+        Ok(false) => return None,
+        Err(e) => {
+            eprintln!("Failed to diff container {}: {}", log::id(container_id), e);
+            return None;
+        }
+    }
+
+    let image = format!(
+        "cnpd-scan/{}:{}",
+        container_id,
+        Utc::now().format("%Y%m%d%H%M%S%3f")
+    );
+    match docker::commit_container(container_id, &image).await {
+        Ok(true) => {}
+        _ => {
+            eprintln!("Failed to snapshot container {} for scanning", log::id(container_id));
+            return None;
+        }
+    }
+
+    let container_id = container_id.to_string();
+    Some(tokio::spawn(async move {
+        let result = scan_image(&image).await.map_err(|e| e.to_string());
+        if !docker::remove_image(&image).await.unwrap_or(false) {
+            eprintln!("Failed to remove scan snapshot image {}", image);
+        }
+        match result {
+            Ok(vulnerabilities) => {
+                info!(
+                    "Trivy found {} HIGH/CRITICAL vulnerabilities in {}",
+                    vulnerabilities.len(),
+                    log::id(&container_id)
+                );
+                vulnerabilities
+            }
+            Err(e) => {
+                eprintln!("Trivy scan of {} failed: {}", log::id(&container_id), e);
+                Vec::new()
+            }
+        }
+    }))
+}
+
+async fn scan_image(image: &str) -> Result<Vec<Vulnerability>, Box<dyn Error>> {
+    let archive = std::env::temp_dir().join(format!("{}.tar", image.replace(['/', ':'], "-")));
+    let archive = archive.to_string_lossy().into_owned();
+
+    if !docker::save_image(image, &archive).await? {
+        return Err(format!("docker save {} failed", image).into());
+    }
+
+    let output = Command::new("trivy")
+        .arg("image")
+        .arg("--quiet")
+        .arg("--format")
+        .arg("json")
+        .arg("--severity")
+        .arg("HIGH,CRITICAL")
+        .arg("--input")
+        .arg(&archive)
+        .output()
+        .await;
+    let _ = tokio::fs::remove_file(&archive).await;
+    let output = output?;
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string().into());
+    }
+    Ok(parse_trivy_report(&String::from_utf8_lossy(&output.stdout))?)
+}
+
+pub fn parse_trivy_report(report: &str) -> Result<Vec<Vulnerability>, String> {
+    let report = json::parse(report)?;
+    let mut vulnerabilities = Vec::new();
+
+    for result in report.get("Results").and_then(Value::as_array).into_iter().flatten() {
+        for vulnerability in result.get("Vulnerabilities").and_then(Value::as_array).into_iter().flatten() {
+            let string = |key: &str| {
+                vulnerability
+                    .get(key)
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let severity = string("Severity");
+            if severity == "HIGH" || severity == "CRITICAL" {
+                vulnerabilities.push(Vulnerability {
+                    id: string("VulnerabilityID"),
+                    package: string("PkgName"),
+                    severity,
+                });
+            }
+        }
+    }
+
+    Ok(vulnerabilities)
+}
@@ -0,0 +1,72 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use tokio::process::Command;
+
+// Outcome of checking a container image's signature with cosign.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureStatus {
+    Verified,
+    // Signatures exist but none of them verify.
+    Unverified,
+    NoSignature,
+}
+
+impl fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SignatureStatus::Verified => "verified",
+            SignatureStatus::Unverified => "unverified",
+            SignatureStatus::NoSignature => "no-signature",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for SignatureStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "verified" => Ok(SignatureStatus::Verified),
+            "unverified" => Ok(SignatureStatus::Unverified),
+            "no-signature" => Ok(SignatureStatus::NoSignature),
+            _ => Err(format!("Unknown signature status: {}", s)),
+        }
+    }
+}
+
+// Runs `cosign verify` on the image. With a key the signature must match it;
+// without one any keyless signature recorded in the transparency log is accepted.
+pub async fn verify_image(image: &str, key: Option<&str>, rekor_url: Option<&str>) -> Result<SignatureStatus, Box<dyn Error>> {
+    let mut command = Command::new("cosign");
+    command.arg("verify");
+    match key {
+        Some(key) => {
+            command.arg("--key").arg(key);
+        }
+        None => {
+            command.args(["--certificate-identity-regexp", ".*", "--certificate-oidc-issuer-regexp", ".*"]);
+        }
+    }
+    if let Some(url) = rekor_url {
+        command.arg("--rekor-url").arg(url);
+    }
+    let output = command
+        .arg(image)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run cosign: {}", e))?;
+    if output.status.success() {
+        return Ok(SignatureStatus::Verified);
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr).to_lowercase();
+    if stderr.contains("no signatures found") {
+        Ok(SignatureStatus::NoSignature)
+    } else if stderr.contains("no matching signatures") || stderr.contains("invalid signature") {
+        Ok(SignatureStatus::Unverified)
+    } else {
+        Err(format!("cosign verify {} failed: {}", image, stderr.trim()).into())
+    }
+}
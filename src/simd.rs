@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+
+// PIDs compared per step: four NEON registers of four 32-bit lanes.
+pub const LANES: usize = 16;
+// Above this a linear scan loses to hashing, so larger containers keep the HashSet. On
+// x86-64 the scan is clearly ahead up to 64 PIDs and about even by 96 to 128.
+pub const MAX_PIDS: usize = 64;
+// Fills the last block. No process has a negative PID.
+const PADDING: i32 = -1;
+
+// The known (PID, start time) keys of a container laid out for vectorized lookups.
+// PIDs are padded to a whole number of LANES blocks; starts have no padding.
+#[derive(Debug)]
+pub struct PidBlocks {
+    pids: Vec<i32>,
+    starts: Vec<u64>,
+}
+
+impl PidBlocks {
+    pub fn new(keys: &HashSet<(i32, u64)>) -> PidBlocks {
+        let mut pids = Vec::with_capacity(keys.len().next_multiple_of(LANES));
+        let mut starts = Vec::with_capacity(keys.len());
+        for (pid, start) in keys {
+            pids.push(*pid);
+            starts.push(*start);
+        }
+        pids.resize(pids.len().next_multiple_of(LANES), PADDING);
+        PidBlocks { pids, starts }
+    }
+
+    // Takes the first padding lane, or starts a new block. The caller keeps the keys
+    // distinct.
+    pub fn insert(&mut self, (pid, start): (i32, u64)) {
+        let lane = self.starts.len();
+        if lane == self.pids.len() {
+            self.pids.resize(lane + LANES, PADDING);
+        }
+        self.pids[lane] = pid;
+        self.starts.push(start);
+    }
+
+    // Whether `pid` is known at all, and whether it is known with this start time. A
+    // reused PID is known under several start times.
+    pub fn find(&self, pid: i32, start: u64) -> (bool, bool) {
+        let mut pid_known = false;
+        for (block, chunk) in self.pids.chunks_exact(LANES).enumerate() {
+            if !block_contains(chunk, pid) {
+                continue;
+            }
+            let base = block * LANES;
+            for (lane, _) in chunk.iter().enumerate().filter(|(_, p)| **p == pid) {
+                // Padding lanes have no start time.
+                if let Some(known_start) = self.starts.get(base + lane) {
+                    pid_known = true;
+                    if *known_start == start {
+                        return (true, true);
+                    }
+                }
+            }
+        }
+        (pid_known, false)
+    }
+}
+
+// `procfs::new_processes` for containers of up to MAX_PIDS known processes.
+pub fn new_processes(blocks: &PidBlocks, current: &HashSet<(i32, u64)>) -> Vec<(i32, u64)> {
+    let mut new: Vec<(i32, u64)> = current
+        .iter()
+        .filter(|(pid, start)| match blocks.find(*pid, *start) {
+            (_, true) => false,
+            (pid_known, false) => *start != 0 || !pid_known,
+        })
+        .copied()
+        .collect();
+    new.sort_unstable();
+    new
+}
+
+#[cfg(target_arch = "aarch64")]
+fn block_contains(chunk: &[i32], pid: i32) -> bool {
+    use std::arch::aarch64::{vceqq_s32, vdupq_n_s32, vld1q_s32_x4, vmaxvq_u32, vorrq_u32};
+
+    debug_assert_eq!(chunk.len(), LANES);
+    // NEON is part of the AArch64 baseline, and chunk holds the 16 lanes loaded.
+    unsafe {
+        let needle = vdupq_n_s32(pid);
+        let lanes = vld1q_s32_x4(chunk.as_ptr());
+        let hits = vorrq_u32(
+            vorrq_u32(vceqq_s32(lanes.0, needle), vceqq_s32(lanes.1, needle)),
+            vorrq_u32(vceqq_s32(lanes.2, needle), vceqq_s32(lanes.3, needle)),
+        );
+        vmaxvq_u32(hits) != 0
+    }
+}
+
+// Elsewhere a fixed-size loop without early exit, which the compiler vectorizes itself.
+#[cfg(not(target_arch = "aarch64"))]
+fn block_contains(chunk: &[i32], pid: i32) -> bool {
+    chunk.iter().fold(false, |found, p| found | (*p == pid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_pids_across_blocks() {
+        let known: HashSet<(i32, u64)> = (1..=40).map(|pid| (pid, pid as u64 * 10)).chain([(7, 999)]).collect();
+        let blocks = PidBlocks::new(&known);
+        assert_eq!(blocks.find(33, 330), (true, true));
+        assert_eq!(blocks.find(7, 999), (true, true));
+        assert_eq!(blocks.find(7, 5), (true, false));
+        assert_eq!(blocks.find(41, 410), (false, false));
+        assert_eq!(blocks.find(PADDING, 0), (false, false));
+
+        let mut grown = PidBlocks::new(&HashSet::new());
+        for pid in 1..=40 {
+            grown.insert((pid, pid as u64 * 10));
+        }
+        assert_eq!(grown.pids.len(), 48);
+        assert_eq!(grown.find(33, 330), (true, true));
+        assert_eq!(grown.find(40, 400), (true, true));
+        assert_eq!(grown.find(41, 410), (false, false));
+    }
+
+    #[test]
+    fn matches_hash_set_lookup() {
+        let known: HashSet<(i32, u64)> = [(1, 100), (1234, 500), (1300, 0)].into_iter().collect();
+        let current: HashSet<(i32, u64)> = [(1, 100), (1234, 900), (1300, 950), (1400, 0)].into_iter().collect();
+        assert_eq!(new_processes(&PidBlocks::new(&known), &current), vec![(1234, 900), (1300, 950), (1400, 0)]);
+    }
+}
@@ -0,0 +1,338 @@
+use std::error::Error;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::ptr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::event::DetectionEvent;
+use crate::info;
+use crate::json::Value;
+
+// libsqlite3 is loaded with dlopen the first time a database is opened, like plugins,
+// so that hosts without it only lose --write-events-to-sqlite and cnpd-ctl query.
+const LIBRARY: &str = "libsqlite3.so.0";
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_ROW: c_int = 100;
+const SQLITE_DONE: c_int = 101;
+const SQLITE_INTEGER: c_int = 1;
+const SQLITE_FLOAT: c_int = 2;
+const SQLITE_NULL: c_int = 5;
+const SQLITE_OPEN_READONLY: c_int = 0x1;
+const SQLITE_OPEN_READWRITE: c_int = 0x2;
+const SQLITE_OPEN_CREATE: c_int = 0x4;
+// Tells sqlite3_bind_text to copy the string before the call returns.
+const SQLITE_TRANSIENT: isize = -1;
+// Readers and the writer share a WAL database; a checkpoint can still hold the lock briefly.
+const BUSY_TIMEOUT_MS: c_int = 5000;
+
+const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY,
+    container_id TEXT,
+    pid INT,
+    exe_path TEXT,
+    cmdline TEXT,
+    detected_at TEXT,
+    action_taken TEXT,
+    score REAL
+)";
+const INSERT_EVENT: &str = "INSERT INTO events (container_id, pid, exe_path, cmdline, detected_at, action_taken, score)
+    VALUES (?, ?, ?, ?, ?, ?, ?)";
+
+struct Api {
+    open_v2: unsafe extern "C" fn(*const c_char, *mut *mut c_void, c_int, *const c_char) -> c_int,
+    close_v2: unsafe extern "C" fn(*mut c_void) -> c_int,
+    errmsg: unsafe extern "C" fn(*mut c_void) -> *const c_char,
+    busy_timeout: unsafe extern "C" fn(*mut c_void, c_int) -> c_int,
+    prepare_v2: unsafe extern "C" fn(*mut c_void, *const c_char, c_int, *mut *mut c_void, *mut *const c_char) -> c_int,
+    bind_text: unsafe extern "C" fn(*mut c_void, c_int, *const c_char, c_int, isize) -> c_int,
+    bind_int64: unsafe extern "C" fn(*mut c_void, c_int, i64) -> c_int,
+    bind_double: unsafe extern "C" fn(*mut c_void, c_int, f64) -> c_int,
+    bind_null: unsafe extern "C" fn(*mut c_void, c_int) -> c_int,
+    step: unsafe extern "C" fn(*mut c_void) -> c_int,
+    finalize: unsafe extern "C" fn(*mut c_void) -> c_int,
+    column_count: unsafe extern "C" fn(*mut c_void) -> c_int,
+    column_name: unsafe extern "C" fn(*mut c_void, c_int) -> *const c_char,
+    column_type: unsafe extern "C" fn(*mut c_void, c_int) -> c_int,
+    column_text: unsafe extern "C" fn(*mut c_void, c_int) -> *const c_char,
+    column_int64: unsafe extern "C" fn(*mut c_void, c_int) -> i64,
+    column_double: unsafe extern "C" fn(*mut c_void, c_int) -> f64,
+}
+
+// Reinterprets a dlsym result as the function pointer type of the Api field it fills.
+unsafe fn function_pointer<F: Copy>(symbol: *mut c_void) -> F {
+    assert_eq!(std::mem::size_of::<F>(), std::mem::size_of::<*mut c_void>());
+    std::mem::transmute_copy(&symbol)
+}
+
+fn load_api() -> Result<Api, String> {
+    let library = CString::new(LIBRARY).unwrap();
+    let handle = unsafe { libc::dlopen(library.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    if handle.is_null() {
+        return Err(format!("Failed to load {}", LIBRARY));
+    }
+    macro_rules! symbol {
+        ($name:literal) => {{
+            let name = CString::new($name).unwrap();
+            let symbol = unsafe { libc::dlsym(handle, name.as_ptr()) };
+            if symbol.is_null() {
+                return Err(format!("{} does not export {}", LIBRARY, $name));
+            }
+            unsafe { function_pointer(symbol) }
+        }};
+    }
+    Ok(Api {
+        open_v2: symbol!("sqlite3_open_v2"),
+        close_v2: symbol!("sqlite3_close_v2"),
+        errmsg: symbol!("sqlite3_errmsg"),
+        busy_timeout: symbol!("sqlite3_busy_timeout"),
+        prepare_v2: symbol!("sqlite3_prepare_v2"),
+        bind_text: symbol!("sqlite3_bind_text"),
+        bind_int64: symbol!("sqlite3_bind_int64"),
+        bind_double: symbol!("sqlite3_bind_double"),
+        bind_null: symbol!("sqlite3_bind_null"),
+        step: symbol!("sqlite3_step"),
+        finalize: symbol!("sqlite3_finalize"),
+        column_count: symbol!("sqlite3_column_count"),
+        column_name: symbol!("sqlite3_column_name"),
+        column_type: symbol!("sqlite3_column_type"),
+        column_text: symbol!("sqlite3_column_text"),
+        column_int64: symbol!("sqlite3_column_int64"),
+        column_double: symbol!("sqlite3_column_double"),
+    })
+}
+
+fn api() -> Result<&'static Api, String> {
+    static API: OnceLock<Result<Api, String>> = OnceLock::new();
+    API.get_or_init(load_api).as_ref().map_err(Clone::clone)
+}
+
+fn c_str(s: *const c_char) -> String {
+    if s.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned()
+}
+
+// One connection, used from one thread at a time.
+pub struct Database {
+    api: &'static Api,
+    db: *mut c_void,
+}
+
+unsafe impl Send for Database {}
+
+impl Database {
+    fn open_with(path: &str, flags: c_int) -> Result<Database, String> {
+        let api = api()?;
+        let c_path = CString::new(path).map_err(|e| e.to_string())?;
+        let mut db = ptr::null_mut();
+        let rc = unsafe { (api.open_v2)(c_path.as_ptr(), &mut db, flags, ptr::null()) };
+        // A handle is returned even on failure, carrying the error message.
+        let database = Database { api, db };
+        if rc != SQLITE_OK {
+            return Err(format!("Failed to open {}: {}", path, database.error()));
+        }
+        unsafe { (api.busy_timeout)(db, BUSY_TIMEOUT_MS) };
+        Ok(database)
+    }
+
+    pub fn open(path: &str) -> Result<Database, String> {
+        Database::open_with(path, SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE)
+    }
+
+    pub fn open_read_only(path: &str) -> Result<Database, String> {
+        Database::open_with(path, SQLITE_OPEN_READONLY)
+    }
+
+    fn error(&self) -> String {
+        c_str(unsafe { (self.api.errmsg)(self.db) })
+    }
+
+    // Runs one statement with `params` bound to its `?` placeholders and returns its
+    // rows as JSON objects keyed by column name.
+    pub fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Value>, String> {
+        let c_sql = CString::new(sql).map_err(|e| e.to_string())?;
+        let mut stmt = ptr::null_mut();
+        let rc = unsafe { (self.api.prepare_v2)(self.db, c_sql.as_ptr(), -1, &mut stmt, ptr::null_mut()) };
+        if rc != SQLITE_OK {
+            return Err(self.error());
+        }
+        let result = self.run(stmt, params);
+        unsafe { (self.api.finalize)(stmt) };
+        result
+    }
+
+    pub fn execute(&self, sql: &str, params: &[Value]) -> Result<(), String> {
+        self.query(sql, params).map(|_| ())
+    }
+
+    fn run(&self, stmt: *mut c_void, params: &[Value]) -> Result<Vec<Value>, String> {
+        let api = self.api;
+        for (i, param) in params.iter().enumerate() {
+            let index = i as c_int + 1;
+            let rc = unsafe {
+                match param {
+                    Value::Null => (api.bind_null)(stmt, index),
+                    Value::Bool(b) => (api.bind_int64)(stmt, index, *b as i64),
+                    Value::Number(n) if n.fract() == 0.0 => (api.bind_int64)(stmt, index, *n as i64),
+                    Value::Number(n) => (api.bind_double)(stmt, index, *n),
+                    Value::String(s) => {
+                        (api.bind_text)(stmt, index, s.as_ptr().cast(), s.len() as c_int, SQLITE_TRANSIENT)
+                    }
+                    other => {
+                        let text = other.to_string();
+                        (api.bind_text)(stmt, index, text.as_ptr().cast(), text.len() as c_int, SQLITE_TRANSIENT)
+                    }
+                }
+            };
+            if rc != SQLITE_OK {
+                return Err(self.error());
+            }
+        }
+
+        let mut rows = Vec::new();
+        loop {
+            match unsafe { (api.step)(stmt) } {
+                SQLITE_ROW => {}
+                SQLITE_DONE => return Ok(rows),
+                _ => return Err(self.error()),
+            }
+            let columns = unsafe { (api.column_count)(stmt) };
+            let row = (0..columns)
+                .map(|i| {
+                    let name = c_str(unsafe { (api.column_name)(stmt, i) });
+                    let value = unsafe {
+                        match (api.column_type)(stmt, i) {
+                            SQLITE_NULL => Value::Null,
+                            SQLITE_INTEGER => Value::Number((api.column_int64)(stmt, i) as f64),
+                            SQLITE_FLOAT => Value::Number((api.column_double)(stmt, i)),
+                            _ => Value::String(c_str((api.column_text)(stmt, i))),
+                        }
+                    };
+                    (name, value)
+                })
+                .collect();
+            rows.push(Value::Object(row));
+        }
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        unsafe { (self.api.close_v2)(self.db) };
+    }
+}
+
+// Stores detections in a local SQLite database, for hosts without a SIEM or Redis to
+// send them to. Inserts happen on a background thread, one transaction per batch of
+// queued events, so a slow disk never holds up a monitoring task.
+pub struct SqliteEventStore {
+    sender: Mutex<Option<Sender<DetectionEvent>>>,
+    thread: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl SqliteEventStore {
+    pub fn open(path: &str) -> Result<SqliteEventStore, Box<dyn Error>> {
+        let db = Database::open(path)?;
+        // WAL lets cnpd-ctl query read while events are being written.
+        db.query("PRAGMA journal_mode=WAL", &[])?;
+        db.execute(CREATE_TABLE, &[])?;
+        info!("Writing events to SQLite database {}", path);
+
+        let (sender, receiver) = mpsc::channel();
+        let path = path.to_string();
+        let thread = thread::Builder::new()
+            .name("cnpd-sqlite".to_string())
+            .spawn(move || run(&db, &path, &receiver))?;
+        Ok(SqliteEventStore {
+            sender: Mutex::new(Some(sender)),
+            thread: Mutex::new(Some(thread)),
+        })
+    }
+
+    pub fn send(&self, event: &DetectionEvent) {
+        if let Some(sender) = self.sender.lock().unwrap().as_ref() {
+            let _ = sender.send(event.clone());
+        }
+    }
+
+    // Waits up to `timeout` for queued events to be written.
+    pub fn shutdown(&self, timeout: Duration) {
+        self.sender.lock().unwrap().take();
+        let deadline = Instant::now() + timeout;
+        let Some(thread) = self.thread.lock().unwrap().take() else {
+            return;
+        };
+        while !thread.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+fn run(db: &Database, path: &str, receiver: &Receiver<DetectionEvent>) {
+    while let Ok(first) = receiver.recv() {
+        let mut batch = vec![first];
+        batch.extend(receiver.try_iter());
+        if let Err(e) = insert(db, &batch) {
+            eprintln!("Failed to write {} events to {}: {}", batch.len(), path, e);
+            let _ = db.execute("ROLLBACK", &[]);
+        }
+    }
+}
+
+fn insert(db: &Database, events: &[DetectionEvent]) -> Result<(), String> {
+    db.execute("BEGIN", &[])?;
+    for event in events {
+        db.execute(
+            INSERT_EVENT,
+            &[
+                event.container_id.as_str().into(),
+                event.pid.into(),
+                event.exe.clone().into(),
+                event.cmdline.clone().into(),
+                event.detected_at.as_str().into(),
+                event.action.to_string().into(),
+                // The risk score of the container, NULL for one without a risk assessment.
+                event.risk_score.map(f64::from).into(),
+            ],
+        )?;
+    }
+    db.execute("COMMIT", &[])
+}
+
+// Filters for `cnpd-ctl query`; unset ones match every event.
+#[derive(Debug, Default)]
+pub struct EventQuery {
+    // A full container ID or a prefix of one, such as the short ID.
+    pub container: Option<String>,
+    // Events detected at or after this local time, in the detected_at format.
+    pub since: Option<String>,
+    pub action: Option<String>,
+}
+
+impl EventQuery {
+    pub fn run(&self, path: &str) -> Result<Vec<Value>, String> {
+        let mut sql = String::from(
+            "SELECT id, container_id, pid, exe_path, cmdline, detected_at, action_taken, score FROM events WHERE 1",
+        );
+        let mut params = Vec::new();
+        if let Some(container) = &self.container {
+            sql.push_str(" AND substr(container_id, 1, length(?)) = ?");
+            params.push(container.as_str().into());
+            params.push(container.as_str().into());
+        }
+        if let Some(since) = &self.since {
+            sql.push_str(" AND detected_at >= ?");
+            params.push(since.as_str().into());
+        }
+        if let Some(action) = &self.action {
+            sql.push_str(" AND action_taken = ?");
+            params.push(action.as_str().into());
+        }
+        sql.push_str(" ORDER BY id");
+        Database::open_read_only(path)?.query(&sql, &params)
+    }
+}
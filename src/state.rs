@@ -0,0 +1,247 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::fs;
+use tokio::sync::Mutex;
+
+use crate::json::{self, Value};
+use crate::procfs;
+
+// A process that was part of a container's baseline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BaselineProcess {
+    pub pid: i32,
+    pub exe: Option<String>,
+    pub cmdline: Option<String>,
+}
+
+impl BaselineProcess {
+    pub async fn read(pid: i32) -> BaselineProcess {
+        BaselineProcess {
+            pid,
+            exe: procfs::read_exe(pid).await,
+            cmdline: procfs::read_cmdline(pid).await,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("pid".to_string(), self.pid.into()),
+            ("exe".to_string(), self.exe.clone().into()),
+            ("cmdline".to_string(), self.cmdline.clone().into()),
+        ])
+    }
+
+    fn from_json(value: &Value) -> Option<BaselineProcess> {
+        let string = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+        Some(BaselineProcess {
+            pid: value.get("pid")?.as_i64()? as i32,
+            exe: string("exe"),
+            cmdline: string("cmdline"),
+        })
+    }
+}
+
+// The process set a container was whitelisted with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Baseline {
+    pub container_id: String,
+    pub procs_path: String,
+    pub processes: Vec<BaselineProcess>,
+}
+
+impl Baseline {
+    fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("container_id".to_string(), self.container_id.as_str().into()),
+            ("procs_path".to_string(), self.procs_path.as_str().into()),
+            (
+                "processes".to_string(),
+                Value::Array(self.processes.iter().map(BaselineProcess::to_json).collect()),
+            ),
+        ])
+    }
+
+    fn from_json(value: &Value) -> Option<Baseline> {
+        Some(Baseline {
+            container_id: value.get("container_id")?.as_str()?.to_string(),
+            procs_path: value.get("procs_path")?.as_str()?.to_string(),
+            processes: value
+                .get("processes")?
+                .as_array()?
+                .iter()
+                .filter_map(BaselineProcess::from_json)
+                .collect(),
+        })
+    }
+}
+
+// Version written into new state files. Bump it together with a new entry in
+// MIGRATIONS whenever the layout changes.
+pub const STATE_VERSION: u32 = 1;
+
+type Migration = fn(Value) -> Result<Value, String>;
+
+// MIGRATIONS[n] upgrades a state file from version n to version n + 1.
+const MIGRATIONS: [Migration; STATE_VERSION as usize] = [migrate_unversioned];
+
+// Files written before versioning have no version field; their layout is version 1's.
+fn migrate_unversioned(state: Value) -> Result<Value, String> {
+    Ok(state)
+}
+
+fn state_version(state: &Value) -> u32 {
+    state.get("version").and_then(Value::as_i64).map_or(0, |v| v as u32)
+}
+
+fn check_supported(path: &str, version: u32) -> Result<(), String> {
+    if version > STATE_VERSION {
+        return Err(format!(
+            "State file {} has version {}, but this build only supports up to version {}",
+            path, version, STATE_VERSION
+        ));
+    }
+    Ok(())
+}
+
+// The state file as a whole: `{"version": 1, "baselines": [...]}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateFile {
+    pub version: u32,
+    pub baselines: Vec<Baseline>,
+}
+
+impl StateFile {
+    pub fn new(baselines: Vec<Baseline>) -> StateFile {
+        StateFile {
+            version: STATE_VERSION,
+            baselines,
+        }
+    }
+
+    pub async fn save(&self, path: &str) -> std::io::Result<()> {
+        let state = Value::Object(vec![
+            ("version".to_string(), self.version.into()),
+            (
+                "baselines".to_string(),
+                Value::Array(self.baselines.iter().map(Baseline::to_json).collect()),
+            ),
+        ]);
+        // Write to a temporary file first so readers never see a partial state file. Its
+        // name is unique, as a migrating cnpd-ctl or another daemon may be saving too.
+        static SAVES: AtomicU64 = AtomicU64::new(0);
+        let tmp = format!("{}.{}.{}.tmp", path, std::process::id(), SAVES.fetch_add(1, Ordering::Relaxed));
+        fs::write(&tmp, format!("{}\n", state)).await?;
+        fs::rename(&tmp, path).await
+    }
+
+    // Files from older versions are migrated and saved back in the current format.
+    pub async fn load(path: &str) -> Result<StateFile, Box<dyn Error>> {
+        let content = fs::read_to_string(path).await?;
+        let mut state = json::parse(&content).map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+        let stored = state_version(&state);
+        check_supported(path, stored)?;
+        for migration in &MIGRATIONS[stored as usize..] {
+            state = migration(state).map_err(|e| format!("Failed to migrate {}: {}", path, e))?;
+        }
+
+        let file = StateFile::new(
+            state
+                .get("baselines")
+                .and_then(Value::as_array)
+                .map(|v| v.iter().filter_map(Baseline::from_json).collect())
+                .unwrap_or_default(),
+        );
+        if stored < STATE_VERSION {
+            file.save(path).await?;
+        }
+        Ok(file)
+    }
+}
+
+// Fails if the file at `path` was written by a newer version. A missing or
+// unreadable file passes, as it is about to be overwritten anyway.
+pub async fn check_version(path: &str) -> Result<(), Box<dyn Error>> {
+    let Ok(content) = fs::read_to_string(path).await else {
+        return Ok(());
+    };
+    match json::parse(&content) {
+        Ok(state) => Ok(check_supported(path, state_version(&state))?),
+        Err(_) => Ok(()),
+    }
+}
+
+// The daemon's state file. Every change is a load, modify and save of the whole file,
+// made under one lock: monitoring tasks exiting together, as on docker compose down,
+// would otherwise save over each other's removals.
+pub struct StateStore {
+    path: String,
+    lock: Mutex<()>,
+}
+
+impl StateStore {
+    pub fn new(path: &str) -> StateStore {
+        StateStore {
+            path: path.to_string(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub async fn save_baselines(&self, baselines: &[Baseline]) -> std::io::Result<()> {
+        let _lock = self.lock.lock().await;
+        StateFile::new(baselines.to_vec()).save(&self.path).await
+    }
+
+    // Drops a container's baseline from the state file, if the file has one.
+    pub async fn remove_baseline(&self, container_id: &str) -> Result<(), Box<dyn Error>> {
+        let _lock = self.lock.lock().await;
+        let mut file = StateFile::load(&self.path).await?;
+        let before = file.baselines.len();
+        file.baselines.retain(|baseline| baseline.container_id != container_id);
+        if file.baselines.len() != before {
+            file.save(&self.path).await?;
+        }
+        Ok(())
+    }
+}
+
+pub async fn load_baselines(path: &str) -> Result<Vec<Baseline>, Box<dyn Error>> {
+    Ok(StateFile::load(path).await?.baselines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_removals_are_all_kept() {
+        let dir = std::env::temp_dir().join(format!("cnpd-state-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("state.json").to_string_lossy().into_owned();
+        let baseline = |id: usize| Baseline {
+            container_id: format!("c{}", id),
+            procs_path: format!("/sys/fs/cgroup/c{}/cgroup.procs", id),
+            processes: Vec::new(),
+        };
+        let store = Arc::new(StateStore::new(&path));
+        store.save_baselines(&(0..17).map(baseline).collect::<Vec<_>>()).await.unwrap();
+
+        let removals: Vec<_> = (0..16)
+            .map(|id| {
+                let store = store.clone();
+                tokio::spawn(async move { store.remove_baseline(&format!("c{}", id)).await.map_err(|e| e.to_string()) })
+            })
+            .collect();
+        for removal in removals {
+            removal.await.unwrap().unwrap();
+        }
+
+        assert_eq!(load_baselines(&path).await.unwrap(), vec![baseline(16)]);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
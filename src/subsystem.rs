@@ -0,0 +1,200 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Instant;
+use tokio::fs;
+
+// Samples are only compared to a baseline once there are this many of them.
+const MIN_SAMPLES: u32 = 5;
+// A rate this many times the baseline is a spike.
+const SPIKE_FACTOR: f64 = 10.0;
+// Weight of a new sample in the baseline, an exponentially weighted moving average.
+const BASELINE_WEIGHT: f64 = 0.1;
+// Below these no rate is a spike: a tenth of a CPU, and 10 MiB/s.
+const MIN_CPU_SPIKE: f64 = 100_000.0;
+const MIN_IO_SPIKE: f64 = 10.0 * 1024.0 * 1024.0;
+
+// A cgroup controller whose files are watched with --monitor-cgroup-subsystems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgroupSubsystem {
+    // usage_usec in cpu.stat.
+    Cpu,
+    // The bytes read and written in io.stat, over all devices.
+    Io,
+    // net_cls.classid, the class network policies match the container's traffic by.
+    NetCls,
+}
+
+impl fmt::Display for CgroupSubsystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CgroupSubsystem::Cpu => "cpu",
+            CgroupSubsystem::Io => "io",
+            CgroupSubsystem::NetCls => "net_cls",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for CgroupSubsystem {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cpu" => Ok(CgroupSubsystem::Cpu),
+            "io" => Ok(CgroupSubsystem::Io),
+            "net_cls" => Ok(CgroupSubsystem::NetCls),
+            _ => Err(format!("Unknown cgroup subsystem: {}", s)),
+        }
+    }
+}
+
+// What a sample of the subsystem files found.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubsystemChange {
+    // CPU microseconds or I/O bytes per second, and the usual rate they are far above.
+    CpuSpike { rate: f64, baseline: f64 },
+    IoSpike { rate: f64, baseline: f64 },
+    NetClassChange { from: u32, to: u32 },
+}
+
+// A counter's usual rate of increase. Spikes are left out of it, so that a sustained
+// spike keeps being one.
+#[derive(Debug, Clone, Default)]
+pub struct RateBaseline {
+    mean: f64,
+    samples: u32,
+}
+
+impl RateBaseline {
+    // Returns the baseline `rate` is a spike against, if it is one.
+    pub fn observe(&mut self, rate: f64, min_spike: f64) -> Option<f64> {
+        let baseline = self.mean;
+        if self.samples >= MIN_SAMPLES && rate >= min_spike && rate > baseline * SPIKE_FACTOR {
+            return Some(baseline);
+        }
+        self.mean = match self.samples {
+            0 => rate,
+            _ => baseline + BASELINE_WEIGHT * (rate - baseline),
+        };
+        self.samples += 1;
+        None
+    }
+}
+
+// A cumulative counter, sampled for its rate of increase.
+#[derive(Debug, Default)]
+struct Counter {
+    last: Option<(Instant, u64)>,
+    baseline: RateBaseline,
+    spiking: bool,
+}
+
+impl Counter {
+    // The spike and its baseline when the rate first goes above it, once per spike.
+    fn sample(&mut self, value: u64, now: Instant, min_spike: f64) -> Option<(f64, f64)> {
+        let (last_at, last) = self.last.replace((now, value))?;
+        let elapsed = now.duration_since(last_at).as_secs_f64();
+        if elapsed <= 0.0 {
+            return None;
+        }
+        // A counter going back means the cgroup was recreated.
+        let rate = value.saturating_sub(last) as f64 / elapsed;
+        let spike = self.baseline.observe(rate, min_spike);
+        let first = spike.is_some() && !self.spiking;
+        self.spiking = spike.is_some();
+        spike.filter(|_| first).map(|baseline| (rate, baseline))
+    }
+}
+
+pub fn cpu_usage(cpu_stat: &str) -> Option<u64> {
+    cpu_stat.lines().find_map(|line| line.strip_prefix("usage_usec ")?.trim().parse().ok())
+}
+
+// Lines like `8:0 rbytes=1024 wbytes=0 rios=1 wios=0 dbytes=0 dios=0`.
+pub fn io_bytes(io_stat: &str) -> u64 {
+    io_stat
+        .split_whitespace()
+        .filter_map(|field| field.strip_prefix("rbytes=").or_else(|| field.strip_prefix("wbytes=")))
+        .filter_map(|bytes| bytes.parse::<u64>().ok())
+        .sum()
+}
+
+// The subsystem files of one container, sampled from its monitoring task. Files the
+// cgroup does not have, like net_cls.classid under cgroup v2, are skipped.
+pub struct SubsystemMonitor {
+    subsystems: Vec<CgroupSubsystem>,
+    cpu: Counter,
+    io: Counter,
+    classid: Option<u32>,
+}
+
+impl SubsystemMonitor {
+    pub fn new(subsystems: &[CgroupSubsystem]) -> SubsystemMonitor {
+        SubsystemMonitor {
+            subsystems: subsystems.to_vec(),
+            cpu: Counter::default(),
+            io: Counter::default(),
+            classid: None,
+        }
+    }
+
+    pub async fn sample(&mut self, cgroup_path: &str) -> Vec<SubsystemChange> {
+        let now = Instant::now();
+        let mut changes = Vec::new();
+        for subsystem in self.subsystems.clone() {
+            let read = |file: &str| fs::read_to_string(format!("{}/{}", cgroup_path, file));
+            match subsystem {
+                CgroupSubsystem::Cpu => {
+                    let Some(usage) = read("cpu.stat").await.ok().as_deref().and_then(cpu_usage) else {
+                        continue;
+                    };
+                    if let Some((rate, baseline)) = self.cpu.sample(usage, now, MIN_CPU_SPIKE) {
+                        changes.push(SubsystemChange::CpuSpike { rate, baseline });
+                    }
+                }
+                CgroupSubsystem::Io => {
+                    let Ok(stat) = read("io.stat").await else {
+                        continue;
+                    };
+                    if let Some((rate, baseline)) = self.io.sample(io_bytes(&stat), now, MIN_IO_SPIKE) {
+                        changes.push(SubsystemChange::IoSpike { rate, baseline });
+                    }
+                }
+                CgroupSubsystem::NetCls => {
+                    let Some(classid) = read("net_cls.classid").await.ok().and_then(|c| c.trim().parse().ok()) else {
+                        continue;
+                    };
+                    // Every change is reported.
+                    match self.classid.replace(classid) {
+                        Some(from) if from != classid => changes.push(SubsystemChange::NetClassChange { from, to: classid }),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn reports_a_spike_once() {
+        let start = Instant::now();
+        let mut counter = Counter::default();
+        let mut usage = 0;
+        let mut spikes = Vec::new();
+        for second in 0..12u64 {
+            usage += if (8..10).contains(&second) { 5_000_000 } else { 50_000 };
+            spikes.push(counter.sample(usage, start + Duration::from_secs(second), MIN_CPU_SPIKE));
+        }
+        assert_eq!(spikes.iter().flatten().count(), 1);
+        assert_eq!(spikes[8], Some((5_000_000.0, 50_000.0)));
+
+        assert_eq!(cpu_usage("usage_usec 1234\nuser_usec 1000\n"), Some(1234));
+        assert_eq!(io_bytes("8:0 rbytes=1024 wbytes=512 rios=1\n253:0 rbytes=1 wbytes=0\n"), 1537);
+    }
+}
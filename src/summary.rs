@@ -0,0 +1,160 @@
+use std::error::Error;
+use std::fmt::Write as _;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::time::Duration;
+use chrono::Local;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::inventory::{ContainerStatus, Inventory};
+use crate::json::Value;
+use crate::metrics::Metrics;
+use crate::{docker, webhook};
+
+const TOP_CONTAINERS: usize = 5;
+
+// Where --report-interval digests are sent, from `--report-notify <kind>:<target>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notifier {
+    // The JSON summary is POSTed as is.
+    Webhook(String),
+    // A Slack incoming webhook, which takes the text digest as `{"text": ...}`.
+    Slack(String),
+    // Mailed with the local `sendmail -t`.
+    Email(String),
+}
+
+impl FromStr for Notifier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("webhook", url)) => Ok(Notifier::Webhook(url.to_string())),
+            Some(("slack", url)) => Ok(Notifier::Slack(url.to_string())),
+            Some(("email", address)) => Ok(Notifier::Email(address.to_string())),
+            _ => Err(format!("Unknown notifier {}: expected webhook:<url>, slack:<url> or email:<address>", s)),
+        }
+    }
+}
+
+impl Notifier {
+    pub async fn send(&self, summary: &SummaryReport) -> Result<(), Box<dyn Error>> {
+        let (url, body) = match self {
+            Notifier::Webhook(url) => (url, summary.to_json().to_string()),
+            Notifier::Slack(url) => (url, Value::Object(vec![("text".to_string(), summary.to_text().into())]).to_string()),
+            Notifier::Email(address) => return send_mail(address, summary).await,
+        };
+        let response = webhook::post_json(url, &body).await?;
+        if !response.is_success() {
+            return Err(format!("{} answered with HTTP {}", url, response.status).into());
+        }
+        Ok(())
+    }
+}
+
+async fn send_mail(address: &str, summary: &SummaryReport) -> Result<(), Box<dyn Error>> {
+    let message = format!(
+        "To: {}\nSubject: cnpd summary {}\nContent-Type: text/plain; charset=utf-8\n\n{}",
+        address,
+        summary.generated_at,
+        summary.to_text()
+    );
+    let mut child = Command::new("sendmail").arg("-t").stdin(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(message.as_bytes()).await?;
+    }
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(format!("sendmail exited with {}", status).into());
+    }
+    Ok(())
+}
+
+// The periodic digest of --report-interval. Counts are totals since the daemon, or for
+// a container since its monitoring task, started.
+#[derive(Debug, Clone)]
+pub struct SummaryReport {
+    pub generated_at: String,
+    pub interval: Duration,
+    pub containers: Vec<ContainerStatus>,
+    pub detections: u64,
+    pub blocked: u64,
+    pub allowed: u64,
+    pub average_restart_ms: Option<f64>,
+}
+
+impl SummaryReport {
+    pub fn collect(inventory: &Inventory, metrics: &Metrics, interval: Duration) -> SummaryReport {
+        let containers = inventory.snapshot();
+        let restarts = metrics.restart_duration();
+        SummaryReport {
+            generated_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            interval,
+            detections: containers.iter().map(|c| c.detections).sum(),
+            containers,
+            blocked: metrics.blocked_total(),
+            allowed: metrics.allowed_total(),
+            average_restart_ms: (!restarts.is_empty()).then(|| restarts.sum() as f64 / restarts.len() as f64),
+        }
+    }
+
+    // Containers with detections, most detections first.
+    pub fn top_detected(&self) -> Vec<&ContainerStatus> {
+        let mut detected: Vec<&ContainerStatus> = self.containers.iter().filter(|c| c.detections > 0).collect();
+        detected.sort_by(|a, b| b.detections.cmp(&a.detections).then(a.container_id.cmp(&b.container_id)));
+        detected.truncate(TOP_CONTAINERS);
+        detected
+    }
+
+    pub fn to_json(&self) -> Value {
+        let container = |status: &ContainerStatus| {
+            Value::Object(vec![
+                ("container_id".to_string(), status.container_id.as_str().into()),
+                ("name".to_string(), status.name.clone().into()),
+                ("detections".to_string(), status.detections.into()),
+                ("uptime_secs".to_string(), status.uptime.as_secs().into()),
+            ])
+        };
+        Value::Object(vec![
+            ("generated_at".to_string(), self.generated_at.as_str().into()),
+            ("interval_secs".to_string(), self.interval.as_secs().into()),
+            ("detections".to_string(), self.detections.into()),
+            ("blocked".to_string(), self.blocked.into()),
+            ("allowed".to_string(), self.allowed.into()),
+            ("average_restart_ms".to_string(), self.average_restart_ms.into()),
+            (
+                "top_containers".to_string(),
+                Value::Array(self.top_detected().into_iter().map(container).collect()),
+            ),
+            ("containers".to_string(), Value::Array(self.containers.iter().map(container).collect())),
+        ])
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = format!(
+            "cnpd summary at {}: {} detections in {} containers, {} processes blocked and {} allowed",
+            self.generated_at,
+            self.detections,
+            self.containers.len(),
+            self.blocked,
+            self.allowed
+        );
+        if let Some(average) = self.average_restart_ms {
+            let _ = write!(out, ", average restart {:.0} ms", average);
+        }
+        out.push('\n');
+        let top = self.top_detected();
+        if !top.is_empty() {
+            out.push_str("Most detections:\n");
+            for status in top {
+                let _ = writeln!(out, "  {} {}", docker::short_id(&status.container_id), status.detections);
+            }
+        }
+        out.push_str("Monitored for:\n");
+        for status in &self.containers {
+            let _ = writeln!(out, "  {} {}s", docker::short_id(&status.container_id), status.uptime.as_secs());
+        }
+        out
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+use std::ffi::CStr;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use chrono::{Local, SecondsFormat};
+
+use crate::event::{DetectionEvent, EventKind};
+use crate::info;
+
+pub const MAX_QUEUED: usize = 10_000;
+const WARN_QUEUED: usize = MAX_QUEUED * 8 / 10;
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// Facility 4 (security/authorization messages).
+const FACILITY: u8 = 4;
+// Private enterprise number reserved for documentation (RFC 5612).
+const SD_ID: &str = "cnpd@32473";
+
+#[derive(Default)]
+struct Queue {
+    messages: VecDeque<String>,
+    warned: bool,
+    shutdown: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    ready: Condvar,
+}
+
+// Sends events to a remote syslog server over one persistent TCP connection, from a
+// background thread. Messages are queued while the server is unreachable; when the
+// queue is full the oldest message is dropped.
+//
+// The connection is plain TCP (RFC 6587 framing), not the TLS of RFC 5425: no TLS
+// implementation is available to this build. It is only used with
+// --syslog-allow-plaintext, and should go through a local relay or a tunnel that
+// adds TLS when the server is off the host.
+pub struct SyslogTcpSink {
+    shared: Arc<Shared>,
+    thread: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl SyslogTcpSink {
+    pub fn start(addr: &str) -> std::io::Result<SyslogTcpSink> {
+        eprintln!("Warning: sending events to syslog server {} unencrypted, over plain TCP", addr);
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            ready: Condvar::new(),
+        });
+        let addr = addr.to_string();
+        let worker = shared.clone();
+        let thread = thread::Builder::new()
+            .name("cnpd-syslog".to_string())
+            .spawn(move || run(&addr, &worker))?;
+        Ok(SyslogTcpSink {
+            shared,
+            thread: Mutex::new(Some(thread)),
+        })
+    }
+
+    pub fn send(&self, event: &DetectionEvent) {
+        let message = format_message(event);
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.messages.len() >= MAX_QUEUED {
+            queue.messages.pop_front();
+            eprintln!("Warning: syslog queue is full, dropping the oldest event");
+        }
+        queue.messages.push_back(message);
+        if queue.messages.len() >= WARN_QUEUED && !queue.warned {
+            queue.warned = true;
+            eprintln!(
+                "Warning: syslog queue is {}% full ({} of {} events)",
+                queue.messages.len() * 100 / MAX_QUEUED,
+                queue.messages.len(),
+                MAX_QUEUED
+            );
+        }
+        self.shared.ready.notify_one();
+    }
+
+    // Waits up to `timeout` for queued events to be sent, then closes the connection.
+    pub fn shutdown(&self, timeout: Duration) {
+        self.shared.queue.lock().unwrap().shutdown = true;
+        self.shared.ready.notify_one();
+
+        let deadline = Instant::now() + timeout;
+        let Some(thread) = self.thread.lock().unwrap().take() else {
+            return;
+        };
+        while !thread.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let left = self.shared.queue.lock().unwrap().messages.len();
+        if left > 0 {
+            eprintln!("Warning: {} syslog events were not delivered before shutdown", left);
+        }
+    }
+}
+
+fn run(addr: &str, shared: &Shared) {
+    let mut stream: Option<TcpStream> = None;
+    let mut backoff = Duration::from_secs(1);
+
+    loop {
+        let message = {
+            let mut queue = shared.queue.lock().unwrap();
+            while queue.messages.is_empty() && !queue.shutdown {
+                queue = shared.ready.wait(queue).unwrap();
+            }
+            match queue.messages.front() {
+                Some(message) => message.clone(),
+                None => return,
+            }
+        };
+
+        let connection = match &mut stream {
+            Some(connection) => connection,
+            None => match TcpStream::connect(addr) {
+                Ok(connection) => {
+                    info!("Connected to syslog server {}", addr);
+                    backoff = Duration::from_secs(1);
+                    stream.insert(connection)
+                }
+                Err(e) => {
+                    if shared.queue.lock().unwrap().shutdown {
+                        return;
+                    }
+                    eprintln!("Failed to connect to syslog server {}: {}, retrying in {}s", addr, e, backoff.as_secs());
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    continue;
+                }
+            },
+        };
+
+        // Octet-counting framing (RFC 5425 section 4.3 / RFC 6587).
+        match connection.write_all(format!("{} {}", message.len(), message).as_bytes()) {
+            Ok(()) => {
+                let mut queue = shared.queue.lock().unwrap();
+                queue.messages.pop_front();
+                if queue.messages.len() < WARN_QUEUED {
+                    queue.warned = false;
+                }
+            }
+            Err(e) => {
+                eprintln!("Lost connection to syslog server {}: {}", addr, e);
+                stream = None;
+            }
+        }
+    }
+}
+
+pub(crate) fn hostname() -> String {
+    let mut buf = [0 as libc::c_char; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) } != 0 {
+        return "-".to_string();
+    }
+    unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy().into_owned()
+}
+
+// Characters that must be escaped inside SD-PARAM values.
+fn escape_param(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]")
+}
+
+// Syslog severity of an event, also used as the journald PRIORITY.
+pub fn severity(event: &DetectionEvent) -> u8 {
+    match event.kind {
+        EventKind::ProcessExit => 5,
+        EventKind::NamespaceEscape => 1,
+        EventKind::PrivilegeEscalation | EventKind::CapabilityAbuse | EventKind::Ptrace => 2,
+        EventKind::ActionTimeout => 2,
+        EventKind::Overload | EventKind::MonitoringDegraded | EventKind::CpuSpike | EventKind::IoSpike => 4,
+        // Critical: the container's traffic may no longer be matched by network policies.
+        EventKind::NetClassChange => 2,
+        EventKind::FileWrite | EventKind::Correlated => 5 - event.action.score(),
+        // Warning: the syscall was already blocked, but something in the container tried it.
+        EventKind::SeccompViolation => 4,
+        EventKind::HiddenFile => 3,
+        // Error when the mount hides a sensitive path, notice otherwise.
+        EventKind::MountChange if event.shadows_sensitive_path == Some(true) => 3,
+        EventKind::MountChange => 5,
+        // Notice for log-only, down to critical for stop; one level more severe for a
+        // process working in a sensitive directory.
+        EventKind::NewProcess if event.suspicious_cwd => 4 - event.action.score(),
+        EventKind::NewProcess => 5 - event.action.score(),
+    }
+}
+
+// An RFC 5424 message whose structured data carries the key fields and whose body is
+// the full event as JSON.
+pub fn format_message(event: &DetectionEvent) -> String {
+    format!(
+        "<{}>1 {} {} cnpd {} {} [{} container=\"{}\" pid=\"{}\" action=\"{}\"] {}",
+        FACILITY * 8 + severity(event),
+        Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+        hostname(),
+        std::process::id(),
+        event.kind,
+        SD_ID,
+        escape_param(&event.container_id),
+        event.pid,
+        event.action,
+        event.to_json()
+    )
+}
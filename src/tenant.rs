@@ -0,0 +1,123 @@
+use std::error::Error;
+
+use crate::json::Value;
+use crate::policy::{glob_match, Policy, PolicyEngine};
+
+// One `[[tenants]]` entry of the config file:
+//
+//     [[tenants]]
+//     name = "payments"
+//     containers = ["payments-*"]
+//     policy = "/etc/cnpd/payments-policy.toml"
+//     webhook = "http://alerts.payments.internal/cnpd"
+//     log_file = "/var/log/cnpd/payments.jsonl"
+//     uids = [1001, 1002]
+#[derive(Debug, Clone)]
+pub struct Tenant {
+    pub name: String,
+    // Globs matched against the container name or ID.
+    pub containers: Vec<String>,
+    pub policy_file: Option<String>,
+    pub webhook: Option<String>,
+    pub log_file: Option<String>,
+    // Local users that see this tenant's containers on the control socket.
+    pub uids: Vec<u32>,
+}
+
+impl Tenant {
+    pub fn from_toml(value: &Value) -> Result<Tenant, String> {
+        let string = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+        let name = string("name").ok_or("Tenant is missing a name")?;
+        let list = |key: &str| value.get(key).and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+        Ok(Tenant {
+            containers: list("containers")
+                .iter()
+                .map(|v| v.as_str().map(str::to_string))
+                .collect::<Option<_>>()
+                .ok_or_else(|| format!("Tenant {}: containers must be a list of patterns", name))?,
+            policy_file: string("policy"),
+            webhook: string("webhook"),
+            log_file: string("log_file"),
+            uids: list("uids")
+                .iter()
+                .map(|v| v.as_i64().and_then(|uid| u32::try_from(uid).ok()))
+                .collect::<Option<_>>()
+                .ok_or_else(|| format!("Tenant {}: uids must be a list of user IDs", name))?,
+            name,
+        })
+    }
+
+    pub fn owns(&self, container_id: &str, name: Option<&str>) -> bool {
+        self.containers.iter().any(|pattern| {
+            glob_match(pattern, container_id) || name.is_some_and(|name| glob_match(pattern, name))
+        })
+    }
+}
+
+// What a control socket client may see.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Access {
+    // Root and the daemon's own user see every container.
+    Admin,
+    Tenants(Vec<String>),
+}
+
+impl Access {
+    pub fn allows(&self, tenant: Option<&str>) -> bool {
+        match self {
+            Access::Admin => true,
+            Access::Tenants(names) => tenant.is_some_and(|tenant| names.iter().any(|name| name == tenant)),
+        }
+    }
+}
+
+// The configured tenants with their policies loaded.
+#[derive(Default)]
+pub struct Tenants {
+    tenants: Vec<(Tenant, Option<PolicyEngine>)>,
+}
+
+impl Tenants {
+    pub async fn load(tenants: &[Tenant]) -> Result<Tenants, Box<dyn Error>> {
+        let mut loaded = Vec::new();
+        for tenant in tenants {
+            let engine = match &tenant.policy_file {
+                Some(path) => Some(PolicyEngine::new(Policy::load(path).await?)),
+                None => None,
+            };
+            loaded.push((tenant.clone(), engine));
+        }
+        Ok(Tenants { tenants: loaded })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tenants.is_empty()
+    }
+
+    // The first tenant whose patterns match wins; containers no tenant owns are admin-only.
+    pub fn find(&self, container_id: &str, name: Option<&str>) -> Option<&Tenant> {
+        self.tenants.iter().map(|(t, _)| t).find(|t| t.owns(container_id, name))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tenant> {
+        self.tenants.iter().map(|(t, _)| t).find(|t| t.name == name)
+    }
+
+    // The tenant's own policy, if it has one.
+    pub fn engine(&self, name: &str) -> Option<&PolicyEngine> {
+        self.tenants.iter().find(|(t, _)| t.name == name).and_then(|(_, engine)| engine.as_ref())
+    }
+
+    pub fn access(&self, uid: u32) -> Access {
+        if uid == 0 || uid == unsafe { libc::geteuid() } {
+            return Access::Admin;
+        }
+        Access::Tenants(
+            self.tenants
+                .iter()
+                .filter(|(t, _)| t.uids.contains(&uid))
+                .map(|(t, _)| t.name.clone())
+                .collect(),
+        )
+    }
+}
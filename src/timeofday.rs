@@ -0,0 +1,167 @@
+use chrono::{DateTime, Days, Local, NaiveDate, Timelike};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use crate::json::Value;
+use crate::sqlite::Database;
+
+// Days of history an hour is judged by, and how long a container is only learned from.
+pub const WINDOW_DAYS: u64 = 7;
+
+const CREATE_ACTIVITY: &str = "CREATE TABLE IF NOT EXISTS process_activity (
+    container TEXT,
+    day TEXT,
+    hour INT,
+    count INT,
+    PRIMARY KEY (container, day, hour)
+)";
+// Kept apart from process_activity, whose old days are pruned.
+const CREATE_STARTED: &str = "CREATE TABLE IF NOT EXISTS activity_started (
+    container TEXT PRIMARY KEY,
+    first_day TEXT
+)";
+const RECORD_ACTIVITY: &str = "INSERT INTO process_activity (container, day, hour, count) VALUES (?, ?, ?, 1)
+    ON CONFLICT (container, day, hour) DO UPDATE SET count = count + 1";
+const RECORD_STARTED: &str = "INSERT OR IGNORE INTO activity_started (container, first_day) VALUES (?, ?)";
+const DAY_FORMAT: &str = "%Y-%m-%d";
+
+// New processes of one container by day and hour of the day, in local time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ActivityHistory {
+    first_day: Option<NaiveDate>,
+    counts: HashMap<(NaiveDate, u32), u32>,
+}
+
+impl ActivityHistory {
+    pub fn record(&mut self, day: NaiveDate, hour: u32) {
+        self.first_day = Some(self.first_day.map_or(day, |first| first.min(day)));
+        *self.counts.entry((day, hour)).or_default() += 1;
+    }
+
+    // Until the container has been watched for WINDOW_DAYS, no hour counts as unusual.
+    pub fn learning(&self, today: NaiveDate) -> bool {
+        match self.first_day {
+            Some(first) => today < first + Days::new(WINDOW_DAYS),
+            None => true,
+        }
+    }
+
+    // Whether the container had new processes at `hour` on any of the last WINDOW_DAYS
+    // days, today included.
+    pub fn active_at(&self, today: NaiveDate, hour: u32) -> bool {
+        let since = today - Days::new(WINDOW_DAYS - 1);
+        self.counts
+            .iter()
+            .any(|((day, h), count)| *h == hour && *day >= since && *day <= today && *count > 0)
+    }
+
+    pub fn is_anomalous(&self, today: NaiveDate, hour: u32) -> bool {
+        !self.learning(today) && !self.active_at(today, hour)
+    }
+
+    fn prune(&mut self, today: NaiveDate) {
+        let since = today - Days::new(WINDOW_DAYS - 1);
+        self.counts.retain(|(day, _), _| *day >= since);
+    }
+}
+
+// --detect-time-of-day-anomalies: flags new processes appearing at hours of the day
+// their container is normally quiet at. The history lives in the --write-events-to-sqlite
+// database, next to the events, so it survives restarts of the daemon.
+pub struct TimeAnomalyDetector {
+    db: Arc<Mutex<Database>>,
+    // Keyed by container name where there is one, which outlives the container's ID.
+    histories: Mutex<HashMap<String, ActivityHistory>>,
+}
+
+impl TimeAnomalyDetector {
+    pub fn open(path: &str) -> Result<TimeAnomalyDetector, Box<dyn Error>> {
+        let db = Database::open(path)?;
+        db.execute(CREATE_ACTIVITY, &[])?;
+        db.execute(CREATE_STARTED, &[])?;
+        let today = Local::now().date_naive();
+        let since = (today - Days::new(WINDOW_DAYS - 1)).format(DAY_FORMAT).to_string();
+        db.execute("DELETE FROM process_activity WHERE day < ?", &[since.into()])?;
+
+        let mut histories: HashMap<String, ActivityHistory> = HashMap::new();
+        let day = |row: &Value, key: &str| {
+            row.get(key)
+                .and_then(Value::as_str)
+                .and_then(|day| NaiveDate::parse_from_str(day, DAY_FORMAT).ok())
+        };
+        for row in db.query("SELECT container, first_day FROM activity_started", &[])? {
+            if let (Some(container), Some(first_day)) = (row.get("container").and_then(Value::as_str), day(&row, "first_day")) {
+                histories.entry(container.to_string()).or_default().first_day = Some(first_day);
+            }
+        }
+        for row in db.query("SELECT container, day, hour, count FROM process_activity", &[])? {
+            let container = row.get("container").and_then(Value::as_str);
+            let hour = row.get("hour").and_then(Value::as_i64);
+            let count = row.get("count").and_then(Value::as_i64);
+            if let (Some(container), Some(day), Some(hour), Some(count)) = (container, day(&row, "day"), hour, count) {
+                let history = histories.entry(container.to_string()).or_default();
+                history.counts.insert((day, hour as u32), count as u32);
+                history.first_day = Some(history.first_day.map_or(day, |first| first.min(day)));
+            }
+        }
+        Ok(TimeAnomalyDetector {
+            db: Arc::new(Mutex::new(db)),
+            histories: Mutex::new(histories),
+        })
+    }
+
+    // Judges a new process against the container's history, then adds it. The database
+    // is updated on a blocking thread, so a slow disk never holds up a monitoring task.
+    pub fn observe(&self, container: &str, at: DateTime<Local>) -> bool {
+        let (today, hour) = (at.date_naive(), at.hour());
+        let anomalous = {
+            let mut histories = self.histories.lock().unwrap();
+            let history = histories.entry(container.to_string()).or_default();
+            let anomalous = history.is_anomalous(today, hour);
+            history.prune(today);
+            history.record(today, hour);
+            anomalous
+        };
+
+        let db = self.db.clone();
+        let container = container.to_string();
+        let day = today.format(DAY_FORMAT).to_string();
+        tokio::task::spawn_blocking(move || {
+            let db = db.lock().unwrap();
+            let result = db
+                .execute(RECORD_STARTED, &[container.as_str().into(), day.as_str().into()])
+                .and_then(|_| db.execute(RECORD_ACTIVITY, &[container.as_str().into(), day.into(), hour.into()]));
+            if let Err(e) = result {
+                eprintln!("Failed to record process activity of {}: {}", container, e);
+            }
+        });
+        anomalous
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_hours_quiet_all_week_after_learning() {
+        let first = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+        let mut history = ActivityHistory::default();
+        for day in 0..WINDOW_DAYS {
+            for hour in 9..18 {
+                assert!(!history.is_anomalous(first + Days::new(day), hour));
+                history.record(first + Days::new(day), hour);
+            }
+        }
+
+        let week_later = first + Days::new(WINDOW_DAYS);
+        assert!(!history.learning(week_later));
+        assert!(history.is_anomalous(week_later, 3));
+        assert!(!history.is_anomalous(week_later, 10));
+
+        // Activity older than the window no longer counts.
+        let month_later = first + Days::new(30);
+        assert!(history.is_anomalous(month_later, 10));
+    }
+}
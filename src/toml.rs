@@ -0,0 +1,298 @@
+use crate::json::Value;
+
+// Parses the subset of TOML used by policy and config files: tables, arrays of
+// tables, dotted keys, strings, numbers, booleans, arrays and inline tables.
+// Tables are returned as `Value::Object`.
+pub fn parse(input: &str) -> Result<Value, String> {
+    let mut parser = Parser { chars: input.chars().collect(), pos: 0, line: 1 };
+    let mut root = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+
+    loop {
+        parser.skip_blank();
+        match parser.peek() {
+            None => break,
+            Some('[') => {
+                parser.pos += 1;
+                let array = parser.peek() == Some('[');
+                if array {
+                    parser.pos += 1;
+                }
+                let path = parser.key()?;
+                parser.expect(']')?;
+                if array {
+                    parser.expect(']')?;
+                    let (last, parent) = path.split_last().ok_or("Empty table name")?;
+                    let fields = table_mut(&mut root, parent).map_err(|e| parser.error(&e))?;
+                    match fields.iter_mut().find(|(k, _)| k == last) {
+                        Some((_, Value::Array(items))) => items.push(Value::Object(Vec::new())),
+                        Some(_) => return Err(parser.error(&format!("'{}' is not an array of tables", last))),
+                        None => fields.push((last.clone(), Value::Array(vec![Value::Object(Vec::new())]))),
+                    }
+                } else {
+                    table_mut(&mut root, &path).map_err(|e| parser.error(&e))?;
+                }
+                current = path;
+            }
+            Some(_) => {
+                let key = parser.key()?;
+                parser.expect('=')?;
+                let value = parser.value()?;
+                let (last, parent) = key.split_last().ok_or("Empty key")?;
+                let path: Vec<String> = current.iter().chain(parent).cloned().collect();
+                let fields = table_mut(&mut root, &path).map_err(|e| parser.error(&e))?;
+                if fields.iter().any(|(k, _)| k == last) {
+                    return Err(parser.error(&format!("Duplicate key '{}'", last)));
+                }
+                fields.push((last.clone(), value));
+            }
+        }
+        parser.end_of_line()?;
+    }
+
+    Ok(Value::Object(root))
+}
+
+fn table_mut<'a>(
+    root: &'a mut Vec<(String, Value)>,
+    path: &[String],
+) -> Result<&'a mut Vec<(String, Value)>, String> {
+    let mut fields = root;
+    for segment in path {
+        let index = match fields.iter().position(|(k, _)| k == segment) {
+            Some(index) => index,
+            None => {
+                fields.push((segment.clone(), Value::Object(Vec::new())));
+                fields.len() - 1
+            }
+        };
+        fields = match &mut fields[index].1 {
+            Value::Object(inner) => inner,
+            Value::Array(items) => match items.last_mut() {
+                Some(Value::Object(inner)) => inner,
+                _ => return Err(format!("'{}' is not a table", segment)),
+            },
+            _ => return Err(format!("'{}' is not a table", segment)),
+        };
+    }
+    Ok(fields)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> String {
+        format!("line {}: {}", self.line, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.pos += 1;
+            }
+        }
+    }
+
+    // Whitespace, newlines and comments.
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') | Some('\r') => {
+                    self.advance();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        self.skip_comment();
+        match self.peek() {
+            None | Some('\n') | Some('\r') => Ok(()),
+            _ => Err(self.error("Expected end of line")),
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_spaces();
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", expected)))
+        }
+    }
+
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut segments = Vec::new();
+        loop {
+            self.skip_spaces();
+            let segment = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let start = self.pos;
+                    while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                        self.pos += 1;
+                    }
+                    if start == self.pos {
+                        return Err(self.error("Expected key"));
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                }
+            };
+            segments.push(segment);
+            self.skip_spaces();
+            if self.peek() == Some('.') {
+                self.pos += 1;
+            } else {
+                return Ok(segments);
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some('t') | Some('f') => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if c.is_ascii_alphabetic()) {
+                    self.pos += 1;
+                }
+                match self.chars[start..self.pos].iter().collect::<String>().as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => Err(self.error("Invalid boolean")),
+                }
+            }
+            Some(c) if c == '-' || c == '+' || c.is_ascii_digit() => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || "+-._".contains(c)) {
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().filter(|c| **c != '_').collect();
+                text.parse()
+                    .map(Value::Number)
+                    .map_err(|_| self.error(&format!("Invalid number '{}'", text)))
+            }
+            _ => Err(self.error("Expected value")),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_blank();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error("Expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut fields = Vec::new();
+        loop {
+            self.skip_spaces();
+            if self.peek() == Some('}') {
+                self.pos += 1;
+                return Ok(Value::Object(fields));
+            }
+            let key = self.key()?;
+            self.expect('=')?;
+            let value = self.value()?;
+            let (last, parent) = key.split_last().ok_or("Empty key")?;
+            table_mut(&mut fields, parent)
+                .map_err(|e| self.error(&e))?
+                .push((last.clone(), value));
+            self.skip_spaces();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(fields));
+                }
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.advance() {
+                None | Some('\n') => return Err(self.error("Unterminated string")),
+                Some('"') => return Ok(s),
+                Some('\\') => match self.advance() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('u') => {
+                        let hex: String = (0..4).filter_map(|_| self.advance()).collect();
+                        let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                        s.push(c.ok_or_else(|| self.error("Invalid unicode escape"))?);
+                    }
+                    _ => return Err(self.error("Invalid escape sequence")),
+                },
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.advance() {
+                None | Some('\n') => return Err(self.error("Unterminated string")),
+                Some('\'') => return Ok(s),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+}
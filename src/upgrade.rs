@@ -0,0 +1,220 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::Command;
+use tokio::fs;
+
+use crate::cgroup::ContainerCgroup;
+use crate::json::{self, Value};
+use crate::metrics::Counters;
+use crate::procfs;
+use crate::sha256;
+
+pub const DEFAULT_UPGRADE_STATE_FILE: &str = "/var/lib/cnpd/upgrade-state.json";
+const RESUME_FLAG: &str = "--resume-from-state-file";
+
+// The binary this process runs, to tell on SIGHUP whether a new one was installed over it.
+pub struct RunningBinary {
+    pub path: PathBuf,
+}
+
+impl RunningBinary {
+    // The /proc/self/exe link gets " (deleted)" appended once the file is replaced, so
+    // the path is taken at startup.
+    pub fn current() -> io::Result<RunningBinary> {
+        Ok(RunningBinary {
+            path: std::env::current_exe()?,
+        })
+    }
+
+    // Whether the file at the path the binary was started from differs from it.
+    // /proc/self/exe still reads as the running binary after it was replaced.
+    pub async fn replaced(&self) -> io::Result<bool> {
+        let running = sha256::hex_digest(&fs::read("/proc/self/exe").await?);
+        Ok(sha256::hex_digest(&fs::read(&self.path).await?) != running)
+    }
+
+    // Replaces this process with the binary now at the path, with the same arguments
+    // and --resume-from-state-file. Only returns if that failed.
+    pub fn exec(&self, state_file: &str) -> io::Error {
+        Command::new(&self.path).args(resume_args(std::env::args().skip(1), state_file)).exec()
+    }
+}
+
+// The arguments without the --resume-from-state-file of an earlier upgrade, and with
+// the one for this upgrade.
+pub fn resume_args<I: IntoIterator<Item = String>>(args: I, state_file: &str) -> Vec<String> {
+    let mut resumed = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == RESUME_FLAG {
+            args.next();
+        } else {
+            resumed.push(arg);
+        }
+    }
+    resumed.extend([RESUME_FLAG.to_string(), state_file.to_string()]);
+    resumed
+}
+
+// A monitored container and the processes its task whitelisted, by PID and start time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResumedContainer {
+    pub cgroup: ContainerCgroup,
+    pub known: Vec<(i32, u64)>,
+}
+
+impl ResumedContainer {
+    fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("root".to_string(), self.cgroup.root.as_str().into()),
+            ("name".to_string(), self.cgroup.name.as_str().into()),
+            ("memory_limit".to_string(), self.cgroup.memory_limit.into()),
+            (
+                "known".to_string(),
+                Value::Array(
+                    self.known
+                        .iter()
+                        .map(|(pid, start)| Value::Array(vec![(*pid).into(), (*start).into()]))
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+
+    fn from_json(value: &Value) -> Option<ResumedContainer> {
+        let known = value
+            .get("known")?
+            .as_array()?
+            .iter()
+            .filter_map(|key| match key.as_array()?.as_slice() {
+                [pid, start] => Some((pid.as_i64()? as i32, start.as_i64()? as u64)),
+                _ => None,
+            })
+            .collect();
+        Some(ResumedContainer {
+            cgroup: ContainerCgroup {
+                root: value.get("root")?.as_str()?.to_string(),
+                name: value.get("name")?.as_str()?.to_string(),
+                memory_limit: value.get("memory_limit").and_then(Value::as_i64).map(|limit| limit as u64),
+            },
+            known,
+        })
+    }
+
+    // The whitelisted processes still running. A PID reused while the binaries were
+    // swapped has a new start time and is left out, to be detected on the first poll.
+    pub async fn still_running(&self) -> HashSet<i32> {
+        procfs::still_running(&self.known).await
+    }
+}
+
+// What the old binary hands the new one on an in-place upgrade.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResumeState {
+    pub containers: Vec<ResumedContainer>,
+    pub counters: Counters,
+}
+
+impl ResumeState {
+    pub fn to_json(&self) -> Value {
+        let counters = &self.counters;
+        Value::Object(vec![
+            (
+                "containers".to_string(),
+                Value::Array(self.containers.iter().map(ResumedContainer::to_json).collect()),
+            ),
+            ("detections_total".to_string(), counters.detections_total.into()),
+            ("blocked_total".to_string(), counters.blocked_total.into()),
+            ("allowed_total".to_string(), counters.allowed_total.into()),
+            ("events_dropped_total".to_string(), counters.events_dropped_total.into()),
+            (
+                "container_detections".to_string(),
+                Value::Object(
+                    counters
+                        .container_detections
+                        .iter()
+                        .map(|(id, count)| (id.clone(), (*count).into()))
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+
+    pub fn from_json(value: &Value) -> ResumeState {
+        let count = |key: &str| value.get(key).and_then(Value::as_i64).unwrap_or(0) as u64;
+        ResumeState {
+            containers: value
+                .get("containers")
+                .and_then(Value::as_array)
+                .map(|containers| containers.iter().filter_map(ResumedContainer::from_json).collect())
+                .unwrap_or_default(),
+            counters: Counters {
+                detections_total: count("detections_total"),
+                blocked_total: count("blocked_total"),
+                allowed_total: count("allowed_total"),
+                events_dropped_total: count("events_dropped_total"),
+                container_detections: match value.get("container_detections") {
+                    Some(Value::Object(detections)) => detections
+                        .iter()
+                        .filter_map(|(id, count)| Some((id.clone(), count.as_i64()? as u64)))
+                        .collect(),
+                    _ => Vec::new(),
+                },
+            },
+        }
+    }
+
+    pub async fn save(&self, path: &str) -> io::Result<()> {
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, format!("{}\n", self.to_json())).await?;
+        fs::rename(&tmp, path).await
+    }
+
+    // The file is removed once read: it describes the moment of the upgrade, and a
+    // later start with the same arguments must not go back to it.
+    pub async fn take(path: &str) -> Result<ResumeState, Box<dyn Error>> {
+        let content = fs::read_to_string(path).await.map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let state = json::parse(&content).map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+        if let Err(e) = fs::remove_file(path).await {
+            eprintln!("Failed to remove {}: {}", path, e);
+        }
+        Ok(ResumeState::from_json(&state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_the_handed_over_state() {
+        let state = ResumeState {
+            containers: vec![ResumedContainer {
+                cgroup: ContainerCgroup {
+                    root: "/sys/fs/cgroup/system.slice".to_string(),
+                    name: "docker-0123456789ab.scope".to_string(),
+                    memory_limit: Some(1 << 30),
+                },
+                known: vec![(1, 4242), (17, 5000)],
+            }],
+            counters: Counters {
+                detections_total: 3,
+                blocked_total: 2,
+                allowed_total: 1,
+                events_dropped_total: 0,
+                container_detections: vec![("0123456789ab".to_string(), 3)],
+            },
+        };
+        let parsed = json::parse(&state.to_json().to_string()).unwrap();
+        assert_eq!(ResumeState::from_json(&parsed), state);
+
+        let args = ["--debug", RESUME_FLAG, "/tmp/old.json", "--state-file", "/tmp/s"].map(str::to_string);
+        assert_eq!(
+            resume_args(args, "/tmp/new.json"),
+            ["--debug", "--state-file", "/tmp/s", RESUME_FLAG, "/tmp/new.json"]
+        );
+    }
+}
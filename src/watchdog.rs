@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{info, log};
+
+enum Message {
+    Register(usize, String),
+    Beat(usize),
+    Pause(usize),
+    Unregister(usize),
+}
+
+struct Task {
+    container: String,
+    last_beat: Instant,
+    stuck: bool,
+    paused: bool,
+}
+
+pub type StuckHandler = Box<dyn Fn(&str) + Send>;
+
+// Runs on its own OS thread so that a saturated Tokio runtime cannot also silence
+// the watchdog. Monitoring tasks ping it through a `Heartbeat` on every iteration.
+pub struct WatchdogTimer {
+    sender: Sender<Message>,
+    next_id: AtomicUsize,
+}
+
+// Sends a heartbeat for one monitoring task. Dropping it unregisters the task, so a
+// task that exits is not reported as stuck.
+pub struct Heartbeat {
+    id: usize,
+    sender: Sender<Message>,
+}
+
+impl Heartbeat {
+    pub fn beat(&self) {
+        let _ = self.sender.send(Message::Beat(self.id));
+    }
+
+    // For the duration of an action, which may take far longer than a poll: docker
+    // stop, a pre-action hook or a core dump. The task is not reported as stuck until
+    // the returned guard is dropped, which counts as a beat.
+    pub fn pause(&self) -> Paused<'_> {
+        let _ = self.sender.send(Message::Pause(self.id));
+        Paused { heartbeat: self }
+    }
+}
+
+pub struct Paused<'a> {
+    heartbeat: &'a Heartbeat,
+}
+
+impl Drop for Paused<'_> {
+    fn drop(&mut self) {
+        self.heartbeat.beat();
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        let _ = self.sender.send(Message::Unregister(self.id));
+    }
+}
+
+impl WatchdogTimer {
+    pub fn start(timeout: Duration, on_stuck: Option<StuckHandler>) -> std::io::Result<WatchdogTimer> {
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("cnpd-watchdog".to_string())
+            .spawn(move || {
+                let mut tasks: HashMap<usize, Task> = HashMap::new();
+                let check_interval = timeout / 4;
+                let mut last_check = Instant::now();
+                loop {
+                    match receiver.recv_timeout(check_interval) {
+                        Ok(Message::Register(id, container)) => {
+                            tasks.insert(id, Task { container, last_beat: Instant::now(), stuck: false, paused: false });
+                        }
+                        Ok(Message::Beat(id)) => {
+                            if let Some(task) = tasks.get_mut(&id) {
+                                if task.stuck {
+                                    info!("Monitoring task for {} has recovered", log::id(&task.container));
+                                }
+                                task.last_beat = Instant::now();
+                                task.stuck = false;
+                                task.paused = false;
+                            }
+                        }
+                        Ok(Message::Pause(id)) => {
+                            if let Some(task) = tasks.get_mut(&id) {
+                                task.paused = true;
+                            }
+                        }
+                        Ok(Message::Unregister(id)) => {
+                            tasks.remove(&id);
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+
+                    if last_check.elapsed() < check_interval {
+                        continue;
+                    }
+                    last_check = Instant::now();
+                    for task in tasks.values_mut() {
+                        if !task.stuck && !task.paused && task.last_beat.elapsed() > timeout {
+                            task.stuck = true;
+                            eprintln!("CRITICAL: Monitoring task for {} appears stuck", log::id(&task.container));
+                            if let Some(on_stuck) = &on_stuck {
+                                on_stuck(&task.container);
+                            }
+                        }
+                    }
+                }
+            })?;
+
+        Ok(WatchdogTimer { sender, next_id: Default::default() })
+    }
+
+    pub fn heartbeat(&self, container: &str) -> Heartbeat {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let _ = self.sender.send(Message::Register(id, container.to_string()));
+        Heartbeat { id, sender: self.sender.clone() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paused_tasks_are_not_reported() {
+        let (stuck_tx, stuck_rx) = mpsc::channel();
+        let on_stuck: StuckHandler = Box::new(move |container: &str| {
+            let _ = stuck_tx.send(container.to_string());
+        });
+        let watchdog = WatchdogTimer::start(Duration::from_millis(40), Some(on_stuck)).unwrap();
+        let acting = watchdog.heartbeat("acting");
+        let _idle = watchdog.heartbeat("idle");
+
+        let paused = acting.pause();
+        assert_eq!(stuck_rx.recv_timeout(Duration::from_secs(5)).unwrap(), "idle");
+        thread::sleep(Duration::from_millis(200));
+        assert!(stuck_rx.try_recv().is_err());
+
+        // Resuming counts as a beat, so only another full timeout reports the task.
+        drop(paused);
+        assert_eq!(stuck_rx.recv_timeout(Duration::from_secs(5)).unwrap(), "acting");
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::asyncset::AsyncHashSet;
+
+// One container's whitelist as seen from outside its monitoring task. The task keeps
+// working on its own known_procs and only publishes a copy when it changes, so polls
+// that find nothing new take no lock here at all.
+#[derive(Default)]
+pub struct ContainerWhitelist {
+    known: AsyncHashSet<(i32, u64)>,
+    // Set when a publish found `known` being read; the task publishes again next poll.
+    stale: AtomicBool,
+    // PIDs added with `cnpd-ctl whitelist add`, taken by the task on its next poll.
+    pending: Mutex<Vec<i32>>,
+    has_pending: AtomicBool,
+}
+
+impl ContainerWhitelist {
+    // Never waits: the poll loop does not queue behind a `cnpd-ctl known-pids`, the
+    // control socket just sees the previous copy until the next poll.
+    pub fn publish(&self, known: &HashSet<(i32, u64)>) {
+        self.stale.store(!self.known.try_replace(known), Ordering::Relaxed);
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.stale.load(Ordering::Relaxed)
+    }
+
+    // A single atomic load unless PIDs were added since the last call.
+    pub fn take_pending(&self) -> Option<Vec<i32>> {
+        if !self.has_pending.swap(false, Ordering::Acquire) {
+            return None;
+        }
+        Some(std::mem::take(&mut *self.pending.lock().unwrap()))
+    }
+
+    // Every (PID, start time) the task knows, for an in-place upgrade.
+    pub async fn known(&self) -> HashSet<(i32, u64)> {
+        self.known.snapshot().await
+    }
+
+    pub async fn pids(&self) -> Vec<i32> {
+        distinct_pids(&self.known.snapshot().await)
+    }
+}
+
+// Sorted, with a reused PID, known under both its old and new start time, once.
+pub fn distinct_pids(known: &HashSet<(i32, u64)>) -> Vec<i32> {
+    let mut pids: Vec<i32> = known.iter().map(|(pid, _)| *pid).collect();
+    pids.sort_unstable();
+    pids.dedup();
+    pids
+}
+
+// The whitelists of all monitored containers, shared between the monitoring tasks and
+// the control socket.
+#[derive(Default)]
+pub struct ProcessWhitelistStore {
+    containers: RwLock<HashMap<String, Arc<ContainerWhitelist>>>,
+}
+
+impl ProcessWhitelistStore {
+    // Called when a monitoring task starts. The task holds on to the returned whitelist
+    // for as long as it runs, so that a whitelist only the store refers to belongs to a
+    // container no longer monitored.
+    pub fn register(&self, container_id: &str) -> Arc<ContainerWhitelist> {
+        let whitelist = Arc::new(ContainerWhitelist::default());
+        self.containers.write().unwrap().insert(container_id.to_string(), whitelist.clone());
+        whitelist
+    }
+
+    // `container` is a full container ID or an unambiguous prefix of one, as shown by
+    // `cnpd-ctl containers`. Only monitored containers count. Returns the full ID.
+    pub fn resolve(&self, container: &str) -> Result<String, String> {
+        let containers = self.containers.read().unwrap();
+        let mut matches = containers
+            .iter()
+            .filter(|(id, whitelist)| id.starts_with(container) && Arc::strong_count(whitelist) > 1);
+        match (matches.next(), matches.next()) {
+            (Some((id, _)), None) => Ok(id.clone()),
+            (Some(_), Some(_)) => Err(format!("Container ID {} is ambiguous", container)),
+            (None, _) => Err(format!("Container {} is not being monitored", container)),
+        }
+    }
+
+    // Queues PIDs for the task monitoring `container_id`, a full ID from `resolve`.
+    pub fn add(&self, container_id: &str, pids: &[i32]) -> Result<(), String> {
+        let containers = self.containers.read().unwrap();
+        let whitelist = containers
+            .get(container_id)
+            .filter(|whitelist| Arc::strong_count(whitelist) > 1)
+            .ok_or_else(|| format!("Container {} is not being monitored", container_id))?;
+        whitelist.pending.lock().unwrap().extend_from_slice(pids);
+        whitelist.has_pending.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn get(&self, container_id: &str) -> Option<Arc<ContainerWhitelist>> {
+        self.containers.read().unwrap().get(container_id).cloned()
+    }
+
+    // Whitelisted PIDs by container, sorted by container ID.
+    pub async fn known_pids(&self) -> Vec<(String, Vec<i32>)> {
+        let whitelists: Vec<(String, Arc<ContainerWhitelist>)> = self
+            .containers
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(id, whitelist)| (id.clone(), whitelist.clone()))
+            .collect();
+        let mut known = Vec::new();
+        for (id, whitelist) in whitelists {
+            known.push((id, whitelist.pids().await));
+        }
+        known.sort();
+        known
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn added_pids_reach_the_running_task_once() {
+        let store = ProcessWhitelistStore::default();
+        let whitelist = store.register("aaa");
+        whitelist.publish(&[(1, 100), (7, 200), (7, 300)].into_iter().collect());
+        assert_eq!(store.known_pids().await, vec![("aaa".to_string(), vec![1, 7])]);
+
+        assert_eq!(whitelist.take_pending(), None);
+        assert_eq!(store.resolve("aa"), Ok("aaa".to_string()));
+        assert_eq!(store.add("aaa", &[42, 43]), Ok(()));
+        assert_eq!(whitelist.take_pending(), Some(vec![42, 43]));
+        assert_eq!(whitelist.take_pending(), None);
+
+        assert!(store.resolve("bbb").is_err());
+        assert!(store.add("aa", &[1]).is_err());
+        drop(whitelist);
+        assert!(store.resolve("aaa").is_err());
+        assert!(store.add("aaa", &[1]).is_err());
+    }
+}